@@ -0,0 +1,155 @@
+// src/aliases.rs
+//! Metric renames (`rename-metric`) and the alias map they leave behind.
+//!
+//! The map lives in `winbox-stats-aliases.json` next to the databases. The
+//! collector writes new samples under the current name, and graph/export show
+//! tables from files that were never renamed under it too, so history stays
+//! one continuous series.
+use anyhow::{Context, Result};
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::audit;
+use crate::cli::RenameMetricArgs;
+use crate::storage::{self, list_writable_tables, METRICS_TABLE, TS_COL, VALUE_COL};
+
+pub const ALIASES_FILE: &str = "winbox-stats-aliases.json";
+
+/// Old name -> new name
+#[derive(Default)]
+pub struct Aliases(BTreeMap<String, String>);
+
+impl Aliases {
+    pub fn load(dir: &Path) -> Result<Aliases> {
+        let path = dir.join(ALIASES_FILE);
+        if !path.exists() {
+            return Ok(Aliases::default());
+        }
+        let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+        let map = serde_json::from_str(&text).with_context(|| format!("parse {}", path.display()))?;
+        Ok(Aliases(map))
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(ALIASES_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&self.0)?)
+            .with_context(|| format!("write {}", path.display()))
+    }
+
+    /// Current name of a metric, following chained renames
+    pub fn resolve(&self, name: &str) -> String {
+        let mut current = name.to_string();
+        // Bounded so a hand-edited cycle cannot hang us
+        for _ in 0..self.0.len() {
+            match self.0.iter().find(|(k, _)| k.eq_ignore_ascii_case(&current)) {
+                Some((_, next)) => current = next.clone(),
+                None => break,
+            }
+        }
+        current
+    }
+}
+
+/// Rows of one renamed table
+struct Moved {
+    rows: usize,
+    /// Already under the new name at the same time, or with a time that
+    /// does not parse, so dropped
+    duplicates: usize,
+}
+
+/// Rename (or merge into) `to` inside one database; `None` if the table does not exist
+fn rename_in_db(path: &Path, from: &str, to: &str) -> Result<Option<Moved>> {
+    let mut conn = storage::open(path)?;
+    // Compacted tables keep the old name; readers resolve it through the alias
+    let tables = list_writable_tables(&conn)?;
+    let Some(src) = tables.iter().find(|t| t.eq_ignore_ascii_case(from)).cloned() else {
+        return Ok(None);
+    };
+    let tx = conn.transaction()?;
+    let total: i64 = tx.query_row(&format!(r#"SELECT COUNT(*) FROM "{}""#, src), [], |r| r.get(0))?;
+    let total = total as usize;
+    tx.execute_batch(&format!(r#"DROP INDEX IF EXISTS "ix_{src}_{TS_COL}"; DROP INDEX IF EXISTS "ux_{src}_{TS_COL}";"#))?;
+    let rows = match tables.iter().find(|t| t.eq_ignore_ascii_case(to)) {
+        // The collector may already have started a table under the new name; its samples win
+        Some(dst) => {
+            // Either side may be a legacy table with text timestamps, so
+            // samples go through epoch time rather than being copied as stored
+            let text = storage::has_text_timestamps(&tx, dst)?;
+            let mut insert = tx.prepare(&format!(r#"INSERT OR IGNORE INTO "{dst}"("{TS_COL}","{VALUE_COL}") VALUES (?1, ?2)"#))?;
+            let mut rows = 0;
+            for (ts_ms, value) in storage::read_table_samples_ms(&tx, &src)? {
+                rows += insert.execute(params![storage::stored_ts(ts_ms, text), value])?;
+            }
+            drop(insert);
+            tx.execute_batch(&format!(r#"DROP TABLE "{src}";"#))?;
+            rows
+        }
+        None => {
+            tx.execute_batch(&format!(r#"ALTER TABLE "{src}" RENAME TO "{to}";"#))?;
+            storage::index_timestamps(&tx, to)?;
+            total
+        }
+    };
+    tx.execute(
+        &format!(r#"DELETE FROM "{}" WHERE "Name" = ?1"#, METRICS_TABLE),
+        params![src],
+    )?;
+    tx.execute(
+        &format!(r#"INSERT OR IGNORE INTO "{}"("Name") VALUES (?1)"#, METRICS_TABLE),
+        params![to],
+    )?;
+    tx.commit()?;
+    Ok(Some(Moved { rows, duplicates: total - rows }))
+}
+
+/// One database for `rename-metric --output json`
+#[derive(Serialize)]
+struct Renamed {
+    database: PathBuf,
+    rows: usize,
+    /// Rows dropped because the new name already had a sample at their time
+    duplicates: usize,
+}
+
+/// Entry point for `winbox-stats rename-metric`
+pub fn run_rename_metric(args: &RenameMetricArgs) -> Result<()> {
+    let dir = Path::new(".");
+    let mut aliases = Aliases::load(dir)?;
+    if aliases.resolve(&args.to).eq_ignore_ascii_case(&args.from) {
+        anyhow::bail!("{} is already an alias of {}; refusing to create a cycle", args.to, args.from);
+    }
+
+    let mut renamed = Vec::new();
+    for entry in WalkDir::new(dir).max_depth(1).sort_by_file_name().into_iter().filter_map(Result::ok) {
+        let p = entry.path();
+        if !p.is_file() || p.extension().map(|e| !e.eq_ignore_ascii_case("sqlite")).unwrap_or(true) {
+            continue;
+        }
+        if let Some(Moved { rows, duplicates }) = rename_in_db(p, &args.from, &args.to)? {
+            let counts = match duplicates {
+                0 => format!("{} rows", rows),
+                n => format!("{} rows, {} duplicates dropped", rows, n),
+            };
+            audit::record(dir, "rename-metric", &p.display().to_string(), &format!("{} -> {} ({})", args.from, args.to, counts))?;
+            if !args.json {
+                println!("{}: {} -> {} ({})", p.display(), args.from, args.to, counts);
+            }
+            renamed.push(Renamed { database: p.to_path_buf(), rows, duplicates });
+        }
+    }
+
+    aliases.0.insert(args.from.clone(), args.to.clone());
+    aliases.save(dir)?;
+    if args.json {
+        let out = serde_json::json!({ "from": args.from, "to": args.to, "renamed": renamed, "aliases": ALIASES_FILE });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        println!("Recorded alias {} -> {} in {}", args.from, args.to, ALIASES_FILE);
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+// src/analyze.rs
+//! `analyze`: a shortlist of unusual hours in one month, per host and metric.
+//!
+//! Samples are averaged per local hour, and each hour is compared with the
+//! same hour of day over the whole month (a simple daily seasonal baseline):
+//! its robust z-score is the distance from that hour's median in units of
+//! the scaled median absolute deviation. Adjacent flagged hours are merged
+//! into one period.
+use anyhow::Result;
+use chrono::{DateTime, Local, Timelike};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::cli::AnalyzeArgs;
+use crate::series;
+use crate::storage;
+
+/// MAD * 1.4826 estimates the standard deviation of normally distributed data
+const MAD_SCALE: f64 = 1.4826;
+/// Hours of day seen on fewer days than this have no usable baseline
+const MIN_DAYS: usize = 5;
+
+#[derive(Serialize)]
+struct Finding {
+    host: String,
+    metric: String,
+    from: String,
+    to: String,
+    /// Hourly average furthest from the baseline within the period
+    value: f64,
+    /// Median of the same hour of day over the month
+    baseline: f64,
+    z: f64,
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    }
+}
+
+/// (median, spread) of one hour of day; `None` when the hour is too sparse or flat
+fn baseline(values: &[f64]) -> Option<(f64, f64)> {
+    if values.len() < MIN_DAYS {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let med = median(&sorted);
+    let mut dev: Vec<f64> = sorted.iter().map(|v| (v - med).abs()).collect();
+    dev.sort_by(f64::total_cmp);
+    let mut spread = median(&dev) * MAD_SCALE;
+    if spread == 0.0 {
+        // Mostly constant (e.g. a service that is always up): fall back to the standard deviation
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        spread = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt();
+    }
+    (spread > 0.0).then_some((med, spread))
+}
+
+/// Unusual periods of one series, as (from, to, value, baseline, z) with `to` exclusive
+fn findings(samples: &[(i64, f64)], threshold: f64) -> Vec<(i64, i64, f64, f64, f64)> {
+    // Hourly averages keyed by the hour's start
+    let mut hours: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for (ts, v) in samples {
+        let h = hours.entry(ts.div_euclid(3600) * 3600).or_insert((0.0, 0));
+        h.0 += v;
+        h.1 += 1;
+    }
+    let hourly: Vec<(i64, u32, f64)> = hours
+        .into_iter()
+        .map(|(start, (sum, n))| {
+            let local = DateTime::from_timestamp(start, 0).unwrap_or_default().with_timezone(&Local);
+            (start, local.hour(), sum / n as f64)
+        })
+        .collect();
+    let mut by_hour: BTreeMap<u32, Vec<f64>> = BTreeMap::new();
+    for (_, hour, v) in &hourly {
+        by_hour.entry(*hour).or_default().push(*v);
+    }
+    let baselines: BTreeMap<u32, (f64, f64)> =
+        by_hour.into_iter().filter_map(|(h, v)| Some((h, baseline(&v)?))).collect();
+
+    let mut out: Vec<(i64, i64, f64, f64, f64)> = Vec::new();
+    for (start, hour, v) in hourly {
+        let Some(&(med, spread)) = baselines.get(&hour) else { continue };
+        let z = (v - med) / spread;
+        if z.abs() < threshold {
+            continue;
+        }
+        match out.last_mut() {
+            // Extend the previous period when this hour follows it directly
+            Some(last) if last.1 == start => {
+                last.1 = start + 3600;
+                if z.abs() > last.4.abs() {
+                    (last.2, last.3, last.4) = (v, med, z);
+                }
+            }
+            _ => out.push((start, start + 3600, v, med, z)),
+        }
+    }
+    out
+}
+
+/// Entry point for `winbox-stats analyze`
+pub fn run_analyze(args: &AnalyzeArgs) -> Result<()> {
+    let filter = series::Filter {
+        metric: args.metric.as_deref(),
+        host: args.host.as_deref(),
+        month: Some(&args.month),
+        ..Default::default()
+    };
+    let mut found = Vec::new();
+    for ((host, metric), samples) in series::load(Path::new("."), &filter)? {
+        for (from, to, value, baseline, z) in findings(&samples, args.threshold) {
+            found.push(Finding {
+                host: host.clone(),
+                metric: metric.clone(),
+                from: storage::format_ts(from),
+                to: storage::format_ts(to),
+                value,
+                baseline,
+                z,
+            });
+        }
+    }
+    found.sort_by(|a, b| b.z.abs().total_cmp(&a.z.abs()));
+    found.truncate(args.n);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&found)?);
+        return Ok(());
+    }
+    if found.is_empty() {
+        println!("Nothing unusual in {} (|z| >= {})", args.month, args.threshold);
+        return Ok(());
+    }
+    let hw = found.iter().map(|f| f.host.len()).max().unwrap_or(0).max(4);
+    let mw = found.iter().map(|f| f.metric.len()).max().unwrap_or(0).max(6);
+    println!("{:<hw$}  {:<mw$}  {:<25}  {:<25}  {:>9}  {:>9}  {:>6}", "HOST", "METRIC", "FROM", "TO", "VALUE", "BASELINE", "Z");
+    for f in &found {
+        println!(
+            "{:<hw$}  {:<mw$}  {:<25}  {:<25}  {:>9.2}  {:>9.2}  {:>6.1}",
+            f.host, f.metric, f.from, f.to, f.value, f.baseline, f.z
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// src/apps.rs
+//! CPU and memory per application: every process matching one of an app's
+//! name patterns is added into `APP_<name>_CPU` (% of the whole machine, so
+//! it reads against `CPU`) and `APP_<name>_MEM` (resident GB).
+//!
+//! ```toml
+//! [apps]
+//! IIS = ["w3wp"]
+//! SQL = ["sqlservr", "sqlagent*"]
+//! ```
+use std::collections::BTreeMap;
+use sysinfo::System;
+
+use crate::config::glob_match;
+use crate::storage::metric_label;
+
+/// Table prefix for per-application metrics
+pub const APP_PREFIX: &str = "APP_";
+
+pub fn is_app_metric(metric: &str) -> bool {
+    metric.to_ascii_uppercase().starts_with(APP_PREFIX)
+}
+
+/// `*` patterns, case-insensitive; `w3wp` also matches `w3wp.exe`
+fn matches(pattern: &str, process: &str) -> bool {
+    let lower = process.to_ascii_lowercase();
+    glob_match(pattern, process) || lower.strip_suffix(".exe").is_some_and(|bare| glob_match(pattern, bare))
+}
+
+/// Totals per app from the processes in `sys`, which must have been refreshed
+/// twice with CPU usage, the measuring window apart
+pub fn sample(sys: &System, apps: &BTreeMap<String, Vec<String>>) -> Vec<(String, f64)> {
+    // Process usage counts one core as 100%
+    let cores = sys.cpus().len().max(1) as f64;
+    let mut out = Vec::new();
+    for (name, patterns) in apps {
+        let (mut cpu, mut mem) = (0.0, 0u64);
+        for p in sys.processes().values() {
+            if patterns.iter().any(|pat| matches(pat, p.name())) {
+                cpu += p.cpu_usage() as f64;
+                mem += p.memory();
+            }
+        }
+        let label = metric_label(name);
+        out.push((format!("{}{}_CPU", APP_PREFIX, label), cpu / cores));
+        out.push((format!("{}{}_MEM", APP_PREFIX, label), mem as f64 / (1u64 << 30) as f64));
+    }
+    out
+}
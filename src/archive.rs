@@ -0,0 +1,238 @@
+// src/archive.rs
+//! `archive`: compress monthly databases that are no longer collected into,
+//! check that the archive reads back the same, and remove the originals.
+//!
+//! Archives stay readable by graph, export and top-hosts, which inflate them
+//! to a temporary file on the fly.
+use anyhow::{Context, Result};
+use chrono::{Datelike, Local};
+use clap::ValueEnum;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::audit;
+use crate::cli::ArchiveArgs;
+use crate::graph::plot::split_stem_sqlite;
+use crate::storage::{self, insert_sample, list_metric_tables, list_writable_tables, read_samples};
+use crate::timespan;
+
+/// One database for `archive --output json`
+#[derive(Serialize)]
+struct Archived {
+    database: PathBuf,
+    /// `None` with --dry-run
+    archive: Option<PathBuf>,
+    bytes_before: u64,
+    bytes_after: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ArchiveFormat {
+    /// `.sqlite.zip`, opens anywhere without extra tools
+    #[default]
+    Zip,
+    /// `.sqlite.gz`
+    Gz,
+    /// `.sqlite.zst`, smallest
+    Zst,
+}
+
+impl ArchiveFormat {
+    fn ext(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Gz => "gz",
+            ArchiveFormat::Zst => "zst",
+        }
+    }
+}
+
+/// `3m`, `3mo` or `3`: a whole number of months
+pub(crate) fn parse_months(s: &str) -> Result<i32> {
+    let t = s.trim().to_ascii_lowercase();
+    let num = t.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = &t[num.len()..];
+    let n: i32 = match unit {
+        "" | "m" | "mo" | "month" | "months" => num.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow::anyhow!("invalid --older-than {:?}; give a number of months, e.g. 3m", s))?;
+    if n < 1 {
+        anyhow::bail!("--older-than must be at least one month");
+    }
+    Ok(n)
+}
+
+/// Months since year 0, so `YYYYMM` values can be compared and subtracted
+pub(crate) fn month_index(ym: &str) -> Option<i32> {
+    if ym.len() != 6 || !ym.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let y: i32 = ym[..4].parse().ok()?;
+    let m: i32 = ym[4..].parse().ok()?;
+    (1..=12).contains(&m).then_some(y * 12 + m - 1)
+}
+
+/// Rows per metric table, to compare an archive against its source
+fn row_counts(conn: &Connection) -> Result<BTreeMap<String, i64>> {
+    let mut out = BTreeMap::new();
+    for t in list_metric_tables(conn)? {
+        let n = storage::count_samples(conn, &t)? as i64;
+        out.insert(t, n);
+    }
+    Ok(out)
+}
+
+/// Replace each table's samples with their average per `secs` bucket;
+/// compacted tables are kept as they are
+fn aggregate(conn: &mut Connection, secs: i64) -> Result<()> {
+    let tx = conn.transaction()?;
+    for t in list_writable_tables(&tx)? {
+        let samples = read_samples(&tx, &t)?;
+        let mut buckets: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+        for (ts, v) in samples {
+            let b = buckets.entry(ts.div_euclid(secs) * secs).or_insert((0.0, 0));
+            b.0 += v;
+            b.1 += 1;
+        }
+        tx.execute(&format!(r#"DELETE FROM "{}""#, t), [])?;
+        let text = storage::has_text_timestamps(&tx, &t)?;
+        for (ts, (sum, n)) in buckets {
+            insert_sample(&tx, &t, ts, sum / n as f64, None, text)?;
+        }
+    }
+    tx.commit()?;
+    conn.execute_batch("VACUUM")?;
+    Ok(())
+}
+
+fn compress(src: &Path, dest: &mut File, format: ArchiveFormat, entry_name: &str) -> Result<()> {
+    let mut input = BufReader::new(File::open(src)?);
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(BufWriter::new(dest));
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .large_file(true);
+            zip.start_file(entry_name, options)?;
+            io::copy(&mut input, &mut zip)?;
+            zip.finish()?.flush()?;
+        }
+        ArchiveFormat::Gz => {
+            let mut gz = flate2::write::GzEncoder::new(BufWriter::new(dest), flate2::Compression::best());
+            io::copy(&mut input, &mut gz)?;
+            gz.finish()?.flush()?;
+        }
+        ArchiveFormat::Zst => zstd::stream::copy_encode(input, BufWriter::new(dest), 19)?,
+    }
+    Ok(())
+}
+
+/// Snapshot, optionally aggregate, compress and verify one database; returns the archive path.
+fn archive_one(path: &Path, args: &ArchiveArgs, aggregate_secs: Option<i64>) -> Result<PathBuf> {
+    let dir = storage::data_dir(path);
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let dest = path.with_file_name(format!("{}.{}", name, args.format.ext()));
+    if dest.exists() {
+        anyhow::bail!("{} already exists", dest.display());
+    }
+
+    // A consistent copy that includes anything still in the WAL
+    let snapshot = tempfile::Builder::new().prefix(".archive-").suffix(".sqlite").tempfile_in(dir)?.into_temp_path();
+    let src = storage::open_read_only(path)?;
+    src.conn
+        .execute("VACUUM INTO ?1", [snapshot.to_string_lossy()])
+        .with_context(|| format!("snapshot {}", path.display()))?;
+    drop(src);
+
+    let expected = {
+        let mut conn = storage::connect(&snapshot, OpenFlags::default(), dir)?;
+        if let Some(secs) = aggregate_secs {
+            aggregate(&mut conn, secs).with_context(|| format!("aggregate {}", path.display()))?;
+        }
+        row_counts(&conn)?
+    };
+
+    // Same suffix as the final name so it is read back as the right format
+    let suffix = format!(".sqlite.{}", args.format.ext());
+    let mut out = tempfile::Builder::new().prefix(".archive-").suffix(&suffix).tempfile_in(dir)?;
+    compress(&snapshot, out.as_file_mut(), args.format, &name)
+        .with_context(|| format!("compress {}", path.display()))?;
+    out.as_file().sync_all()?;
+    // Temp files are owner-only; keep the share's permissions instead
+    fs::set_permissions(out.path(), fs::metadata(path)?.permissions())?;
+    let out = out.into_temp_path();
+
+    // Read the archive back the way graph/export will before trusting it
+    let db = storage::open_read_only(&out).with_context(|| format!("verify archive of {}", path.display()))?;
+    let integrity: String = db.conn.query_row("PRAGMA integrity_check", [], |r| r.get(0))?;
+    if integrity != "ok" {
+        anyhow::bail!("archive of {} fails integrity check: {}", path.display(), integrity);
+    }
+    if row_counts(&db.conn)? != expected {
+        anyhow::bail!("archive of {} does not hold the same rows as the original", path.display());
+    }
+    drop(db);
+    out.persist_noclobber(&dest).with_context(|| format!("write {}", dest.display()))?;
+    Ok(dest)
+}
+
+/// Entry point for `winbox-stats archive`
+pub fn run_archive(args: &ArchiveArgs) -> Result<()> {
+    let dir = Path::new(".");
+    let months = parse_months(&args.older_than)?;
+    let aggregate_secs = args.aggregate.as_deref().map(timespan::parse).transpose()?;
+    let now = Local::now();
+    let cutoff = now.year() * 12 + now.month0() as i32 - months;
+
+    let mut archived = Vec::new();
+    for p in storage::find_databases(dir, 1) {
+        let (ym, host, _) = split_stem_sqlite(&storage::db_stem(&p));
+        if storage::is_archive(&p) || host.is_empty() || month_index(&ym).is_none_or(|i| i > cutoff) {
+            continue;
+        }
+        if args.dry_run {
+            if !args.json {
+                println!("Would archive {}", p.display());
+            }
+            let before = fs::metadata(&p)?.len();
+            archived.push(Archived { database: p, archive: None, bytes_before: before, bytes_after: 0 });
+            continue;
+        }
+        let before = fs::metadata(&p)?.len();
+        let dest = archive_one(&p, args, aggregate_secs)?;
+        let after = fs::metadata(&dest)?.len();
+
+        let detail = match &args.aggregate {
+            Some(span) => format!("{} ({} averages)", dest.display(), span),
+            None => dest.display().to_string(),
+        };
+        audit::record(dir, "archive", &p.display().to_string(), &detail)?;
+        fs::remove_file(&p).with_context(|| format!("remove {}", p.display()))?;
+        for side in ["-wal", "-shm"] {
+            let mut s = p.clone().into_os_string();
+            s.push(side);
+            let _ = fs::remove_file(s);
+        }
+        if !args.json {
+            println!(
+                "Archived {} -> {} ({:.1} MB -> {:.1} MB)",
+                p.display(),
+                dest.display(),
+                before as f64 / 1e6,
+                after as f64 / 1e6
+            );
+        }
+        archived.push(Archived { database: p, archive: Some(dest), bytes_before: before, bytes_after: after });
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&archived)?);
+    } else if archived.is_empty() && !args.dry_run {
+        log::warn!("no databases older than {} months here", months);
+    }
+    Ok(())
+}
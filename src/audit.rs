@@ -0,0 +1,240 @@
+// src/audit.rs
+//! Append-only audit log of operations that rewrite stored data.
+//!
+//! Entries are JSON Lines in `winbox-stats-audit.jsonl` next to the databases.
+//! Each entry carries the SHA-256 of the previous one, so editing or deleting a
+//! line breaks the chain and `winbox-stats audit` reports where.
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cli::AuditArgs;
+use crate::identity;
+
+pub const AUDIT_FILE: &str = "winbox-stats-audit.jsonl";
+
+/// Hash of the (non-existent) entry before the first one
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Bytes read at a time when looking for the last entry
+const TAIL_CHUNK: u64 = 4096;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub seq: u64,
+    pub ts: String,
+    pub user: String,
+    pub host: String,
+    /// e.g. `migrate`, `prune`, `import`
+    pub action: String,
+    /// File (or files) affected
+    pub target: String,
+    /// Affected time range or other detail, free-form
+    pub detail: String,
+    pub prev: String,
+    pub hash: String,
+}
+
+fn current_user() -> String {
+    std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_else(|_| "unknown".into())
+}
+
+fn entry_hash(e: &Entry) -> String {
+    let mut h = Sha256::new();
+    for field in [
+        &e.seq.to_string(),
+        &e.ts,
+        &e.user,
+        &e.host,
+        &e.action,
+        &e.target,
+        &e.detail,
+        &e.prev,
+    ] {
+        h.update(field.as_bytes());
+        h.update([0u8]);
+    }
+    format!("{:x}", h.finalize())
+}
+
+fn audit_path(dir: &Path) -> PathBuf {
+    dir.join(AUDIT_FILE)
+}
+
+pub fn read_entries(dir: &Path) -> Result<Vec<Entry>> {
+    let path = audit_path(dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .enumerate()
+        .map(|(i, l)| {
+            serde_json::from_str(l).with_context(|| format!("{} line {}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// The log's last line, read back from the end in chunks
+fn last_line(f: &mut File) -> Result<Option<String>> {
+    let mut pos = f.seek(SeekFrom::End(0))?;
+    let mut tail: Vec<u8> = Vec::new();
+    loop {
+        let trimmed = tail.trim_ascii_end();
+        if let Some(i) = trimmed.iter().rposition(|&b| b == b'\n') {
+            return Ok(Some(String::from_utf8_lossy(&trimmed[i + 1..]).into_owned()));
+        }
+        if pos == 0 {
+            return Ok((!trimmed.is_empty()).then(|| String::from_utf8_lossy(trimmed).into_owned()));
+        }
+        let start = pos.saturating_sub(TAIL_CHUNK);
+        let mut chunk = vec![0; (pos - start) as usize];
+        f.seek(SeekFrom::Start(start))?;
+        f.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+        pos = start;
+    }
+}
+
+/// Append one entry to the log in `dir`. The log stays locked from reading
+/// the last entry to writing the new one, so maintenance commands running at
+/// once never chain two entries to the same predecessor.
+pub fn record(dir: &Path, action: &str, target: &str, detail: &str) -> Result<()> {
+    let path = audit_path(dir);
+    let mut f = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(&path)
+        .with_context(|| format!("open {}", path.display()))?;
+    f.lock().with_context(|| format!("lock {}", path.display()))?;
+    let last: Option<Entry> = match last_line(&mut f).with_context(|| format!("read {}", path.display()))? {
+        Some(line) => Some(serde_json::from_str(&line).with_context(|| format!("{} last line", path.display()))?),
+        None => None,
+    };
+    let mut e = Entry {
+        seq: last.as_ref().map(|l| l.seq + 1).unwrap_or(1),
+        ts: Local::now().to_rfc3339(),
+        user: current_user(),
+        host: identity::hostname_upper(),
+        action: action.to_string(),
+        target: target.to_string(),
+        detail: detail.to_string(),
+        prev: last.map(|l| l.hash).unwrap_or_else(|| GENESIS.to_string()),
+        hash: String::new(),
+    };
+    e.hash = entry_hash(&e);
+    writeln!(f, "{}", serde_json::to_string(&e)?)?;
+    // Unlocked when `f` is closed
+    Ok(())
+}
+
+/// First broken link in the chain, as (seq, reason)
+pub fn verify(entries: &[Entry]) -> Option<(u64, &'static str)> {
+    let mut prev = GENESIS.to_string();
+    for (i, e) in entries.iter().enumerate() {
+        if e.seq != i as u64 + 1 {
+            return Some((e.seq, "sequence gap (entry removed or reordered)"));
+        }
+        if e.prev != prev {
+            return Some((e.seq, "previous-hash mismatch"));
+        }
+        if entry_hash(e) != e.hash {
+            return Some((e.seq, "entry modified"));
+        }
+        prev = e.hash.clone();
+    }
+    None
+}
+
+/// Entry point for `winbox-stats audit`
+pub fn run_audit(args: &AuditArgs) -> Result<()> {
+    let entries = read_entries(Path::new("."))?;
+    let broken = verify(&entries);
+    if args.json {
+        let at = broken.map(|(seq, why)| serde_json::json!({ "seq": seq, "reason": why }));
+        let out = serde_json::json!({ "entries": entries, "intact": broken.is_none(), "broken": at });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        for e in &entries {
+            println!(
+                "{:>5}  {}  {:<20} {:<10} {}  {}",
+                e.seq, e.ts, e.user, e.action, e.target, e.detail
+            );
+        }
+    }
+    match broken {
+        None => {
+            if !args.json {
+                println!("{} entries, chain intact", entries.len());
+            }
+            Ok(())
+        }
+        Some((seq, why)) => anyhow::bail!("audit log tampered at entry {}: {}", seq, why),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_chain_and_tail_edits_are_caught() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..3 {
+            record(dir.path(), "prune", "202608@WEB01.sqlite", &format!("run {}", i)).unwrap();
+        }
+        let entries = read_entries(dir.path()).unwrap();
+        assert_eq!(entries.iter().map(|e| e.seq).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(entries[0].prev, GENESIS);
+        assert_eq!(entries[2].prev, entries[1].hash);
+        assert!(verify(&entries).is_none());
+
+        // The last entry edited
+        let mut edited = entries.clone();
+        edited[2].detail = "nothing happened".into();
+        assert_eq!(verify(&edited), Some((3, "entry modified")));
+        // Rehashed to hide the edit, which the next entry still catches
+        let mut rehashed = entries.clone();
+        rehashed[1].detail = "nothing happened".into();
+        rehashed[1].hash = entry_hash(&rehashed[1]);
+        assert_eq!(verify(&rehashed), Some((3, "previous-hash mismatch")));
+        // An entry removed
+        assert_eq!(verify(&[entries[0].clone(), entries[2].clone()]), Some((3, "sequence gap (entry removed or reordered)")));
+    }
+
+    #[test]
+    fn the_last_line_is_read_from_the_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        let long = "x".repeat(TAIL_CHUNK as usize * 2 + 7);
+        fs::write(&path, format!("first\n{}\n\n", long)).unwrap();
+        assert_eq!(last_line(&mut File::open(&path).unwrap()).unwrap().as_deref(), Some(long.as_str()));
+        fs::write(&path, "only").unwrap();
+        assert_eq!(last_line(&mut File::open(&path).unwrap()).unwrap().as_deref(), Some("only"));
+        fs::write(&path, "").unwrap();
+        assert_eq!(last_line(&mut File::open(&path).unwrap()).unwrap(), None);
+    }
+
+    #[test]
+    fn records_continue_the_chain_after_appends_by_others() {
+        let dir = tempfile::tempdir().unwrap();
+        std::thread::scope(|s| {
+            for i in 0..4 {
+                let dir = dir.path();
+                s.spawn(move || record(dir, "dedupe", "x.sqlite", &format!("worker {}", i)).unwrap());
+            }
+        });
+        let entries = read_entries(dir.path()).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(verify(&entries).is_none());
+    }
+}
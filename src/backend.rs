@@ -0,0 +1,249 @@
+// src/backend.rs
+//! Where the collector stores its samples: `[storage] backend`.
+//!
+//! SQLite (`YYYYMM@HOST.sqlite`, see `storage`) is the default and the only
+//! format the rest of the tool reads: graph, report, export, retention and
+//! the maintenance commands all skip other files. Builds with
+//! `--features duckdb` can write `YYYYMM@HOST.duckdb` instead, for analytics
+//! that query DuckDB directly. Those files are write-only as far as this
+//! tool is concerned and hold the samples alone, in one `Samples` table of
+//! `Metric`, `Timestamp` (epoch milliseconds), `Value` and `Seq`; the time
+//! zone, clock, node and `SELFMON` records of a SQLite file are not kept.
+//!
+//! The backend is picked from the file's extension, so databases of either
+//! kind can sit side by side after the setting changes.
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::collect::{self, Run};
+use crate::storage::{self, Db};
+
+/// Database format, from `[storage] backend`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    #[default]
+    Sqlite,
+    Duckdb,
+}
+
+impl Engine {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Engine::Sqlite => "sqlite",
+            Engine::Duckdb => "duckdb",
+        }
+    }
+
+    fn of(path: &Path) -> Engine {
+        match path.extension() {
+            Some(e) if e.eq_ignore_ascii_case(Engine::Duckdb.extension()) => Engine::Duckdb,
+            _ => Engine::Sqlite,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: Engine,
+}
+
+/// One monthly database, as the collector writes it
+pub trait Backend {
+    /// Write a run in one transaction; returns the SMART alerts it triggered
+    fn write_run(&mut self, run: &Run) -> Result<Vec<String>>;
+    /// Whether `metric` has a sample at `ts_ms`; false when that cannot be
+    /// told, so the sample is written rather than lost
+    fn contains(&self, metric: &str, ts_ms: i64) -> bool;
+    /// Epoch seconds of the last run written
+    fn last_ts(&self) -> Result<Option<i64>>;
+}
+
+pub struct Sqlite(Db);
+
+impl Backend for Sqlite {
+    fn write_run(&mut self, run: &Run) -> Result<Vec<String>> {
+        collect::write_run(&mut self.0.conn, run)
+    }
+
+    fn contains(&self, metric: &str, ts_ms: i64) -> bool {
+        storage::has_sample_ms(&self.0.conn, metric, ts_ms).unwrap_or(false)
+    }
+
+    fn last_ts(&self) -> Result<Option<i64>> {
+        Ok(storage::get_meta(&self.0.conn, "last_ts")?.and_then(|v| v.parse().ok()))
+    }
+}
+
+/// Open the database at `path` for the collector
+pub fn open_for_collection(path: &Path) -> Result<Box<dyn Backend>> {
+    match Engine::of(path) {
+        Engine::Sqlite => Ok(Box::new(Sqlite(storage::open_for_collection(path)?.into()))),
+        Engine::Duckdb => duck::open(path, false),
+    }
+}
+
+/// As [`open_for_collection`], for a database on a network share
+pub fn open_on_share(path: &Path) -> Result<Box<dyn Backend>> {
+    match Engine::of(path) {
+        Engine::Sqlite => Ok(Box::new(Sqlite(storage::open_on_share(path)?.into()))),
+        Engine::Duckdb => duck::open(path, false),
+    }
+}
+
+/// Open the database at `path` to look at what was written, as
+/// `storage::open_read_only` does
+pub fn open_read_only(path: &Path) -> Result<Box<dyn Backend>> {
+    match Engine::of(path) {
+        Engine::Sqlite => Ok(Box::new(Sqlite(storage::open_read_only(path)?))),
+        Engine::Duckdb => duck::open(path, true),
+    }
+}
+
+#[cfg(feature = "duckdb")]
+mod duck {
+    use anyhow::{Context, Result};
+    use duckdb::{params, AccessMode, Config, Connection, OptionalExt};
+    use std::path::Path;
+
+    use super::Backend;
+    use crate::collect::Run;
+    use crate::smart;
+
+    /// `INSERT OR REPLACE` keeps one sample per metric and time, as SQLite's upsert does
+    const SCHEMA: &str = r#"CREATE TABLE IF NOT EXISTS "Samples"(
+        "Metric" VARCHAR NOT NULL,
+        "Timestamp" BIGINT NOT NULL,
+        "Value" DOUBLE NOT NULL,
+        "Seq" BIGINT,
+        PRIMARY KEY ("Metric", "Timestamp")
+    )"#;
+
+    pub struct DuckDb(Connection);
+
+    pub fn open(path: &Path, read_only: bool) -> Result<Box<dyn Backend>> {
+        let mode = if read_only { AccessMode::ReadOnly } else { AccessMode::ReadWrite };
+        let conn = Connection::open_with_flags(path, Config::default().access_mode(mode)?)
+            .with_context(|| format!("open {}", path.display()))?;
+        if !read_only {
+            conn.execute_batch(SCHEMA)?;
+        }
+        Ok(Box::new(DuckDb(conn)))
+    }
+
+    fn last_value(conn: &Connection, metric: &str) -> Result<Option<f64>> {
+        Ok(conn
+            .query_row(
+                r#"SELECT "Value" FROM "Samples" WHERE "Metric" = ? ORDER BY "Timestamp" DESC LIMIT 1"#,
+                params![metric],
+                |r| r.get(0),
+            )
+            .optional()?)
+    }
+
+    impl Backend for DuckDb {
+        fn write_run(&mut self, run: &Run) -> Result<Vec<String>> {
+            let tx = self.0.transaction()?;
+            let seq: Option<i64> = if run.sequence {
+                Some(tx.query_row(r#"SELECT coalesce(max("Seq"), 0) + 1 FROM "Samples""#, [], |r| r.get(0))?)
+            } else {
+                None
+            };
+            let ts_ms = run.ts * 1000 + run.millis as i64;
+            let mut alerts = Vec::new();
+            for (metric, value) in &run.samples {
+                if smart::is_smart_metric(metric) {
+                    alerts.extend(smart::alert(metric, *value, last_value(&tx, metric)?));
+                }
+                tx.execute(r#"INSERT OR REPLACE INTO "Samples" VALUES (?, ?, ?, ?)"#, params![metric, ts_ms, value, seq])?;
+            }
+            tx.commit()?;
+            Ok(alerts)
+        }
+
+        fn contains(&self, metric: &str, ts_ms: i64) -> bool {
+            self.0
+                .query_row(
+                    r#"SELECT 1 FROM "Samples" WHERE "Metric" = ? AND "Timestamp" = ?"#,
+                    params![metric, ts_ms],
+                    |_| Ok(()),
+                )
+                .is_ok()
+        }
+
+        fn last_ts(&self) -> Result<Option<i64>> {
+            let last: Option<i64> = self.0.query_row(r#"SELECT max("Timestamp") FROM "Samples""#, [], |r| r.get(0))?;
+            Ok(last.map(|ms| ms.div_euclid(1000)))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn run(ts: i64, samples: &[(&str, f64)]) -> Run {
+            Run {
+                db_name: String::new(),
+                ts,
+                millis: 250,
+                node: "WEB01".into(),
+                cluster: None,
+                role: None,
+                host_label: None,
+                virtualization: None,
+                sample_ms: None,
+                sequence: true,
+                partial: false,
+                samples: samples.iter().map(|(m, v)| (m.to_string(), *v)).collect(),
+            }
+        }
+
+        #[test]
+        fn samples_read_back_as_written() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("202608@WEB01.duckdb");
+            {
+                let mut db = open(&path, false).unwrap();
+                db.write_run(&run(1_723_600_000, &[("CPU", 12.5), ("RAM", 40.0)])).unwrap();
+                db.write_run(&run(1_723_600_060, &[("CPU", 13.0)])).unwrap();
+                // Written again, as the spool may after a lost commit: replaced, not doubled
+                db.write_run(&run(1_723_600_060, &[("CPU", 14.0)])).unwrap();
+            }
+            let db = open(&path, true).unwrap();
+            assert_eq!(db.last_ts().unwrap(), Some(1_723_600_060));
+            assert!(db.contains("CPU", 1_723_600_000_250));
+            assert!(!db.contains("CPU", 1_723_600_000_000));
+            assert!(!db.contains("Disk", 1_723_600_000_250));
+
+            let conn = Connection::open(&path).unwrap();
+            let mut stmt = conn.prepare(r#"SELECT "Metric", "Timestamp", "Value", "Seq" FROM "Samples" ORDER BY 2, 1"#).unwrap();
+            let rows: Vec<(String, i64, f64, i64)> = stmt
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+                .unwrap()
+                .collect::<duckdb::Result<_>>()
+                .unwrap();
+            assert_eq!(
+                rows,
+                vec![
+                    ("CPU".into(), 1_723_600_000_250, 12.5, 1),
+                    ("RAM".into(), 1_723_600_000_250, 40.0, 1),
+                    ("CPU".into(), 1_723_600_060_250, 14.0, 3),
+                ]
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "duckdb"))]
+mod duck {
+    use anyhow::Result;
+    use std::path::Path;
+
+    use super::Backend;
+
+    pub fn open(path: &Path, _read_only: bool) -> Result<Box<dyn Backend>> {
+        anyhow::bail!("{} is a DuckDB database but this build has no DuckDB support; rebuild with --features duckdb", path.display())
+    }
+}
@@ -0,0 +1,176 @@
+// src/backfill.rs
+//! `backfill`: move history between the two layouts, the per-host monthly
+//! `YYYYMM@HOST.sqlite` files the collector writes and one central database
+//! holding every host, as a receiver of `collect --push` keeps it. `--from
+//! central.db` writes whatever the monthly files here are missing, creating
+//! the months that do not exist; `--to central.db` copies the monthly files
+//! here into the central database. Samples already present are left alone
+//! either way, so a backfill can be repeated after an interrupted run.
+//!
+//! The central database has a single table, `Samples("Host", "Metric",
+//! "Timestamp", "Value")`, with the time in epoch milliseconds.
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::aliases::Aliases;
+use crate::audit;
+use crate::cli::BackfillArgs;
+use crate::graph::plot::{split_stem_sqlite, ADHOC_PREFIX};
+use crate::storage::{self, ensure_table, insert_sample_ms, list_metric_tables, read_samples_ms};
+
+pub const CENTRAL_TABLE: &str = "Samples";
+
+/// Samples written into one database
+#[derive(Serialize)]
+struct Backfilled {
+    database: PathBuf,
+    host: String,
+    rows: usize,
+}
+
+fn open_central(path: &Path, create: bool) -> Result<Connection> {
+    if !create && !path.exists() {
+        bail!("{} not found", path.display());
+    }
+    let conn = Connection::open(path).with_context(|| format!("open {}", path.display()))?;
+    if create {
+        conn.execute_batch(&format!(
+            r#"CREATE TABLE IF NOT EXISTS "{CENTRAL_TABLE}"(
+                "Host"      TEXT NOT NULL,
+                "Metric"    TEXT NOT NULL,
+                "Timestamp" INTEGER NOT NULL,
+                "Value"     REAL NOT NULL,
+                PRIMARY KEY("Host", "Metric", "Timestamp")
+            ) WITHOUT ROWID"#
+        ))?;
+    }
+    let found: Option<i64> = conn
+        .query_row("SELECT 1 FROM sqlite_master WHERE type='table' AND name=?1", params![CENTRAL_TABLE], |r| r.get(0))
+        .optional()?;
+    if found.is_none() {
+        bail!("{} has no {} table; is it a central database?", path.display(), CENTRAL_TABLE);
+    }
+    Ok(conn)
+}
+
+/// Monthly files here -> central database
+fn to_central(dir: &Path, central: &Path, host: Option<&str>) -> Result<Vec<Backfilled>> {
+    let mut conn = open_central(central, true)?;
+    let aliases = Aliases::load(dir)?;
+    let central_file = fs::canonicalize(central).ok();
+    let mut out = Vec::new();
+    for p in storage::find_databases(dir, 1) {
+        let stem = storage::db_stem(&p);
+        let (_, db_host, metric_opt) = split_stem_sqlite(&stem);
+        // Bursts are troubleshooting captures, not part of the series
+        if db_host.is_empty() || stem.starts_with(&format!("{}@", ADHOC_PREFIX)) || fs::canonicalize(&p).ok() == central_file {
+            continue;
+        }
+        if host.is_some_and(|h| !h.eq_ignore_ascii_case(&db_host)) {
+            continue;
+        }
+        let db = storage::open_read_only(&p)?;
+        let tx = conn.transaction()?;
+        let mut rows = 0;
+        {
+            let mut insert = tx.prepare(&format!(
+                r#"INSERT OR IGNORE INTO "{CENTRAL_TABLE}"("Host","Metric","Timestamp","Value") VALUES (?1, ?2, ?3, ?4)"#
+            ))?;
+            for t in list_metric_tables(&db.conn)? {
+                // Per-metric DBs carry the metric in the filename, monthly DBs in the table name
+                let metric = aliases.resolve(metric_opt.as_deref().unwrap_or(&t));
+                for (ts, value) in read_samples_ms(&db.conn, &t).with_context(|| format!("read {} table {}", p.display(), t))? {
+                    rows += insert.execute(params![db_host, metric, ts, value])?;
+                }
+            }
+        }
+        tx.commit()?;
+        if rows > 0 {
+            let detail = format!("{} samples of {} from {}", rows, db_host, p.display());
+            audit::record(dir, "backfill", &central.display().to_string(), &detail)?;
+            out.push(Backfilled { database: p, host: db_host, rows });
+        }
+    }
+    Ok(out)
+}
+
+/// Central database -> monthly files here
+fn from_central(dir: &Path, central: &Path, host: Option<&str>) -> Result<Vec<Backfilled>> {
+    let conn = open_central(central, false)?;
+    let aliases = Aliases::load(dir)?;
+    let hosts: Vec<String> = conn
+        .prepare(&format!(r#"SELECT DISTINCT "Host" FROM "{CENTRAL_TABLE}" ORDER BY "Host""#))?
+        .query_map([], |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut out = Vec::new();
+    for db_host in hosts.into_iter().filter(|h| host.is_none_or(|want| want.eq_ignore_ascii_case(h))) {
+        // One host at a time, so a large central database is never read whole
+        let mut months: BTreeMap<String, BTreeMap<String, Vec<(i64, f64)>>> = BTreeMap::new();
+        let mut stmt = conn.prepare(&format!(
+            r#"SELECT "Metric","Timestamp","Value" FROM "{CENTRAL_TABLE}" WHERE "Host" = ?1 ORDER BY "Timestamp""#
+        ))?;
+        let rows = stmt.query_map(params![db_host], |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?, r.get::<_, f64>(2)?)))?;
+        for row in rows {
+            let (metric, ts, value) = row?;
+            let Some(t) = DateTime::from_timestamp_millis(ts) else { continue };
+            let ym = t.with_timezone(&Local).format("%Y%m").to_string();
+            months.entry(ym).or_default().entry(aliases.resolve(&metric)).or_default().push((ts, value));
+        }
+        for (ym, metrics) in months {
+            let path = dir.join(format!("{}@{}.sqlite", ym, db_host));
+            let mut db = storage::open(&path)?;
+            let tx = db.transaction()?;
+            if storage::get_meta(&tx, "timezone")?.is_none() {
+                storage::record_timezone(&tx)?;
+            }
+            let mut written = 0;
+            for (metric, samples) in metrics {
+                ensure_table(&tx, &metric)?;
+                let text = storage::has_text_timestamps(&tx, &metric)?;
+                let present: HashSet<i64> = read_samples_ms(&tx, &metric)?.into_iter().map(|(ts, _)| ts).collect();
+                for (ts, value) in samples.into_iter().filter(|(ts, _)| !present.contains(ts)) {
+                    insert_sample_ms(&tx, &metric, ts, value, None, text)?;
+                    written += 1;
+                }
+            }
+            tx.commit()?;
+            if written > 0 {
+                let detail = format!("{} samples of {} from {}", written, db_host, central.display());
+                audit::record(dir, "backfill", &path.display().to_string(), &detail)?;
+                out.push(Backfilled { database: path, host: db_host.clone(), rows: written });
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Entry point for `winbox-stats backfill`
+pub fn run_backfill(args: &BackfillArgs) -> Result<()> {
+    let dir = Path::new(".");
+    let (backfilled, central) = match (&args.from, &args.to) {
+        (Some(from), _) => (from_central(dir, from, args.host.as_deref())?, from),
+        (None, Some(to)) => (to_central(dir, to, args.host.as_deref())?, to),
+        (None, None) => unreachable!("clap requires --from or --to"),
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&backfilled)?);
+        return Ok(());
+    }
+    if backfilled.is_empty() {
+        println!("Nothing to backfill; every sample is already in place");
+    }
+    for b in &backfilled {
+        if args.from.is_some() {
+            println!("Wrote {} samples of {} into {}", b.rows, b.host, b.database.display());
+        } else {
+            println!("Copied {} samples of {} from {} into {}", b.rows, b.host, b.database.display(), central.display());
+        }
+    }
+    Ok(())
+}
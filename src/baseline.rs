@@ -0,0 +1,170 @@
+// src/baseline.rs
+//! `baseline save` keeps the typical daily load profile of every series, the
+//! average of each local hour of day over one month, in
+//! `winbox-stats-baseline.json`. `baseline diff` compares another month with
+//! it and lists the series whose profile drifted.
+//!
+//! Drift is the mean absolute change of the 24 hourly averages, as a
+//! percentage of the baseline's overall average, so load moving from day to
+//! night counts even when the monthly average stays the same.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{BaselineDiffArgs, BaselineSaveArgs};
+use crate::report_html::{latest_month, parse_month};
+use crate::series::{self, Series};
+
+pub const BASELINE_FILE: &str = "winbox-stats-baseline.json";
+
+#[derive(Serialize, Deserialize)]
+struct Baseline {
+    /// `YYYY-MM` the profiles were taken from
+    month: String,
+    saved: String,
+    profiles: Vec<Profile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Profile {
+    host: String,
+    metric: String,
+    avg: f64,
+    /// Average of each local hour of day, 0-23; `None` for hours never sampled
+    hours: Vec<Option<f64>>,
+}
+
+#[derive(Serialize)]
+struct Drift {
+    host: String,
+    metric: String,
+    baseline: f64,
+    now: f64,
+    /// Change of the monthly average, %
+    change: f64,
+    /// Mean absolute change of the hourly averages, % of the baseline average
+    drift: f64,
+    /// Hour of day that moved most, and by how much in %
+    worst_hour: u32,
+    worst_change: f64,
+}
+
+fn profile(host: String, metric: String, samples: &[(i64, f64)]) -> Option<Profile> {
+    let mut sums = [(0.0, 0usize); 24];
+    for &(ts, v) in samples {
+        let hour = DateTime::from_timestamp(ts, 0)?.with_timezone(&Local).hour() as usize;
+        sums[hour].0 += v;
+        sums[hour].1 += 1;
+    }
+    let n: usize = sums.iter().map(|s| s.1).sum();
+    if n == 0 {
+        return None;
+    }
+    let avg = sums.iter().map(|s| s.0).sum::<f64>() / n as f64;
+    let hours = sums.iter().map(|&(sum, n)| (n > 0).then(|| sum / n as f64)).collect();
+    Some(Profile { host, metric, avg, hours })
+}
+
+fn profiles(series: Series) -> Vec<Profile> {
+    series.into_iter().filter_map(|((host, metric), samples)| profile(host, metric, &samples)).collect()
+}
+
+/// `--month`, or the newest month with a database here
+fn month(arg: Option<&str>) -> Result<String> {
+    let start = match arg {
+        Some(m) => parse_month(m).with_context(|| format!("invalid --month {:?}; use e.g. 2025-11", m))?,
+        None => latest_month(Path::new(".")).context("no databases here")?,
+    };
+    Ok(start.format("%Y-%m").to_string())
+}
+
+fn path(file: Option<&Path>) -> PathBuf {
+    file.map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from(BASELINE_FILE))
+}
+
+fn drift(base: &Profile, now: &Profile) -> Option<Drift> {
+    // Relative changes of a series that is normally zero mean nothing
+    if base.avg.abs() < f64::EPSILON {
+        return None;
+    }
+    let scale = base.avg.abs() / 100.0;
+    let changes: Vec<(u32, f64)> = base
+        .hours
+        .iter()
+        .zip(&now.hours)
+        .enumerate()
+        .filter_map(|(h, (b, n))| Some((h as u32, (n.as_ref()? - b.as_ref()?) / scale)))
+        .collect();
+    let (worst_hour, worst_change) = changes.iter().copied().max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+    Some(Drift {
+        host: now.host.clone(),
+        metric: now.metric.clone(),
+        baseline: base.avg,
+        now: now.avg,
+        change: (now.avg - base.avg) / scale,
+        drift: changes.iter().map(|c| c.1.abs()).sum::<f64>() / changes.len() as f64,
+        worst_hour,
+        worst_change,
+    })
+}
+
+/// Entry point for `winbox-stats baseline save`
+pub fn run_save(args: &BaselineSaveArgs) -> Result<()> {
+    let month = month(args.month.as_deref())?;
+    let filter = series::Filter { metric: args.metric.as_deref(), host: args.host.as_deref(), month: Some(&month), ..Default::default() };
+    let profiles = profiles(series::load(Path::new("."), &filter)?);
+    if profiles.is_empty() {
+        anyhow::bail!("no samples for {} to take a baseline from", month);
+    }
+    let baseline = Baseline { month, saved: Local::now().to_rfc3339(), profiles };
+    let out = path(args.file.as_deref());
+    fs::write(&out, serde_json::to_string_pretty(&baseline)?).with_context(|| format!("write {}", out.display()))?;
+    if args.json {
+        let saved = serde_json::json!({ "file": out, "month": baseline.month, "series": baseline.profiles.len() });
+        println!("{}", serde_json::to_string_pretty(&saved)?);
+    } else {
+        println!("Saved the {} profile of {} series to {}", baseline.month, baseline.profiles.len(), out.display());
+    }
+    Ok(())
+}
+
+/// Entry point for `winbox-stats baseline diff`
+pub fn run_diff(args: &BaselineDiffArgs) -> Result<()> {
+    let file = path(args.file.as_deref());
+    let text = fs::read_to_string(&file).with_context(|| format!("read {}; run baseline save first", file.display()))?;
+    let baseline: Baseline = serde_json::from_str(&text).with_context(|| format!("parse {}", file.display()))?;
+    let month = month(args.month.as_deref())?;
+    let filter = series::Filter { metric: args.metric.as_deref(), host: args.host.as_deref(), month: Some(&month), ..Default::default() };
+
+    let mut drifted: Vec<Drift> = profiles(series::load(Path::new("."), &filter)?)
+        .iter()
+        .filter_map(|now| {
+            let base = baseline.profiles.iter().find(|b| b.host == now.host && b.metric == now.metric)?;
+            drift(base, now)
+        })
+        .filter(|d| d.drift >= args.threshold)
+        .collect();
+    drifted.sort_by(|a, b| b.drift.total_cmp(&a.drift));
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&drifted)?);
+        return Ok(());
+    }
+    if drifted.is_empty() {
+        println!("No series drifted {}% or more from the {} baseline in {}", args.threshold, baseline.month, month);
+        return Ok(());
+    }
+    let hw = drifted.iter().map(|d| d.host.len()).max().unwrap_or(0).max(4);
+    let mw = drifted.iter().map(|d| d.metric.len()).max().unwrap_or(0).max(6);
+    println!("{} compared with the {} baseline", month, baseline.month);
+    println!("{:<hw$}  {:<mw$}  {:>9}  {:>9}  {:>8}  {:>7}  WORST HOUR", "HOST", "METRIC", "BASELINE", "NOW", "CHANGE", "DRIFT");
+    for d in &drifted {
+        println!(
+            "{:<hw$}  {:<mw$}  {:>9.2}  {:>9.2}  {:>+7.1}%  {:>6.1}%  {:02}:00 {:+.1}%",
+            d.host, d.metric, d.baseline, d.now, d.change, d.drift, d.worst_hour, d.worst_change
+        );
+    }
+    Ok(())
+}
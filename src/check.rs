@@ -0,0 +1,206 @@
+// src/check.rs
+//! `check`: the newest sample of every series with a configured threshold,
+//! rated OK, WARNING or CRITICAL, for a quick look or a scheduled alert mail.
+//! `--format nagios` prints the same as a Nagios plugin status line with
+//! performance data, for NRPE or NSClient++.
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::cli::CheckArgs;
+use crate::config::Config;
+use crate::metric_info::{self, MetricInfo};
+use crate::notify;
+use crate::series;
+use crate::storage;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Status {
+    #[default]
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+        }
+    }
+
+    /// Process exit code, as Nagios and Zabbix external checks read it
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Critical => 2,
+        }
+    }
+}
+
+/// Rating of `value` against the thresholds of `metric`; `None` without any
+pub fn rate(config: &Config, metric: &str, value: f64) -> Option<Status> {
+    let th = config.threshold_for(metric)?;
+    Some(if th.critical.is_some_and(|c| value >= c) {
+        Status::Critical
+    } else if th.warning.is_some_and(|w| value >= w) {
+        Status::Warning
+    } else {
+        Status::Ok
+    })
+}
+
+/// Worst rating of freshly taken samples, for the exit code of `collect`
+pub fn worst(config: &Config, samples: &[(String, f64)]) -> Status {
+    samples.iter().filter_map(|(metric, value)| rate(config, metric, *value)).max().unwrap_or_default()
+}
+
+/// Service name at the start of the `--format nagios` line
+const PLUGIN_LABEL: &str = "WINBOX-STATS";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CheckFormat {
+    /// Aligned table with a count per status
+    #[default]
+    Table,
+    /// `WINBOX-STATS WARNING - … | perfdata`, one line as Nagios plugins print
+    Nagios,
+}
+
+#[derive(Serialize)]
+struct Checked {
+    status: Status,
+    host: String,
+    metric: String,
+    value: f64,
+    /// Time of the sample rated
+    ts: String,
+    warning: Option<f64>,
+    critical: Option<f64>,
+    #[serde(skip)]
+    info: MetricInfo,
+}
+
+fn level(v: Option<f64>) -> String {
+    v.map(|v| format!("{}", v)).unwrap_or_else(|| "-".into())
+}
+
+fn format_table(checked: &[Checked]) -> Result<String> {
+    let hw = checked.iter().map(|c| c.host.len()).max().unwrap_or(0).max(4);
+    let mw = checked.iter().map(|c| c.metric.len()).max().unwrap_or(0).max(6);
+    let mut out = String::new();
+    writeln!(out, "{:<8}  {:<hw$}  {:<mw$}  {:>9}  {:>8}  {:>8}  SAMPLE", "STATUS", "HOST", "METRIC", "VALUE", "WARNING", "CRITICAL")?;
+    for c in checked {
+        writeln!(
+            out,
+            "{:<8}  {:<hw$}  {:<mw$}  {:>9.2}  {:>8}  {:>8}  {}",
+            c.status.label(),
+            c.host,
+            c.metric,
+            c.value,
+            level(c.warning),
+            level(c.critical),
+            c.ts
+        )?;
+    }
+    Ok(out)
+}
+
+/// Shortest form of a value for plugin output: `12`, `74.67`
+fn number(v: f64) -> String {
+    let s = format!("{:.2}", v);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// `WINBOX-STATS WARNING - C_Drive=74.67% CPU=6.94% | C_Drive=74.67%;70;90;0;100 CPU=6.94%;60;95;0;100`;
+/// labels get the host in front when several hosts are checked
+fn format_nagios(checked: &[Checked]) -> String {
+    let worst = checked.first().map_or(Status::Ok, |c| c.status);
+    let one_host = checked.windows(2).all(|w| w[0].host == w[1].host);
+    let mut text = Vec::new();
+    let mut perf = Vec::new();
+    for c in checked {
+        let label = if one_host { c.metric.clone() } else { format!("{}/{}", c.host, c.metric) };
+        text.push(format!("{}={}{}", label, number(c.value), c.info.unit));
+        // Nagios only understands a few units of measure; leave the others off
+        let uom = match c.info.unit.as_str() {
+            u @ ("%" | "s" | "ms" | "us" | "B" | "KB" | "MB" | "GB" | "TB" | "c") => u,
+            _ => "",
+        };
+        let opt = |v: Option<f64>| v.map(number).unwrap_or_default();
+        perf.push(format!(
+            "'{}'={}{};{};{};{};{}",
+            label,
+            number(c.value),
+            uom,
+            opt(c.warning),
+            opt(c.critical),
+            opt(c.info.y_min),
+            opt(c.info.y_max)
+        ));
+    }
+    format!("{} {} - {} | {}\n", PLUGIN_LABEL, worst.label(), text.join(", "), perf.join(" "))
+}
+
+/// "2 critical, 1 warning, 14 ok"
+fn summary(checked: &[Checked]) -> String {
+    let count = |s: Status| checked.iter().filter(|c| c.status == s).count();
+    format!("{} critical, {} warning, {} ok", count(Status::Critical), count(Status::Warning), count(Status::Ok))
+}
+
+/// Entry point for `winbox-stats check`; returns the worst status for the exit code
+pub fn run_check(args: &CheckArgs, config: &Config) -> Result<Status> {
+    let smtp = if args.email { Some(notify::smtp(config)?) } else { None };
+    let filter = series::Filter { metric: args.metric.as_deref(), host: args.host.as_deref(), ..Default::default() };
+    let mut checked = Vec::new();
+    let (series, infos) = series::load_described(Path::new("."), &filter)?;
+    for (key, samples) in series {
+        let (Some(th), Some(&(ts, value))) = (config.threshold_for(&key.1), samples.last()) else { continue };
+        let Some(status) = rate(config, &key.1, value) else { continue };
+        let info = infos.get(&key).cloned().unwrap_or_else(|| metric_info::describe(&key.1));
+        let (host, metric) = key;
+        checked.push(Checked { status, host, metric, value, ts: storage::format_ts(ts), warning: th.warning, critical: th.critical, info });
+    }
+    // Worst first, then by host and metric as loaded
+    checked.sort_by_key(|c| std::cmp::Reverse(c.status));
+    let nagios = args.format == CheckFormat::Nagios && !args.json;
+    if checked.is_empty() && nagios {
+        // Nagios shows the first line of stdout; the error alone would read as UNKNOWN without a reason
+        println!("{} UNKNOWN - no series here has a threshold in the config", PLUGIN_LABEL);
+        bail!("no series here has a threshold in the config");
+    }
+    if checked.is_empty() && !args.json {
+        log::warn!("no series here has a threshold in the config");
+        return Ok(Status::Ok);
+    }
+    let worst = checked.first().map_or(Status::Ok, |c| c.status);
+
+    let output = if args.json {
+        serde_json::to_string_pretty(&checked)? + "\n"
+    } else if nagios {
+        format_nagios(&checked)
+    } else {
+        format_table(&checked)?
+    };
+    print!("{}", output);
+    if !args.json && !nagios {
+        println!("{}", summary(&checked));
+    }
+    // Only mail when something needs attention, so a scheduled check stays quiet
+    if let Some(smtp) = smtp.filter(|_| checked.iter().any(|c| c.status != Status::Ok)) {
+        let message = notify::Message {
+            subject: format!("winbox-stats check: {}", summary(&checked)),
+            body: output,
+            content_type: "text/plain",
+            attachment: None,
+        };
+        notify::send(smtp, &message)?;
+    }
+    Ok(worst)
+}
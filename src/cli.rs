@@ -1,14 +1,803 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use crate::archive::ArchiveFormat;
+use crate::check::CheckFormat;
+use crate::graph::plot::Period;
+use crate::graph::style::{Envelope, Theme, ThresholdStyle};
+use crate::push::PushFormat;
+use crate::statsd::StatsdFormat;
+use crate::stats::{Per, Stat};
+use crate::timespan::Zone;
 
 #[derive(Debug, Parser)]
 #[command(name = "winbox-stats", version)]
 pub struct Cli {
+    /// Settings file (default: winbox-stats.toml in the current directory, if present)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// More diagnostics on stderr (-v info, -vv debug)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Only errors on stderr
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Append diagnostics to this file instead of stderr
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Print results (files written, rows inserted, alerts) as JSON on stdout, for scripts
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub output: Output,
+    /// Read databases without taking any lock (SQLite `immutable=1`), e.g. on a
+    /// read-only share; a sample written during the read may be missed
+    #[arg(long, global = true)]
+    pub immutable: bool,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
+    /// Capture one sample (the default when no subcommand is given)
+    Collect(CollectArgs),
     /// Render PNG graphs directly from all *.sqlite files in the current directory
-    Graph,
+    Graph(GraphArgs),
+    /// Write a systemd service and timer that run `collect` periodically (Linux)
+    InstallSystemd(InstallSystemdArgs),
+    /// Rename a metric table in every database here and remember the alias
+    RenameMetric(RenameMetricArgs),
+    /// Rank hosts by a statistic of one metric across all databases here
+    TopHosts(TopHostsArgs),
+    /// Min, average, max and p50/p95/p99 of every metric per host, per month or day
+    Report(ReportArgs),
+    /// Print one metric's samples from all databases here, or its statistics per day or month
+    Query(QueryArgs),
+    /// Rate the newest sample of every series against the config thresholds
+    Check(CheckArgs),
+    /// Live CPU, RAM, disk and network charts in the terminal
+    Watch(WatchArgs),
+    /// List the most unusual hours of a month per host and metric
+    Analyze(AnalyzeArgs),
+    /// Project when volumes reach 90% and 100% used from their recent trend
+    Forecast(ForecastArgs),
+    /// Save a month's hour-of-day load profile, or compare another month with it
+    Baseline {
+        #[command(subcommand)]
+        action: BaselineAction,
+    },
+    /// Compress old monthly databases here, verify the archives and remove the originals
+    Archive(ArchiveArgs),
+    /// Compress the tables of old monthly databases here in place, keeping them readable
+    Compact(CompactArgs),
+    /// Store a database passphrase read from stdin in winbox-stats.key, protected with DPAPI (Windows)
+    ProtectKey,
+    /// Integrity-check every database here and validate its timestamps
+    Verify(VerifyArgs),
+    /// Remove duplicate and overlapping rows from the databases here
+    Dedupe(DedupeArgs),
+    /// Fill the monthly databases here from a central database of every host, or the other way round
+    Backfill(BackfillArgs),
+    /// Show the maintenance audit log and verify its hash chain
+    Audit(AuditArgs),
+    /// Check databases, disks, config and permissions and explain any problems
+    Doctor(DoctorArgs),
+    /// Make a running `collect --every` re-read its configuration (Windows)
+    Reload(ReloadArgs),
+    /// Serve the databases here over HTTP for other tools
+    Serve {
+        #[command(subcommand)]
+        api: ServeApi,
+    },
+    /// Write samples exported by other tools into the monthly databases here
+    Import {
+        #[command(subcommand)]
+        format: ImportFormat,
+    },
+    /// Export samples from the *.sqlite files in the current directory
+    Export {
+        /// List what would be written without writing it
+        #[arg(long, global = true)]
+        dry_run: bool,
+        #[command(subcommand)]
+        format: ExportFormat,
+    },
+}
+
+/// How commands print their results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    #[default]
+    Text,
+    Json,
+}
+
+impl Command {
+    /// `--output json`: the same as each command's own `--json`
+    pub fn set_json(&mut self) {
+        match self {
+            Command::Collect(a) => a.json = true,
+            Command::Graph(a) => a.json = true,
+            Command::RenameMetric(a) => a.json = true,
+            Command::TopHosts(a) => a.json = true,
+            Command::Report(a) => a.json = true,
+            Command::Query(a) => a.json = true,
+            Command::Check(a) => a.json = true,
+            Command::Analyze(a) => a.json = true,
+            Command::Forecast(a) => a.json = true,
+            Command::Baseline { action: BaselineAction::Save(a) } => a.json = true,
+            Command::Baseline { action: BaselineAction::Diff(a) } => a.json = true,
+            Command::Archive(a) => a.json = true,
+            Command::Compact(a) => a.json = true,
+            Command::Verify(a) => a.json = true,
+            Command::Dedupe(a) => a.json = true,
+            Command::Backfill(a) => a.json = true,
+            Command::Export { format: ExportFormat::Zabbix(a), .. } => a.json = true,
+            Command::Import { format: ImportFormat::Csv(a) } => a.json = true,
+            Command::Import { format: ImportFormat::Json(a) } => a.json = true,
+            Command::Import { format: ImportFormat::Perfmon(a) } => a.json = true,
+            Command::Audit(a) => a.json = true,
+            Command::Doctor(a) => a.json = true,
+            // JSON export and its dry runs follow the global flag; the rest
+            // have nothing to report
+            Command::Export { .. }
+            | Command::InstallSystemd(_)
+            | Command::Watch(_)
+            | Command::ProtectKey
+            | Command::Reload(_)
+            | Command::Serve { .. } => {}
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ImportFormat {
+    /// `timestamp,value` lines of one host and metric
+    Csv(ImportCsvArgs),
+    /// A JSON array or JSON Lines of {ts, value, host, metric}, as `export jsonl` writes
+    Json(ImportJsonArgs),
+    /// A Performance Monitor log: .blg (through relog, Windows) or relog's CSV/TSV
+    Perfmon(ImportPerfmonArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ImportPerfmonArgs {
+    pub file: PathBuf,
+    /// File every counter under this host instead of the computer in its path
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportCsvArgs {
+    /// CSV file; a header line is skipped
+    pub file: PathBuf,
+    /// Host the samples belong to
+    #[arg(long)]
+    pub host: String,
+    /// Metric (table) to write, e.g. CPU
+    #[arg(long)]
+    pub metric: String,
+
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportJsonArgs {
+    pub file: PathBuf,
+    /// Host of every record, instead of each record's `host`
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Metric of every record, instead of each record's `metric`
+    #[arg(long)]
+    pub metric: Option<String>,
+
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExportFormat {
+    /// Write a pretty-printed JSON array next to each *.sqlite file
+    Json {
+        /// Keep at most this many samples per table, picked to preserve the shape of the line (LTTB)
+        #[arg(long, value_name = "N")]
+        max_points: Option<usize>,
+    },
+    /// Stream one JSON object per sample (host, metric, ts, value)
+    Jsonl {
+        /// Write to this file instead of stdout
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+        /// Keep at most this many samples per table, picked to preserve the shape of the line (LTTB)
+        #[arg(long, value_name = "N")]
+        max_points: Option<usize>,
+    },
+    /// Send the newest sample of every series (or all in --from/--to) to Zabbix trapper items
+    Zabbix(ExportZabbixArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ExportZabbixArgs {
+    /// Zabbix server or proxy, as HOST or HOST:PORT (default port 10051)
+    #[arg(long)]
+    pub server: String,
+    /// Item keys are this, a dot and the metric name in lowercase
+    #[arg(long, default_value = "winbox")]
+    pub key_prefix: String,
+    /// Only this metric, or metrics matching a `*` pattern
+    #[arg(long)]
+    pub metric: Option<String>,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Send every sample from this local time on, e.g. "2025-08-14 02:00", instead of the newest
+    #[arg(long, value_name = "TIME")]
+    pub from: Option<String>,
+    /// Send every sample before this local time
+    #[arg(long, value_name = "TIME")]
+    pub to: Option<String>,
+
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BaselineAction {
+    /// Store the average of each hour of day of every series in a baseline file
+    Save(BaselineSaveArgs),
+    /// List the series whose hourly profile drifted from the baseline
+    Diff(BaselineDiffArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct BaselineSaveArgs {
+    /// Month to take the profile from, as YYYY-MM (default: the newest here)
+    #[arg(long, value_name = "MONTH")]
+    pub month: Option<String>,
+    /// Only this metric, or metrics matching a `*` pattern
+    #[arg(long)]
+    pub metric: Option<String>,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Baseline file (default: winbox-stats-baseline.json here)
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct BaselineDiffArgs {
+    /// Month to compare, as YYYY-MM (default: the newest here)
+    #[arg(long, value_name = "MONTH")]
+    pub month: Option<String>,
+    /// Only this metric, or metrics matching a `*` pattern
+    #[arg(long)]
+    pub metric: Option<String>,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// List series whose hourly averages moved at least this many percent on average
+    #[arg(long, default_value_t = 20.0, value_name = "PERCENT")]
+    pub threshold: f64,
+    /// Baseline file (default: winbox-stats-baseline.json here)
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ServeApi {
+    /// Grafana JSON datasource API (SimpleJSON, JSON API and Infinity plugins)
+    Grafana(ServeGrafanaArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ServeGrafanaArgs {
+    /// Address and port to listen on; use 0.0.0.0:PORT to accept remote Grafana servers
+    #[arg(long, default_value = "127.0.0.1:8427")]
+    pub listen: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ArchiveArgs {
+    /// Archive months at least this many months before the current one, e.g. 3m
+    #[arg(long, value_name = "MONTHS")]
+    pub older_than: String,
+
+    #[arg(long, value_enum, default_value_t = ArchiveFormat::Zip)]
+    pub format: ArchiveFormat,
+
+    /// Average samples into buckets of this span first (e.g. 1h) to shrink the archive further
+    #[arg(long, value_name = "SPAN")]
+    pub aggregate: Option<String>,
+
+    /// List the databases that would be archived without touching them
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CompactArgs {
+    /// Compact months at least this many months before the current one, e.g. 6m
+    #[arg(long, value_name = "MONTHS")]
+    pub older_than: String,
+    /// List the databases that would be compacted without touching them
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Copy the readable rows of damaged databases into salvaged/<name>
+    #[arg(long)]
+    pub salvage: bool,
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DedupeArgs {
+    /// Also treat rows closer than this to the previous one as overlaps, e.g. 30s for a task that fired twice
+    #[arg(long, value_name = "SPAN")]
+    pub within: Option<String>,
+    /// Count the rows that would be removed without removing them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct BackfillArgs {
+    /// Central database to write the missing samples of the monthly files here from
+    #[arg(long, value_name = "DB", required_unless_present = "to", conflicts_with = "to")]
+    pub from: Option<PathBuf>,
+    /// Central database to copy the monthly files here into; created if missing
+    #[arg(long, value_name = "DB")]
+    pub to: Option<PathBuf>,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct CollectArgs {
+    /// On a failover cluster node, file samples under this clustered role
+    /// instead of the node name so the series survives failovers
+    #[arg(long, value_name = "ROLE")]
+    pub cluster_role: Option<String>,
+
+    /// File samples under this name instead of the computer name (overrides
+    /// `host_label` in the config), e.g. for VDI clones or a machine being renamed
+    #[arg(long, value_name = "NAME")]
+    pub host_label: Option<String>,
+
+    /// Tag added to the file name (`YYYYMM@HOST#TAG.sqlite`) to keep several
+    /// collectors on one machine apart (overrides `instance` in the config)
+    #[arg(long, value_name = "TAG")]
+    pub instance: Option<String>,
+
+    /// Take the sample and show what would be written, without touching any file or pushing
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// If another collect of this series is running, wait up to this long
+    /// (30s, 5m, ...) for it to finish instead of exiting with code 4
+    #[arg(long, value_name = "SPAN", conflicts_with = "dry_run")]
+    pub wait: Option<String>,
+
+    /// Keep running and collect every SPAN (30s, 5m, ...) until stopped, for
+    /// running as a service; SIGHUP or `winbox-stats reload` re-reads the config
+    #[arg(long, value_name = "SPAN", conflicts_with_all = ["dry_run", "burst"])]
+    pub every: Option<String>,
+
+    /// With --every, sample on wall-clock multiples of SPAN (5m: :00, :05,
+    /// ...), so hosts' samples line up for comparison
+    #[arg(long, requires = "every")]
+    pub align: bool,
+
+    /// With --every, delay each sample by a random part of SPAN, so a fleet
+    /// pushing to one server does not arrive all at once
+    #[arg(long, value_name = "SPAN", requires = "every")]
+    pub jitter: Option<String>,
+
+    /// Take this many samples --interval apart into their own
+    /// `adhoc@HOST@<time>.sqlite`, for troubleshooting, instead of one sample
+    #[arg(long, value_name = "N", group = "burst", conflicts_with_all = ["dry_run", "spool_dir", "push", "statsd"])]
+    pub count: Option<u32>,
+    /// Like --count, sampling for this long (30s, 10m, 1h, ...)
+    #[arg(long, value_name = "SPAN", group = "burst", conflicts_with_all = ["dry_run", "spool_dir", "push", "statsd"])]
+    pub duration: Option<String>,
+    /// Time between samples of a --count or --duration burst; under a second
+    /// as e.g. 250ms or 0.5s
+    #[arg(long, value_name = "SPAN", default_value = "5s", requires = "burst")]
+    pub interval: String,
+    /// Chart the burst once it is done
+    #[arg(long, requires = "burst")]
+    pub graph: bool,
+
+    /// Queue each sample in a local database in this directory first, then move
+    /// queued samples into the databases here (e.g. on a network share), retrying
+    /// on later runs while the share is unreachable
+    #[arg(long, value_name = "DIR")]
+    pub spool_dir: Option<PathBuf>,
+
+    /// Also send each sample to this HTTP endpoint (queued on disk while unreachable)
+    #[arg(long, value_name = "URL")]
+    pub push: Option<String>,
+    /// Body format for --push
+    #[arg(long, value_enum, default_value_t)]
+    pub push_format: PushFormat,
+    /// Samples per HTTP request
+    #[arg(long, default_value_t = 500)]
+    pub push_batch: usize,
+    /// Oldest queued samples are dropped beyond this many
+    #[arg(long, default_value_t = 100_000)]
+    pub push_max_queue: usize,
+
+    /// Also mirror each sample to this StatsD or Graphite server (not queued)
+    #[arg(long, value_name = "HOST:PORT")]
+    pub statsd: Option<String>,
+    /// Protocol for --statsd
+    #[arg(long, value_enum, default_value_t, requires = "statsd")]
+    pub statsd_format: StatsdFormat,
+    /// First level of the metric paths sent with --statsd
+    #[arg(long, default_value = "winbox", requires = "statsd")]
+    pub statsd_prefix: String,
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct InstallSystemdArgs {
+    /// Collection interval as a systemd time span
+    #[arg(long, default_value = "5min")]
+    pub interval: String,
+    /// Directory the databases are written to
+    #[arg(long, default_value = "/var/lib/winbox-stats")]
+    pub data_dir: PathBuf,
+    /// Where the unit files go
+    #[arg(long, default_value = "/etc/systemd/system")]
+    pub unit_dir: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct GraphArgs {
+    /// Image width in pixels
+    #[arg(long, default_value_t = 1600)]
+    pub width: u32,
+    /// Image height in pixels
+    #[arg(long, default_value_t = 900)]
+    pub height: u32,
+    /// Background, axis and grid colours
+    #[arg(long, value_enum, default_value_t)]
+    pub theme: Theme,
+    /// Series colour as #RRGGBB or a name (blue, red, orange, ...)
+    #[arg(long, value_name = "COLOR")]
+    pub line_color: Option<String>,
+    /// How configured warning/critical thresholds are drawn
+    #[arg(long, value_enum, default_value_t)]
+    pub threshold_style: ThresholdStyle,
+    /// Break the line where samples are more than this many intervals apart
+    #[arg(long, default_value_t = 3.0, value_name = "N")]
+    pub gap_factor: f64,
+    /// Draw a min–max band with the average line instead of raw samples
+    #[arg(long, value_enum, default_value_t)]
+    pub envelope: Envelope,
+    /// Draw a rolling average over this window (15m, 1h, ...) in bold over the faded samples
+    #[arg(long, value_name = "WINDOW")]
+    pub smooth: Option<String>,
+    /// Logarithmic y axis, for metrics spanning several orders of magnitude
+    #[arg(long, conflicts_with_all = ["compare", "heatmap", "histogram"])]
+    pub log_y: bool,
+    /// One chart per month (whole database), ISO week or day
+    #[arg(long, value_enum, default_value_t, conflicts_with = "around")]
+    pub period: Period,
+    /// Drill into the samples around this time in the chart's zone, e.g. "2025-08-14 02:00"
+    #[arg(long, value_name = "TIME")]
+    pub around: Option<String>,
+    /// Width of the --around window (30m, 6h, 2d, ...)
+    #[arg(long, default_value = "6h", requires = "around")]
+    pub window: String,
+    /// Chart caption template from {title} (the usual caption) {host} {when}
+    /// {metric}; overrides `title` in [charts]
+    #[arg(long, value_name = "TEMPLATE")]
+    pub title: Option<String>,
+    /// Small print along the bottom of every chart, e.g. a company name
+    #[arg(long, value_name = "TEXT")]
+    pub footer: Option<String>,
+    /// Image (PNG, JPEG or BMP) drawn in the top right corner of every chart
+    #[arg(long, value_name = "FILE")]
+    pub logo: Option<PathBuf>,
+    /// Font family of all chart text, e.g. "Segoe UI"
+    #[arg(long, value_name = "FAMILY")]
+    pub font: Option<String>,
+    /// Directory the charts are written to
+    #[arg(long, default_value = ".")]
+    pub out_dir: PathBuf,
+    /// Chart file name under --out-dir, from {host} {ym} {metric} {stem} {zoom};
+    /// may contain subfolders, e.g. "{host}/{ym}-{metric}.png"
+    #[arg(long, value_name = "TEMPLATE")]
+    pub name_template: Option<String>,
+    /// Redraw charts even if they are newer than their database
+    #[arg(long)]
+    pub force: bool,
+    /// Also render the databases in subfolders, e.g. an archive tree like
+    /// 2025/HOST/; charts go to the same subfolder under --out-dir
+    #[arg(long, conflicts_with = "compare")]
+    pub recursive: bool,
+    /// Show the charts written in the default image viewer (the output folder if there are many)
+    #[arg(long)]
+    pub open: bool,
+    /// Print the charts written and skipped as JSON
+    #[arg(long)]
+    pub json: bool,
+    /// Overlay two months of each host and metric by day of month instead,
+    /// e.g. 2025-10,2025-11
+    #[arg(long, value_name = "MONTH,MONTH", value_delimiter = ',', group = "by_metric", conflicts_with_all = ["around", "period"])]
+    pub compare: Option<Vec<String>>,
+    /// Two metrics of each host on one chart per month instead, the first
+    /// against the left axis and the second the right, e.g. CPU,NET_MBPS
+    #[arg(long, value_name = "METRIC,METRIC", value_delimiter = ',', conflicts_with_all = ["around", "period", "compare"])]
+    pub combine: Option<Vec<String>>,
+    /// Draw each month as an hour-of-day by day-of-month heatmap of hourly
+    /// averages instead
+    #[arg(long, group = "by_metric", conflicts_with_all = ["around", "period", "combine"])]
+    pub heatmap: bool,
+    /// Draw each month's spread of values as a histogram, with the 95th and
+    /// 99th percentiles marked, instead
+    #[arg(long, group = "by_metric", conflicts_with_all = ["around", "period", "combine"])]
+    pub histogram: bool,
+    /// Draw each host's used GB per volume as one stacked area chart per
+    /// month instead
+    #[arg(long, conflicts_with_all = ["around", "period", "combine", "by_metric"])]
+    pub disk_stack: bool,
+    /// Only this metric, or metrics matching a `*` pattern, with --compare,
+    /// --heatmap or --histogram
+    #[arg(long, requires = "by_metric")]
+    pub metric: Option<String>,
+    /// Charts that do not depend on when or where in the world they are
+    /// drawn: the time axis in UTC unless --tz is given, the default font,
+    /// and no creation time in the metadata. Text still comes from the fonts
+    /// installed, so the PNGs can differ between machines; --check compares
+    /// charts across machines
+    #[arg(long, conflicts_with = "font")]
+    pub deterministic: bool,
+    /// Compare a hash of each line chart with the hashes in FILE instead of
+    /// writing the charts; implies --deterministic
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["font", "combine", "disk_stack", "by_metric", "list_meta", "open"])]
+    pub check: Option<PathBuf>,
+    /// With --check, store the hashes in FILE instead of comparing
+    #[arg(long, requires = "check")]
+    pub update: bool,
+    /// Print the host, metric, period, coverage and source embedded in
+    /// these charts instead of drawing any
+    #[arg(long, value_name = "PNG", num_args = 1..)]
+    pub list_meta: Option<Vec<PathBuf>>,
+    /// Charts rendered at once (default: one per CPU core)
+    #[arg(long, short, value_name = "N")]
+    pub jobs: Option<usize>,
+    /// Label the time axis in this zone: local, utc or an IANA name such as
+    /// Europe/Berlin (default: the zone each file was collected in)
+    #[arg(long, value_name = "ZONE")]
+    pub tz: Option<Zone>,
+}
+
+#[derive(Debug, Args)]
+pub struct TopHostsArgs {
+    /// Metric (table) name, e.g. CPU or C_Drive
+    #[arg(long)]
+    pub metric: String,
+    /// Statistic to rank by
+    #[arg(long, value_enum, default_value = "p95")]
+    pub stat: Stat,
+    /// Only files for this month, as YYYY-MM or YYYYMM
+    #[arg(long, value_name = "MONTH")]
+    pub month: Option<String>,
+    /// How many hosts to show
+    #[arg(short, default_value_t = 10)]
+    pub n: usize,
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// Only files for this month, as YYYY-MM or YYYYMM
+    #[arg(long, value_name = "MONTH")]
+    pub month: Option<String>,
+    /// One row per metric per month or per day
+    #[arg(long, value_enum, default_value_t)]
+    pub by: Per,
+    /// Only this metric, or metrics matching a `*` pattern
+    #[arg(long)]
+    pub metric: Option<String>,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+    /// Write one HTML page for the month (default: the newest) with charts and the change since the month before
+    #[arg(long, group = "page", conflicts_with_all = ["json", "by"])]
+    pub html: bool,
+    /// Print the same page to PDF with a headless Edge or Chrome
+    #[arg(long, group = "page", conflicts_with_all = ["json", "by"])]
+    pub pdf: bool,
+    /// File to write (default: report-YYYY-MM.html or .pdf)
+    #[arg(long, value_name = "FILE", requires = "page")]
+    pub out: Option<PathBuf>,
+    /// Browser for --pdf (default: Edge or Chrome from the usual install locations)
+    #[arg(long, value_name = "PATH", requires = "pdf", conflicts_with = "html")]
+    pub browser: Option<PathBuf>,
+    /// Also mail the report to the [smtp] recipients in the config
+    #[arg(long)]
+    pub email: bool,
+    /// Show the page in the default browser or PDF viewer once written
+    #[arg(long, requires = "page")]
+    pub open: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AuditArgs {
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DoctorArgs {
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ReloadArgs {
+    /// The --cluster-role the collector was started with
+    #[arg(long, value_name = "ROLE")]
+    pub cluster_role: Option<String>,
+    /// The --host-label the collector was started with
+    #[arg(long, value_name = "NAME")]
+    pub host_label: Option<String>,
+    /// The --instance the collector was started with
+    #[arg(long, value_name = "TAG")]
+    pub instance: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Time between samples (2s, 10s, 1m, ...)
+    #[arg(long, value_name = "SPAN", default_value = "2s")]
+    pub interval: String,
+    /// Also write every sample into this month's database, as collect does
+    #[arg(long)]
+    pub write: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// Only this metric, or metrics matching a `*` pattern
+    #[arg(long)]
+    pub metric: Option<String>,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+    /// `nagios` prints one plugin status line with performance data, for NRPE or NSClient++
+    #[arg(long, value_enum, default_value_t, conflicts_with = "json")]
+    pub format: CheckFormat,
+    /// Mail the result to the [smtp] recipients when anything is WARNING or CRITICAL
+    #[arg(long)]
+    pub email: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    /// Metric (table) name or `*` pattern, e.g. CPU or *_Drive
+    #[arg(long)]
+    pub metric: String,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Samples from this local time on, e.g. "2025-08-14 02:00" or 2025-08-14
+    #[arg(long, value_name = "TIME")]
+    pub from: Option<String>,
+    /// Samples before this local time
+    #[arg(long, value_name = "TIME")]
+    pub to: Option<String>,
+    /// Print min/avg/max/p50/p95/p99 per day or month instead of the samples
+    #[arg(long, value_enum)]
+    pub by: Option<Per>,
+    /// Print JSON instead of text
+    #[arg(long)]
+    pub json: bool,
+    /// Draw each series as a one-line chart instead of listing its samples
+    #[arg(long, conflicts_with_all = ["by", "json"])]
+    pub sparkline: bool,
+    /// Characters per --sparkline (default: the terminal width)
+    #[arg(long, value_name = "N", requires = "sparkline")]
+    pub width: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    /// Month to analyze, as YYYY-MM or YYYYMM
+    #[arg(long, value_name = "MONTH")]
+    pub month: String,
+    /// Only this metric, or metrics matching a `*` pattern
+    #[arg(long)]
+    pub metric: Option<String>,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Flag hours at least this many deviations from the same hour's usual level
+    #[arg(long, default_value_t = 3.5, value_name = "Z")]
+    pub threshold: f64,
+    /// How many periods to list, most unusual first
+    #[arg(short, default_value_t = 20)]
+    pub n: usize,
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ForecastArgs {
+    /// Metric name or `*` pattern of used-percent series
+    #[arg(long, default_value = "*_Drive")]
+    pub metric: String,
+    /// Only this host
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Fit the trend over this many days before each series' last sample
+    #[arg(long, default_value_t = 90)]
+    pub days: i64,
+    /// Print JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RenameMetricArgs {
+    /// Current table name, e.g. C_Drive
+    #[arg(long)]
+    pub from: String,
+    /// New table name, e.g. DISK_C
+    #[arg(long)]
+    pub to: String,
+    /// Set by the global `--output json`
+    #[arg(skip)]
+    pub json: bool,
 }
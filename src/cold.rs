@@ -0,0 +1,323 @@
+// src/cold.rs
+//! `compact`: cold storage for months that are no longer collected into.
+//! Each metric table becomes one row of the `Cold` table holding its samples
+//! as two columns, times as deltas and values XORed with the previous one,
+//! compressed with zstd; a month of 5-minute samples shrinks about tenfold.
+//! The database stays a `.sqlite` file next to the others and graph, query,
+//! report and export read it as before.
+//!
+//! Compacted samples lose their `Seq` run numbers and cannot be changed in
+//! place: dedupe and retention skip them. Samples written into a compacted
+//! month later (an import, a backfill) go to a normal table again and are
+//! read together with the cold ones; compacting again folds them in.
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Local};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::archive::{month_index, parse_months};
+use crate::audit;
+use crate::cli::CompactArgs;
+use crate::graph::plot::split_stem_sqlite;
+use crate::storage::{self, list_writable_tables, COLD_TABLE};
+
+/// First byte of every blob, for changing the layout later
+const FORMAT: u8 = 1;
+const ZSTD_LEVEL: i32 = 19;
+/// Longest varint: ten bytes of seven bits
+const MAX_VARINT_BYTES: u64 = 10;
+/// Most a sample takes inflated: a time varint and eight bytes of value
+const MAX_SAMPLE_BYTES: u64 = MAX_VARINT_BYTES + 8;
+
+/// One database for `compact --output json`
+#[derive(Serialize)]
+struct Compacted {
+    database: PathBuf,
+    tables: usize,
+    rows: usize,
+    bytes_before: u64,
+    /// 0 with --dry-run
+    bytes_after: u64,
+}
+
+fn put_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn get_varint(data: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let Some(&b) = data.get(*pos) else { bail!("compacted samples are truncated") };
+        *pos += 1;
+        v |= u64::from(b & 0x7f) << shift;
+        if b & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    bail!("compacted samples are damaged")
+}
+
+/// Time deltas are mostly small and positive, but the clock can go back
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+/// (epoch ms, value) samples, in the order given
+pub fn encode(samples: &[(i64, f64)]) -> Result<Vec<u8>> {
+    let mut raw = Vec::with_capacity(samples.len() * 10);
+    put_varint(&mut raw, samples.len() as u64);
+    let mut prev = 0i64;
+    for &(ts, _) in samples {
+        put_varint(&mut raw, zigzag(ts.wrapping_sub(prev)));
+        prev = ts;
+    }
+    // A value like its predecessor XORs to mostly zero bytes, which zstd packs tightly
+    let mut prev = 0u64;
+    for &(_, v) in samples {
+        raw.extend_from_slice(&(v.to_bits() ^ prev).to_le_bytes());
+        prev = v.to_bits();
+    }
+    let mut out = vec![FORMAT];
+    out.extend(zstd::bulk::compress(&raw, ZSTD_LEVEL)?);
+    Ok(out)
+}
+
+pub fn decode(blob: &[u8], rows: usize) -> Result<Vec<(i64, f64)>> {
+    let Some((&format, compressed)) = blob.split_first() else { bail!("compacted samples are empty") };
+    if format != FORMAT {
+        bail!("compacted samples are in format {}, which this build cannot read", format);
+    }
+    // `rows` samples inflate to at most this, so a damaged blob is never
+    // inflated without limit
+    let limit = (rows as u64).saturating_mul(MAX_SAMPLE_BYTES).saturating_add(MAX_VARINT_BYTES);
+    let mut raw = Vec::new();
+    zstd::stream::read::Decoder::new(compressed)?
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut raw)
+        .context("decompress compacted samples")?;
+    if raw.len() as u64 > limit {
+        bail!("compacted samples are damaged");
+    }
+    let mut pos = 0;
+    let n = get_varint(&raw, &mut pos)?;
+    // Each sample takes at least a byte of time and eight of value, so a
+    // count beyond that is damage, not a reason to allocate
+    let n = usize::try_from(n).ok().filter(|n| *n == rows && *n <= raw.len() / 9).context("compacted samples are damaged")?;
+    let mut times = Vec::with_capacity(n);
+    let mut prev = 0i64;
+    for _ in 0..n {
+        prev = prev.wrapping_add(unzigzag(get_varint(&raw, &mut pos)?));
+        times.push(prev);
+    }
+    if n.checked_mul(8) != Some(raw.len() - pos) {
+        bail!("compacted samples are damaged");
+    }
+    let mut prev = 0u64;
+    let values = raw[pos..].chunks_exact(8).map(|b| {
+        prev ^= u64::from_le_bytes(b.try_into().expect("chunks of 8"));
+        f64::from_bits(prev)
+    });
+    Ok(times.into_iter().zip(values).collect())
+}
+
+fn has_cold_table(conn: &Connection) -> Result<bool> {
+    let found: Option<i64> = conn
+        .query_row("SELECT 1 FROM sqlite_master WHERE type='table' AND name=?1", params![COLD_TABLE], |r| r.get(0))
+        .optional()?;
+    Ok(found.is_some())
+}
+
+/// Compacted tables
+pub fn names(conn: &Connection) -> Result<Vec<String>> {
+    if !has_cold_table(conn)? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(&format!(r#"SELECT "Name" FROM "{}" ORDER BY "Name""#, COLD_TABLE))?;
+    let names = stmt.query_map([], |r| r.get(0))?.collect::<rusqlite::Result<_>>()?;
+    Ok(names)
+}
+
+/// Compacted samples of `table`, oldest first; empty when it has none
+pub fn samples(conn: &Connection, table: &str) -> Result<Vec<(i64, f64)>> {
+    if !has_cold_table(conn)? {
+        return Ok(Vec::new());
+    }
+    let sql = format!(r#"SELECT "Rows", "Data" FROM "{}" WHERE "Name" = ?1 COLLATE NOCASE"#, COLD_TABLE);
+    let found: Option<(i64, Vec<u8>)> = conn.query_row(&sql, params![table], |r| Ok((r.get(0)?, r.get(1)?))).optional()?;
+    found
+        .map(|(rows, b)| decode(&b, usize::try_from(rows).unwrap_or(0)).with_context(|| format!("read compacted {}", table)))
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+pub fn count(conn: &Connection, table: &str) -> Result<usize> {
+    if !has_cold_table(conn)? {
+        return Ok(0);
+    }
+    let sql = format!(r#"SELECT "Rows" FROM "{}" WHERE "Name" = ?1 COLLATE NOCASE"#, COLD_TABLE);
+    let rows: Option<i64> = conn.query_row(&sql, params![table], |r| r.get(0)).optional()?;
+    Ok(rows.unwrap_or(0) as usize)
+}
+
+/// Fold every table of rows into `Cold`; returns (tables, samples)
+fn compact_db(conn: &mut Connection) -> Result<(usize, usize)> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(&format!(
+        r#"CREATE TABLE IF NOT EXISTS "{COLD_TABLE}"("Name" TEXT PRIMARY KEY NOT NULL COLLATE NOCASE, "Rows" INTEGER NOT NULL, "Data" BLOB NOT NULL);"#
+    ))?;
+    let (mut tables, mut rows) = (0, 0);
+    for table in list_writable_tables(&tx)? {
+        // Cold and newer samples together, as readers see them
+        let samples = storage::read_samples_ms(&tx, &table)?;
+        let blob = encode(&samples)?;
+        // The rows are dropped below, so the blob must give back exactly them
+        let same = |a: &(i64, f64), b: &(i64, f64)| a.0 == b.0 && a.1.to_bits() == b.1.to_bits();
+        let back = decode(&blob, samples.len())?;
+        if back.len() != samples.len() || !back.iter().zip(&samples).all(|(a, b)| same(a, b)) {
+            bail!("{} does not read back from its compacted form", table);
+        }
+        tx.execute(
+            &format!(r#"INSERT OR REPLACE INTO "{COLD_TABLE}"("Name","Rows","Data") VALUES (?1, ?2, ?3)"#),
+            params![table, samples.len() as i64, blob],
+        )?;
+        tx.execute_batch(&format!(r#"DROP TABLE "{}";"#, table))?;
+        tables += 1;
+        rows += samples.len();
+    }
+    tx.commit()?;
+    // Hand the freed pages back to the file system
+    conn.execute_batch("VACUUM")?;
+    Ok((tables, rows))
+}
+
+/// Entry point for `winbox-stats compact`
+pub fn run_compact(args: &CompactArgs) -> Result<()> {
+    let dir = Path::new(".");
+    let months = parse_months(&args.older_than)?;
+    let now = Local::now();
+    let cutoff = now.year() * 12 + now.month0() as i32 - months;
+
+    let mut compacted = Vec::new();
+    for p in storage::find_databases(dir, 1) {
+        let (ym, host, _) = split_stem_sqlite(&storage::db_stem(&p));
+        if storage::is_archive(&p) || host.is_empty() || month_index(&ym).is_none_or(|i| i > cutoff) {
+            continue;
+        }
+        let before = fs::metadata(&p)?.len();
+        if args.dry_run {
+            let db = storage::open_read_only(&p)?;
+            let writable = list_writable_tables(&db.conn)?;
+            if writable.is_empty() {
+                continue;
+            }
+            if !args.json {
+                println!("Would compact {} ({} tables)", p.display(), writable.len());
+            }
+            let rows = writable.iter().map(|t| storage::count_samples(&db.conn, t)).sum::<Result<usize>>()?;
+            compacted.push(Compacted { database: p, tables: writable.len(), rows, bytes_before: before, bytes_after: 0 });
+            continue;
+        }
+        let mut conn = storage::open(&p)?;
+        if list_writable_tables(&conn)?.is_empty() {
+            continue;
+        }
+        let (tables, rows) = compact_db(&mut conn).with_context(|| format!("compact {}", p.display()))?;
+        drop(conn);
+        let after = fs::metadata(&p)?.len();
+        let detail = format!("{} tables, {} samples, {} -> {} bytes", tables, rows, before, after);
+        audit::record(dir, "compact", &p.display().to_string(), &detail)?;
+        if !args.json {
+            println!("Compacted {} ({:.1} MB -> {:.1} MB)", p.display(), before as f64 / 1e6, after as f64 / 1e6);
+        }
+        compacted.push(Compacted { database: p, tables, rows, bytes_before: before, bytes_after: after });
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&compacted)?);
+    } else if compacted.is_empty() && !args.dry_run {
+        log::warn!("nothing to compact older than {} months here", months);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(samples: &[(i64, f64)]) -> Vec<(i64, f64)> {
+        decode(&encode(samples).unwrap(), samples.len()).unwrap()
+    }
+
+    fn bits(samples: &[(i64, f64)]) -> Vec<(i64, u64)> {
+        samples.iter().map(|(ts, v)| (*ts, v.to_bits())).collect()
+    }
+
+    #[test]
+    fn zigzag_and_varints_round_trip() {
+        for v in [0, 1, -1, 63, -64, 300_000, -300_000, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(v)), v);
+        }
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        let mut buf = Vec::new();
+        for v in [0, 127, 128, 300_000, u64::MAX] {
+            put_varint(&mut buf, v);
+        }
+        let mut pos = 0;
+        for v in [0, 127, 128, 300_000, u64::MAX] {
+            assert_eq!(get_varint(&buf, &mut pos).unwrap(), v);
+        }
+        assert_eq!(pos, buf.len());
+        assert!(get_varint(&[0x80, 0x80], &mut 0).is_err());
+    }
+
+    #[test]
+    fn samples_round_trip_bit_for_bit() {
+        let samples = [(1_780_000_000_000, 12.5), (1_780_000_300_000, -0.0), (1_779_999_000_000, f64::NAN), (1_780_000_600_000, f64::from_bits(0x7ff8_0000_dead_beef)), (i64::MIN, f64::INFINITY)];
+        assert_eq!(bits(&round_trip(&samples)), bits(&samples));
+        assert!(round_trip(&[]).is_empty());
+    }
+
+    #[test]
+    fn damaged_blobs_fail_cleanly() {
+        let blob = encode(&[(1, 1.0), (2, 2.0), (3, 3.0)]).unwrap();
+        assert!(decode(&[], 3).is_err());
+        assert!(decode(&[FORMAT + 1], 3).is_err());
+        assert!(decode(&blob[..blob.len() - 1], 3).is_err());
+        // A count other than the table's
+        assert!(decode(&blob, 2).is_err());
+        assert!(decode(&blob, 4).is_err());
+        // A huge sample count over a few bytes of data
+        let mut raw = Vec::new();
+        put_varint(&mut raw, u64::MAX / 2);
+        raw.extend_from_slice(&[0; 9]);
+        let mut bad = vec![FORMAT];
+        bad.extend(zstd::bulk::compress(&raw, 1).unwrap());
+        assert!(decode(&bad, 1).is_err());
+        // Values cut short
+        let mut raw = Vec::new();
+        put_varint(&mut raw, 1);
+        put_varint(&mut raw, 0);
+        raw.extend_from_slice(&[0; 7]);
+        let mut short = vec![FORMAT];
+        short.extend(zstd::bulk::compress(&raw, 1).unwrap());
+        assert!(decode(&short, 1).is_err());
+        // Far more data than the table's count allows is not inflated
+        let mut raw = Vec::new();
+        put_varint(&mut raw, 1);
+        raw.resize(1 << 20, 0);
+        let mut bomb = vec![FORMAT];
+        bomb.extend(zstd::bulk::compress(&raw, 1).unwrap());
+        assert!(decode(&bomb, 1).is_err());
+    }
+}
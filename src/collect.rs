@@ -1,73 +1,64 @@
 // src/collect.rs
 use anyhow::Result;
 use chrono::{Datelike, Local};
-use hostname::get as get_hostname;
-use rusqlite::{params, Connection};
+use rusqlite::{Connection, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
-use std::time::Duration;
-use sysinfo::{
-    CpuRefreshKind, Disks, MemoryRefreshKind, RefreshKind, System,
-};
+use std::time::{Duration, Instant};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, ProcessRefreshKind, RefreshKind, System, MINIMUM_CPU_UPDATE_INTERVAL};
 
-const CPU_SAMPLE_MS: u64 = 750; // 500–1000ms gives stable CPU readings
+use crate::aliases::Aliases;
+use crate::apps;
+use crate::backend::{self, Engine};
+use crate::check::{self, Status};
+use crate::cli::CollectArgs;
+use crate::config::Config;
+use crate::disks;
+use crate::eventlog;
+use crate::exec;
+use crate::graph::{self, plot::ADHOC_PREFIX};
+use crate::identity;
+use crate::lock;
+use crate::patches;
+use crate::perf;
+use crate::pgsink;
+use crate::probes;
+use crate::progress::Progress;
+use crate::push::{self, PushTarget};
+use crate::retention;
+use crate::selfmon;
+use crate::services;
+use crate::shares;
+use crate::smart;
+use crate::spool;
+use crate::statsd::{self, StatsdTarget};
+use crate::storage::{self, ensure_table, insert_sample_ms};
+use crate::timespan;
+use crate::virt;
+use crate::wmi;
 
-fn hostname_upper() -> String {
-    get_hostname()
-        .ok()
-        .and_then(|s| s.into_string().ok())
-        .unwrap_or_else(|| "UNKNOWN".into())
-        .to_uppercase()
-}
+const CPU_SAMPLE_MS: u64 = 750; // 500–1000ms gives stable CPU readings
 
 fn month_prefix_yyyymm() -> String {
     let now = Local::now();
     format!("{:04}{:02}", now.year(), now.month())
 }
 
-fn now_timestamp() -> String {
-    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
-}
-
-fn ensure_table(conn: &Connection, table: &str) -> Result<()> {
-    let sql = format!(
-        r#"
-        CREATE TABLE IF NOT EXISTS "{t}"(
-            "Timestamp" TEXT NOT NULL,
-            "Value"     REAL NOT NULL
-        );
-        CREATE INDEX IF NOT EXISTS "ix_{t}_Timestamp" ON "{t}"("Timestamp");
-        "#,
-        t = table
-    );
-    conn.execute_batch(&sql)?;
-    Ok(())
+/// `YYYYMM@<series>.sqlite` (or `.duckdb`) for the current month
+pub fn monthly_db_name(series: &str, engine: Engine) -> String {
+    format!("{}@{}.{}", month_prefix_yyyymm(), series, engine.extension())
 }
 
-fn insert_sample(conn: &Connection, table: &str, ts: &str, value: f64) -> Result<()> {
-    let sql = format!(r#"INSERT INTO "{t}"("Timestamp","Value") VALUES (?1, ?2)"#, t = table);
-    conn.execute(&sql, params![ts, value])?;
-    Ok(())
+pub fn now_timestamp() -> i64 {
+    Local::now().timestamp()
 }
 
-fn label_for_mount_point(mp: &str) -> String {
-    // Windows like "C:\..." -> "C_Drive"; POSIX -> last segment uppercased + _Drive
-    if mp.len() >= 2 && mp.chars().nth(1) == Some(':') {
-        let drive = mp.chars().next().unwrap().to_ascii_uppercase();
-        format!("{}_Drive", drive)
-    } else {
-        let last = mp
-            .split(['/', '\\'])
-            .filter(|s| !s.is_empty())
-            .last()
-            .unwrap_or("Disk");
-        format!("{}_Drive", last.replace(':', "").to_uppercase())
-    }
-}
-
-fn sample_cpu_percent(sys: &mut System) -> f64 {
+fn sample_cpu_percent(sys: &mut System, window: Duration) -> f64 {
     // Two refreshes with delay to compute usage delta
     sys.refresh_cpu(); // baseline
-    sleep(Duration::from_millis(CPU_SAMPLE_MS));
+    sleep(window);
     sys.refresh_cpu(); // measure window
     sys.global_cpu_info().cpu_usage() as f64 // 0..100 already normalized
 }
@@ -83,10 +74,321 @@ fn sample_ram_percent(sys: &mut System) -> f64 {
     }
 }
 
-pub fn run_collect(_debug: bool) -> Result<()> {
-    let host = hostname_upper();
-    let db_name = format!("{}@{}.sqlite", month_prefix_yyyymm(), host);
-    let conn = Connection::open(&db_name)?;
+/// Used inodes as a percentage; `None` where the filesystem has no fixed inode table
+#[cfg(unix)]
+fn sample_inode_percent(mount_point: &Path) -> Option<f64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(mount_point.as_os_str().as_bytes()).ok()?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut st) } != 0 {
+        return None;
+    }
+    let total = st.f_files as f64;
+    let free = st.f_ffree as f64;
+    if total <= 0.0 {
+        None
+    } else {
+        Some((1.0 - (free / total)) * 100.0)
+    }
+}
+
+/// NTFS master file table size and the zone reserved for it to grow, in GB;
+/// `None` for other filesystems. Once a filling volume hands the zone out to
+/// files, the MFT fragments and every file lookup gets slower.
+#[cfg(windows)]
+fn sample_mft_gb(mount_point: &Path) -> Option<(f64, f64)> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{CreateFileW, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING};
+    use windows_sys::Win32::System::Ioctl::{FSCTL_GET_NTFS_VOLUME_DATA, NTFS_VOLUME_DATA_BUFFER};
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    // `C:\` -> `\\.\C:`; the control code needs no access rights, so no elevation either
+    let drive = mount_point.to_string_lossy();
+    let device: Vec<u16> = format!(r"\\.\{}", drive.trim_end_matches('\\')).encode_utf16().chain(Some(0)).collect();
+    // SAFETY: the handle is closed before returning and `data` outlives the call
+    unsafe {
+        let h = CreateFileW(
+            device.as_ptr(),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            0,
+            std::ptr::null_mut(),
+        );
+        if h == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let mut data: NTFS_VOLUME_DATA_BUFFER = std::mem::zeroed();
+        let mut returned = 0u32;
+        // Fails on FAT, exFAT and ReFS volumes
+        let ok = DeviceIoControl(
+            h,
+            FSCTL_GET_NTFS_VOLUME_DATA,
+            std::ptr::null(),
+            0,
+            &mut data as *mut _ as *mut std::ffi::c_void,
+            std::mem::size_of::<NTFS_VOLUME_DATA_BUFFER>() as u32,
+            &mut returned,
+            std::ptr::null_mut(),
+        );
+        CloseHandle(h);
+        if ok == 0 {
+            return None;
+        }
+        let zone = (data.MftZoneEnd - data.MftZoneStart).max(0) as f64 * data.BytesPerCluster as f64;
+        Some((data.MftValidDataLength as f64 / GB, zone / GB))
+    }
+}
+
+fn sample_process_count(sys: &mut System) -> f64 {
+    // Only the process list; per-process CPU, memory and disk stats are not needed
+    sys.refresh_processes_specifics(ProcessRefreshKind::new());
+    sys.processes().len() as f64
+}
+
+/// Context switches and interrupts since boot, from the `ctxt` and `intr` lines of /proc/stat
+#[cfg(target_os = "linux")]
+fn proc_stat_counts() -> Option<(u64, u64)> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let field = |name: &str| {
+        stat.lines()
+            .find_map(|l| l.strip_prefix(name)?.strip_prefix(' '))?
+            .split_whitespace()
+            .next()?
+            .parse::<u64>()
+            .ok()
+    };
+    Some((field("ctxt")?, field("intr")?))
+}
+
+/// `CTX_SWITCHES` and `INTERRUPTS` per second since `start`. On Windows
+/// these are performance counters, sampled with the configured ones.
+#[cfg(target_os = "linux")]
+fn sample_system_rates(start: (Instant, Option<(u64, u64)>)) -> Vec<(String, f64)> {
+    let (Some((ctxt0, intr0)), Some((ctxt, intr))) = (start.1, proc_stat_counts()) else { return Vec::new() };
+    let secs = start.0.elapsed().as_secs_f64().max(0.001);
+    vec![
+        ("CTX_SWITCHES".into(), ctxt.saturating_sub(ctxt0) as f64 / secs),
+        ("INTERRUPTS".into(), intr.saturating_sub(intr0) as f64 / secs),
+    ]
+}
+
+/// 1, 5 and 15 minute load averages; Windows has no equivalent
+#[cfg(unix)]
+fn sample_load_average() -> [(String, f64); 3] {
+    let load = System::load_average();
+    [("LOAD1".into(), load.one), ("LOAD5".into(), load.five), ("LOAD15".into(), load.fifteen)]
+}
+
+#[cfg(windows)]
+fn performance_info() -> Option<windows_sys::Win32::System::ProcessStatus::PERFORMANCE_INFORMATION> {
+    use windows_sys::Win32::System::ProcessStatus::{GetPerformanceInfo, PERFORMANCE_INFORMATION};
+
+    let size = std::mem::size_of::<PERFORMANCE_INFORMATION>() as u32;
+    // SAFETY: plain output struct of the size we pass
+    let mut info: PERFORMANCE_INFORMATION = unsafe { std::mem::zeroed() };
+    info.cb = size;
+    if unsafe { GetPerformanceInfo(&mut info, size) } == 0 {
+        log::warn!("read performance info: {}", std::io::Error::last_os_error());
+        return None;
+    }
+    Some(info)
+}
+
+/// Open handles across all processes; climbs steadily when something leaks them
+#[cfg(windows)]
+fn sample_handle_count() -> Option<f64> {
+    performance_info().map(|info| info.HandleCount as f64)
+}
+
+const GB: f64 = (1u64 << 30) as f64;
+
+/// Committed, cache (incl. standby) and nonpaged pool memory in GB, to tell
+/// real pressure from a full but reclaimable cache
+#[cfg(windows)]
+fn sample_memory_breakdown() -> Vec<(String, f64)> {
+    let Some(info) = performance_info() else { return Vec::new() };
+    let gb = |pages: usize| (pages * info.PageSize) as f64 / GB;
+    vec![
+        ("RAM_COMMITTED_GB".to_string(), gb(info.CommitTotal)),
+        ("RAM_CACHED_GB".to_string(), gb(info.SystemCache)),
+        ("RAM_NONPAGED_GB".to_string(), gb(info.KernelNonpaged)),
+    ]
+}
+
+/// Committed and page cache memory in GB from /proc/meminfo (Linux; empty elsewhere)
+#[cfg(not(windows))]
+fn sample_memory_breakdown() -> Vec<(String, f64)> {
+    let Ok(text) = std::fs::read_to_string("/proc/meminfo") else { return Vec::new() };
+    let kb = |key: &str| {
+        text.lines()
+            .find_map(|l| l.strip_prefix(key)?.strip_prefix(':'))
+            .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<f64>().ok())
+    };
+    let mut out = Vec::new();
+    if let Some(v) = kb("Committed_AS") {
+        out.push(("RAM_COMMITTED_GB".to_string(), v * 1024.0 / GB));
+    }
+    if let Some(v) = kb("Cached") {
+        out.push(("RAM_CACHED_GB".to_string(), v * 1024.0 / GB));
+    }
+    out
+}
+
+/// Active interactive sessions (console and RDP); `None` if they cannot be enumerated
+#[cfg(windows)]
+fn sample_sessions() -> Option<f64> {
+    use windows_sys::Win32::System::RemoteDesktop::{
+        WTSActive, WTSEnumerateSessionsW, WTSFreeMemory, WTS_CURRENT_SERVER_HANDLE, WTS_SESSION_INFOW,
+    };
+
+    let mut info: *mut WTS_SESSION_INFOW = std::ptr::null_mut();
+    let mut count = 0u32;
+    // SAFETY: on success `info` points to `count` entries, freed below
+    unsafe {
+        if WTSEnumerateSessionsW(WTS_CURRENT_SERVER_HANDLE, 0, 1, &mut info, &mut count) == 0 {
+            log::warn!("enumerate sessions: {}", std::io::Error::last_os_error());
+            return None;
+        }
+        let sessions = std::slice::from_raw_parts(info, count as usize);
+        let active = sessions.iter().filter(|s| s.State == WTSActive).count();
+        WTSFreeMemory(info.cast());
+        Some(active as f64)
+    }
+}
+
+/// Logged-in users per utmp (terminals, SSH and desktop sessions)
+#[cfg(unix)]
+fn sample_sessions() -> Option<f64> {
+    let mut active = 0;
+    // SAFETY: utmpx iteration is not thread-safe, but only the collector calls it
+    unsafe {
+        libc::setutxent();
+        loop {
+            let entry = libc::getutxent();
+            if entry.is_null() {
+                break;
+            }
+            if (*entry).ut_type == libc::USER_PROCESS {
+                active += 1;
+            }
+        }
+        libc::endutxent();
+    }
+    Some(active as f64)
+}
+
+/// CPU, with context switch and interrupt rates and per-app usage over the same window
+fn sample_cpu(config: &Config, sys: &mut System, cpu_window: Duration) -> Vec<(String, f64)> {
+    let mut out = Vec::new();
+    #[cfg(target_os = "linux")]
+    let rates_start = (Instant::now(), proc_stat_counts());
+    #[cfg(target_os = "linux")]
+    let steal_start = virt::is_guest().then(virt::cpu_times).flatten();
+    let app_usage = ProcessRefreshKind::new().with_cpu().with_memory();
+    if !config.apps.is_empty() {
+        sys.refresh_processes_specifics(app_usage);
+    }
+    out.push(("CPU".to_string(), sample_cpu_percent(sys, cpu_window)));
+    #[cfg(target_os = "linux")]
+    out.extend(sample_system_rates(rates_start));
+    #[cfg(target_os = "linux")]
+    if let Some(steal) = virt::steal_percent(steal_start) {
+        out.push((virt::CPU_STEAL.to_string(), steal));
+    }
+    if !config.apps.is_empty() {
+        sys.refresh_processes_specifics(app_usage);
+        out.extend(apps::sample(sys, &config.apps));
+    }
+    out
+}
+
+fn sample_system(sys: &mut System) -> Vec<(String, f64)> {
+    // Process (and on Windows handle) counts, for spotting leaks over weeks
+    let mut out = vec![("PROCS".to_string(), sample_process_count(sys))];
+    #[cfg(windows)]
+    if let Some(handles) = sample_handle_count() {
+        out.push(("HANDLES".to_string(), handles));
+    }
+    #[cfg(unix)]
+    out.extend(sample_load_average());
+
+    // Logged-on users, for correlating load on terminal servers
+    if let Some(sessions) = sample_sessions() {
+        out.push(("SESSIONS".to_string(), sessions));
+    }
+
+    // Pending reboot and days since the last update, for patch compliance
+    out.extend(patches::sample());
+    out
+}
+
+/// Groups of metrics that `[intervals]` can sample at a cadence of their own
+/// under `collect --every`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Class {
+    /// `CPU`, its rates and steal, and `[apps]`, all measured over one window
+    Cpu,
+    /// `RAM` and its breakdown
+    Memory,
+    /// Process and handle counts, load, sessions and patch state
+    System,
+    /// Local volumes
+    Disks,
+    Shares,
+    Services,
+    /// `[[ping]]` and `[[http]]`
+    Probes,
+    /// `[[perf_counters]]` and `[[wmi]]`
+    Counters,
+    Exec,
+    Smart,
+}
+
+impl Class {
+    pub const ALL: [Class; 10] = [
+        Class::Cpu,
+        Class::Memory,
+        Class::System,
+        Class::Disks,
+        Class::Shares,
+        Class::Services,
+        Class::Probes,
+        Class::Counters,
+        Class::Exec,
+        Class::Smart,
+    ];
+
+    /// The key under `[intervals]`
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Cpu => "cpu",
+            Class::Memory => "memory",
+            Class::System => "system",
+            Class::Disks => "disks",
+            Class::Shares => "shares",
+            Class::Services => "services",
+            Class::Probes => "probes",
+            Class::Counters => "counters",
+            Class::Exec => "exec",
+            Class::Smart => "smart",
+        }
+    }
+}
+
+/// Take one reading of every metric as (table, value) pairs
+pub fn sample_all(config: &Config) -> Vec<(String, f64)> {
+    sample_all_within(config, Duration::from_millis(CPU_SAMPLE_MS), &Class::ALL)
+}
+
+/// As [`sample_all`] for the `classes` given, measuring CPU over
+/// `cpu_window` so sub-second bursts keep their schedule
+fn sample_all_within(config: &Config, cpu_window: Duration, classes: &[Class]) -> Vec<(String, f64)> {
+    let mut out = Vec::new();
 
     // Ask sysinfo only for CPU + Memory; disks are read via `Disks`
     let mut sys = System::new_with_specifics(
@@ -95,35 +397,407 @@ pub fn run_collect(_debug: bool) -> Result<()> {
             .with_memory(MemoryRefreshKind::everything()),
     );
 
-    let ts = now_timestamp();
+    if classes.contains(&Class::Cpu) {
+        out.extend(sample_cpu(config, &mut sys, cpu_window));
+    }
+    if classes.contains(&Class::Memory) {
+        out.push(("RAM".to_string(), sample_ram_percent(&mut sys)));
+        out.extend(sample_memory_breakdown());
+    }
+    if classes.contains(&Class::System) {
+        out.extend(sample_system(&mut sys));
+    }
+
+    // Disks (independent of `System`)
+    if classes.contains(&Class::Disks) {
+        for vol in disks::list(&config.volume_names) {
+            // Inode exhaustion fills a disk long before the bytes run out
+            #[cfg(unix)]
+            if let Some(inode_pct) = sample_inode_percent(&vol.mount_point) {
+                out.push((format!("{}_INODES", vol.label), inode_pct));
+            }
+            // NTFS has no inode limit, but a full volume squeezes out the MFT zone
+            #[cfg(windows)]
+            if let Some((mft_gb, zone_gb)) = sample_mft_gb(&vol.mount_point) {
+                out.push((format!("{}_MFT_GB", vol.label), mft_gb));
+                out.push((format!("{}_MFT_ZONE_GB", vol.label), zone_gb));
+            }
 
-    // CPU
-    let cpu = sample_cpu_percent(&mut sys);
-    ensure_table(&conn, "CPU")?;
-    insert_sample(&conn, "CPU", &ts, cpu)?;
+            out.push((vol.label.clone(), vol.used_percent()));
+            // "8% free" means little without knowing the size of the disk
+            out.push((format!("{}_FREE_GB", vol.label), vol.free_gb()));
+        }
+    }
 
-    // RAM
-    let ram_used_pct = sample_ram_percent(&mut sys);
-    ensure_table(&conn, "RAM")?;
-    insert_sample(&conn, "RAM", &ts, ram_used_pct)?;
+    // Network shares, when enabled
+    if classes.contains(&Class::Shares) {
+        out.extend(shares::sample(&config.shares));
+    }
 
-    // Disks (independent of `System`)
-    let disks = Disks::new_with_refreshed_list();
-    for d in disks.list() {
-        let total = d.total_space() as f64;
-        let avail = d.available_space() as f64;
-        if total <= 0.0 {
-            continue;
+    // Configured services, 1 = running
+    if classes.contains(&Class::Services) {
+        out.extend(services::sample(&config.services));
+    }
+
+    // Network round-trip times
+    if classes.contains(&Class::Probes) {
+        out.extend(probes::sample(&config.ping));
+        out.extend(probes::sample_http(&config.http));
+    }
+
+    // User-listed Windows performance counters and WMI queries
+    if classes.contains(&Class::Counters) {
+        out.extend(perf::sample(&config.perf_counters));
+        out.extend(wmi::sample(&config.wmi));
+    }
+
+    // Site-specific metrics from external commands
+    if classes.contains(&Class::Exec) {
+        out.extend(exec::sample(&config.exec));
+    }
+
+    // Physical disk health, when smartctl is available and enabled
+    if classes.contains(&Class::Smart) {
+        out.extend(smart::sample(&config.smart));
+    }
+
+    // NaN is stored as NULL, which the NOT NULL value column rejects along
+    // with the rest of the run
+    out.retain(|(table, value)| {
+        if !value.is_finite() {
+            log::warn!("{} skipped: {} is not a finite number", table, value);
+        }
+        value.is_finite()
+    });
+    out
+}
+
+/// When the previous run wrote to `db_name`, if it exists
+fn previous_run(db_name: &str) -> Option<i64> {
+    let path = Path::new(db_name);
+    if !path.exists() {
+        return None;
+    }
+    backend::open_read_only(path).ok()?.last_ts().ok()?
+}
+
+/// One collection run, as written to its monthly database
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Run {
+    pub db_name: String,
+    pub ts: i64,
+    /// Milliseconds past `ts`, kept for sub-second bursts
+    #[serde(default)]
+    pub millis: u32,
+    pub node: String,
+    pub cluster: Option<String>,
+    pub role: Option<String>,
+    /// Runs queued by older builds have none
+    #[serde(default)]
+    pub host_label: Option<String>,
+    /// Hypervisor or container the collector runs in, from [`virt::detect`]
+    #[serde(default)]
+    pub virtualization: Option<String>,
+    /// Milliseconds sampling took; runs that have it get a `SELFMON` row
+    #[serde(default)]
+    pub sample_ms: Option<f64>,
+    /// Number the run in each table's `Seq` column
+    #[serde(default)]
+    pub sequence: bool,
+    /// Only some metric classes were due, so metrics the previous run had
+    /// are not reported missing
+    #[serde(default)]
+    pub partial: bool,
+    pub samples: Vec<(String, f64)>,
+}
+
+/// Write a run in one transaction, so a failure never leaves half the metrics
+/// written. Returns the SMART alerts it triggered.
+pub fn write_run(conn: &mut Connection, run: &Run) -> Result<Vec<String>> {
+    let started = Instant::now();
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    // Read before record_clock moves it on to this run
+    let previous = storage::get_meta(&tx, "last_ts")?.and_then(|v| v.parse::<i64>().ok());
+    storage::record_timezone(&tx)?;
+    if let Some(back) = storage::record_clock(&tx, run.ts)? {
+        // DST never moves epoch time, so this is a clock correction (NTP, VM resume)
+        log::warn!("clock went back {}s since the previous sample in {}; samples keep collection order", back, run.db_name);
+    }
+    let seq = if run.sequence { Some(storage::next_seq(&tx)?) } else { None };
+    if let Some(platform) = &run.virtualization {
+        storage::set_meta(&tx, "virtualization", platform)?;
+    }
+    if let Some(role) = &run.role {
+        storage::set_meta(&tx, "role", role)?;
+        if let Some(cluster) = &run.cluster {
+            storage::set_meta(&tx, "cluster", cluster)?;
+        }
+        storage::record_node(&tx, run.ts, &run.node)?;
+    } else if let Some(label) = &run.host_label {
+        // Which machine (or VDI clone) stood behind the label at each sample
+        storage::set_meta(&tx, "host_label", label)?;
+        storage::record_node(&tx, run.ts, &run.node)?;
+    }
+    let missing = match previous {
+        Some(p) if run.sample_ms.is_some() && !run.partial && p != run.ts => selfmon::missing(&tx, p, &run.samples)?,
+        _ => Vec::new(),
+    };
+    let mut alerts = Vec::new();
+    for (table, value) in &run.samples {
+        ensure_table(&tx, table)?;
+        if smart::is_smart_metric(table) {
+            alerts.extend(smart::alert(table, *value, storage::last_value(&tx, table)?));
+        }
+        let text = storage::has_text_timestamps(&tx, table)?;
+        insert_sample_ms(&tx, table, run.ts * 1000 + run.millis as i64, *value, seq, text)?;
+    }
+    tx.commit()?;
+    // After the commit, so the write time includes flushing it to disk
+    if let Some(sample_ms) = run.sample_ms {
+        let write_ms = started.elapsed().as_secs_f64() * 1000.0;
+        if let Err(e) = selfmon::record(conn, run.ts, sample_ms, write_ms, run.samples.len(), &missing) {
+            log::warn!("{} not updated: {:#}", storage::SELFMON_TABLE, e);
+        }
+    }
+    Ok(alerts)
+}
+
+/// What `collect --output json` prints
+#[derive(Default, Serialize)]
+struct Collected {
+    database: String,
+    /// Time of the (last) sample
+    ts: String,
+    /// Samples taken, or queued records delivered with --spool-dir
+    records: usize,
+    /// Rows inserted, one per metric of each record
+    rows: usize,
+    /// Records left in the --spool-dir queue
+    #[serde(skip_serializing_if = "Option::is_none")]
+    queued: Option<usize>,
+    /// Worst threshold rating of the samples taken
+    status: Status,
+    /// SMART alerts raised
+    alerts: Vec<String>,
+    /// Retention deletes and roll-ups done after the write
+    pruned: Vec<String>,
+    /// Charts of a burst with --graph
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    charts: Vec<PathBuf>,
+}
+
+/// `--count`/`--duration`: samples `--interval` apart into a database of their
+/// own, so a burst of high-frequency readings never mixes with the monthly series
+fn run_burst(args: &CollectArgs, config: &Config, id: &identity::Identity) -> Result<Status> {
+    let interval = timespan::parse_ms(&args.interval)?;
+    let count = match (args.count, &args.duration) {
+        (Some(n), _) => n.max(1) as i64,
+        (None, Some(d)) => (timespan::parse_ms(d)? / interval).max(1),
+        (None, None) => unreachable!("--interval requires --count or --duration"),
+    };
+    let started = Local::now();
+    let db_name = format!("{}@{}@{}.sqlite", ADHOC_PREFIX, id.series, started.format("%Y%m%dT%H%M%S"));
+    let aliases = Aliases::load(Path::new("."))?;
+    let mut conn = storage::open_for_collection(Path::new(&db_name))?;
+    log::info!("taking {} samples {} apart into {}", count, args.interval, db_name);
+    // Half the interval at most, so CPU measuring leaves time for the rest
+    let cpu_window = Duration::from_millis((interval as u64 / 2).min(CPU_SAMPLE_MS)).max(MINIMUM_CPU_UPDATE_INTERVAL);
+
+    let virtualization = virt::detect();
+    let progress = Progress::new("Sampling", count as usize);
+    let mut collected = Collected { database: db_name.clone(), records: count as usize, ..Default::default() };
+    let begin = Instant::now();
+    for i in 0..count {
+        // Keep to the schedule even though sampling itself takes time
+        if let Some(wait) = (Duration::from_millis((i * interval) as u64)).checked_sub(begin.elapsed()) {
+            sleep(wait);
+        }
+        let now = Local::now();
+        let sampling = Instant::now();
+        let samples = sample_all_within(config, cpu_window, &Class::ALL).into_iter().map(|(table, value)| (aliases.resolve(&table), value)).collect();
+        let run = Run {
+            db_name: db_name.clone(),
+            ts: now.timestamp(),
+            millis: now.timestamp_subsec_millis(),
+            node: id.node.clone(),
+            cluster: id.cluster.clone(),
+            role: id.role.clone(),
+            host_label: id.host_label.clone(),
+            virtualization: virtualization.clone(),
+            sample_ms: Some(sampling.elapsed().as_secs_f64() * 1000.0),
+            sequence: config.sequence,
+            partial: false,
+            samples,
+        };
+        for message in write_run(&mut conn, &run)? {
+            smart::raise(&config.smart, &message);
+            collected.alerts.push(message);
+        }
+        collected.rows += run.samples.len();
+        collected.status = collected.status.max(check::worst(config, &run.samples));
+        collected.ts = storage::format_ts_ms(run.ts * 1000 + run.millis as i64);
+        progress.tick();
+    }
+    progress.finish();
+    if !args.json {
+        println!("Wrote {} samples into {}", count, db_name);
+    }
+
+    if args.graph {
+        drop(conn);
+        collected.charts = graph::graph_file(Path::new(&db_name), config)?;
+        if !args.json {
+            for chart in &collected.charts {
+                println!("Wrote {}", chart.display());
+            }
+        }
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&collected)?);
+    }
+    Ok(collected.status)
+}
+
+/// Entry point for `winbox-stats collect`; returns the worst threshold rating
+/// of the samples taken, for the exit code
+pub fn run_collect(args: &CollectArgs, config: &Config) -> Result<Status> {
+    let id = identity::resolve(&identity::Naming {
+        cluster_role: args.cluster_role.as_deref(),
+        host_label: args.host_label.as_deref().or(config.host_label.as_deref()),
+        instance: args.instance.as_deref().or(config.instance.as_deref()),
+    })?;
+    // Held until the run returns; a dry run writes nothing, so it needs none
+    let _lock = if args.dry_run {
+        None
+    } else {
+        let wait = args.wait.as_deref().map(timespan::parse).transpose()?.unwrap_or(0);
+        Some(lock::acquire(Path::new("."), &id.series, wait)?)
+    };
+    // Before sampling, so a volume seen for the first time is labelled from the record
+    if !args.dry_run {
+        disks::remember(Path::new("."), &config.volume_names)?;
+    }
+    if args.count.is_some() || args.duration.is_some() {
+        return run_burst(args, config, &id);
+    }
+    collect_once(args, config, &id, &Class::ALL)
+}
+
+/// One sample of `classes` into this month's database, then retention, push
+/// and statsd; the caller holds the lock
+pub fn collect_once(args: &CollectArgs, config: &Config, id: &identity::Identity, classes: &[Class]) -> Result<Status> {
+    let db_name = monthly_db_name(&id.series, config.storage.backend);
+
+    // Sample before touching the DB so the write lock is held only briefly
+    let ts = now_timestamp();
+    let aliases = Aliases::load(Path::new("."))?;
+    // Event counts cover the time since the previous run; a new file has none
+    let sampling = Instant::now();
+    let events = previous_run(&db_name).map(eventlog::sample).unwrap_or_default();
+    let samples: Vec<(String, f64)> = sample_all_within(config, Duration::from_millis(CPU_SAMPLE_MS), classes)
+        .into_iter()
+        .chain(events)
+        .map(|(table, value)| (aliases.resolve(&table), value))
+        .collect();
+
+    let sample_ms = sampling.elapsed().as_secs_f64() * 1000.0;
+    for (table, value) in &samples {
+        log::debug!("{} = {:.2}", table, value);
+    }
+    let status = check::worst(config, &samples);
+    if args.dry_run && args.json {
+        let samples: BTreeMap<&str, f64> = samples.iter().map(|(table, value)| (table.as_str(), *value)).collect();
+        let would = serde_json::json!({ "database": db_name, "ts": storage::format_ts(ts), "samples": samples, "push": args.push });
+        println!("{}", serde_json::to_string_pretty(&would)?);
+        return Ok(status);
+    }
+    if args.dry_run {
+        println!("Would write {} metrics into {} at {}:", samples.len(), db_name, storage::format_ts(ts));
+        for (table, value) in &samples {
+            println!("  {} = {:.2}", table, value);
+        }
+        if let Some(url) = &args.push {
+            println!("Would queue them for {}", url);
         }
-        let used_pct = (1.0 - (avail / total)) * 100.0;
+        if let Some(addr) = &args.statsd {
+            println!("Would mirror them to {}", addr);
+        }
+        return Ok(status);
+    }
 
-        let mp = d.mount_point().to_string_lossy().to_string();
-        let label = label_for_mount_point(&mp);
+    let run = Run {
+        db_name,
+        ts,
+        millis: 0,
+        node: id.node.clone(),
+        cluster: id.cluster.clone(),
+        role: id.role.clone(),
+        host_label: id.host_label.clone(),
+        virtualization: virt::detect(),
+        sample_ms: Some(sample_ms),
+        sequence: config.sequence,
+        partial: classes.len() < Class::ALL.len(),
+        samples,
+    };
+    let mut collected = Collected { database: run.db_name.clone(), ts: storage::format_ts(ts), status, ..Default::default() };
+    if let Some(dir) = &args.spool_dir {
+        let delivered = spool::write(dir, &run, &config.smart)?;
+        if !args.json && delivered.queued == 0 {
+            if delivered.records > 1 {
+                println!("Wrote {} queued records, the last into {} at {}", delivered.records, run.db_name, collected.ts);
+            } else {
+                println!("Wrote record into {} at {}", run.db_name, collected.ts);
+            }
+        }
+        collected.records = delivered.records;
+        collected.rows = delivered.rows;
+        collected.queued = Some(delivered.queued);
+        collected.alerts = delivered.alerts;
+    } else {
+        let mut db = backend::open_for_collection(Path::new(&run.db_name))?;
+        for message in db.write_run(&run)? {
+            smart::raise(&config.smart, &message);
+            collected.alerts.push(message);
+        }
+        collected.records = 1;
+        collected.rows = run.samples.len();
+        if !args.json {
+            println!("Wrote record into {} at {}", run.db_name, collected.ts);
+        }
+    }
+    let samples = run.samples;
 
-        ensure_table(&conn, &label)?;
-        insert_sample(&conn, &label, &ts, used_pct)?;
+    // After the write, so a slow roll-up never delays or loses a sample
+    match retention::maintain(Path::new("."), &id.series, &config.retention, ts) {
+        Ok(pruned) => collected.pruned = pruned,
+        Err(e) => log::warn!("retention: {:#}", e),
     }
 
-    println!("Wrote record into {} at {}", db_name, ts);
-    Ok(())
+    // The local DB is the source of truth; a push failure only delays delivery
+    if let Some(url) = &args.push {
+        let target = PushTarget {
+            url,
+            format: args.push_format,
+            batch_size: args.push_batch.max(1),
+            max_queue: args.push_max_queue,
+        };
+        if let Err(e) = push::push_samples(Path::new("."), &target, &id.series, ts, &samples) {
+            log::warn!("{:#}", e);
+        }
+    }
+    if let Some(pg) = &config.postgres {
+        if let Err(e) = pgsink::write(Path::new("."), pg, &id.series, ts, &samples) {
+            log::warn!("{:#}", e);
+        }
+    }
+    if let Some(addr) = &args.statsd {
+        let target = StatsdTarget { addr, format: args.statsd_format, prefix: &args.statsd_prefix };
+        if let Err(e) = statsd::send(&target, &id.series, ts, &samples) {
+            log::warn!("statsd: {:#}", e);
+        }
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&collected)?);
+    }
+    Ok(status)
 }
@@ -0,0 +1,229 @@
+// src/config.rs
+//! Optional settings file: `winbox-stats.toml` in the working directory, or
+//! the path given with `--config`.
+//!
+//! ```toml
+//! host_label = "VDI-POOL-A"
+//! services = ["MSSQLSERVER", "W3SVC"]
+//!
+//! [[ping]]
+//! host = "gateway"
+//!
+//! [[http]]
+//! name = "intranet"
+//! url = "https://intranet.corp.local/health"
+//!
+//! [[perf_counters]]
+//! label = "SQL_PLE"
+//! path = '\SQLServer:Buffer Manager\Page life expectancy'
+//!
+//! [[wmi]]
+//! label = "CPU_LOAD"
+//! query = "SELECT LoadPercentage FROM Win32_Processor"
+//!
+//! [[exec]]
+//! label = "QUEUE"
+//! command = 'C:\Scripts\queue-depth.exe'
+//!
+//! [apps]
+//! IIS = ["w3wp"]
+//!
+//! [shares]
+//! enabled = true
+//!
+//! [smart]
+//! enabled = true
+//!
+//! [retention]
+//! enabled = true
+//!
+//! [intervals]
+//! disks = "10m"
+//! smart = "1h"
+//!
+//! [charts]
+//! footer = "Example Corp managed services"
+//!
+//! [storage]
+//! backend = "sqlite"
+//!
+//! [postgres]
+//! url = "host=historian.corp.local user=stats dbname=metrics"
+//!
+//! [smtp]
+//! host = "relay.corp.local"
+//! username = "svc-stats"
+//! password = "..."
+//! from = "winbox-stats@corp.local"
+//! to = ["ops@corp.local"]
+//!
+//! [volume_names]
+//! "D:" = "DATA"
+//! '\\?\Volume{2d6f5b3e-0000-0000-0000-100000000000}\' = "BACKUP"
+//!
+//! [thresholds]
+//! CPU = { warning = 80, critical = 95 }
+//! "*_Drive" = { warning = 80, critical = 90 }
+//! ```
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::backend::StorageConfig;
+use crate::daemon::IntervalsConfig;
+use crate::exec::ExecCommand;
+use crate::graph::style::ChartsConfig;
+use crate::notify::SmtpConfig;
+use crate::perf::PerfCounter;
+use crate::pgsink::PostgresConfig;
+use crate::probes::{HttpProbe, PingProbe};
+use crate::retention::RetentionConfig;
+use crate::shares::SharesConfig;
+use crate::smart::SmartConfig;
+use crate::wmi::WmiQuery;
+
+pub const CONFIG_FILE: &str = "winbox-stats.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Name databases after this instead of the computer name
+    pub host_label: Option<String>,
+    /// Tag appended to the database name, for several collectors on one machine
+    pub instance: Option<String>,
+    /// Number every collection run in a `Seq` column, so the order samples were
+    /// taken survives the clock going back
+    pub sequence: bool,
+    /// Metric name or `*` pattern -> alert levels
+    pub thresholds: BTreeMap<String, Threshold>,
+    /// Application name -> process name patterns, collected as `APP_<name>_CPU`
+    /// and `APP_<name>_MEM`
+    pub apps: BTreeMap<String, Vec<String>>,
+    /// Services whose up/down state is collected as `SVC_<name>`
+    pub services: Vec<String>,
+    /// Round-trip time probes, one table each
+    pub ping: Vec<PingProbe>,
+    /// HTTP(S) GET probes
+    pub http: Vec<HttpProbe>,
+    /// Windows performance counters, one table each
+    pub perf_counters: Vec<PerfCounter>,
+    /// WMI queries returning one number each
+    pub wmi: Vec<WmiQuery>,
+    /// External commands whose output is recorded
+    pub exec: Vec<ExecCommand>,
+    /// Mount point or volume ID -> name to record the volume under, e.g.
+    /// `"D:" = "DATA"` for `DATA_Drive`
+    pub volume_names: BTreeMap<String, String>,
+    /// Mapped network drives and NFS/SMB mounts as `SHARE_*`
+    pub shares: SharesConfig,
+    /// Disk health through smartctl
+    pub smart: SmartConfig,
+    /// Raw, 5-minute and hourly tiers kept in the monthly databases
+    pub retention: RetentionConfig,
+    /// Slower cadences for some metric classes under `collect --every`
+    pub intervals: IntervalsConfig,
+    /// Chart title, footer, logo and font
+    pub charts: ChartsConfig,
+    /// Database format the collector writes
+    pub storage: StorageConfig,
+    /// Also write every run's samples to a PostgreSQL/TimescaleDB table
+    pub postgres: Option<PostgresConfig>,
+    /// Mail relay for `report --email` and `check --email`
+    pub smtp: Option<SmtpConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    pub warning: Option<f64>,
+    pub critical: Option<f64>,
+}
+
+/// Case-insensitive match where `*` stands for any run of characters
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p = pattern.to_ascii_uppercase();
+    let n = name.to_ascii_uppercase();
+    let parts: Vec<&str> = p.split('*').collect();
+    if parts.len() == 1 {
+        return p == n;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !n.starts_with(first) || !n[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &n[first.len()..n.len() - last.len()];
+    for mid in &parts[1..parts.len() - 1] {
+        match rest.find(mid) {
+            Some(i) => rest = &rest[i + mid.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Exact key first, otherwise the most specific (longest) matching pattern
+fn lookup<'a, T>(map: &'a BTreeMap<String, T>, name: &str) -> Option<&'a T> {
+    map.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .or_else(|| {
+            map.iter()
+                .filter(|(k, _)| k.contains('*') && glob_match(k, name))
+                .max_by_key(|(k, _)| k.len())
+        })
+        .map(|(_, v)| v)
+}
+
+/// Settings that differ between two versions of the file, as `services
+/// changed`, `apps.SQL added` or `thresholds.CPU removed`; values are left
+/// out, as they may be passwords
+pub fn changes(old: &toml::Table, new: &toml::Table) -> Vec<String> {
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    let mut out = Vec::new();
+    for key in keys {
+        match (old.get(key), new.get(key)) {
+            (Some(a), Some(b)) if a == b => {}
+            (Some(toml::Value::Table(a)), Some(toml::Value::Table(b))) => {
+                out.extend(changes(a, b).into_iter().map(|c| format!("{}.{}", key, c)));
+            }
+            (None, Some(_)) => out.push(format!("{} added", key)),
+            (Some(_), None) => out.push(format!("{} removed", key)),
+            _ => out.push(format!("{} changed", key)),
+        }
+    }
+    out
+}
+
+impl Config {
+    /// An explicit path must exist; the default file is optional.
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let Some(text) = Config::read(path)? else { return Ok(Config::default()) };
+        toml::from_str(&text).with_context(|| format!("parse {}", Config::path(path).display()))
+    }
+
+    /// The file's settings as plain TOML, for [`changes`]; empty without a file
+    pub fn load_table(path: Option<&Path>) -> Result<toml::Table> {
+        let Some(text) = Config::read(path)? else { return Ok(toml::Table::new()) };
+        toml::from_str(&text).with_context(|| format!("parse {}", Config::path(path).display()))
+    }
+
+    /// The file `load` reads: the explicit path, or the default one
+    pub fn path(path: Option<&Path>) -> &Path {
+        path.unwrap_or(Path::new(CONFIG_FILE))
+    }
+
+    fn read(path: Option<&Path>) -> Result<Option<String>> {
+        if path.is_none() && !Path::new(CONFIG_FILE).exists() {
+            return Ok(None);
+        }
+        let path = Config::path(path);
+        fs::read_to_string(path).map(Some).with_context(|| format!("read {}", path.display()))
+    }
+
+    pub fn threshold_for(&self, metric: &str) -> Option<Threshold> {
+        lookup(&self.thresholds, metric).copied()
+    }
+}
@@ -0,0 +1,114 @@
+// src/crypto.rs
+//! Optional database encryption (SQLCipher, in builds with the `encryption`
+//! feature).
+//!
+//! The passphrase comes from the `WINBOX_STATS_KEY` environment variable or,
+//! on Windows, from `winbox-stats.key` next to the databases: a DPAPI blob
+//! written by `protect-key` that only this machine can decrypt. Databases
+//! created while a key is set are encrypted; older plain files stay readable.
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use std::path::Path;
+
+pub const KEY_ENV: &str = "WINBOX_STATS_KEY";
+
+/// DPAPI-protected passphrase (Windows)
+pub const KEY_FILE: &str = "winbox-stats.key";
+
+/// Passphrase for databases in `dir`, if encryption is configured
+pub fn database_key(dir: &Path) -> Result<Option<String>> {
+    if let Ok(key) = std::env::var(KEY_ENV) {
+        if !key.is_empty() {
+            return Ok(Some(key));
+        }
+    }
+    let path = dir.join(KEY_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let blob = std::fs::read(&path).with_context(|| format!("read {}", path.display()))?;
+    let key = dpapi::unprotect(&blob).with_context(|| format!("decrypt {}", path.display()))?;
+    Ok(Some(String::from_utf8(key).with_context(|| format!("decrypt {}", path.display()))?))
+}
+
+#[cfg(feature = "encryption")]
+pub fn apply_key(conn: &Connection, key: &str) -> Result<()> {
+    conn.pragma_update(None, "key", key)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "encryption"))]
+pub fn apply_key(_conn: &Connection, _key: &str) -> Result<()> {
+    anyhow::bail!(
+        "a database key is configured ({} or {}) but this build has no encryption support; rebuild with --features encryption",
+        KEY_ENV,
+        KEY_FILE
+    )
+}
+
+#[cfg(windows)]
+mod dpapi {
+    use anyhow::Result;
+    use std::io;
+    use windows_sys::Win32::Foundation::LocalFree;
+    use windows_sys::Win32::Security::Cryptography::{
+        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_LOCAL_MACHINE, CRYPTPROTECT_UI_FORBIDDEN,
+        CRYPT_INTEGER_BLOB,
+    };
+
+    fn run(data: &[u8], protect: bool) -> Result<Vec<u8>> {
+        let input = CRYPT_INTEGER_BLOB { cbData: data.len() as u32, pbData: data.as_ptr() as *mut u8 };
+        let mut output = CRYPT_INTEGER_BLOB { cbData: 0, pbData: std::ptr::null_mut() };
+        // Machine scope: the collector's service account and administrators can both read it
+        let flags = CRYPTPROTECT_LOCAL_MACHINE | CRYPTPROTECT_UI_FORBIDDEN;
+        // SAFETY: `input` borrows `data` for the call; `output` is allocated by DPAPI and freed below
+        unsafe {
+            let ok = if protect {
+                CryptProtectData(&input, std::ptr::null(), std::ptr::null(), std::ptr::null(), std::ptr::null(), flags, &mut output)
+            } else {
+                CryptUnprotectData(&input, std::ptr::null_mut(), std::ptr::null(), std::ptr::null(), std::ptr::null(), flags, &mut output)
+            };
+            if ok == 0 {
+                anyhow::bail!("DPAPI: {}", io::Error::last_os_error());
+            }
+            let out = std::slice::from_raw_parts(output.pbData, output.cbData as usize).to_vec();
+            LocalFree(output.pbData.cast());
+            Ok(out)
+        }
+    }
+
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>> {
+        run(data, true)
+    }
+
+    pub fn unprotect(blob: &[u8]) -> Result<Vec<u8>> {
+        run(blob, false)
+    }
+}
+
+#[cfg(not(windows))]
+mod dpapi {
+    use anyhow::Result;
+
+    pub fn protect(_data: &[u8]) -> Result<Vec<u8>> {
+        anyhow::bail!("{} needs Windows DPAPI; set {} instead", super::KEY_FILE, super::KEY_ENV)
+    }
+
+    pub fn unprotect(_blob: &[u8]) -> Result<Vec<u8>> {
+        protect(&[])
+    }
+}
+
+/// Entry point for `winbox-stats protect-key`: passphrase on stdin -> DPAPI key file here
+pub fn run_protect_key() -> Result<()> {
+    let mut key = String::new();
+    std::io::stdin().read_line(&mut key).context("read passphrase from stdin")?;
+    let key = key.trim_end_matches(['\r', '\n']);
+    if key.is_empty() {
+        anyhow::bail!("empty passphrase");
+    }
+    let blob = dpapi::protect(key.as_bytes())?;
+    std::fs::write(KEY_FILE, blob).with_context(|| format!("write {}", KEY_FILE))?;
+    println!("Wrote {}; new databases here will be encrypted", KEY_FILE);
+    Ok(())
+}
@@ -0,0 +1,425 @@
+// src/daemon.rs
+//! `collect --every SPAN`: the collector as one long-running process, for a
+//! service wrapper or a systemd `Type=simple` unit instead of a scheduled task.
+//!
+//! A stop request (Ctrl+C, SIGTERM, closing the console, logoff or shutdown)
+//! is only acted on between samples: the sample in flight is finished and
+//! written, the WAL is checkpointed back into the database, and the process
+//! exits 0. SIGHUP, or `winbox-stats reload` on Windows, re-reads the config
+//! before the next sample; so does saving the file. Added or removed probes,
+//! apps and counters, new thresholds and other settings apply from that
+//! sample on, and the log says which settings changed.
+//!
+//! `--align` puts samples on wall-clock multiples of `--every` (local time,
+//! so `1d` means midnight) and `--jitter` delays each one by a random part of
+//! its span.
+//!
+//! `[intervals]` samples some metric classes less often than `--every`, to
+//! keep the databases small while the fast classes still catch CPU spikes:
+//!
+//! ```toml
+//! [intervals]
+//! disks = "10m"
+//! smart = "1h"
+//! ```
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use chrono::Local;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cli::{CollectArgs, ReloadArgs};
+use crate::collect::{self, Class};
+use crate::config::{self, Config};
+use crate::disks;
+use crate::identity;
+use crate::lock;
+use crate::storage;
+use crate::timespan;
+
+/// How often the wait between samples looks for a stop request
+const POLL: Duration = Duration::from_millis(250);
+
+#[cfg(unix)]
+mod os {
+    use anyhow::Result;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use crate::lock;
+
+    pub struct Signals {
+        stop: Arc<AtomicBool>,
+        reload: Arc<AtomicBool>,
+    }
+
+    impl Signals {
+        pub fn install(_series: &str) -> Result<Signals> {
+            use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+            let (stop, reload) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+            for signal in [SIGTERM, SIGINT] {
+                signal_hook::flag::register(signal, Arc::clone(&stop))?;
+            }
+            signal_hook::flag::register(SIGHUP, Arc::clone(&reload))?;
+            Ok(Signals { stop, reload })
+        }
+
+        pub fn stop_requested(&self) -> bool {
+            self.stop.load(Ordering::SeqCst)
+        }
+
+        /// Clears the request
+        pub fn reload_requested(&self) -> bool {
+            self.reload.swap(false, Ordering::SeqCst)
+        }
+    }
+
+    pub fn request_reload(dir: &Path, series: &str) -> Result<()> {
+        anyhow::bail!(
+            "send the collector SIGHUP instead, e.g. `systemctl reload <unit>` or `kill -HUP <pid>`; its pid is in {}",
+            lock::lock_path(dir, series).display()
+        )
+    }
+}
+
+#[cfg(windows)]
+mod os {
+    use anyhow::{bail, Result};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use windows_sys::Win32::Foundation::{CloseHandle, BOOL, HANDLE, WAIT_OBJECT_0};
+    use windows_sys::Win32::System::Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT};
+    use windows_sys::Win32::System::Threading::{CreateEventW, OpenEventW, SetEvent, WaitForSingleObject, EVENT_MODIFY_STATE};
+
+    static STOP: AtomicBool = AtomicBool::new(false);
+    /// Set once the daemon has checkpointed and is about to return
+    static STOPPED: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "system" fn on_console_event(kind: u32) -> BOOL {
+        STOP.store(true, Ordering::SeqCst);
+        // Close, logoff and shutdown end the process as soon as this returns,
+        // so hold on while the sample in flight is written (Windows allows ~5 s)
+        if kind != CTRL_C_EVENT && kind != CTRL_BREAK_EVENT {
+            let deadline = Instant::now() + Duration::from_millis(4500);
+            while !STOPPED.load(Ordering::SeqCst) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+        1
+    }
+
+    /// `Global\` reaches a collector running as a service from an
+    /// administrator's session but needs a privilege most users lack
+    fn event_names(series: &str) -> [Vec<u16>; 2] {
+        ["Global", "Local"].map(|ns| format!(r"{}\winbox-stats-reload-{}", ns, series).encode_utf16().chain(Some(0)).collect())
+    }
+
+    pub struct Signals {
+        reload: HANDLE,
+    }
+
+    impl Signals {
+        pub fn install(series: &str) -> Result<Signals> {
+            // SAFETY: the handler only touches atomics
+            if unsafe { SetConsoleCtrlHandler(Some(on_console_event), 1) } == 0 {
+                bail!("install the console control handler: {}", std::io::Error::last_os_error());
+            }
+            // Auto-reset, so each reload request is seen once
+            // SAFETY: the names are NUL-terminated; the handle is closed on drop
+            let reload = event_names(series)
+                .iter()
+                .map(|name| unsafe { CreateEventW(std::ptr::null(), 0, 0, name.as_ptr()) })
+                .find(|h| !h.is_null());
+            match reload {
+                Some(reload) => Ok(Signals { reload }),
+                None => bail!("create the reload event: {}", std::io::Error::last_os_error()),
+            }
+        }
+
+        pub fn stop_requested(&self) -> bool {
+            STOP.load(Ordering::SeqCst)
+        }
+
+        /// Clears the request
+        pub fn reload_requested(&self) -> bool {
+            // SAFETY: a valid event handle; a zero timeout only polls
+            unsafe { WaitForSingleObject(self.reload, 0) == WAIT_OBJECT_0 }
+        }
+    }
+
+    impl Drop for Signals {
+        fn drop(&mut self) {
+            STOPPED.store(true, Ordering::SeqCst);
+            // SAFETY: created in install and not closed elsewhere
+            unsafe { CloseHandle(self.reload) };
+        }
+    }
+
+    pub fn request_reload(_dir: &Path, series: &str) -> Result<()> {
+        for name in event_names(series) {
+            // SAFETY: the name is NUL-terminated; the handle is closed right after use
+            unsafe {
+                let event = OpenEventW(EVENT_MODIFY_STATE, 0, name.as_ptr());
+                if event.is_null() {
+                    continue;
+                }
+                let ok = SetEvent(event);
+                CloseHandle(event);
+                if ok == 0 {
+                    bail!("signal the collector: {}", std::io::Error::last_os_error());
+                }
+                return Ok(());
+            }
+        }
+        bail!("no `collect --every` is running for {}", series)
+    }
+}
+
+use os::Signals;
+
+/// Cadence per metric class; classes left out are sampled every `--every`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntervalsConfig {
+    pub cpu: Option<String>,
+    pub memory: Option<String>,
+    pub system: Option<String>,
+    pub disks: Option<String>,
+    pub shares: Option<String>,
+    pub services: Option<String>,
+    pub probes: Option<String>,
+    pub counters: Option<String>,
+    pub exec: Option<String>,
+    pub smart: Option<String>,
+}
+
+impl IntervalsConfig {
+    fn get(&self, class: Class) -> Option<&str> {
+        match class {
+            Class::Cpu => self.cpu.as_deref(),
+            Class::Memory => self.memory.as_deref(),
+            Class::System => self.system.as_deref(),
+            Class::Disks => self.disks.as_deref(),
+            Class::Shares => self.shares.as_deref(),
+            Class::Services => self.services.as_deref(),
+            Class::Probes => self.probes.as_deref(),
+            Class::Counters => self.counters.as_deref(),
+            Class::Exec => self.exec.as_deref(),
+            Class::Smart => self.smart.as_deref(),
+        }
+    }
+
+    fn parse(&self) -> Result<BTreeMap<Class, Duration>> {
+        let mut out = BTreeMap::new();
+        for class in Class::ALL {
+            if let Some(span) = self.get(class) {
+                let ms = timespan::parse_ms(span).with_context(|| format!("intervals.{}", class.name()))?;
+                out.insert(class, Duration::from_millis(ms as u64));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Classes whose interval has passed, give or take half a tick so a `10m`
+/// class on `30s` ticks is not pushed to the tick after
+fn due(intervals: &BTreeMap<Class, Duration>, last: &BTreeMap<Class, Instant>, now: Instant, every: Duration) -> Vec<Class> {
+    Class::ALL
+        .into_iter()
+        .filter(|class| match (intervals.get(class), last.get(class)) {
+            (Some(interval), Some(at)) => now.duration_since(*at) + every / 2 >= *interval,
+            _ => true,
+        })
+        .collect()
+}
+
+/// The config file as last loaded, to notice edits and say what they changed
+struct Watched<'a> {
+    path: Option<&'a Path>,
+    modified: Option<SystemTime>,
+    table: toml::Table,
+}
+
+impl<'a> Watched<'a> {
+    fn new(path: Option<&'a Path>) -> Watched<'a> {
+        let table = Config::load_table(path).unwrap_or_default();
+        Watched { path, modified: Watched::modified(path), table }
+    }
+
+    /// `None` while the file is missing, so creating it counts as a change
+    fn modified(path: Option<&Path>) -> Option<SystemTime> {
+        fs::metadata(Config::path(path)).and_then(|m| m.modified()).ok()
+    }
+
+    fn changed(&self) -> bool {
+        Watched::modified(self.path) != self.modified
+    }
+
+    fn reload(&mut self, config: &mut Config) {
+        self.modified = Watched::modified(self.path);
+        let loaded = Config::load(self.path).and_then(|c| {
+            c.intervals.parse()?;
+            Ok((c, Config::load_table(self.path)?))
+        });
+        match loaded {
+            Ok((c, table)) => {
+                let changes = config::changes(&self.table, &table);
+                *config = c;
+                self.table = table;
+                if changes.is_empty() {
+                    log::info!("configuration reloaded, nothing changed");
+                } else {
+                    log::info!("configuration reloaded: {}", changes.join(", "));
+                }
+            }
+            Err(e) => log::error!("configuration not reloaded, keeping the previous one: {:#}", e),
+        }
+    }
+}
+
+/// When samples are due
+struct Schedule {
+    every: Duration,
+    align: bool,
+    jitter: Duration,
+}
+
+impl Schedule {
+    /// Time left to the next wall-clock multiple of `every`
+    fn to_boundary(&self) -> Duration {
+        let now = Local::now();
+        let local_ms = now.timestamp_millis() + now.offset().local_minus_utc() as i64 * 1000;
+        let every_ms = self.every.as_millis().max(1) as i64;
+        Duration::from_millis((every_ms - local_ms.rem_euclid(every_ms)) as u64)
+    }
+
+    /// The first slot: now, or the next boundary
+    fn first(&self) -> Instant {
+        let now = Instant::now();
+        if self.align {
+            now + self.to_boundary()
+        } else {
+            now
+        }
+    }
+
+    /// The slot after `slot`; slots that have already passed are skipped,
+    /// not made up in a burst
+    fn after(&self, mut slot: Instant) -> Instant {
+        let now = Instant::now();
+        if self.align {
+            // Re-read the wall clock each time, so NTP steps are followed; a
+            // wake-up a hair early must not find the same boundary again
+            let next = now + self.to_boundary();
+            return if next < slot + self.every / 2 { next + self.every } else { next };
+        }
+        while slot <= now {
+            slot += self.every;
+        }
+        slot
+    }
+
+    /// When to sample for `slot`
+    fn sample_at(&self, slot: Instant) -> Instant {
+        if self.jitter.is_zero() {
+            return slot;
+        }
+        // std seeds every `RandomState` at random, which is all the spread needs
+        let random = RandomState::new().build_hasher().finish();
+        slot + Duration::from_millis(random % self.jitter.as_millis().max(1) as u64)
+    }
+}
+
+/// Move what the WAL holds into the database file, so a copy taken while the
+/// service is stopped is complete on its own
+fn checkpoint(db_name: &str) -> Result<()> {
+    let path = Path::new(db_name);
+    // DuckDB checkpoints its own files when they are closed
+    if !path.exists() || !storage::is_database(path) {
+        return Ok(());
+    }
+    let conn = storage::open_for_collection(path)?;
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+        .with_context(|| format!("checkpoint {}", path.display()))
+}
+
+/// Entry point for `winbox-stats collect --every`
+pub fn run_daemon(args: &CollectArgs, mut config: Config, config_path: Option<&Path>) -> Result<()> {
+    let dir = Path::new(".");
+    let every = args.every.as_deref().context("--every is required")?;
+    let interval = Duration::from_millis(timespan::parse_ms(every)?.max(1) as u64);
+    let jitter = args.jitter.as_deref().map(timespan::parse_ms).transpose()?.unwrap_or(0);
+    let schedule = Schedule { every: interval, align: args.align, jitter: Duration::from_millis(jitter as u64) };
+    if schedule.jitter >= interval {
+        anyhow::bail!("--jitter must be shorter than --every");
+    }
+    let id = identity::resolve(&identity::Naming {
+        cluster_role: args.cluster_role.as_deref(),
+        host_label: args.host_label.as_deref().or(config.host_label.as_deref()),
+        instance: args.instance.as_deref().or(config.instance.as_deref()),
+    })?;
+    // First, so the drop that releases a waiting console handler comes last
+    let signals = Signals::install(&id.series)?;
+    let wait = args.wait.as_deref().map(timespan::parse).transpose()?.unwrap_or(0);
+    let _lock = lock::acquire(dir, &id.series, wait)?;
+    let mut watched = Watched::new(config_path);
+    let mut intervals = config.intervals.parse()?;
+    log::info!("collecting every {} as {}; stop with Ctrl+C or SIGTERM", every, id.series);
+
+    let mut slot = schedule.first();
+    let mut last: BTreeMap<Class, Instant> = BTreeMap::new();
+    loop {
+        let at = schedule.sample_at(slot);
+        while !signals.stop_requested() && Instant::now() < at {
+            thread::sleep(POLL.min(at.saturating_duration_since(Instant::now())));
+        }
+        if signals.stop_requested() {
+            break;
+        }
+        // A half-saved file fails to parse and is retried on the next save
+        if signals.reload_requested() || watched.changed() {
+            watched.reload(&mut config);
+            intervals = config.intervals.parse()?;
+        }
+        if let Err(e) = disks::remember(dir, &config.volume_names) {
+            log::warn!("{:#}", e);
+        }
+        // Counted from the slot, so jitter does not shift the classes' cadence
+        let classes = due(&intervals, &last, slot, interval);
+        for class in &classes {
+            last.insert(*class, slot);
+        }
+        // One failed sample must not end the service; the next may well work
+        if let Err(e) = collect::collect_once(args, &config, &id, &classes) {
+            log::error!("collection failed: {:#}", e);
+        }
+        slot = schedule.after(slot);
+    }
+
+    if let Err(e) = checkpoint(&collect::monthly_db_name(&id.series, config.storage.backend)) {
+        log::warn!("{:#}", e);
+    }
+    log::info!("stopped");
+    Ok(())
+}
+
+/// Entry point for `winbox-stats reload`
+pub fn run_reload(args: &ReloadArgs, config: &Config) -> Result<()> {
+    let id = identity::resolve(&identity::Naming {
+        cluster_role: args.cluster_role.as_deref(),
+        host_label: args.host_label.as_deref().or(config.host_label.as_deref()),
+        instance: args.instance.as_deref().or(config.instance.as_deref()),
+    })?;
+    os::request_reload(Path::new("."), &id.series)?;
+    println!("Asked the collector of {} to reload its configuration", id.series);
+    Ok(())
+}
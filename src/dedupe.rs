@@ -0,0 +1,128 @@
+// src/dedupe.rs
+//! `dedupe`: remove the extra rows a double-fired scheduled task or a repeated
+//! import leaves behind. A row with the same time and value as an earlier one
+//! is a duplicate; a row with the same time but another value (or, with
+//! `--within`, a time too close to the previous row) is an overlap, and the
+//! row written first is kept.
+use anyhow::{Context, Result};
+use rusqlite::params;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::audit;
+use crate::cli::DedupeArgs;
+use crate::storage::{self, list_writable_tables, read_rows_between_ms};
+use crate::timespan;
+
+/// Rows removed from one table
+#[derive(Serialize)]
+struct Removed {
+    database: PathBuf,
+    table: String,
+    duplicates: usize,
+    overlaps: usize,
+}
+
+/// Row ids to delete, oldest first: (duplicates, overlaps); times in milliseconds
+fn extra_rows(rows: &[(i64, i64, f64)], within: i64) -> (Vec<i64>, Vec<i64>) {
+    let (mut duplicates, mut overlaps) = (Vec::new(), Vec::new());
+    let mut kept: Option<(i64, f64)> = None;
+    // Rows come by time, then in the order they were written
+    for &(rowid, ts, value) in rows {
+        match kept {
+            Some((t, v)) if t == ts && v == value => duplicates.push(rowid),
+            Some((t, _)) if ts - t < within.max(1) => overlaps.push(rowid),
+            _ => kept = Some((ts, value)),
+        }
+    }
+    (duplicates, overlaps)
+}
+
+fn dedupe_db(path: &Path, within: i64, dry_run: bool) -> Result<Vec<Removed>> {
+    let mut conn = storage::open(path)?;
+    let tx = conn.transaction()?;
+    let mut removed = Vec::new();
+    for table in list_writable_tables(&tx)? {
+        let rows = read_rows_between_ms(&tx, &table, i64::MIN, i64::MAX)?;
+        let (duplicates, overlaps) = extra_rows(&rows, within.saturating_mul(1000));
+        if duplicates.is_empty() && overlaps.is_empty() {
+            continue;
+        }
+        if !dry_run {
+            let mut stmt = tx.prepare(&format!(r#"DELETE FROM "{}" WHERE rowid = ?1"#, table))?;
+            for rowid in duplicates.iter().chain(&overlaps) {
+                stmt.execute(params![rowid])?;
+            }
+        }
+        removed.push(Removed { database: path.to_path_buf(), table, duplicates: duplicates.len(), overlaps: overlaps.len() });
+    }
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// Entry point for `winbox-stats dedupe`
+pub fn run_dedupe(args: &DedupeArgs) -> Result<()> {
+    let dir = Path::new(".");
+    let within = args.within.as_deref().map(timespan::parse).transpose()?.unwrap_or(0);
+    let mut removed = Vec::new();
+    for p in storage::find_databases(dir, 1) {
+        // Archives are not written to; restore one first to clean it
+        if storage::is_archive(&p) {
+            continue;
+        }
+        let found = dedupe_db(&p, within, args.dry_run).with_context(|| format!("dedupe {}", p.display()))?;
+        if !args.dry_run {
+            for r in &found {
+                let detail = format!("{}: {} duplicates, {} overlaps removed", r.table, r.duplicates, r.overlaps);
+                audit::record(dir, "dedupe", &p.display().to_string(), &detail)?;
+            }
+        }
+        removed.extend(found);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&removed)?);
+        return Ok(());
+    }
+    if removed.is_empty() {
+        println!("No duplicate or overlapping rows found");
+        return Ok(());
+    }
+    let dw = removed.iter().map(|r| r.database.display().to_string().len()).max().unwrap_or(0).max(8);
+    let tw = removed.iter().map(|r| r.table.len()).max().unwrap_or(0).max(5);
+    println!("{:<dw$}  {:<tw$}  {:>10}  {:>8}", "DATABASE", "TABLE", "DUPLICATES", "OVERLAPS");
+    for r in &removed {
+        println!("{:<dw$}  {:<tw$}  {:>10}  {:>8}", r.database.display(), r.table, r.duplicates, r.overlaps);
+    }
+    let total: usize = removed.iter().map(|r| r.duplicates + r.overlaps).sum();
+    println!("{} {} rows", if args.dry_run { "Would remove" } else { "Removed" }, total);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_times_are_duplicates_or_overlaps_by_value() {
+        // (rowid, ms, value), by time and then in write order
+        let rows = [(1, 0, 5.0), (2, 0, 5.0), (3, 0, 6.0), (4, 60_000, 7.0), (5, 60_000, 7.0)];
+        assert_eq!(extra_rows(&rows, 0), (vec![2, 5], vec![3]));
+    }
+
+    #[test]
+    fn the_row_written_first_is_kept() {
+        // Compared with the kept row, not the one just before
+        let rows = [(7, 1_000, 1.0), (3, 1_000, 2.0), (9, 1_000, 1.0)];
+        assert_eq!(extra_rows(&rows, 0), (vec![9], vec![3]));
+    }
+
+    #[test]
+    fn within_widens_overlaps_to_a_window() {
+        let rows = [(1, 0, 1.0), (2, 20_000, 2.0), (3, 59_999, 3.0), (4, 60_000, 4.0), (5, 130_000, 5.0)];
+        assert_eq!(extra_rows(&rows, 60_000), (vec![], vec![2, 3]));
+        // Without --within only equal times count
+        assert_eq!(extra_rows(&rows, 0), (vec![], vec![]));
+        assert_eq!(extra_rows(&[], 60_000), (vec![], vec![]));
+    }
+}
@@ -0,0 +1,245 @@
+// src/disks.rs
+//! Volume discovery and table naming for disk metrics.
+//!
+//! Drive letters move: a disk that comes back as `E:` should keep writing to
+//! `D_Drive`. Each collection remembers the table of every volume it sees by
+//! the volume's stable ID in `winbox-stats-volumes.json`, next to the
+//! databases, and later runs label the volume from there whatever its
+//! current mount point.
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use sysinfo::Disks;
+
+/// Filesystems that never represent real storage worth tracking
+const PSEUDO_FS: [&str; 17] = [
+    "autofs", "binfmt_misc", "cgroup", "cgroup2", "configfs", "debugfs", "devpts", "devtmpfs",
+    "efivarfs", "fuse.snapfuse", "nsfs", "overlay", "proc", "ramfs", "squashfs", "sysfs", "tmpfs",
+];
+
+pub const VOLUMES_FILE: &str = "winbox-stats-volumes.json";
+
+/// Volume ID -> table label it was first recorded under
+#[derive(Default)]
+pub struct KnownVolumes(BTreeMap<String, String>);
+
+impl KnownVolumes {
+    pub fn load(dir: &Path) -> Result<KnownVolumes> {
+        let path = dir.join(VOLUMES_FILE);
+        if !path.exists() {
+            return Ok(KnownVolumes::default());
+        }
+        let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+        let map = serde_json::from_str(&text).with_context(|| format!("parse {}", path.display()))?;
+        Ok(KnownVolumes(map))
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(VOLUMES_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&self.0)?)
+            .with_context(|| format!("write {}", path.display()))
+    }
+
+    fn label(&self, id: &str) -> Option<&String> {
+        self.0.iter().find(|(k, _)| same_key(k, id)).map(|(_, label)| label)
+    }
+
+    fn is_taken(&self, label: &str) -> bool {
+        self.0.values().any(|l| l.eq_ignore_ascii_case(label))
+    }
+}
+
+pub struct Volume {
+    pub mount_point: PathBuf,
+    /// Table name, e.g. `C_Drive` or `ROOT_Drive`
+    pub label: String,
+    /// Stable identity: the volume GUID path on Windows
+    /// (`\\?\Volume{...}\`), the filesystem UUID on Linux
+    pub id: Option<String>,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+impl Volume {
+    pub fn used_percent(&self) -> f64 {
+        (1.0 - (self.available_bytes as f64 / self.total_bytes as f64)) * 100.0
+    }
+
+    /// Free space in GB as Explorer counts them (2^30 bytes)
+    pub fn free_gb(&self) -> f64 {
+        self.available_bytes as f64 / (1u64 << 30) as f64
+    }
+}
+
+/// Upper case, with anything but letters and digits turned into `_`
+fn table_part(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+/// Windows `C:\` -> `C_Drive`; POSIX `/` -> `ROOT_Drive`, `/var/log` -> `VAR_LOG_Drive`
+pub fn label_for_mount_point(mp: &str) -> String {
+    if mp.len() >= 2 && mp.chars().nth(1) == Some(':') {
+        let drive = mp.chars().next().unwrap().to_ascii_uppercase();
+        return format!("{}_Drive", drive);
+    }
+    let parts: Vec<String> = mp.split(['/', '\\']).filter(|s| !s.is_empty()).map(table_part).collect();
+    if parts.is_empty() {
+        "ROOT_Drive".to_string()
+    } else {
+        format!("{}_Drive", parts.join("_"))
+    }
+}
+
+/// `D:`, `D:\` and `d:\` name the same volume, as do a GUID path with or
+/// without its trailing backslash
+fn same_key(a: &str, b: &str) -> bool {
+    let trim = |s: &str| s.trim_end_matches(['/', '\\']).to_string();
+    trim(a).eq_ignore_ascii_case(&trim(b))
+}
+
+/// `DATA_Drive` for a volume named `DATA` in `volume_names`, looked up by
+/// mount point or volume ID
+fn configured_label(mount_point: &str, id: Option<&str>, names: &BTreeMap<String, String>) -> Option<String> {
+    names
+        .iter()
+        .find(|(key, _)| same_key(key, mount_point) || id.is_some_and(|id| same_key(key, id)))
+        .map(|(_, name)| format!("{}_Drive", table_part(name)))
+}
+
+/// A configured name first, then the label the volume was first recorded
+/// under, then the mount point's label
+fn label_for(mount_point: &str, id: Option<&str>, names: &BTreeMap<String, String>, known: &KnownVolumes) -> String {
+    if let Some(label) = configured_label(mount_point, id, names) {
+        return label;
+    }
+    match id.and_then(|id| known.label(id)) {
+        Some(label) => {
+            if !label.eq_ignore_ascii_case(&label_for_mount_point(mount_point)) {
+                log::debug!("{} is the volume recorded as {}", mount_point, label);
+            }
+            label.clone()
+        }
+        None => label_for_mount_point(mount_point),
+    }
+}
+
+#[cfg(windows)]
+fn volume_id(mount_point: &Path, _device: &str) -> Option<String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetVolumeNameForVolumeMountPointW;
+
+    // The mount point must end in a backslash
+    let mut mp: Vec<u16> = mount_point.as_os_str().encode_wide().collect();
+    if mp.last() != Some(&(b'\\' as u16)) {
+        mp.push(b'\\' as u16);
+    }
+    mp.push(0);
+    let mut buf = [0u16; 64];
+    // SAFETY: both buffers are NUL-terminated and `buf.len()` is the capacity
+    if unsafe { GetVolumeNameForVolumeMountPointW(mp.as_ptr(), buf.as_mut_ptr(), buf.len() as u32) } == 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    Some(String::from_utf16_lossy(&buf[..len]))
+}
+
+/// UUID of the filesystem on `device`, from the udev links in `/dev/disk/by-uuid`
+#[cfg(target_os = "linux")]
+fn volume_id(_mount_point: &Path, device: &str) -> Option<String> {
+    let device = std::fs::canonicalize(device).ok()?;
+    std::fs::read_dir("/dev/disk/by-uuid")
+        .ok()?
+        .flatten()
+        .find(|e| std::fs::canonicalize(e.path()).is_ok_and(|p| p == device))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn volume_id(_mount_point: &Path, _device: &str) -> Option<String> {
+    None
+}
+
+fn is_pseudo(fs: &str, device: &str, mount_point: &str) -> bool {
+    PSEUDO_FS.iter().any(|p| fs.eq_ignore_ascii_case(p))
+        // Snap packages are read-only loop images, always 100% full
+        || (device.starts_with("/dev/loop") && mount_point.starts_with("/snap"))
+}
+
+/// Real volumes, without pseudo filesystems and with bind mounts collapsed
+/// onto the shortest mount point of the same device. `names` maps mount
+/// points or volume IDs to the name to record instead (`volume_names`).
+pub fn list(names: &BTreeMap<String, String>) -> Vec<Volume> {
+    let known = KnownVolumes::load(Path::new(".")).unwrap_or_else(|e| {
+        log::warn!("volumes are labelled by mount point: {:#}", e);
+        KnownVolumes::default()
+    });
+    let disks = Disks::new_with_refreshed_list();
+    let mut found: Vec<(String, Volume)> = Vec::new();
+    for d in disks.list() {
+        if d.total_space() == 0 {
+            continue;
+        }
+        let fs = d.file_system().to_string_lossy().to_string();
+        let device = d.name().to_string_lossy().to_string();
+        let mp = d.mount_point().to_string_lossy().to_string();
+        if is_pseudo(&fs, &device, &mp) {
+            continue;
+        }
+
+        let id = volume_id(d.mount_point(), &device);
+        let vol = Volume {
+            mount_point: d.mount_point().to_path_buf(),
+            label: label_for(&mp, id.as_deref(), names, &known),
+            id,
+            total_bytes: d.total_space(),
+            available_bytes: d.available_space(),
+        };
+
+        // A bind mount reports the same device and the same numbers
+        let dup = found.iter_mut().find(|(dev, v)| {
+            !device.is_empty()
+                && *dev == device
+                && v.total_bytes == vol.total_bytes
+                && v.available_bytes == vol.available_bytes
+        });
+        match dup {
+            Some((_, existing)) => {
+                if vol.mount_point.as_os_str().len() < existing.mount_point.as_os_str().len() {
+                    *existing = vol;
+                }
+            }
+            None => found.push((device, vol)),
+        }
+    }
+    found.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Record the ID of every volume not seen before in `dir`, under the label
+/// it has now. A volume whose mount point label already belongs to another
+/// recorded volume (the letter was reassigned) gets `D_2_Drive` and so on.
+pub fn remember(dir: &Path, names: &BTreeMap<String, String>) -> Result<()> {
+    let mut known = KnownVolumes::load(dir)?;
+    let mut changed = false;
+    for vol in list(names) {
+        let Some(id) = vol.id else { continue };
+        if known.label(&id).is_some() {
+            continue;
+        }
+        let mut label = vol.label;
+        let configured = configured_label(&vol.mount_point.to_string_lossy(), Some(&id), names).is_some();
+        if !configured && known.is_taken(&label) {
+            let base = label.trim_end_matches("_Drive").to_string();
+            label = (2..).map(|n| format!("{}_{}_Drive", base, n)).find(|l| !known.is_taken(l)).unwrap();
+        }
+        log::info!("recording volume {} at {} as {}", id, vol.mount_point.display(), label);
+        known.0.insert(id, label);
+        changed = true;
+    }
+    if changed {
+        known.save(dir)?;
+    }
+    Ok(())
+}
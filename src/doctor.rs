@@ -0,0 +1,340 @@
+// src/doctor.rs
+//! `doctor`: check that this machine and directory can collect, graph and
+//! export, and say what to do about anything that cannot.
+//!
+//! Everything is read-only: databases are opened without migrating them and
+//! the only file written is a throwaway probe in the data directory.
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::aliases::Aliases;
+use crate::audit;
+use crate::backend::Engine;
+use crate::cli::DoctorArgs;
+use crate::collect;
+use crate::config::Config;
+use crate::disks;
+use crate::identity;
+use crate::metric_info::{describe, Kind};
+use crate::pgsink;
+use crate::push;
+use crate::selfmon;
+use crate::services;
+use crate::storage::{self, list_metric_tables, read_samples, SCHEMA_VERSION};
+use crate::virt;
+
+/// One check for `doctor --output json`
+#[derive(Serialize)]
+struct Finding {
+    status: &'static str,
+    what: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+}
+
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    failures: usize,
+    /// Kept for JSON instead of printed as they come
+    json: bool,
+    findings: Vec<Finding>,
+}
+
+impl Report {
+    fn add(&mut self, status: &'static str, what: &str, hint: Option<&str>) {
+        if self.json {
+            self.findings.push(Finding { status, what: what.to_string(), hint: hint.map(str::to_string) });
+            return;
+        }
+        println!("[{:^4}] {}", status.to_uppercase(), what);
+        if let Some(hint) = hint {
+            println!("       -> {}", hint);
+        }
+    }
+
+    fn ok(&mut self, what: &str) {
+        self.add("ok", what, None);
+    }
+
+    fn warn(&mut self, what: &str, hint: &str) {
+        self.warnings += 1;
+        self.add("warn", what, Some(hint));
+    }
+
+    fn fail(&mut self, what: &str, hint: &str) {
+        self.failures += 1;
+        self.add("fail", what, Some(hint));
+    }
+}
+
+fn check_data_dir(r: &mut Report, dir: &Path) {
+    let shown = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    match tempfile::NamedTempFile::new_in(dir) {
+        Ok(_) => r.ok(&format!("Data directory {} is writable", shown.display())),
+        Err(e) => r.fail(
+            &format!("Cannot write to {}: {}", shown.display(), e),
+            "run from a directory this account can write to, or grant it Modify rights (icacls / chmod)",
+        ),
+    }
+}
+
+fn check_config(r: &mut Report, path: Option<&Path>) -> Config {
+    match Config::load(path) {
+        Ok(c) => {
+            r.ok(&format!(
+                "Configuration loads ({} threshold rules, {} services)",
+                c.thresholds.len(),
+                c.services.len()
+            ));
+            check_storage(r, &c);
+            c
+        }
+        Err(e) => {
+            r.fail(&format!("{:#}", e), "fix the file or pass a different one with --config");
+            Config::default()
+        }
+    }
+}
+
+fn check_storage(r: &mut Report, config: &Config) {
+    if config.storage.backend != Engine::Duckdb {
+        return;
+    }
+    if cfg!(feature = "duckdb") {
+        r.warn(
+            "The DuckDB backend writes files the other commands do not read",
+            "graph, report, export and retention work on .sqlite files only; query the .duckdb files with DuckDB",
+        );
+    } else {
+        r.fail(
+            "[storage] backend is duckdb but this build has no DuckDB support",
+            "rebuild with --features duckdb, or set backend = \"sqlite\"",
+        );
+    }
+}
+
+fn check_side_files(r: &mut Report, dir: &Path) {
+    match Aliases::load(dir) {
+        Ok(_) => r.ok("Metric aliases load"),
+        Err(e) => r.fail(&format!("{:#}", e), "fix or remove the aliases file; rename-metric rewrites it"),
+    }
+    match disks::KnownVolumes::load(dir) {
+        Ok(_) => r.ok("Volume IDs load"),
+        Err(e) => r.fail(&format!("{:#}", e), "fix the volumes file; removed, volumes are labelled by their current mount point again"),
+    }
+    match audit::read_entries(dir) {
+        Ok(entries) => match audit::verify(&entries) {
+            None => r.ok(&format!("Audit log intact ({} entries)", entries.len())),
+            Some((seq, why)) => r.fail(
+                &format!("Audit log broken at entry {}: {}", seq, why),
+                "the log was edited or truncated; keep a copy and investigate before running maintenance",
+            ),
+        },
+        Err(e) => r.fail(&format!("{:#}", e), "the audit log is not valid JSON Lines; restore it from backup"),
+    }
+    match push::queued(dir) {
+        Ok(None) | Ok(Some(0)) => {}
+        Ok(Some(n)) => r.warn(
+            &format!("{} samples waiting in the push outbox", n),
+            "check that the --push URL is reachable from this machine (proxy, firewall, TLS inspection)",
+        ),
+        Err(e) => r.fail(&format!("{:#}", e), "move the outbox aside; it is recreated on the next push"),
+    }
+    match pgsink::queued(dir) {
+        Ok(None) | Ok(Some(0)) => {}
+        Ok(Some(n)) => r.warn(
+            &format!("{} samples waiting for Postgres", n),
+            "check the [postgres] url and that the server accepts connections from this machine",
+        ),
+        Err(e) => r.fail(&format!("{:#}", e), "move the queue aside; it is recreated on the next run"),
+    }
+}
+
+fn check_sampling(r: &mut Report, config: &Config) {
+    let naming = identity::Naming {
+        host_label: config.host_label.as_deref(),
+        instance: config.instance.as_deref(),
+        ..Default::default()
+    };
+    let id = match identity::resolve(&naming) {
+        Ok(id) => id,
+        Err(e) => {
+            r.fail(&format!("{:#}", e), "fix host_label or instance in the config");
+            identity::resolve(&identity::Naming::default()).expect("no overrides to reject")
+        }
+    };
+    match &id.cluster {
+        Some(c) => r.ok(&format!("Host name {} (cluster {})", id.node, c)),
+        None => r.ok(&format!("Host name {}", id.node)),
+    }
+    if id.series != id.node {
+        r.ok(&format!("Databases are named after {}", id.series));
+    }
+    if id.node == "UNKNOWN" {
+        r.warn("Host name could not be read", "databases will be named @UNKNOWN; check the computer name");
+    }
+
+    match virt::detect() {
+        Some(platform) => r.ok(&format!("Running on {}", platform)),
+        None => r.ok("No hypervisor or container detected"),
+    }
+
+    let volumes = disks::list(&config.volume_names);
+    if volumes.is_empty() {
+        r.warn(
+            "No disks discovered",
+            "only CPU and RAM will be recorded; the account may lack rights to query volumes",
+        );
+    } else {
+        // The IDs are what `volume_names` can be keyed on
+        let labels: Vec<String> = volumes
+            .iter()
+            .map(|v| match &v.id {
+                Some(id) => format!("{} ({})", v.label, id),
+                None => v.label.clone(),
+            })
+            .collect();
+        r.ok(&format!("Disks discovered: {}", labels.join(", ")));
+    }
+
+    for s in &config.services {
+        match services::is_running(s) {
+            Ok(true) => r.ok(&format!("Service {} is running", s)),
+            Ok(false) => r.ok(&format!("Service {} is stopped", s)),
+            Err(e) => r.warn(
+                &format!("{:#}", e),
+                "use the service name (e.g. W3SVC), not its display name, in `services`",
+            ),
+        }
+    }
+
+    let samples = collect::sample_all(&Config::default());
+    let bad: Vec<&str> = samples
+        .iter()
+        .filter(|(t, v)| !v.is_finite() || *v < 0.0 || (describe(t).kind == Kind::Percent && *v > 100.0))
+        .map(|(t, _)| t.as_str())
+        .collect();
+    if bad.is_empty() {
+        r.ok(&format!("Sampled {} metrics", samples.len()));
+    } else {
+        r.warn(
+            &format!("Implausible readings for {}", bad.join(", ")),
+            "performance counters may be disabled or corrupt; try `lodctr /R` as an administrator",
+        );
+    }
+}
+
+fn check_database(r: &mut Report, path: &Path) {
+    let name = path.display();
+    let db = match storage::open_read_only(path) {
+        Ok(db) => db,
+        Err(e) => {
+            r.fail(&format!("{:#}", e), "the file may be locked by another program, not be a database, or need a different database key");
+            return;
+        }
+    };
+    let integrity: Result<String, _> = db.conn.query_row("PRAGMA quick_check", [], |row| row.get(0));
+    match integrity {
+        Ok(s) if s == "ok" => {}
+        Ok(s) => {
+            r.fail(&format!("{} is damaged: {}", name, s), "restore it from backup or move it aside");
+            return;
+        }
+        Err(e) => {
+            r.fail(&format!("{}: {}", name, e), "the file is not a SQLite database; move it out of the data directory");
+            return;
+        }
+    }
+
+    match storage::schema_version(&db.conn) {
+        Ok(v) if v > SCHEMA_VERSION => r.fail(
+            &format!("{} has schema v{}, newer than this build (v{})", name, v, SCHEMA_VERSION),
+            "upgrade winbox-stats on this machine",
+        ),
+        Ok(v) if v < SCHEMA_VERSION => r.warn(
+            &format!("{} has schema v{}", name, v),
+            "it is migrated to the current schema the next time it is collected into",
+        ),
+        Ok(_) => {}
+        Err(e) => r.fail(&format!("{}: {:#}", name, e), "restore it from backup or move it aside"),
+    }
+
+    let tables = match list_metric_tables(&db.conn) {
+        Ok(t) => t,
+        Err(e) => {
+            r.fail(&format!("{}: {:#}", name, e), "restore it from backup or move it aside");
+            return;
+        }
+    };
+    let mut problems = 0;
+    for t in &tables {
+        let total = storage::count_samples(&db.conn, t);
+        match (total, read_samples(&db.conn, t)) {
+            (Ok(total), Ok(samples)) if total > samples.len() => {
+                problems += 1;
+                r.warn(
+                    &format!("{} table {}: {} of {} timestamps cannot be parsed", name, t, total - samples.len(), total),
+                    "those rows are skipped by graph and export; fix or delete them with a SQLite tool",
+                );
+            }
+            (Ok(_), Ok(_)) => {}
+            (Err(e), _) => {
+                problems += 1;
+                r.fail(&format!("{} table {}: {}", name, t, e), "restore it from backup or move it aside");
+            }
+            (_, Err(e)) => {
+                problems += 1;
+                r.fail(
+                    &format!("{} table {}: {:#}", name, t, e),
+                    "the table holds values that are not numbers; fix or drop it with a SQLite tool",
+                );
+            }
+        }
+    }
+    if problems == 0 {
+        r.ok(&format!("{} ({} metrics)", name, tables.len()));
+    }
+
+    match selfmon::read(&db.conn) {
+        Ok(Some(health)) if health.missing.is_empty() => r.ok(&format!("{} collector: {}", name, health)),
+        Ok(Some(health)) => r.warn(
+            &format!("{} collector: {}", name, health),
+            "those metrics failed in some runs; collect with -v to see why",
+        ),
+        Ok(None) => {}
+        Err(e) => r.warn(&format!("{} {}: {:#}", name, storage::SELFMON_TABLE, e), "restore it from backup or drop the table"),
+    }
+}
+
+/// Entry point for `winbox-stats doctor`; fails if any check failed
+pub fn run_doctor(args: &DoctorArgs, config: Option<&Path>) -> Result<()> {
+    let dir = Path::new(".");
+    let mut r = Report { json: args.json, ..Default::default() };
+
+    check_data_dir(&mut r, dir);
+    let config = check_config(&mut r, config);
+    check_side_files(&mut r, dir);
+    check_sampling(&mut r, &config);
+
+    let dbs = storage::find_databases(dir, 1);
+    if dbs.is_empty() {
+        r.warn("No databases in this directory", "run `winbox-stats collect` here, or cd to the data directory");
+    }
+    for p in &dbs {
+        check_database(&mut r, p);
+    }
+
+    if r.json {
+        let out = serde_json::json!({ "checks": r.findings, "failures": r.failures, "warnings": r.warnings });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        println!();
+        println!("{} failed, {} warnings", r.failures, r.warnings);
+    }
+    if r.failures > 0 {
+        anyhow::bail!("{} checks failed", r.failures);
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+// src/eventlog.rs
+//! `EVT_ERRORS` and `EVT_WARNINGS`: error and warning events logged since
+//! the previous collection run, a cheap "something is wrong" signal next to
+//! the resource charts. Windows counts the System and Application logs;
+//! Linux counts journal entries at priority err (and above) and warning.
+#[cfg(any(windows, target_os = "linux"))]
+use anyhow::Result;
+
+pub const ERRORS: &str = "EVT_ERRORS";
+pub const WARNINGS: &str = "EVT_WARNINGS";
+
+#[cfg(windows)]
+const CHANNELS: [&str; 2] = ["System", "Application"];
+
+/// Events in `channel` at one of `levels` (1 critical, 2 error, 3 warning)
+/// from the last `within_ms` milliseconds
+#[cfg(windows)]
+fn count(channel: &str, levels: &[u8], within_ms: i64) -> Result<u64> {
+    use windows_sys::Win32::Foundation::GetLastError;
+    use windows_sys::Win32::System::EventLog::{EvtClose, EvtNext, EvtQuery, EvtQueryChannelPath};
+
+    let levels: Vec<String> = levels.iter().map(|l| format!("Level={}", l)).collect();
+    let query = format!("*[System[({}) and TimeCreated[timediff(@SystemTime) <= {}]]]", levels.join(" or "), within_ms);
+    let channel_w: Vec<u16> = channel.encode_utf16().chain(Some(0)).collect();
+    let query_w: Vec<u16> = query.encode_utf16().chain(Some(0)).collect();
+    // SAFETY: every handle EvtQuery and EvtNext return is closed before leaving
+    unsafe {
+        let results = EvtQuery(0, channel_w.as_ptr(), query_w.as_ptr(), EvtQueryChannelPath);
+        if results == 0 {
+            anyhow::bail!("query the {} log: error {}", channel, GetLastError());
+        }
+        let mut n = 0;
+        let mut events = [0isize; 64];
+        loop {
+            let mut returned = 0u32;
+            // Fails with ERROR_NO_MORE_ITEMS at the end
+            if EvtNext(results, events.len() as u32, events.as_mut_ptr(), 1000, 0, &mut returned) == 0 {
+                break;
+            }
+            for &e in &events[..returned as usize] {
+                EvtClose(e);
+            }
+            n += returned as u64;
+        }
+        EvtClose(results);
+        Ok(n)
+    }
+}
+
+#[cfg(windows)]
+fn counts(since: i64) -> Result<(u64, u64)> {
+    let within_ms = (chrono::Utc::now().timestamp() - since).max(1) * 1000;
+    let (mut errors, mut warnings) = (0, 0);
+    for channel in CHANNELS {
+        errors += count(channel, &[1, 2], within_ms)?;
+        warnings += count(channel, &[3], within_ms)?;
+    }
+    Ok((errors, warnings))
+}
+
+/// Journal entries at `priority` (e.g. `0..3`) since `since`, one JSON object per line
+#[cfg(target_os = "linux")]
+fn journal(priority: &str, since: i64) -> Result<u64> {
+    use anyhow::Context;
+
+    let out = std::process::Command::new("journalctl")
+        .args(["--quiet", "--no-pager", "--output=json", "--output-fields=PRIORITY"])
+        .arg(format!("--priority={}", priority))
+        .arg(format!("--since=@{}", since))
+        .output()
+        .context("run journalctl")?;
+    if !out.status.success() {
+        anyhow::bail!("journalctl: {}", String::from_utf8_lossy(&out.stderr).trim());
+    }
+    Ok(out.stdout.split(|&b| b == b'\n').filter(|l| !l.is_empty()).count() as u64)
+}
+
+#[cfg(target_os = "linux")]
+fn counts(since: i64) -> Result<(u64, u64)> {
+    Ok((journal("0..3", since)?, journal("4..4", since)?))
+}
+
+/// Errors and warnings logged since `since` (epoch seconds), the previous run
+#[cfg(any(windows, target_os = "linux"))]
+pub fn sample(since: i64) -> Vec<(String, f64)> {
+    match counts(since) {
+        Ok((errors, warnings)) => vec![(ERRORS.to_string(), errors as f64), (WARNINGS.to_string(), warnings as f64)],
+        Err(e) => {
+            log::warn!("{} and {} skipped: {:#}", ERRORS, WARNINGS, e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn sample(_since: i64) -> Vec<(String, f64)> {
+    Vec::new()
+}
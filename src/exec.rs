@@ -0,0 +1,156 @@
+// src/exec.rs
+//! External commands from `[[exec]]` in the config, run on every collection.
+//!
+//! Stdout is either a single number, stored under the entry's label, or
+//! `name=value` lines stored as `<label>_<name>`. Every run also records
+//! `EXEC_<label>_OK`: 1 when the command exited cleanly within its timeout
+//! and printed something usable, otherwise 0 and no values.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::storage::metric_label;
+
+/// Table prefix for the per-command success series
+const EXEC_PREFIX: &str = "EXEC_";
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecCommand {
+    /// Table name, or prefix for `name=value` output
+    pub label: String,
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Killed after this long
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// A sample value; `NaN` and `inf` parse as `f64` but cannot be stored
+fn parse_value(s: &str) -> Result<f64> {
+    let v: f64 = s.parse().with_context(|| format!("`{}` is not a number", s))?;
+    if !v.is_finite() {
+        anyhow::bail!("`{}` is not a finite number", s);
+    }
+    Ok(v)
+}
+
+/// `42` -> one value under the label; `name=value` lines -> one each
+fn parse_output(label: &str, stdout: &str) -> Result<Vec<(String, f64)>> {
+    let text = stdout.trim();
+    if !text.contains('=') {
+        return Ok(vec![(label.to_string(), parse_value(text)?)]);
+    }
+    let mut out = Vec::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let (name, value) = line.split_once('=').with_context(|| format!("unexpected output line `{}`", line))?;
+        let value = parse_value(value.trim())?;
+        out.push((format!("{}_{}", label, metric_label(name.trim())), value));
+    }
+    if out.is_empty() {
+        anyhow::bail!("no output");
+    }
+    Ok(out)
+}
+
+fn run(c: &ExecCommand) -> Result<Vec<(String, f64)>> {
+    let mut child = Command::new(&c.command)
+        .args(&c.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("run {}", c.command.display()))?;
+    // Drain both pipes while waiting so a chatty command cannot block on a full pipe
+    let mut stdout = child.stdout.take().expect("piped stdout");
+    let mut stderr = child.stderr.take().expect("piped stderr");
+    let out = std::thread::spawn(move || {
+        let mut buf = String::new();
+        stdout.read_to_string(&mut buf).map(|_| buf)
+    });
+    let err = std::thread::spawn(move || {
+        let mut buf = String::new();
+        stderr.read_to_string(&mut buf).map(|_| buf)
+    });
+    let timeout = c.timeout_secs.max(1);
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            // The readers are left behind: a grandchild may still hold the pipes open
+            anyhow::bail!("timed out after {}s", timeout);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let out = out.join().expect("reader thread panicked");
+    let err = err.join().expect("reader thread panicked");
+    if !status.success() {
+        let reason = match status.code() {
+            Some(code) => format!("exited with code {}", code),
+            None => format!("exited with {}", status),
+        };
+        match err.unwrap_or_default().lines().find(|l| !l.trim().is_empty()) {
+            Some(line) => anyhow::bail!("{}: {}", reason, line.trim()),
+            None => anyhow::bail!("{}", reason),
+        }
+    }
+    parse_output(&metric_label(&c.label), &out.context("read output")?)
+}
+
+/// Run every command concurrently; failures are logged and recorded as `EXEC_<label>_OK` = 0
+pub fn sample(commands: &[ExecCommand]) -> Vec<(String, f64)> {
+    std::thread::scope(|s| {
+        let running: Vec<_> = commands.iter().map(|c| (c, s.spawn(|| run(c)))).collect();
+        let mut out = Vec::new();
+        for (c, h) in running {
+            let ok = format!("{}{}_OK", EXEC_PREFIX, metric_label(&c.label));
+            match h.join().expect("exec thread panicked") {
+                Ok(values) => {
+                    out.extend(values);
+                    out.push((ok, 1.0));
+                }
+                Err(e) => {
+                    log::warn!("{}: {:#}", c.command.display(), e);
+                    out.push((ok, 0.0));
+                }
+            }
+        }
+        out
+    })
+}
+
+pub fn is_exec_ok_metric(metric: &str) -> bool {
+    let m = metric.to_ascii_uppercase();
+    m.starts_with(EXEC_PREFIX) && m.ends_with("_OK")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_number_or_name_value_lines() {
+        assert_eq!(parse_output("JOBS", " 42\n").unwrap(), vec![("JOBS".to_string(), 42.0)]);
+        let lines = parse_output("Q", "depth=3\nage = 1.5\n").unwrap();
+        assert_eq!(lines, vec![("Q_DEPTH".to_string(), 3.0), ("Q_AGE".to_string(), 1.5)]);
+    }
+
+    #[test]
+    fn rejects_values_that_cannot_be_stored() {
+        for out in ["NaN", "inf", "-infinity", "a=1\nb=nan"] {
+            assert!(parse_output("X", out).is_err(), "{:?}", out);
+        }
+    }
+}
@@ -1,9 +1,16 @@
 use anyhow::{Context, Result};
-use rusqlite::{Connection, Row};
+use log::info;
+use rusqlite::Connection;
 use serde::Serialize;
 use std::fs;
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+
+use crate::aliases::Aliases;
+use crate::graph::lttb;
+use crate::graph::plot::split_stem_sqlite;
+use crate::metric_info;
+use crate::storage::{self, list_metric_tables, read_samples_ms};
 
 #[derive(Serialize)]
 struct RowOut {
@@ -13,34 +20,139 @@ struct RowOut {
     value: f64,
 }
 
-fn rows(conn: &Connection) -> Result<Vec<RowOut>> {
-    let mut stmt = conn.prepare("SELECT ts, value FROM stats ORDER BY ts ASC")?;
-    let mapped = stmt
-        .query_map([], |r: &Row| {
-            let ts: String = r.get(0)?;
-            let v: f64 = r.get(1)?;
-            Ok(RowOut { ts, value: v })
-        })?
-        .collect::<rusqlite::Result<Vec<_>>>()?;
-    Ok(mapped)
+/// Every sample of `table`, or at most `max_points` of them
+fn samples(conn: &Connection, table: &str, max_points: Option<usize>) -> Result<Vec<(i64, f64)>> {
+    let samples = read_samples_ms(conn, table)?;
+    Ok(match max_points {
+        Some(max) => lttb::downsample(&samples, max),
+        None => samples,
+    })
+}
+
+fn rows(conn: &Connection, table: &str, max_points: Option<usize>) -> Result<Vec<RowOut>> {
+    Ok(samples(conn, table, max_points)?
+        .into_iter()
+        .map(|(ts, value)| RowOut { ts: storage::format_ts_ms(ts), value })
+        .collect())
 }
 
-fn to_json_path(sqlite_path: &Path) -> PathBuf {
-    sqlite_path.with_extension("json")
+fn write_json(json_path: &Path, data: &[RowOut]) -> Result<()> {
+    let json = serde_json::to_string_pretty(data)?;
+    fs::write(json_path, json).with_context(|| format!("write {}", json_path.display()))
 }
 
-pub fn export_all_sqlite_to_json(start_dir: &Path) -> Result<Vec<PathBuf>> {
+/// Per-metric DBs produce `<stem>.json`, monthly DBs one `<stem>@<table>.json` per table.
+/// With `dry_run` only the paths are returned.
+pub fn export_all_sqlite_to_json(start_dir: &Path, dry_run: bool, max_points: Option<usize>) -> Result<Vec<PathBuf>> {
     let mut out = Vec::new();
-    for entry in WalkDir::new(start_dir).into_iter().filter_map(Result::ok) {
-        let p = entry.path();
-        if p.is_file() && p.extension().map(|e| e.eq_ignore_ascii_case("sqlite")).unwrap_or(false) {
-            let conn = Connection::open(p).with_context(|| format!("open {}", p.display()))?;
-            let data = rows(&conn).with_context(|| format!("read {}", p.display()))?;
-            let json_path = to_json_path(p);
-            let json = serde_json::to_string_pretty(&data)?;
-            fs::write(&json_path, json).with_context(|| format!("write {}", json_path.display()))?;
+    let aliases = Aliases::load(start_dir)?;
+    for p in storage::find_databases(start_dir, usize::MAX) {
+        let stem = storage::db_stem(&p);
+        let (_, _, metric_opt) = split_stem_sqlite(&stem);
+        let db = storage::open_read_only(&p)?;
+        let tables = list_metric_tables(&db.conn)?;
+        if metric_opt.is_some() {
+            let Some(table) = tables.first() else { continue };
+            let data = rows(&db.conn, table, max_points).with_context(|| format!("read {}", p.display()))?;
+            let json_path = p.with_file_name(format!("{}.json", stem));
+            if !dry_run {
+                write_json(&json_path, &data)?;
+            }
+            info!("{}: {} rows -> {}", p.display(), data.len(), json_path.display());
+            out.push(json_path);
+            continue;
+        }
+        for t in tables {
+            let data = rows(&db.conn, &t, max_points).with_context(|| format!("read {} table {}", p.display(), t))?;
+            let json_path = p.with_file_name(format!("{}@{}.json", stem, aliases.resolve(&t)));
+            if !dry_run {
+                write_json(&json_path, &data)?;
+            }
+            info!("{} table {}: {} rows -> {}", p.display(), t, data.len(), json_path.display());
             out.push(json_path);
         }
     }
     Ok(out)
 }
+
+/// One sample per line for `export jsonl`
+#[derive(Serialize)]
+struct SampleOut<'a> {
+    host: &'a str,
+    metric: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    unit: &'a str,
+    ts: String,
+    value: f64,
+}
+
+fn write_table_jsonl<W: Write>(
+    w: &mut W,
+    conn: &Connection,
+    host: &str,
+    metric: &str,
+    unit: &str,
+    table: &str,
+    max_points: Option<usize>,
+) -> Result<usize> {
+    let mut n = 0;
+    for (ts, value) in samples(conn, table, max_points)? {
+        let ts = storage::format_ts_ms(ts);
+        serde_json::to_writer(&mut *w, &SampleOut { host, metric, unit, ts, value })?;
+        w.write_all(b"\n")?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// Stream every sample of every *.sqlite file in `start_dir` as JSON Lines.
+/// Returns the number of samples written.
+pub fn export_all_sqlite_to_jsonl<W: Write>(start_dir: &Path, w: &mut W, max_points: Option<usize>) -> Result<usize> {
+    let mut total = 0;
+    let aliases = Aliases::load(start_dir)?;
+    for p in storage::find_databases(start_dir, 1) {
+        let stem = storage::db_stem(&p);
+        let (_, host, metric_opt) = split_stem_sqlite(&stem);
+        let db = storage::open_read_only(&p)?;
+        let conn = &db.conn;
+        for t in list_metric_tables(conn)? {
+            // Per-metric DBs carry the metric in the filename, monthly DBs in the table name
+            let metric = aliases.resolve(metric_opt.as_deref().unwrap_or(&t));
+            let info = if metric_opt.is_some() { metric_info::describe(&metric) } else { storage::metric_info(conn, &t) };
+            total += write_table_jsonl(w, conn, &host, &metric, &info.unit, &t, max_points)
+                .with_context(|| format!("read {} table {}", p.display(), t))?;
+        }
+    }
+    w.flush()?;
+    Ok(total)
+}
+
+/// Entry point for `winbox-stats export jsonl`; `json` reports a dry run or
+/// a written file as JSON, while samples sent to stdout are JSON already
+pub fn run_export_jsonl(out: Option<&Path>, dry_run: bool, max_points: Option<usize>, json: bool) -> Result<()> {
+    let report = |n: usize, dest: &str| -> Result<()> {
+        if json {
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "samples": n, "to": dest, "dry_run": dry_run }))?);
+        } else {
+            println!("{} {} samples to {}", if dry_run { "Would write" } else { "Wrote" }, n, dest);
+        }
+        Ok(())
+    };
+    if dry_run {
+        let n = export_all_sqlite_to_jsonl(Path::new("."), &mut io::sink(), max_points)?;
+        let dest = out.map(|p| p.display().to_string()).unwrap_or_else(|| "stdout".into());
+        return report(n, &dest);
+    }
+    match out {
+        Some(path) => {
+            let f = fs::File::create(path).with_context(|| format!("create {}", path.display()))?;
+            let n = export_all_sqlite_to_jsonl(Path::new("."), &mut BufWriter::new(f), max_points)?;
+            report(n, &path.display().to_string())?;
+        }
+        None => {
+            let stdout = io::stdout();
+            export_all_sqlite_to_jsonl(Path::new("."), &mut BufWriter::new(stdout.lock()), max_points)?;
+        }
+    }
+    Ok(())
+}
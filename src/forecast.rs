@@ -0,0 +1,117 @@
+// src/forecast.rs
+//! `forecast`: when each volume is projected to reach 90% and 100% used.
+//!
+//! A least-squares line is fitted through the daily averages of the last
+//! `--days` of samples of each series, so busy days and gaps in collection
+//! weigh the same as quiet ones.
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::cli::ForecastArgs;
+use crate::series;
+
+const DAY: i64 = 86_400;
+/// Fewer daily averages than this give no trend worth printing
+const MIN_DAYS: usize = 3;
+
+#[derive(Serialize)]
+struct Forecast {
+    host: String,
+    metric: String,
+    /// Fitted value at the last sample
+    now: f64,
+    /// Change per day
+    per_day: f64,
+    /// Local date the fitted line reaches 90 and 100; `None` when it never does
+    at_90: Option<String>,
+    at_100: Option<String>,
+}
+
+/// (intercept, slope) of the least-squares line through `points`
+fn fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let (mx, my) = points.iter().fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+    let sxx: f64 = points.iter().map(|p| (p.0 - mx).powi(2)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = points.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum::<f64>() / sxx;
+    Some((my - slope * mx, slope))
+}
+
+/// Local date the line `(intercept, slope)` over epoch days reaches `level`
+fn reaches(intercept: f64, slope: f64, now_day: f64, level: f64) -> Option<String> {
+    let now = intercept + slope * now_day;
+    let day = if now >= level {
+        now_day
+    } else if slope > 0.0 {
+        (level - intercept) / slope
+    } else {
+        return None;
+    };
+    let ts = (day * DAY as f64) as i64;
+    Some(DateTime::from_timestamp(ts, 0)?.with_timezone(&Local).format("%Y-%m-%d").to_string())
+}
+
+fn forecast(host: String, metric: String, samples: &[(i64, f64)], days: i64) -> Option<Forecast> {
+    let last = samples.last()?.0;
+    let mut daily: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for (ts, v) in samples.iter().filter(|s| s.0 > last - days * DAY) {
+        let d = daily.entry(ts.div_euclid(DAY)).or_insert((0.0, 0));
+        d.0 += v;
+        d.1 += 1;
+    }
+    if daily.len() < MIN_DAYS {
+        return None;
+    }
+    // Each day's average sits at its midday
+    let points: Vec<(f64, f64)> = daily.iter().map(|(d, (sum, n))| (*d as f64 + 0.5, sum / *n as f64)).collect();
+    let (intercept, slope) = fit(&points)?;
+    let now_day = last as f64 / DAY as f64;
+    Some(Forecast {
+        host,
+        metric,
+        now: intercept + slope * now_day,
+        per_day: slope,
+        at_90: reaches(intercept, slope, now_day, 90.0),
+        at_100: reaches(intercept, slope, now_day, 100.0),
+    })
+}
+
+/// Entry point for `winbox-stats forecast`
+pub fn run_forecast(args: &ForecastArgs) -> Result<()> {
+    let filter = series::Filter { metric: Some(&args.metric), host: args.host.as_deref(), ..Default::default() };
+    let mut out: Vec<Forecast> = series::load(Path::new("."), &filter)?
+        .into_iter()
+        .filter_map(|((host, metric), samples)| forecast(host, metric, &samples, args.days))
+        .collect();
+    // Soonest to fill first; volumes that never do last
+    out.sort_by_key(|f| (f.at_100.is_none(), f.at_100.clone(), f.at_90.is_none(), f.at_90.clone()));
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+    if out.is_empty() {
+        log::warn!("not enough samples of {} in the last {} days to forecast", args.metric, args.days);
+        return Ok(());
+    }
+    let hw = out.iter().map(|f| f.host.len()).max().unwrap_or(0).max(4);
+    let mw = out.iter().map(|f| f.metric.len()).max().unwrap_or(0).max(6);
+    println!("{:<hw$}  {:<mw$}  {:>7}  {:>8}  {:<10}  100%", "HOST", "METRIC", "NOW", "PER DAY", "90%");
+    for f in &out {
+        println!(
+            "{:<hw$}  {:<mw$}  {:>7.1}  {:>+8.2}  {:<10}  {}",
+            f.host,
+            f.metric,
+            f.now,
+            f.per_day,
+            f.at_90.as_deref().unwrap_or("never"),
+            f.at_100.as_deref().unwrap_or("never")
+        );
+    }
+    Ok(())
+}
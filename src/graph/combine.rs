@@ -0,0 +1,206 @@
+//! `graph --combine A,B`: two metrics of one host on one chart per month,
+//! the first against the left axis and the second against the right, to
+//! line up metrics with very different ranges such as CPU % and network
+//! MB/s. Each axis is scaled to its own metric, and `--log-y` applies to both.
+use anyhow::{bail, Context, Result};
+use chrono::DateTime;
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use super::pngmeta::{self, ChartMeta};
+use super::style::Style;
+use super::ticks::{self, LogY};
+use super::{gaps, lttb};
+use crate::metric_info::{self, Kind, MetricInfo};
+use crate::series::{self, Infos, Series};
+use crate::timespan::Zone;
+
+/// Samples kept per pixel of width for each line
+const POINTS_PER_PX: usize = 2;
+
+/// One metric's lines on its axis
+struct Side<'a> {
+    metric: &'a str,
+    info: MetricInfo,
+    segments: Vec<Vec<(i64, f64)>>,
+    log: Option<(LogY, f64)>,
+}
+
+impl Side<'_> {
+    fn range(&self) -> Range<f64> {
+        if let Some((log, hi)) = self.log {
+            let (lo, hi) = log.range(hi);
+            return lo..hi;
+        }
+        if self.info.kind == Kind::State {
+            return -0.1..1.1;
+        }
+        let hi = self.segments.iter().flatten().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        let pad = (hi.abs() * 0.05).max(1.0);
+        self.info.y_min.unwrap_or(0.0)..self.info.y_max.unwrap_or(hi + pad)
+    }
+
+    fn labels(&self) -> usize {
+        self.log.map_or(10, |(log, hi)| log.labels(hi))
+    }
+}
+
+/// Wall-clock milliseconds in `zone` (as the other charts plot) -> samples per `YYYYMM`
+fn by_month(samples: &[(i64, f64)], zone: Zone) -> BTreeMap<String, Vec<(i64, f64)>> {
+    let mut months: BTreeMap<String, Vec<(i64, f64)>> = BTreeMap::new();
+    let mut latest = i64::MIN;
+    for &(ts, v) in samples {
+        let Some(wall) = zone.wall_clock(ts) else { continue };
+        let Some(t) = DateTime::from_timestamp(wall, 0) else { continue };
+        latest = latest.max(wall * 1000);
+        months.entry(t.format("%Y%m").to_string()).or_default().push((latest, v));
+    }
+    months
+}
+
+fn side<'a>(metric: &'a str, info: MetricInfo, pts: &[(i64, f64)], style: &Style) -> Side<'a> {
+    let kept = lttb::downsample(pts, style.width as usize * POINTS_PER_PX);
+    let span = pts.last().map_or(0, |p| p.0) - pts.first().map_or(0, |p| p.0);
+    // Downsampled points are further apart than the samples; that is no gap
+    let interval = gaps::median_interval(pts).map(|i| i.max(span / kept.len().max(1) as i64));
+    let mut segments = gaps::split(&kept, interval, style.gap_factor, (0, 0)).segments;
+    let log = (style.log_y && info.kind != Kind::State).then(|| {
+        let values = || segments.iter().flatten().map(|p| p.1);
+        (LogY::new(values()), values().fold(info.y_max.unwrap_or(0.0), f64::max))
+    });
+    if let Some((log, _)) = log {
+        for seg in &mut segments {
+            seg.iter_mut().for_each(|p| p.1 = log.y(p.1));
+        }
+    }
+    Side { metric, info, segments, log }
+}
+
+fn draw(out: &Path, style: &Style, when: &str, host: &str, zone: Zone, sides: [Side; 2]) -> Result<()> {
+    let [left, right] = sides;
+    let xs = || left.segments.iter().chain(&right.segments).flatten().map(|p| p.0);
+    let (min_x, max_x) = (xs().min().unwrap_or(0), xs().max().unwrap_or(0));
+    let max_x = max_x.max(min_x + ticks::SECOND);
+    let plan = ticks::plan(min_x, max_x, style.width);
+
+    let root = BitMapBackend::new(out, (style.width, style.height)).into_drawing_area();
+    root.fill(&style.background)?;
+    let x_range = (min_x..max_x)
+        .with_key_points(plan.major.clone())
+        .with_light_points(plan.minor.clone());
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            style.caption(
+                format!("{} {} {} and {}", when, host, left.metric, right.metric),
+                host,
+                when,
+                &format!("{}+{}", left.metric, right.metric),
+            ),
+            (style.font.as_str(), 28).into_font().color(&style.text),
+        )
+        .margin(10)
+        .x_label_area_size(60)
+        .y_label_area_size(80)
+        .right_y_label_area_size(80)
+        .build_cartesian_2d(x_range, left.range())?
+        .set_secondary_coord(min_x..max_x, right.range());
+
+    let format_log = |y: &f64| ticks::format_log(*y);
+    let mut mesh = chart.configure_mesh();
+    if left.log.is_some() {
+        mesh.y_label_formatter(&format_log);
+    }
+    mesh.axis_style(style.axis)
+        .bold_line_style(style.grid_major)
+        .light_line_style(style.grid_minor)
+        .x_label_formatter(&|x| ticks::format_x(*x, plan.label_fmt))
+        .y_labels(left.labels())
+        .y_desc(format!("{} ({})", left.info.label, left.metric))
+        .x_desc(match zone {
+            Zone::Local => plan.axis_desc.to_string(),
+            zone => format!("{} ({})", plan.axis_desc, zone.name()),
+        })
+        .axis_desc_style((style.font.as_str(), 22).into_font().color(&style.text))
+        .label_style((style.font.as_str(), 16).into_font().color(&style.text))
+        .draw()?;
+    let mut secondary = chart.configure_secondary_axes();
+    if right.log.is_some() {
+        secondary.y_label_formatter(&format_log);
+    }
+    secondary
+        .axis_style(style.axis)
+        .y_labels(right.labels())
+        .y_desc(format!("{} ({})", right.info.label, right.metric))
+        .axis_desc_style((style.font.as_str(), 22).into_font().color(&style.text))
+        .label_style((style.font.as_str(), 16).into_font().color(&style.text))
+        .draw()?;
+
+    let (left_color, right_color) = (style.line, style.second);
+    for (i, seg) in left.segments.iter().enumerate() {
+        let series = chart.draw_series(LineSeries::new(seg.iter().cloned(), left_color))?;
+        if i == 0 {
+            series
+                .label(format!("{} (left)", left.metric))
+                .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], left_color.stroke_width(2)));
+        }
+    }
+    for (i, seg) in right.segments.iter().enumerate() {
+        let series = chart.draw_secondary_series(LineSeries::new(seg.iter().cloned(), right_color))?;
+        if i == 0 {
+            series
+                .label(format!("{} (right)", right.metric))
+                .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], right_color.stroke_width(2)));
+        }
+    }
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(style.background.mix(0.85))
+        .border_style(style.axis)
+        .label_font((style.font.as_str(), 18).into_font().color(&style.text))
+        .draw()?;
+    style.decorate(&root)?;
+    root.present()?;
+    Ok(())
+}
+
+fn load(dir: &Path, metric: &str) -> Result<(Series, Infos)> {
+    series::load_described(dir, &series::Filter { metric: Some(metric), ..Default::default() })
+}
+
+/// Entry point for `winbox-stats graph --combine A,B`
+pub fn run_combine(metrics: &[String], style: &Style, out_dir: &Path, zone: Zone) -> Result<Vec<PathBuf>> {
+    let [a, b] = metrics else { bail!("--combine takes two metrics, e.g. CPU,NET_MBPS") };
+    let dir = Path::new(".");
+    let ((left, left_infos), (right, right_infos)) = (load(dir, a)?, load(dir, b)?);
+
+    fs::create_dir_all(out_dir).with_context(|| format!("create {}", out_dir.display()))?;
+    let mut written = Vec::new();
+    for (key @ (host, left_metric), left_samples) in &left {
+        let left_months = by_month(left_samples, zone);
+        for (right_key @ (_, right_metric), right_samples) in right.iter().filter(|((h, _), _)| h == host) {
+            let right_months = by_month(right_samples, zone);
+            for (ym, pts) in &left_months {
+                let Some(other) = right_months.get(ym) else { continue };
+                let info = |infos: &Infos, key: &(String, String)| infos.get(key).cloned().unwrap_or_else(|| metric_info::describe(&key.1));
+                let sides = [
+                    side(left_metric, info(&left_infos, key), pts, style),
+                    side(right_metric, info(&right_infos, right_key), other, style),
+                ];
+                let out = out_dir.join(format!("{}@{}@{}+{}.png", ym, host, left_metric, right_metric));
+                draw(&out, style, ym, host, zone, sides)?;
+                let metric = format!("{}+{}", left_metric, right_metric);
+                pngmeta::write(&out, &ChartMeta { host, metric: &metric, period: ym, coverage: None, source: None, dated: !style.deterministic })?;
+                log::info!("wrote {}", out.display());
+                written.push(out);
+            }
+        }
+    }
+    if written.is_empty() {
+        log::warn!("no host has samples of both {} and {} in the same month", a, b);
+    }
+    Ok(written)
+}
@@ -0,0 +1,130 @@
+//! `graph --compare`: two months of the same host and metric on one chart,
+//! aligned by day of month, to see what changed after an upgrade or a new
+//! workload.
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Timelike};
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::pngmeta::{self, ChartMeta};
+use super::style::Style;
+use crate::metric_info::{self, Kind};
+use crate::report_html::parse_month;
+use crate::series::{self, Infos, Series};
+use crate::timespan::Zone;
+
+const HOUR: i64 = 3600;
+
+/// Hourly averages at fractional day of month (1.0 = the 1st at midnight);
+/// raw samples of two months on top of each other are unreadable
+fn by_day_of_month(samples: &[(i64, f64)], zone: Zone) -> Vec<(f64, f64)> {
+    let mut hours: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for &(ts, v) in samples {
+        let Some(wall) = zone.wall_clock(ts) else { continue };
+        let h = hours.entry(wall.div_euclid(HOUR) * HOUR).or_insert((0.0, 0));
+        h.0 += v;
+        h.1 += 1;
+    }
+    hours
+        .into_iter()
+        .filter_map(|(wall, (sum, n))| {
+            let t = DateTime::from_timestamp(wall, 0)?.naive_utc();
+            let day = t.day() as f64 + t.hour() as f64 / 24.0;
+            Some((day, sum / n as f64))
+        })
+        .collect()
+}
+
+fn draw(out: &Path, style: &Style, host: &str, metric: &str, info: &metric_info::MetricInfo, lines: [(&str, &[(f64, f64)]); 2]) -> Result<()> {
+    let root = BitMapBackend::new(out, (style.width, style.height)).into_drawing_area();
+    root.fill(&style.background)?;
+    let (lo, hi) = lines
+        .iter()
+        .flat_map(|(_, pts)| pts.iter())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+    let (min_y, max_y) = match info.kind {
+        Kind::State => (-0.1, 1.1),
+        _ => {
+            let pad = ((hi - lo) * 0.05).max(1.0);
+            (info.y_min.unwrap_or(0.0).min(lo), info.y_max.unwrap_or(hi + pad).max(hi))
+        }
+    };
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            style.caption(
+                format!("{} {} {} vs {}", host, metric, lines[0].0, lines[1].0),
+                host,
+                &format!("{} vs {}", lines[0].0, lines[1].0),
+                metric,
+            ),
+            (style.font.as_str(), 28).into_font().color(&style.text),
+        )
+        .margin(10)
+        .x_label_area_size(60)
+        .y_label_area_size(80)
+        .build_cartesian_2d(1.0..32.0, min_y..max_y)?;
+    chart
+        .configure_mesh()
+        .axis_style(style.axis)
+        .bold_line_style(style.grid_major)
+        .light_line_style(style.grid_minor)
+        .x_labels(31)
+        .x_label_formatter(&|x| format!("{}", *x as u32))
+        .y_labels(10)
+        .y_desc(&info.label)
+        .x_desc("Day of month")
+        .axis_desc_style((style.font.as_str(), 22).into_font().color(&style.text))
+        .label_style((style.font.as_str(), 16).into_font().color(&style.text))
+        .draw()?;
+
+    // The earlier month muted, the later one in the series colour on top
+    for ((label, pts), color) in lines.into_iter().zip([style.baseline, style.line]) {
+        chart
+            .draw_series(LineSeries::new(pts.iter().cloned(), color.stroke_width(2)))?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(style.background.mix(0.85))
+        .border_style(style.axis)
+        .label_font((style.font.as_str(), 18).into_font().color(&style.text))
+        .draw()?;
+    style.decorate(&root)?;
+    root.present()?;
+    Ok(())
+}
+
+/// Entry point for `winbox-stats graph --compare A,B`
+pub fn run_compare(months: &[String], metric: Option<&str>, style: &Style, out_dir: &Path, zone: Zone) -> Result<Vec<PathBuf>> {
+    let [a, b] = months else { bail!("--compare takes two months, e.g. 2025-10,2025-11") };
+    let mut loaded: Vec<(String, (Series, Infos))> = Vec::new();
+    for m in [a, b] {
+        let month = parse_month(m).with_context(|| format!("invalid month {:?} in --compare; use e.g. 2025-10", m))?;
+        let ym = month.format("%Y-%m").to_string();
+        let filter = series::Filter { metric, month: Some(&ym), ..Default::default() };
+        loaded.push((ym.clone(), series::load_described(Path::new("."), &filter)?));
+    }
+    let [(first_ym, (first, _)), (second_ym, (second, infos))] = &loaded[..] else { unreachable!("two months loaded") };
+
+    fs::create_dir_all(out_dir).with_context(|| format!("create {}", out_dir.display()))?;
+    let mut written = Vec::new();
+    for (key @ (host, metric), later) in second {
+        let Some(earlier) = first.get(key) else { continue };
+        let info = infos.get(key).cloned().unwrap_or_else(|| metric_info::describe(metric));
+        let (before, after) = (by_day_of_month(earlier, zone), by_day_of_month(later, zone));
+        let out = out_dir.join(format!("{}-vs-{}@{}@{}.png", first_ym, second_ym, host, metric));
+        draw(&out, style, host, metric, &info, [(first_ym, &before), (second_ym, &after)])?;
+        let period = format!("{} vs {}", first_ym, second_ym);
+        pngmeta::write(&out, &ChartMeta { host, metric, period: &period, coverage: None, source: None, dated: !style.deterministic })?;
+        log::info!("wrote {}", out.display());
+        written.push(out);
+    }
+    if written.is_empty() {
+        log::warn!("no host and metric has samples in both {} and {}", first_ym, second_ym);
+    }
+    Ok(written)
+}
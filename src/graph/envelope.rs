@@ -0,0 +1,68 @@
+//! Min/avg/max buckets for charts with more samples than pixels.
+//!
+//! A month of 5-minute samples is ~9000 points on a 1600 px chart; drawn raw
+//! the line turns into noise. Bucketing keeps the range (shaded band) and
+//! the trend (average line) visible.
+
+/// Horizontal pixels per bucket
+const BUCKET_PX: u32 = 4;
+
+pub struct Bucket {
+    /// Mean timestamp of the samples in the bucket
+    pub x: i64,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+/// Trailing mean over `window` x units at every point of one gap-free segment
+pub fn rolling_average(seg: &[(i64, f64)], window: i64) -> Vec<(i64, f64)> {
+    let mut out = Vec::with_capacity(seg.len());
+    let (mut start, mut sum) = (0, 0.0);
+    for (i, &(x, y)) in seg.iter().enumerate() {
+        sum += y;
+        while seg[start].0 <= x - window {
+            sum -= seg[start].1;
+            start += 1;
+        }
+        out.push((x, sum / (i + 1 - start) as f64));
+    }
+    out
+}
+
+/// Bucket width in plot x units for a chart spanning `min_x..max_x`
+pub fn bucket_width(min_x: i64, max_x: i64, width_px: u32) -> i64 {
+    let buckets = (width_px / BUCKET_PX).max(1) as i64;
+    ((max_x - min_x) / buckets).max(1)
+}
+
+/// Group one gap-free segment into fixed-width buckets aligned to `origin`
+pub fn bucket(seg: &[(i64, f64)], origin: i64, width: i64) -> Vec<Bucket> {
+    let mut out: Vec<Bucket> = Vec::new();
+    let mut current = None;
+    let (mut n, mut sum_x, mut sum_y) = (0_i64, 0_i64, 0.0);
+    for &(x, y) in seg {
+        let key = (x - origin).div_euclid(width);
+        if current != Some(key) {
+            if n > 0 {
+                let last = out.last_mut().unwrap();
+                last.x = sum_x / n;
+                last.avg = sum_y / n as f64;
+            }
+            current = Some(key);
+            (n, sum_x, sum_y) = (0, 0, 0.0);
+            out.push(Bucket { x, min: y, avg: y, max: y });
+        }
+        let b = out.last_mut().unwrap();
+        b.min = b.min.min(y);
+        b.max = b.max.max(y);
+        n += 1;
+        sum_x += x;
+        sum_y += y;
+    }
+    if let Some(last) = out.last_mut() {
+        last.x = sum_x / n;
+        last.avg = sum_y / n as f64;
+    }
+    out
+}
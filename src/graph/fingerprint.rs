@@ -0,0 +1,199 @@
+//! `graph --check`: a hash of every chart as drawn, compared with hashes
+//! stored earlier, so a change to the rendering shows up as a list of
+//! charts rather than by eye.
+//!
+//! Pixels of text depend on the fonts installed and how the system renders
+//! them, so the hash covers everything but glyphs: shapes are drawn into an
+//! in-memory bitmap as usual, while text is laid out with fixed metrics and
+//! hashed as its string, position, size and colour. The same charts give the
+//! same hashes on any machine.
+use anyhow::{bail, Context, Result};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters_backend::text_anchor::{HPos, VPos};
+use plotters_backend::{BackendColor, BackendCoord, BackendStyle, BackendTextStyle, DrawingErrorKind, FontTransform};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Width of a character in ems, for laying out text without a font
+const CHAR_EM: f64 = 0.6;
+
+type BitMapError = <BitMapBackend<'static> as DrawingBackend>::ErrorType;
+
+/// Bitmap backend that records text instead of rasterizing it
+pub struct Fingerprint<'a> {
+    pixels: BitMapBackend<'a>,
+    text: &'a mut Sha256,
+}
+
+impl DrawingBackend for Fingerprint<'_> {
+    type ErrorType = BitMapError;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.pixels.get_size()
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.ensure_prepared()
+    }
+
+    fn present(&mut self) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.present()
+    }
+
+    fn draw_pixel(&mut self, point: BackendCoord, color: BackendColor) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.draw_pixel(point, color)
+    }
+
+    fn draw_line<S: BackendStyle>(&mut self, from: BackendCoord, to: BackendCoord, style: &S) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.draw_line(from, to, style)
+    }
+
+    fn draw_rect<S: BackendStyle>(&mut self, upper_left: BackendCoord, bottom_right: BackendCoord, style: &S, fill: bool) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.draw_rect(upper_left, bottom_right, style, fill)
+    }
+
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(&mut self, path: I, style: &S) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.draw_path(path, style)
+    }
+
+    fn draw_circle<S: BackendStyle>(&mut self, center: BackendCoord, radius: u32, style: &S, fill: bool) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.draw_circle(center, radius, style, fill)
+    }
+
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(&mut self, vert: I, style: &S) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.fill_polygon(vert, style)
+    }
+
+    fn blit_bitmap(&mut self, pos: BackendCoord, size: (u32, u32), src: &[u8]) -> Result<(), DrawingErrorKind<BitMapError>> {
+        self.pixels.blit_bitmap(pos, size, src)
+    }
+
+    fn draw_text<S: BackendTextStyle>(&mut self, text: &str, style: &S, pos: BackendCoord) -> Result<(), DrawingErrorKind<BitMapError>> {
+        let color = style.color();
+        let anchor = style.anchor();
+        let h = match anchor.h_pos {
+            HPos::Left => 0u8,
+            HPos::Center => 1,
+            HPos::Right => 2,
+        };
+        let v = match anchor.v_pos {
+            VPos::Top => 0u8,
+            VPos::Center => 1,
+            VPos::Bottom => 2,
+        };
+        let rotation = match style.transform() {
+            FontTransform::None => 0u8,
+            FontTransform::Rotate90 => 1,
+            FontTransform::Rotate180 => 2,
+            FontTransform::Rotate270 => 3,
+        };
+        self.text.update(text.as_bytes());
+        self.text.update([0]);
+        self.text.update(pos.0.to_le_bytes());
+        self.text.update(pos.1.to_le_bytes());
+        self.text.update(style.size().to_le_bytes());
+        self.text.update([color.rgb.0, color.rgb.1, color.rgb.2, (color.alpha * 255.0).round() as u8, h, v, rotation]);
+        Ok(())
+    }
+
+    fn estimate_text_size<S: BackendTextStyle>(&self, text: &str, style: &S) -> Result<(u32, u32), DrawingErrorKind<BitMapError>> {
+        let size = style.size();
+        Ok(((text.chars().count() as f64 * size * CHAR_EM).round() as u32, size.round() as u32))
+    }
+}
+
+/// SHA-256 of the chart `draw` puts on a `size` drawing area
+pub fn hash(size: (u32, u32), draw: impl FnOnce(DrawingArea<Fingerprint, Shift>) -> Result<()>) -> Result<String> {
+    let mut buffer = vec![0u8; size.0 as usize * size.1 as usize * 3];
+    let mut text = Sha256::new();
+    let root = Fingerprint { pixels: BitMapBackend::with_buffer(&mut buffer, size), text: &mut text }.into_drawing_area();
+    draw(root)?;
+    let mut h = Sha256::new();
+    h.update(&buffer);
+    h.update(text.finalize());
+    Ok(format!("{:x}", h.finalize()))
+}
+
+/// Chart name (relative, `/`-separated) -> hash, from a file of
+/// `<hash>  <name>` lines as `sha256sum` writes them
+pub fn parse(text: &str) -> Result<BTreeMap<String, String>> {
+    let mut hashes = BTreeMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((hash, name)) = line.split_once("  ") else { bail!("line {}: expected \"<hash>  <chart>\"", n + 1) };
+        hashes.insert(name.to_string(), hash.to_string());
+    }
+    Ok(hashes)
+}
+
+/// The inverse of [`parse`]
+pub fn format(hashes: &BTreeMap<String, String>) -> String {
+    hashes.iter().map(|(name, hash)| format!("{}  {}\n", hash, name)).collect()
+}
+
+/// How the charts drawn compare with the stored hashes
+#[derive(Default, Serialize)]
+struct Report {
+    checked: usize,
+    changed: Vec<String>,
+    /// Drawn, but not in the file
+    added: Vec<String>,
+    /// In the file, but not drawn
+    missing: Vec<String>,
+}
+
+/// Entry point for `winbox-stats graph --check FILE`; `update` stores the
+/// hashes instead of comparing
+pub fn run_check(file: &Path, update: bool, drawn: &[(PathBuf, String)], out_dir: &Path, json: bool) -> Result<()> {
+    // Names as under --out-dir, so the file does not depend on where it runs
+    let drawn: BTreeMap<String, String> = drawn
+        .iter()
+        .map(|(out, hash)| {
+            let name = out.strip_prefix(out_dir).unwrap_or(out);
+            let name = name.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            (name, hash.clone())
+        })
+        .collect();
+    if update {
+        fs::write(file, format(&drawn)).with_context(|| format!("write {}", file.display()))?;
+        println!("Recorded {} chart hashes in {}", drawn.len(), file.display());
+        return Ok(());
+    }
+    let text = fs::read_to_string(file).with_context(|| format!("read {}; create it with --update", file.display()))?;
+    let stored = parse(&text).with_context(|| format!("parse {}", file.display()))?;
+
+    let mut report = Report { checked: drawn.len(), ..Default::default() };
+    for (name, hash) in &drawn {
+        match stored.get(name) {
+            Some(h) if h == hash => {}
+            Some(_) => report.changed.push(name.clone()),
+            None => report.added.push(name.clone()),
+        }
+    }
+    report.missing = stored.keys().filter(|name| !drawn.contains_key(*name)).cloned().collect();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for (label, names) in [("changed", &report.changed), ("new", &report.added), ("missing", &report.missing)] {
+            for name in names {
+                println!("{:<8} {}", label, name);
+            }
+        }
+    }
+    let differ = report.changed.len() + report.added.len() + report.missing.len();
+    if differ > 0 {
+        bail!("{} charts differ from {}; rerun with --update if that is intended", differ, file.display());
+    }
+    if !json {
+        println!("All {} charts match {}", report.checked, file.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Missing-sample detection (machine off, collection task disabled).
+//!
+//! The sampling interval is taken as the median spacing between points, which
+//! is robust against both the gaps themselves and the odd manual run.
+use std::collections::BTreeMap;
+
+pub struct Gaps {
+    /// Runs of points with no gap inside, each drawn as its own line
+    pub segments: Vec<Vec<(i64, f64)>>,
+    /// (last sample before, first sample after) for each gap
+    pub gaps: Vec<(i64, i64)>,
+    /// Samples present vs. expected within the charted range, 0..=100
+    pub coverage: f64,
+}
+
+pub fn median_interval(pts: &[(i64, f64)]) -> Option<i64> {
+    let mut deltas: Vec<i64> = pts.windows(2).map(|w| w[1].0 - w[0].0).filter(|d| *d > 0).collect();
+    if deltas.is_empty() {
+        return None;
+    }
+    deltas.sort_unstable();
+    Some(deltas[deltas.len() / 2])
+}
+
+/// [`median_interval`] of points streamed past one at a time; the spacings
+/// are counted rather than kept, and a series has few distinct ones
+#[derive(Default)]
+pub struct Intervals {
+    counts: BTreeMap<i64, usize>,
+    n: usize,
+    last: Option<i64>,
+}
+
+impl Intervals {
+    pub fn add(&mut self, x: i64) {
+        if let Some(d) = self.last.map(|last| x - last).filter(|d| *d > 0) {
+            *self.counts.entry(d).or_default() += 1;
+            self.n += 1;
+        }
+        self.last = Some(x);
+    }
+
+    pub fn median(&self) -> Option<i64> {
+        let mut seen = 0;
+        self.counts.iter().find_map(|(&d, &n)| {
+            seen += n;
+            (seen > self.n / 2).then_some(d)
+        })
+    }
+}
+
+/// Break `pts` wherever consecutive samples are more than `factor` intervals
+/// apart. `interval` comes from the whole series, so a short zoom window with
+/// only a few points still sees the gaps around them.
+pub fn split(pts: &[(i64, f64)], interval: Option<i64>, factor: f64, range: (i64, i64)) -> Gaps {
+    let Some(interval) = interval else {
+        return Gaps { segments: vec![pts.to_vec()], gaps: Vec::new(), coverage: 100.0 };
+    };
+    let limit = (interval as f64 * factor.max(1.0)) as i64;
+
+    let mut segments = vec![Vec::new()];
+    let mut gaps = Vec::new();
+    for (i, p) in pts.iter().enumerate() {
+        if i > 0 && p.0 - pts[i - 1].0 > limit {
+            gaps.push((pts[i - 1].0, p.0));
+            segments.push(Vec::new());
+        }
+        segments.last_mut().unwrap().push(*p);
+    }
+
+    let present = pts.iter().filter(|p| (range.0..=range.1).contains(&p.0)).count();
+    let expected = ((range.1 - range.0) / interval + 1) as f64;
+    let coverage = (present as f64 / expected * 100.0).min(100.0);
+    Gaps { segments, gaps, coverage }
+}
+
+/// Cut a segment to `lo..=hi`, interpolating where it crosses either edge
+pub fn clip(seg: &[(i64, f64)], lo: i64, hi: i64) -> Vec<(i64, f64)> {
+    let lerp = |a: (i64, f64), b: (i64, f64), x: i64| (x, a.1 + (b.1 - a.1) * (x - a.0) as f64 / (b.0 - a.0) as f64);
+    let mut out = Vec::new();
+    for (i, &p) in seg.iter().enumerate() {
+        if i > 0 {
+            let q = seg[i - 1];
+            if q.0 < lo && p.0 > lo {
+                out.push(lerp(q, p, lo));
+            }
+            if q.0 < hi && p.0 > hi {
+                out.push(lerp(q, p, hi));
+            }
+        }
+        if (lo..=hi).contains(&p.0) {
+            out.push(p);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn streamed(pts: &[(i64, f64)]) -> Option<i64> {
+        let mut intervals = Intervals::default();
+        pts.iter().for_each(|p| intervals.add(p.0));
+        intervals.median()
+    }
+
+    #[test]
+    fn streamed_median_matches_median_interval() {
+        let mut cases: Vec<Vec<i64>> = vec![
+            vec![],
+            vec![5],
+            vec![0, 0, 0],
+            vec![0, 60, 120, 180],
+            vec![0, 60, 120, 3600, 3660],
+            // Clock going back, and repeated times
+            vec![0, 60, 30, 90, 90, 150, 400],
+            vec![0, 10, 30, 60],
+        ];
+        // Irregular series from a fixed seed
+        let mut seed = 7u64;
+        for len in [2, 3, 10, 101, 1000] {
+            let mut x = 0;
+            cases.push(
+                (0..len)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                        x += (seed >> 33) as i64 % 7 * 30 - 30;
+                        x
+                    })
+                    .collect(),
+            );
+        }
+        for xs in cases {
+            let pts: Vec<(i64, f64)> = xs.iter().map(|&x| (x, 0.0)).collect();
+            assert_eq!(streamed(&pts), median_interval(&pts), "{:?}", xs);
+        }
+    }
+}
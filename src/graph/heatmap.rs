@@ -0,0 +1,150 @@
+//! `graph --heatmap`: one calendar chart per host, metric and month, day of
+//! month across and hour of day up, each cell coloured by that hour's
+//! average. Office hours, nightly backups and quiet weekends show up as
+//! blocks and stripes that a line chart runs together.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Timelike, Weekday};
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::pngmeta::{self, ChartMeta};
+use super::style::Style;
+use crate::metric_info::{self, Kind, MetricInfo};
+use crate::series;
+use crate::timespan::Zone;
+
+/// Width of the colour scale on the right
+const SCALE_PX: u32 = 140;
+/// Steps the colour scale is drawn in
+const SCALE_STEPS: usize = 100;
+
+/// (day, hour) -> average, for one month
+type Cells = BTreeMap<(u32, u32), f64>;
+
+/// First of the month -> hourly averages at wall-clock time in `zone`
+fn by_month(samples: &[(i64, f64)], zone: Zone) -> BTreeMap<NaiveDate, Cells> {
+    let mut sums: BTreeMap<(NaiveDate, (u32, u32)), (f64, usize)> = BTreeMap::new();
+    for &(ts, v) in samples {
+        let Some(t) = zone.wall_clock(ts).and_then(|wall| DateTime::from_timestamp(wall, 0)) else { continue };
+        let Some(month) = t.date_naive().with_day(1) else { continue };
+        let cell = sums.entry((month, (t.day(), t.hour()))).or_insert((0.0, 0));
+        cell.0 += v;
+        cell.1 += 1;
+    }
+    let mut months: BTreeMap<NaiveDate, Cells> = BTreeMap::new();
+    for ((month, cell), (sum, n)) in sums {
+        months.entry(month).or_default().insert(cell, sum / n as f64);
+    }
+    months
+}
+
+fn days_in(month: NaiveDate) -> u32 {
+    month.checked_add_months(Months::new(1)).and_then(|next| next.pred_opt()).map_or(31, |last| last.day())
+}
+
+fn draw(out: &Path, style: &Style, host: &str, metric: &str, info: &MetricInfo, month: NaiveDate, cells: &Cells) -> Result<()> {
+    let (lo, hi) = match info.kind {
+        Kind::State => (0.0, 1.0),
+        _ => cells.values().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(*v), hi.max(*v))),
+    };
+    // A flat month still needs a scale to colour against
+    let hi = if hi > lo { hi } else { lo + 1.0 };
+    let color = |v: f64| ViridisRGB::get_color_normalized(v.clamp(lo, hi), lo, hi);
+
+    let root = BitMapBackend::new(out, (style.width, style.height)).into_drawing_area();
+    root.fill(&style.background)?;
+    let (left, right) = root.split_horizontally(style.width.saturating_sub(SCALE_PX));
+    let days = days_in(month);
+    let when = month.format("%Y%m").to_string();
+    let mut chart = ChartBuilder::on(&left)
+        .caption(
+            style.caption(format!("{} {} {}, hourly average", when, host, metric), host, &when, metric),
+            (style.font.as_str(), 28).into_font().color(&style.text),
+        )
+        .margin(10)
+        .x_label_area_size(60)
+        .y_label_area_size(80)
+        .build_cartesian_2d((1..days).into_segmented(), (0..23u32).into_segmented())?;
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .axis_style(style.axis)
+        .x_labels(days as usize)
+        .x_label_formatter(&|x| match x {
+            SegmentValue::CenterOf(d) => format!("{:02}", d),
+            _ => String::new(),
+        })
+        .y_labels(24)
+        .y_label_formatter(&|y| match y {
+            SegmentValue::CenterOf(h) => format!("{:02}:00", h),
+            _ => String::new(),
+        })
+        .x_desc("Day of month")
+        .y_desc("Hour of day")
+        .axis_desc_style((style.font.as_str(), 22).into_font().color(&style.text))
+        .label_style((style.font.as_str(), 16).into_font().color(&style.text))
+        .draw()?;
+
+    // Hours without samples stay grey rather than taking the bottom colour
+    let cell = |d: u32, h: u32| [(SegmentValue::Exact(d), SegmentValue::Exact(h)), (SegmentValue::Exact(d + 1), SegmentValue::Exact(h + 1))];
+    chart.draw_series((1..=days).flat_map(|d| (0..24).map(move |h| (d, h))).map(|(d, h)| {
+        let fill = cells.get(&(d, h)).map_or(style.grid_minor, |v| color(*v));
+        Rectangle::new(cell(d, h), fill.filled())
+    }))?;
+    // Week boundaries, so weekday and weekend patterns line up
+    let mondays = (2..=days).filter(|d| month.with_day(*d).is_some_and(|date| date.weekday() == Weekday::Mon));
+    chart.draw_series(mondays.map(|d| {
+        PathElement::new([(SegmentValue::Exact(d), SegmentValue::Exact(0)), (SegmentValue::Exact(d), SegmentValue::Exact(24))], style.background.stroke_width(2))
+    }))?;
+
+    let mut scale = ChartBuilder::on(&right)
+        .margin_top(42)
+        .margin_bottom(70)
+        .margin_right(10)
+        .y_label_area_size(70)
+        .build_cartesian_2d(0.0..1.0, lo..hi)?;
+    scale
+        .configure_mesh()
+        .disable_mesh()
+        .disable_x_axis()
+        .axis_style(style.axis)
+        .y_labels(10)
+        .y_desc(&info.label)
+        .axis_desc_style((style.font.as_str(), 18).into_font().color(&style.text))
+        .label_style((style.font.as_str(), 16).into_font().color(&style.text))
+        .draw()?;
+    let step = (hi - lo) / SCALE_STEPS as f64;
+    scale.draw_series((0..SCALE_STEPS).map(|i| {
+        let v = lo + i as f64 * step;
+        Rectangle::new([(0.0, v), (1.0, v + step)], color(v + step / 2.0).filled())
+    }))?;
+    style.decorate(&root)?;
+    root.present()?;
+    Ok(())
+}
+
+/// Entry point for `winbox-stats graph --heatmap`
+pub fn run_heatmap(metric: Option<&str>, style: &Style, out_dir: &Path, zone: Zone) -> Result<Vec<PathBuf>> {
+    let filter = series::Filter { metric, ..Default::default() };
+    let (loaded, infos) = series::load_described(Path::new("."), &filter)?;
+
+    fs::create_dir_all(out_dir).with_context(|| format!("create {}", out_dir.display()))?;
+    let mut written = Vec::new();
+    for (key @ (host, metric), samples) in &loaded {
+        let info = infos.get(key).cloned().unwrap_or_else(|| metric_info::describe(metric));
+        for (month, cells) in by_month(samples, zone) {
+            let out = out_dir.join(format!("{}@{}@{}-heatmap.png", month.format("%Y%m"), host, metric));
+            draw(&out, style, host, metric, &info, month, &cells)?;
+            let period = month.format("%Y%m").to_string();
+            pngmeta::write(&out, &ChartMeta { host, metric, period: &period, coverage: None, source: None, dated: !style.deterministic })?;
+            log::info!("wrote {}", out.display());
+            written.push(out);
+        }
+    }
+    if written.is_empty() {
+        log::warn!("no samples to draw a heatmap of");
+    }
+    Ok(written)
+}
@@ -0,0 +1,159 @@
+//! Largest-Triangle-Three-Buckets downsampling, fed one sample at a time.
+//!
+//! Sub-minute sampling puts millions of points in a month, far more than a
+//! chart has pixels. Time is cut into fixed-width buckets and each keeps the
+//! one sample that spans the largest triangle with the sample kept before it
+//! and the average of the next bucket, which holds on to spikes where an
+//! average would flatten them. Only two buckets are held at a time, so
+//! memory follows the chart's width rather than the table's size.
+//!
+//! `export --max-points` uses [`downsample`], the original form with buckets
+//! of equal sample counts, which keeps exactly the number of samples asked for.
+
+pub struct Lttb {
+    /// Bucket width in x units
+    width: i64,
+    out: Vec<(i64, f64)>,
+    /// Complete bucket waiting for the average of the next one
+    held: Vec<(i64, f64)>,
+    current: Vec<(i64, f64)>,
+    key: i64,
+}
+
+impl Lttb {
+    pub fn new(width: i64) -> Self {
+        Lttb { width: width.max(1), out: Vec::new(), held: Vec::new(), current: Vec::new(), key: 0 }
+    }
+
+    /// Add the next sample; x must not decrease
+    pub fn push(&mut self, p: (i64, f64)) {
+        // The first sample is always kept, as the first corner of the first triangle
+        if self.out.is_empty() {
+            self.out.push(p);
+            self.key = p.0.div_euclid(self.width);
+            return;
+        }
+        let key = p.0.div_euclid(self.width);
+        if key != self.key && !self.current.is_empty() {
+            self.close();
+        }
+        self.key = key;
+        self.current.push(p);
+    }
+
+    pub fn extend(&mut self, pts: impl IntoIterator<Item = (i64, f64)>) {
+        pts.into_iter().for_each(|p| self.push(p));
+    }
+
+    /// `current` is complete: pick from `held` and hold `current` instead
+    fn close(&mut self) {
+        if !self.held.is_empty() {
+            self.select(average(&self.current));
+        }
+        self.held = std::mem::take(&mut self.current);
+    }
+
+    fn select(&mut self, next: (f64, f64)) {
+        let a = *self.out.last().expect("first sample kept");
+        self.out.extend(pick(a, &self.held, next));
+    }
+
+    /// The kept samples; the last one is always among them
+    pub fn finish(mut self) -> Vec<(i64, f64)> {
+        if !self.current.is_empty() {
+            self.close();
+        }
+        if let Some(last) = self.held.pop() {
+            if !self.held.is_empty() {
+                self.select((last.0 as f64, last.1));
+            }
+            self.out.push(last);
+        }
+        self.out
+    }
+}
+
+fn average(bucket: &[(i64, f64)]) -> (f64, f64) {
+    let n = bucket.len() as f64;
+    (bucket.iter().map(|p| p.0 as f64).sum::<f64>() / n, bucket.iter().map(|p| p.1).sum::<f64>() / n)
+}
+
+/// The sample of `bucket` spanning the largest triangle with `a` and `next`
+fn pick(a: (i64, f64), bucket: &[(i64, f64)], next: (f64, f64)) -> Option<(i64, f64)> {
+    let (ax, ay) = (a.0 as f64, a.1);
+    let area = |p: &(i64, f64)| ((ax - next.0) * (p.1 - ay) - (ax - p.0 as f64) * (next.1 - ay)).abs();
+    bucket.iter().max_by(|p, q| area(p).total_cmp(&area(q))).copied()
+}
+
+/// At most `max` of `pts` (but always the first and the last), or all of
+/// them when there are no more than that
+pub fn downsample(pts: &[(i64, f64)], max: usize) -> Vec<(i64, f64)> {
+    let max = max.max(3);
+    if pts.len() <= max {
+        return pts.to_vec();
+    }
+    let (first, last) = (pts[0], pts[pts.len() - 1]);
+    let inner = &pts[1..pts.len() - 1];
+    let buckets = max - 2;
+    let bucket = |i: usize| &inner[i * inner.len() / buckets..(i + 1) * inner.len() / buckets];
+    let mut out = vec![first];
+    for i in 0..buckets {
+        let next = if i + 1 < buckets { average(bucket(i + 1)) } else { (last.0 as f64, last.1) };
+        let a = out[out.len() - 1];
+        out.extend(pick(a, bucket(i), next));
+    }
+    out.push(last);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A flat minute-by-minute day with one spike at noon
+    fn day_with_spike() -> Vec<(i64, f64)> {
+        (0..1440).map(|i| (i * 60, if i == 720 { 100.0 } else { (i % 7) as f64 * 0.1 })).collect()
+    }
+
+    fn streamed(pts: &[(i64, f64)], width: i64) -> Vec<(i64, f64)> {
+        let mut lttb = Lttb::new(width);
+        lttb.extend(pts.iter().copied());
+        lttb.finish()
+    }
+
+    #[test]
+    fn first_and_last_samples_are_kept() {
+        let pts = day_with_spike();
+        for out in [streamed(&pts, 3600), downsample(&pts, 24)] {
+            assert_eq!(out.first(), pts.first());
+            assert_eq!(out.last(), pts.last());
+        }
+        assert_eq!(streamed(&pts[..1], 3600), &pts[..1]);
+        assert_eq!(streamed(&pts[..2], 3600), &pts[..2]);
+        assert!(streamed(&[], 3600).is_empty());
+    }
+
+    #[test]
+    fn spikes_survive() {
+        let pts = day_with_spike();
+        for out in [streamed(&pts, 3600), downsample(&pts, 24)] {
+            assert!(out.contains(&(720 * 60, 100.0)));
+        }
+    }
+
+    #[test]
+    fn output_is_bounded_by_the_buckets() {
+        let pts = day_with_spike();
+        for width in [60, 600, 3600, 86_400] {
+            let buckets = (pts[pts.len() - 1].0 / width + 1) as usize;
+            let out = streamed(&pts, width);
+            // One per bucket, plus the first and last samples
+            assert!(out.len() <= buckets + 2, "{} samples from {} buckets", out.len(), buckets);
+            assert!(out.windows(2).all(|w| w[0].0 < w[1].0));
+        }
+        for max in [3, 10, 100] {
+            assert_eq!(downsample(&pts, max).len(), max);
+        }
+        assert_eq!(downsample(&pts[..5], 10), &pts[..5]);
+    }
+}
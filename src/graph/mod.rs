@@ -1,8 +1,169 @@
+mod combine;
+mod compare;
+mod envelope;
+mod fingerprint;
+mod gaps;
+mod heatmap;
+mod histogram;
+pub mod lttb;
 pub mod plot;
+mod pngmeta;
+mod stacked;
+pub mod style;
+mod ticks;
 
 use anyhow::Result;
+use chrono::NaiveDateTime;
+use std::path::{Path, PathBuf};
+
+use crate::cli::GraphArgs;
+use crate::config::Config;
+use crate::timespan::{self, Zone};
+use crate::viewer;
+use plot::{Period, Rendered, Settings, Zoom};
+use style::{ChartsConfig, Smooth, Style, Theme};
+
+/// `graph --open` shows the output folder instead when more charts than this were written
+const MAX_OPEN: usize = 8;
+
+/// `--around` accepts the stored timestamp formats, with or without seconds
+fn parse_around(s: &str) -> Result<NaiveDateTime> {
+    timespan::parse_local_time(s).ok_or_else(|| anyhow::anyhow!("invalid --around {:?}; use e.g. \"2025-08-14 02:00\"", s))
+}
+
+/// Charts of one database with the `graph` defaults, e.g. right after `collect --count`
+pub fn graph_file(path: &Path, config: &Config) -> Result<Vec<PathBuf>> {
+    let mut style = Style::new(Theme::default(), 1600, 900, None)?;
+    style.brand(&config.charts)?;
+    let settings = Settings {
+        style: &style,
+        config,
+        zoom: None,
+        period: Period::default(),
+        force: true,
+        out_dir: Path::new("."),
+        name_template: None,
+        zone: None,
+        fingerprint: false,
+    };
+    Ok(plot::plot_one(path, &settings)?.written)
+}
 
 /// Entry point for `winbox-stats graph`
-pub fn run_graph() -> Result<()> {
-    plot::plot_all_sqlite_in_cwd().map(|_| ())
+pub fn run_graph(args: &GraphArgs, config: &Config) -> Result<()> {
+    if let Some(files) = &args.list_meta {
+        return pngmeta::run_list_meta(files, args.json);
+    }
+    let mut style = Style::new(args.theme, args.width, args.height, args.line_color.as_deref())?;
+    style.threshold_style = args.threshold_style;
+    style.gap_factor = args.gap_factor;
+    style.envelope = args.envelope;
+    style.log_y = args.log_y;
+    let charts = &config.charts;
+    style.brand(&ChartsConfig {
+        title: args.title.clone().or_else(|| charts.title.clone()),
+        footer: args.footer.clone().or_else(|| charts.footer.clone()),
+        logo: args.logo.clone().or_else(|| charts.logo.clone()),
+        font: args.font.clone().or_else(|| charts.font.clone()),
+    })?;
+    let deterministic = args.deterministic || args.check.is_some();
+    if deterministic {
+        style.deterministic = true;
+        style.font = style::DEFAULT_FONT.into();
+    }
+    if let Some(window) = &args.smooth {
+        style.smooth = Some(Smooth { window_ms: timespan::parse_ms(window)?, label: window.clone() });
+    }
+    // The zone of the machine drawing would make the same data chart differently
+    let tz = args.tz.or(deterministic.then_some(Zone::Utc));
+    let zone = tz.unwrap_or(Zone::Local);
+    if let Some(metrics) = &args.combine {
+        return finish_written(args, combine::run_combine(metrics, &style, &args.out_dir, zone)?);
+    }
+    if args.heatmap {
+        return finish_written(args, heatmap::run_heatmap(args.metric.as_deref(), &style, &args.out_dir, zone)?);
+    }
+    if args.disk_stack {
+        return finish_written(args, stacked::run_stacked(&style, &args.out_dir, zone)?);
+    }
+    if args.histogram {
+        return finish_written(args, histogram::run_histogram(args.metric.as_deref(), &style, &args.out_dir, zone)?);
+    }
+    if let Some(months) = &args.compare {
+        return finish_written(args, compare::run_compare(months, args.metric.as_deref(), &style, &args.out_dir, zone)?);
+    }
+    let zoom = match &args.around {
+        Some(around) => {
+            let center = parse_around(around)?;
+            let half = timespan::parse_ms(&args.window)? / 2;
+            // Wall-clock time in the chart's zone, the same x units the plots use
+            let x = center.and_utc().timestamp_millis();
+            Some(Zoom {
+                from: x - half,
+                to: x + half,
+                tag: format!("zoom-{}-{}", center.format("%Y%m%dT%H%M"), args.window),
+            })
+        }
+        None => None,
+    };
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(jobs) = args.jobs {
+        pool = pool.num_threads(jobs.max(1));
+    }
+    if let Some(t) = &args.name_template {
+        // Anything still in braces after filling every field is a typo
+        let probe = plot::expand_template(t, ["h", "y", "m", "s", "", ""]);
+        if probe.contains(['{', '}']) {
+            anyhow::bail!("unknown field in --name-template {:?}; use {}", t, plot::TEMPLATE_FIELDS.join(" "));
+        }
+    }
+    let settings = Settings {
+        style: &style,
+        config,
+        zoom: zoom.as_ref(),
+        period: args.period,
+        force: args.force,
+        out_dir: &args.out_dir,
+        name_template: args.name_template.as_deref(),
+        zone: tz,
+        fingerprint: args.check.is_some(),
+    };
+    let rendered = pool.build()?.install(|| plot::plot_all_sqlite_in_cwd(&settings, args.recursive))?;
+    if let Some(file) = &args.check {
+        return fingerprint::run_check(file, args.update, &rendered.hashes, &args.out_dir, args.json);
+    }
+    if rendered.up_to_date > 0 {
+        log::info!("{} charts already up to date (--force to redraw)", rendered.up_to_date);
+    } else if zoom.is_some() && rendered.written.is_empty() {
+        log::warn!("no samples in that window");
+    }
+    finish(args, &rendered)
+}
+
+/// [`finish`] for the modes that draw every chart afresh
+fn finish_written(args: &GraphArgs, written: Vec<PathBuf>) -> Result<()> {
+    if !args.json {
+        for out in &written {
+            println!("Wrote {}", out.display());
+        }
+    }
+    finish(args, &Rendered { written, ..Default::default() })
+}
+
+/// `--json` and `--open` once the charts are written
+fn finish(args: &GraphArgs, rendered: &Rendered) -> Result<()> {
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(rendered)?);
+    }
+    if args.open {
+        // Past a handful of charts a folder window is more use than a window each
+        if rendered.written.len() > MAX_OPEN {
+            viewer::open(&args.out_dir)?;
+        } else {
+            for out in &rendered.written {
+                viewer::open(out)?;
+            }
+        }
+    }
+    Ok(())
 }
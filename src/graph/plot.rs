@@ -1,223 +1,705 @@
 use anyhow::{Context, Result};
-use chrono::{Datelike, Duration, NaiveDateTime};
+use chrono::{Datelike, Days, NaiveTime};
+use clap::ValueEnum;
 use plotters::prelude::*;
-use rusqlite::{Connection, Row};
+use rayon::prelude::*;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::fs;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::time::SystemTime;
+
+use super::style::{Envelope, Style, ThresholdStyle};
+use super::lttb::Lttb;
+use super::pngmeta::{self, ChartMeta};
+use super::ticks::LogY;
+use super::{envelope, fingerprint, gaps, ticks};
+use crate::aliases::Aliases;
+use crate::config::{Config, Threshold};
+use crate::metric_info::{self, Kind, MetricInfo};
+use crate::progress::Progress;
+use crate::storage::{self, list_metric_tables};
+use crate::timespan::Zone;
+
+/// Samples kept per horizontal pixel when a table is downsampled, enough for
+/// the min/max envelope to still show the spread within each pixel
+const POINTS_PER_PX: i64 = 8;
+
+/// First part of the name of a `collect --count`/`--duration` burst database
+pub const ADHOC_PREFIX: &str = "adhoc";
+
+/// Bursts are kept out of monthly statistics
+pub fn is_adhoc(stem: &str) -> bool {
+    stem.split('@').next() == Some(ADHOC_PREFIX)
+}
 
 /// Detect per-metric vs single-month DB by filename
 /// - "YYYYMM@HOST.sqlite"                    => monthly DB, several tables
 /// - "YYYY-MM@HOST@METRIC.sqlite"            => per-metric DB, table likely "stats"
-fn split_stem_sqlite(stem: &str) -> (String, String, Option<String>) {
+/// - "adhoc@HOST@YYYYMMDDTHHMMSS.sqlite"     => `collect --count` burst, the time in place of the month
+pub(crate) fn split_stem_sqlite(stem: &str) -> (String, String, Option<String>) {
     let parts: Vec<&str> = stem.split('@').collect();
     match parts.as_slice() {
         [ym, host] => (ym.to_string(), host.to_string(), None),
+        [ADHOC_PREFIX, host, started] => (started.to_string(), host.to_string(), None),
         [ym, host, metric] => (ym.to_string(), host.to_string(), Some((*metric).to_string())),
         _ => (stem.to_string(), String::new(), None),
     }
 }
 
-fn y_label(metric: &str) -> &'static str {
-    if metric.eq_ignore_ascii_case("RAM") {
-        "RAM % Usage"
-    } else if metric.eq_ignore_ascii_case("CPU") {
-        "CPU % Usage"
-    } else if metric.to_ascii_uppercase().ends_with("_DRIVE") {
-        "HDD % Usage"
-    } else {
-        "Value"
-    }
-}
-
-fn parse_ts(s: &str) -> Option<NaiveDateTime> {
-    // Support the formats your data uses
-    const F: [&str; 5] = [
-        "%Y-%m-%d %H:%M:%S",
-        "%Y/%m/%d %H:%M:%S",
-        "%Y-%m-%dT%H:%M:%S",
-        "%Y-%m-%d %H:%M:%S%.f",
-        "%Y-%m-%dT%H:%M:%S%.f",
-    ];
-    for f in F {
-        if let Ok(dt) = NaiveDateTime::parse_from_str(s, f) {
-            return Some(dt);
-        }
+/// Plot x values are wall-clock milliseconds in the chart's zone so tick
+/// boundaries fall on its days and sub-second bursts keep their spacing.
+/// When DST ends the wall clock repeats an hour; those samples are held at the
+/// latest time reached instead of drawing the line back over the first pass.
+struct WallClock {
+    zone: Zone,
+    latest: i64,
+}
+
+impl WallClock {
+    fn new(zone: Zone) -> Self {
+        WallClock { zone, latest: i64::MIN }
+    }
+
+    fn x(&mut self, ts_ms: i64) -> Option<i64> {
+        let wall = self.zone.wall_clock(ts_ms.div_euclid(1000))? * 1000 + ts_ms.rem_euclid(1000);
+        self.latest = self.latest.max(wall);
+        Some(self.latest)
     }
-    None
 }
 
-fn list_tables(conn: &Connection) -> Result<Vec<String>> {
-    let mut stmt = conn.prepare(
-        "SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
-    )?;
-    let mut out = Vec::new();
-    let rows = stmt.query_map([], |r: &Row| r.get::<_, String>(0))?;
-    for t in rows {
-        out.push(t?);
-    }
-    Ok(out)
-}
-
-fn pick_cols(conn: &Connection, table: &str) -> Result<(String, String)> {
-    // Accept Timestamp/Value or ts/value
-    let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
-    let mut time_col: Option<String> = None;
-    let mut val_col: Option<String> = None;
-
-    let mut rows = stmt.query([])?;
-    while let Some(row) = rows.next()? {
-        let name: String = row.get(1)?; // 1 = name
-        let lname = name.to_lowercase();
-        if time_col.is_none() && (lname == "timestamp" || lname == "ts" || lname == "time") {
-            time_col = Some(name.clone());
+fn wall_clock_points(samples: Vec<(i64, f64)>, zone: Zone) -> Vec<(i64, f64)> {
+    let mut clock = WallClock::new(zone);
+    samples.into_iter().filter_map(|(ts_ms, val)| Some((clock.x(ts_ms)?, val))).collect()
+}
+
+#[derive(Default)]
+struct Points {
+    pts: Vec<(i64, f64)>,
+    interval: Option<i64>,
+}
+
+/// Points of `table` and the sampling interval of the whole series. The
+/// table is streamed through LTTB at `POINTS_PER_PX` per pixel of the
+/// narrowest chart drawn from it, so a month of sub-minute samples is never
+/// held whole; with a zoom only the samples in and next to it are kept. Gaps
+/// narrower than a bucket are below what the chart can show, so the interval
+/// is at least the bucket width.
+fn read_points(conn: &Connection, table: &str, zone: Zone, settings: &Settings) -> Result<Points> {
+    let Some((first, last)) = storage::sample_span_ms(conn, table)? else { return Ok(Points::default()) };
+    let span = match (settings.zoom, settings.period) {
+        (Some(z), _) => z.to - z.from,
+        (None, Period::Day) => ticks::DAY,
+        (None, Period::Week) => 7 * ticks::DAY,
+        (None, Period::Month) => last - first,
+    };
+    let bucket = (span / (settings.style.width as i64 * POINTS_PER_PX)).max(1);
+    let mut lttb = Lttb::new(bucket);
+    let mut intervals = gaps::Intervals::default();
+    let mut clock = WallClock::new(zone);
+    // Zoomed: the last sample before the window waits for one inside or after it
+    let (mut before, mut past) = (None, false);
+    storage::for_each_sample_ms(conn, table, |ts_ms, val| {
+        let Some(x) = clock.x(ts_ms) else { return };
+        intervals.add(x);
+        match settings.zoom {
+            Some(z) if x < z.from => before = Some((x, val)),
+            Some(z) if x > z.to && past => {}
+            _ => {
+                past = settings.zoom.is_some_and(|z| x > z.to);
+                lttb.extend(before.take());
+                lttb.push((x, val));
+            }
         }
-        if val_col.is_none() && (lname == "value" || lname == "val") {
-            val_col = Some(name.clone());
+    })?;
+    lttb.extend(before);
+    Ok(Points { pts: lttb.finish(), interval: intervals.median().map(|i| i.max(bucket)) })
+}
+
+/// The zone the file was collected in, if it recorded one this build knows
+fn recorded_zone(conn: &Connection) -> Option<Zone> {
+    storage::get_meta(conn, "timezone").ok().flatten()?.parse().ok()
+}
+
+/// A time window to drill into, in plot x units (wall-clock milliseconds)
+pub struct Zoom {
+    pub from: i64,
+    pub to: i64,
+    /// Added to output file names so zoomed charts sit beside the full ones
+    pub tag: String,
+}
+
+/// How much time one chart covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Period {
+    /// Everything in the database, normally one month
+    #[default]
+    Month,
+    /// One chart per ISO week, Monday to Monday
+    Week,
+    /// One chart per calendar day
+    Day,
+}
+
+/// One chart's slice of a table
+struct Window {
+    /// Month, ISO week or day, shown in the caption
+    when: String,
+    /// Fixed x range; `None` spans the data
+    range: Option<(i64, i64)>,
+    /// `{period}` in file names, empty for whole-month charts
+    period: String,
+}
+
+fn windows(pts: &[(i64, f64)], ym: &str, period: Period, zoom: Option<&Zoom>) -> Vec<Window> {
+    if let Some(z) = zoom {
+        return vec![Window { when: ym.to_string(), range: Some((z.from, z.to)), period: String::new() }];
+    }
+    let mut out: Vec<Window> = Vec::new();
+    for p in pts {
+        let date = ticks::to_naive(p.0).date();
+        let (start, len, label) = match period {
+            Period::Month => return vec![Window { when: ym.to_string(), range: None, period: String::new() }],
+            Period::Week => {
+                let week = date.iso_week();
+                let monday = date - Days::new(date.weekday().num_days_from_monday() as u64);
+                (monday, 7 * ticks::DAY, format!("{}-W{:02}", week.year(), week.week()))
+            }
+            Period::Day => (date, ticks::DAY, date.format("%Y-%m-%d").to_string()),
+        };
+        let from = start.and_time(NaiveTime::MIN).and_utc().timestamp_millis();
+        if out.last().and_then(|w| w.range).map(|r| r.0) != Some(from) {
+            out.push(Window { when: label.clone(), range: Some((from, from + len)), period: label });
         }
     }
+    out
+}
 
-    let tc = time_col.unwrap_or_else(|| "Timestamp".to_string());
-    let vc = val_col.unwrap_or_else(|| "Value".to_string());
-    Ok((tc, vc))
-}
-
-fn read_points(conn: &Connection, table: &str) -> Result<Vec<(i64, f64)>> {
-    let (tc, vc) = pick_cols(conn, table)?;
-    let sql = format!(
-        r#"SELECT "{tc}", "{vc}" FROM "{table}" ORDER BY "{tc}" ASC"#,
-        tc = tc,
-        vc = vc,
-        table = table
-    );
-    let mut stmt = conn.prepare(&sql)?;
-    let mut out = Vec::new();
-    let mut rows = stmt.query([])?;
-    while let Some(row) = rows.next()? {
-        let ts: String = row.get(0)?;
-        let val: f64 = row.get(1)?;
-        if let Some(dt) = parse_ts(&ts) {
-            out.push((dt.and_utc().timestamp(), val));
-        }
+/// Caption parts and points of one chart
+struct Series<'a> {
+    when: String,
+    host: &'a str,
+    metric: String,
+    /// Axis title and range
+    info: MetricInfo,
+    /// Zone of the x axis
+    zone: Zone,
+    pts: Vec<(i64, f64)>,
+    /// Charted x range
+    range: (i64, i64),
+    /// Sampling interval of the full series, before any windowing
+    interval: Option<i64>,
+}
+
+impl<'a> Series<'a> {
+    /// Windowed series keep one sample either side of the window so lines and
+    /// gaps run to the chart edges; `render_series` clips them.
+    fn new(
+        host: &'a str,
+        metric: &str,
+        info: MetricInfo,
+        zone: Zone,
+        pts: &[(i64, f64)],
+        interval: Option<i64>,
+        window: &Window,
+    ) -> Self {
+        let (pts, range) = match window.range {
+            Some((from, to)) => {
+                let first = pts.partition_point(|p| p.0 < from).saturating_sub(1);
+                let end = (pts.partition_point(|p| p.0 <= to) + 1).min(pts.len());
+                let inside = pts[first..end].iter().any(|p| (from..=to).contains(&p.0));
+                (if inside { pts[first..end].to_vec() } else { Vec::new() }, (from, to))
+            }
+            // A single sample still needs a non-empty range
+            None if pts.is_empty() => (Vec::new(), (0, ticks::SECOND)),
+            None => (pts.to_vec(), (pts[0].0, pts[pts.len() - 1].0.max(pts[0].0 + ticks::SECOND))),
+        };
+        Series { when: window.when.clone(), host, metric: metric.to_string(), info, zone, pts, range, interval }
     }
-    Ok(out)
 }
 
-fn render_series(out: &Path, ym: &str, host: &str, metric: &str, pts: &[(i64, f64)]) -> Result<()> {
-    if pts.is_empty() {
+/// Tight y range around the data, for zoomed charts
+fn fit_y(pts: &[(i64, f64)]) -> (f64, f64) {
+    let lo = pts.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let hi = pts.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    let pad = ((hi - lo) * 0.05).max(1.0);
+    ((lo - pad).max(0.0), hi + pad)
+}
+
+fn render_series(
+    out: &Path,
+    style: &Style,
+    series: &Series,
+    threshold: Option<Threshold>,
+    fit: bool,
+    source: &Path,
+) -> Result<()> {
+    if series.pts.is_empty() {
         return Ok(());
     }
-    let min_x = pts.first().unwrap().0;
-    let max_x = pts.last().unwrap().0;
-    let min_y = 0.0_f64;
-    let max_y = 100.0_f64;
+    let root = BitMapBackend::new(out, (style.width, style.height)).into_drawing_area();
+    let coverage = draw_series(root, style, series, threshold, fit)?;
+    let meta = ChartMeta {
+        host: series.host,
+        metric: &series.metric,
+        period: &series.when,
+        coverage: Some(coverage),
+        source: Some(source),
+        dated: !style.deterministic,
+    };
+    pngmeta::write(out, &meta)
+}
 
-    let root = BitMapBackend::new(out, (1600, 900)).into_drawing_area();
-    root.fill(&WHITE)?;
+/// What `graph --check` compares instead of the PNG
+fn fingerprint_series(style: &Style, series: &Series, threshold: Option<Threshold>, fit: bool) -> Result<String> {
+    fingerprint::hash((style.width, style.height), |root| draw_series(root, style, series, threshold, fit).map(drop))
+}
 
+/// One month of samples (epoch seconds) as an SVG document, for `report --html`
+pub fn render_svg(
+    style: &Style,
+    when: &str,
+    host: &str,
+    metric: &str,
+    info: &MetricInfo,
+    samples: &[(i64, f64)],
+    threshold: Option<Threshold>,
+) -> Result<String> {
+    let pts = wall_clock_points(samples.iter().map(|&(ts, v)| (ts * 1000, v)).collect(), Zone::Local);
+    if pts.is_empty() {
+        return Ok(String::new());
+    }
+    let window = Window { when: when.to_string(), range: None, period: String::new() };
+    let series = Series::new(host, metric, info.clone(), Zone::Local, &pts, gaps::median_interval(&pts), &window);
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, (style.width, style.height)).into_drawing_area();
+        draw_series(root, style, &series, threshold, false)?;
+    }
+    Ok(svg)
+}
+
+fn draw_series<DB: DrawingBackend>(
+    root: DrawingArea<DB, plotters::coord::Shift>,
+    style: &Style,
+    series: &Series,
+    threshold: Option<Threshold>,
+    fit: bool,
+) -> Result<f64>
+where
+    DB::ErrorType: 'static,
+{
+    let (when, host, metric, pts) = (series.when.as_str(), series.host, series.metric.as_str(), &series.pts[..]);
+    let (min_x, max_x) = series.range;
+    let ticks = ticks::plan(min_x, max_x, style.width);
+    let gaps = gaps::split(pts, series.interval, style.gap_factor, (min_x, max_x));
+    // Plotters pins out-of-range points to the edge rather than clipping them
+    let mut segments: Vec<_> = gaps
+        .segments
+        .iter()
+        .map(|seg| gaps::clip(seg, min_x, max_x))
+        .filter(|seg| !seg.is_empty())
+        .collect();
+    let info = &series.info;
+    // Log scale: from here on y is log10 of the value, labelled as the value
+    let log = (style.log_y && info.kind != Kind::State).then(|| {
+        let log = LogY::new(segments.iter().flatten().map(|p| p.1));
+        let hi = segments.iter().flatten().map(|p| p.1).fold(info.y_max.unwrap_or(0.0), f64::max);
+        (log, hi)
+    });
+    let mut threshold = threshold;
+    if let Some((log, _)) = log {
+        for seg in &mut segments {
+            seg.iter_mut().for_each(|p| p.1 = log.y(p.1));
+        }
+        threshold = threshold.map(|th| Threshold { warning: th.warning.map(|v| log.y(v)), critical: th.critical.map(|v| log.y(v)) });
+    }
+    let (min_y, max_y) = if let Some((log, hi)) = log {
+        log.range(hi)
+    } else if info.kind == Kind::State {
+        // Keep the 0/1 line off the frame
+        (-0.1, 1.1)
+    } else if fit {
+        fit_y(&segments.concat())
+    } else {
+        let (lo, hi) = (info.y_min.unwrap_or(0.0), info.y_max);
+        (lo, hi.unwrap_or_else(|| fit_y(&segments.concat()).1))
+    };
+
+    root.fill(&style.background)?;
+
+    let x_range = (min_x..max_x)
+        .with_key_points(ticks.major.clone())
+        .with_light_points(ticks.minor.clone());
     let mut chart = ChartBuilder::on(&root)
-        .caption(format!("{} {} {}", ym, host, metric), ("sans-serif", 28))
+        .caption(
+            style.caption(
+                match &style.smooth {
+                    Some(s) => format!("{} {} {} ({:.1}% coverage, {} average)", when, host, metric, gaps.coverage, s.label),
+                    None => format!("{} {} {} ({:.1}% coverage)", when, host, metric, gaps.coverage),
+                },
+                host,
+                when,
+                metric,
+            ),
+            (style.font.as_str(), 28).into_font().color(&style.text),
+        )
         .margin(10)
         .x_label_area_size(60)   // ensure x labels render below the axis
         .y_label_area_size(80)
-        .build_cartesian_2d(min_x..max_x, min_y..max_y)?;
-
-    let first_dt = chrono::DateTime::from_timestamp(min_x, 0)
-        .unwrap()
-        .with_timezone(&chrono::Local);
-    let month_start = chrono::NaiveDate::from_ymd_opt(first_dt.year(), first_dt.month(), 1)
-        .unwrap()
-        .and_hms_opt(0, 0, 0)
-        .unwrap();
-    let (ny, nm) = if first_dt.month() == 12 {
-        (first_dt.year() + 1, 1)
+        .build_cartesian_2d(x_range, min_y..max_y)?;
+
+    // Major ticks get labels and darker verticals, minor ticks only a faint line
+    let mut mesh = chart.configure_mesh();
+    if let Some((log, hi)) = log {
+        mesh.y_labels(log.labels(hi)).y_label_formatter(&|y| ticks::format_log(*y));
     } else {
-        (first_dt.year(), first_dt.month() + 1)
-    };
-    let next_month_start = chrono::NaiveDate::from_ymd_opt(ny, nm, 1)
-        .unwrap()
-        .and_hms_opt(0, 0, 0)
-        .unwrap();
-    let last_day = (next_month_start - Duration::days(1)).day();
-
-    chart
-        .configure_mesh()
-        .disable_x_mesh()                              // we draw our own verticals
-        .x_labels((last_day - 1) as usize)             // one label per day (2..=last_day)
-        .x_label_formatter(&|ts| {
-            let dt = chrono::DateTime::from_timestamp(*ts, 0).unwrap().with_timezone(&chrono::Local);
-            format!("{:02}", dt.day())
+        mesh.y_labels(10);
+    }
+    mesh.axis_style(style.axis)
+        .bold_line_style(style.grid_major)
+        .light_line_style(style.grid_minor)
+        .x_label_formatter(&|x| ticks::format_x(*x, ticks.label_fmt))
+        .y_desc(&info.label)
+        .x_desc(match series.zone {
+            Zone::Local => ticks.axis_desc.to_string(),
+            zone => format!("{} ({})", ticks.axis_desc, zone.name()),
         })
-        .y_labels(10)
-        .y_desc(y_label(metric))
-        .x_desc("Date")
-        .axis_desc_style(("sans-serif", 22).into_font())
-        .label_style(("sans-serif", 16).into_font())
+        .axis_desc_style((style.font.as_str(), 22).into_font().color(&style.text))
+        .label_style((style.font.as_str(), 16).into_font().color(&style.text))
         .draw()?;
 
-    // Vertical day grid lines across the plot area (no text inside the plot)
-    let grid = RGBColor(220, 220, 220);
-    for day in 2..=last_day {
-        let tick_naive = chrono::NaiveDate::from_ymd_opt(month_start.year(), month_start.month(), day)
-            .unwrap()
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let x = tick_naive.and_utc().timestamp();
-        if x < min_x || x > max_x {
-            continue;
+    // Drawn before the series so the line stays on top
+    if let Some(th) = threshold {
+        let clamp = |v: f64| v.clamp(min_y, max_y);
+        let levels = [
+            (th.warning, th.critical.unwrap_or(max_y), style.warning),
+            (th.critical, max_y, style.critical),
+        ];
+        for (from, to, color) in levels {
+            let Some(from) = from.map(clamp) else { continue };
+            match style.threshold_style {
+                ThresholdStyle::Band => {
+                    chart.draw_series(std::iter::once(Rectangle::new(
+                        [(min_x, from), (max_x, clamp(to))],
+                        color.mix(0.15).filled(),
+                    )))?;
+                }
+                ThresholdStyle::Line => {
+                    chart.draw_series(LineSeries::new(
+                        [(min_x, from), (max_x, from)],
+                        color.stroke_width(2),
+                    ))?;
+                }
+            }
+        }
+    }
+
+    // Shade missing stretches and draw each run separately so no line bridges them
+    chart.draw_series(
+        gaps.gaps
+            .iter()
+            .map(|&(from, to)| {
+                Rectangle::new([(from.max(min_x), min_y), (to.min(max_x), max_y)], style.grid_minor.mix(0.6).filled())
+            }),
+    )?;
+    let bucketed = match style.envelope {
+        Envelope::Always => true,
+        Envelope::Never => false,
+        Envelope::Auto => segments.iter().map(Vec::len).sum::<usize>() > style.width as usize,
+    };
+    let bucket_width = envelope::bucket_width(min_x, max_x, style.width);
+    // Smoothed charts keep the samples as faded context under the average
+    let (line, band_alpha) = match style.smooth {
+        Some(_) => (style.line.mix(0.2), 0.1),
+        None => (style.line.mix(1.0), 0.25),
+    };
+    for segment in &segments {
+        if bucketed && segment.len() > 1 {
+            let buckets = envelope::bucket(segment, min_x, bucket_width);
+            let band: Vec<(i64, f64)> = buckets
+                .iter()
+                .map(|b| (b.x, b.max))
+                .chain(buckets.iter().rev().map(|b| (b.x, b.min)))
+                .collect();
+            chart.draw_series(std::iter::once(Polygon::new(band, style.line.mix(band_alpha).filled())))?;
+            chart.draw_series(LineSeries::new(buckets.iter().map(|b| (b.x, b.avg)), line))?;
+        } else if let [(x, y)] = segment[..] {
+            // An isolated sample has no line to draw
+            chart.draw_series(std::iter::once(Circle::new((x, y), 2, line.filled())))?;
+        } else {
+            chart.draw_series(LineSeries::new(segment.iter().cloned(), line))?;
+        }
+    }
+    if let Some(smooth) = &style.smooth {
+        for segment in &segments {
+            let averaged = envelope::rolling_average(segment, smooth.window_ms);
+            chart.draw_series(LineSeries::new(averaged, style.line.stroke_width(3)))?;
         }
-        chart.draw_series(std::iter::once(PathElement::new(
-            vec![(x, min_y), (x, max_y)],
-            &grid,
-        )))?;
-        // If you ever want labels inside the plot, re-enable Text::new here.
     }
+    style.decorate(&root)?;
+    Ok(gaps.coverage)
+}
 
-    chart.draw_series(LineSeries::new(pts.iter().cloned(), &BLUE))?;
-    Ok(())
+/// What to render, shared by every chart of one `graph` run
+pub struct Settings<'a> {
+    pub style: &'a Style,
+    pub config: &'a Config,
+    pub zoom: Option<&'a Zoom>,
+    pub period: Period,
+    /// Redraw charts even when they are newer than their database
+    pub force: bool,
+    pub out_dir: &'a Path,
+    /// e.g. `{host}/{ym}-{metric}.png`; default is `<stem>@<metric>.png`
+    pub name_template: Option<&'a str>,
+    /// Axis zone; `None` uses the zone each file was collected in
+    pub zone: Option<Zone>,
+    /// Hash each chart for `--check` instead of writing it
+    pub fingerprint: bool,
 }
 
-pub fn plot_all_sqlite_in_cwd() -> Result<Vec<PathBuf>> {
-    let mut outs = Vec::new();
+pub const TEMPLATE_FIELDS: [&str; 6] = ["{host}", "{ym}", "{metric}", "{stem}", "{zoom}", "{period}"];
 
-    for entry in WalkDir::new(".").max_depth(1).into_iter().filter_map(Result::ok) {
-        let p = entry.path();
-        if !p.is_file() || p.extension().map(|e| !e.eq_ignore_ascii_case("sqlite")).unwrap_or(true) {
+/// Fill in `--name-template`. Zoom and period tags the template does not
+/// place are added before the extension, so such charts never overwrite the
+/// whole-month ones.
+pub fn expand_template(template: &str, fields: [&str; 6]) -> String {
+    let mut name = template.to_string();
+    for (key, value) in TEMPLATE_FIELDS.iter().zip(fields) {
+        name = name.replace(key, value);
+    }
+    for (key, tag) in TEMPLATE_FIELDS[4..].iter().zip(&fields[4..]) {
+        if tag.is_empty() || template.contains(key) {
             continue;
         }
-
-        let stem = p.file_stem().unwrap().to_string_lossy().to_string();
-        let (ym, host, metric_opt) = split_stem_sqlite(&stem);
-        let conn = Connection::open(p).with_context(|| format!("open {}", p.display()))?;
-        let tables = list_tables(&conn)?;
-        if tables.is_empty() {
-            continue;
+        let tag = format!(".{}", tag);
+        match name.rfind('.') {
+            Some(dot) => name.insert_str(dot, &tag),
+            None => name.push_str(&tag),
         }
+    }
+    name
+}
 
-        // Per-metric DB (e.g., 2025-11@HOST@CPU.sqlite)
-        if let Some(metric) = metric_opt.clone() {
-            let table = if tables.iter().any(|t| t.eq_ignore_ascii_case("stats")) {
-                "stats".to_string()
-            } else {
-                tables[0].clone()
-            };
-            let pts = read_points(&conn, &table)?;
-            let out = p.with_extension("png"); // one png per file
-            render_series(&out, &ym, &host, &metric, &pts)?;
-            outs.push(out);
-            continue;
-        }
+/// Where one chart goes. Without a template: `<stem>.png` for per-metric
+/// files, `<stem>@<metric>.png` for monthly ones.
+fn output_path(settings: &Settings, stem: &str, ym: &str, host: &str, metric: &str, per_metric_db: bool, period: &str) -> PathBuf {
+    let zoom = settings.zoom.map(|z| z.tag.as_str()).unwrap_or_default();
+    let template = match settings.name_template {
+        Some(t) => t,
+        None if per_metric_db => "{stem}.png",
+        None => "{stem}@{metric}.png",
+    };
+    settings.out_dir.join(expand_template(template, [host, ym, metric, stem, zoom, period]))
+}
+
+#[derive(Default, Serialize)]
+pub struct Rendered {
+    pub written: Vec<PathBuf>,
+    /// Charts skipped because the PNG is newer than its database
+    pub up_to_date: usize,
+    /// Chart -> hash, for `--check`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hashes: Vec<(PathBuf, String)>,
+}
+
+fn modified(p: &Path) -> Option<SystemTime> {
+    fs::metadata(p).and_then(|m| m.modified()).ok()
+}
+
+/// Collection writes land in the `-wal` file until a checkpoint, so that counts too
+fn source_modified(db: &Path) -> Option<SystemTime> {
+    let mut wal = db.as_os_str().to_owned();
+    wal.push("-wal");
+    modified(db).max(modified(Path::new(&wal)))
+}
+
+/// Render every chart of one database; tables are drawn in parallel
+fn plot_file(p: &Path, settings: &Settings, aliases: &Aliases) -> Result<Rendered> {
+    let zoom = settings.zoom;
+    let stem = storage::db_stem(p);
+    let (ym, host, metric_opt) = split_stem_sqlite(&stem);
+    let db = storage::open_read_only(p)?;
+    let conn = &db.conn;
+    let tables = list_metric_tables(conn)?;
+    let zone = settings.zone.or_else(|| recorded_zone(conn)).unwrap_or(Zone::Local);
+
+    let src_modified = source_modified(p);
+    let mut up_to_date = 0;
+    let mut stale = |out: &Path| {
+        let fresh = !settings.force && !settings.fingerprint && matches!((modified(out), src_modified), (Some(o), Some(s)) if o >= s);
+        up_to_date += fresh as usize;
+        !fresh
+    };
 
+    // (table, metric, per-metric DB)
+    let mut sources = Vec::new();
+    if let Some(metric) = metric_opt.as_deref().map(|m| aliases.resolve(m)) {
+        // Per-metric DB (e.g., 2025-11@HOST@CPU.sqlite) → one png per file
+        let Some(first) = tables.first() else { return Ok(Rendered::default()) };
+        let table = if tables.iter().any(|t| t.eq_ignore_ascii_case("stats")) { "stats" } else { first };
+        sources.push((table.to_string(), metric, true));
+    } else {
         // Monthly DB (YYYYMM@HOST.sqlite) → one png per table
-        for t in tables {
-            let pts = read_points(&conn, &t)?;
-            if pts.is_empty() {
-                continue;
+        sources.extend(tables.iter().map(|t| (t.clone(), aliases.resolve(t), false)));
+    }
+
+    // A connection cannot be shared across threads, so read everything first
+    let mut charts = Vec::new();
+    for (table, metric, per_metric_db) in sources {
+        let Points { pts, interval } = read_points(conn, &table, zone, settings)?;
+        // A per-metric DB's table is not named after its metric
+        let info = if per_metric_db { metric_info::describe(&metric) } else { storage::metric_info(conn, &table) };
+        for window in windows(&pts, &ym, settings.period, zoom) {
+            let out = output_path(settings, &stem, &ym, &host, &metric, per_metric_db, &window.period);
+            if stale(&out) {
+                charts.push((out, Series::new(&host, &metric, info.clone(), zone, &pts, interval, &window)));
             }
-            let out = PathBuf::from(format!("{}@{}.png", stem, t));
-            render_series(&out, &ym, &host, &t, &pts)?;
-            outs.push(out);
         }
     }
+    charts.retain(|(_, series)| !series.pts.is_empty());
 
-    Ok(outs)
+    let drawn = charts
+        .into_par_iter()
+        .map(|(out, series)| {
+            let threshold = settings.config.threshold_for(&series.metric);
+            if settings.fingerprint {
+                let hash = fingerprint_series(settings.style, &series, threshold, zoom.is_some())?;
+                return Ok((out, Some(hash)));
+            }
+            if let Some(dir) = out.parent() {
+                fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+            }
+            render_series(&out, settings.style, &series, threshold, zoom.is_some(), p)?;
+            log::info!("wrote {} ({} points)", out.display(), series.pts.len());
+            Ok((out, None))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut rendered = Rendered { up_to_date, ..Default::default() };
+    for (out, hash) in drawn {
+        match hash {
+            Some(hash) => rendered.hashes.push((out, hash)),
+            None => rendered.written.push(out),
+        }
+    }
+    Ok(rendered)
+}
+
+/// Every chart of one database
+pub fn plot_one(p: &Path, settings: &Settings) -> Result<Rendered> {
+    plot_file(p, settings, &Aliases::load(Path::new("."))?)
+}
+
+/// Every chart of every database here; with `recursive` also those in
+/// subfolders, whose charts go to the same subfolder under `out_dir`
+pub fn plot_all_sqlite_in_cwd(settings: &Settings, recursive: bool) -> Result<Rendered> {
+    let aliases = Aliases::load(Path::new("."))?;
+    let files = storage::find_databases(Path::new("."), if recursive { usize::MAX } else { 1 });
+    let progress = Progress::new("Rendering", files.len());
+    let per_file = files
+        .par_iter()
+        .map(|p| {
+            // `./2025/HOST/x.sqlite` -> `<out_dir>/2025/HOST/`
+            let sub = p.parent().and_then(|d| d.strip_prefix(".").ok()).unwrap_or(Path::new(""));
+            let out_dir = settings.out_dir.join(sub);
+            let rendered = plot_file(p, &Settings { out_dir: &out_dir, ..*settings }, &aliases);
+            progress.tick();
+            rendered
+        })
+        .collect::<Result<Vec<_>>>();
+    progress.finish();
+
+    let mut all = Rendered::default();
+    for r in per_file? {
+        all.written.extend(r.written);
+        all.up_to_date += r.up_to_date;
+        all.hashes.extend(r.hashes);
+    }
+    all.written.sort();
+    all.hashes.sort();
+    Ok(all)
+}
+
+/// Golden-image tests: charts of fixed synthetic data against the hashes in
+/// `golden.sha256`. After an intended change to the drawing, rerun with
+/// `WINBOX_BLESS=1 cargo test` to store the new hashes, and look at the
+/// charts before committing them.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::style::Theme;
+    use chrono::NaiveDate;
+    use std::collections::BTreeMap;
+
+    const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/graph/golden.sha256");
+    const FIVE_MINUTES: i64 = 300_000;
+
+    /// A month of 5-minute samples from `f(i)`, with the 11th missing
+    fn month(f: impl Fn(i64) -> f64) -> Vec<(i64, f64)> {
+        let start = NaiveDate::from_ymd_opt(2026, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
+        let gap = start + 10 * ticks::DAY..start + 11 * ticks::DAY;
+        (0..30 * ticks::DAY / FIVE_MINUTES)
+            .map(|i| (start + i * FIVE_MINUTES, f(i)))
+            .filter(|p| !gap.contains(&p.0))
+            .collect()
+    }
+
+    fn hash(style: &Style, metric: &str, info: MetricInfo, pts: &[(i64, f64)], threshold: Option<Threshold>) -> String {
+        let window = Window { when: "202609".into(), range: None, period: String::new() };
+        let series = Series::new("GOLDEN", metric, info, Zone::Utc, pts, Some(FIVE_MINUTES), &window);
+        fingerprint_series(style, &series, threshold, false).unwrap()
+    }
+
+    fn style(theme: Theme) -> Style {
+        Style::new(theme, 1600, 900, None).unwrap()
+    }
+
+    fn charts() -> BTreeMap<String, String> {
+        let cpu = month(|i| 50.0 + 40.0 * (i as f64 / 50.0).sin());
+        let net = month(|i| 10f64.powf(3.0 * ((i as f64 / 300.0).sin() + 1.0) / 2.0));
+        let up = month(|i| if i % 500 < 20 { 0.0 } else { 1.0 });
+        let state = MetricInfo { unit: String::new(), kind: Kind::State, label: "Service running (1 = up)".into(), y_min: Some(0.0), y_max: Some(1.0) };
+        let threshold = Threshold { warning: Some(70.0), critical: Some(90.0) };
+
+        let mut charts = BTreeMap::new();
+        let light = style(Theme::Light);
+        charts.insert("cpu", hash(&light, "CPU", metric_info::describe("CPU"), &cpu, None));
+        charts.insert("cpu-threshold", hash(&light, "CPU", metric_info::describe("CPU"), &cpu, Some(threshold)));
+        charts.insert("state", hash(&light, "SVC_Spooler", state, &up, None));
+        let mut dark = style(Theme::Dark);
+        dark.envelope = Envelope::Always;
+        charts.insert("dark-envelope", hash(&dark, "CPU", metric_info::describe("CPU"), &cpu, None));
+        let mut log = style(Theme::Light);
+        log.log_y = true;
+        charts.insert("log-y", hash(&log, "NET_MBPS", metric_info::describe("NET_MBPS"), &net, None));
+        let mut branded = style(Theme::Light);
+        branded.title = Some("{host} {metric}, {when}".into());
+        branded.footer = Some("Example Corp".into());
+        charts.insert("branded", hash(&branded, "CPU", metric_info::describe("CPU"), &cpu, None));
+        charts.into_iter().map(|(name, hash)| (name.to_string(), hash)).collect()
+    }
+
+    #[test]
+    fn charts_match_golden_hashes() {
+        let drawn = charts();
+        if std::env::var_os("WINBOX_BLESS").is_some() {
+            fs::write(GOLDEN, fingerprint::format(&drawn)).unwrap();
+            return;
+        }
+        let golden = fingerprint::parse(&fs::read_to_string(GOLDEN).unwrap()).unwrap();
+        let changed: Vec<_> = drawn.iter().filter(|(name, hash)| golden.get(*name) != Some(hash)).map(|(name, _)| name).collect();
+        assert!(changed.is_empty(), "charts differ from {}: {:?}; rerun with WINBOX_BLESS=1 if intended", GOLDEN, changed);
+        assert_eq!(golden.len(), drawn.len(), "stale entries in {}", GOLDEN);
+    }
+
+    #[test]
+    fn hash_ignores_fonts() {
+        let cpu = month(|i| (i % 100) as f64);
+        let mut serif = style(Theme::Light);
+        serif.font = "serif".into();
+        assert_eq!(
+            hash(&style(Theme::Light), "CPU", metric_info::describe("CPU"), &cpu, None),
+            hash(&serif, "CPU", metric_info::describe("CPU"), &cpu, None),
+        );
+    }
 }
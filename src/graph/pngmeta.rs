@@ -0,0 +1,139 @@
+//! `tEXt` chunks naming where a chart came from, so a PNG pasted into a
+//! chat or a ticket can be traced back to its host, metric and database.
+//! `graph --list-meta` reads them back; image viewers show them as
+//! properties.
+//!
+//! Plotters writes the file without metadata, so the chunks are spliced in
+//! after the header once the chart is saved.
+use anyhow::{bail, Context, Result};
+use chrono::{Local, SecondsFormat};
+use flate2::Crc;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// Signature, then the IHDR chunk: length, type, 13 bytes, CRC
+const HEADER_LEN: usize = 8 + 4 + 4 + 13 + 4;
+
+/// What one chart shows
+pub struct ChartMeta<'a> {
+    pub host: &'a str,
+    pub metric: &'a str,
+    /// Month, ISO week or day
+    pub period: &'a str,
+    /// Samples present vs. expected, 0..=100, for line charts
+    pub coverage: Option<f64>,
+    /// The database the samples were read from
+    pub source: Option<&'a Path>,
+    /// With the tool version and creation time; off for --deterministic, so
+    /// redrawing the same data does not change the file
+    pub dated: bool,
+}
+
+fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    let mut out = Vec::with_capacity(data.len() + 12);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+    out
+}
+
+/// tEXt is Latin-1; anything else is replaced rather than mis-encoded
+fn latin1(s: &str) -> Vec<u8> {
+    s.chars().map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?')).collect()
+}
+
+/// Add `meta` to the PNG at `path`
+pub fn write(path: &Path, meta: &ChartMeta) -> Result<()> {
+    let png = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+    if !png.starts_with(SIGNATURE) || png.len() < HEADER_LEN || &png[12..16] != b"IHDR" {
+        bail!("{} is not a PNG", path.display());
+    }
+    let mut fields = vec![
+        ("Host", meta.host.to_string()),
+        ("Metric", meta.metric.to_string()),
+        ("Period", meta.period.to_string()),
+    ];
+    if let Some(coverage) = meta.coverage {
+        fields.push(("Coverage", format!("{:.1}%", coverage)));
+    }
+    if let Some(source) = meta.source {
+        fields.push(("Source", source.display().to_string()));
+    }
+    if meta.dated {
+        fields.push(("Software", format!("winbox-stats {}", env!("CARGO_PKG_VERSION"))));
+        fields.push(("Creation Time", Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)));
+    }
+
+    let mut out = png[..HEADER_LEN].to_vec();
+    for (key, value) in fields {
+        let mut data = latin1(key);
+        data.push(0);
+        data.extend(latin1(&value));
+        out.extend(chunk(b"tEXt", &data));
+    }
+    out.extend_from_slice(&png[HEADER_LEN..]);
+    fs::write(path, out).with_context(|| format!("write {}", path.display()))
+}
+
+/// The tEXt chunks of the PNG at `path`, in file order
+pub fn read(path: &Path) -> Result<Vec<(String, String)>> {
+    let png = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+    if !png.starts_with(SIGNATURE) {
+        bail!("{} is not a PNG", path.display());
+    }
+    let mut out = Vec::new();
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= png.len() {
+        let len = u32::from_be_bytes(png[pos..pos + 4].try_into()?) as usize;
+        let kind = &png[pos + 4..pos + 8];
+        let Some(data) = png.get(pos + 8..pos + 8 + len) else { bail!("{} is truncated", path.display()) };
+        if kind == b"tEXt" {
+            if let Some(nul) = data.iter().position(|b| *b == 0) {
+                let text = |b: &[u8]| b.iter().map(|c| char::from(*c)).collect::<String>();
+                out.push((text(&data[..nul]), text(&data[nul + 1..])));
+            }
+        }
+        if kind == b"IEND" {
+            break;
+        }
+        pos += len + 12;
+    }
+    Ok(out)
+}
+
+/// One file for `graph --list-meta --json`
+#[derive(Serialize)]
+struct Listed {
+    file: PathBuf,
+    meta: BTreeMap<String, String>,
+}
+
+/// Entry point for `winbox-stats graph --list-meta`
+pub fn run_list_meta(files: &[PathBuf], json: bool) -> Result<()> {
+    let mut listed = Vec::new();
+    for file in files {
+        let meta = read(file)?;
+        if json {
+            listed.push(Listed { file: file.clone(), meta: meta.into_iter().collect() });
+            continue;
+        }
+        println!("{}", file.display());
+        if meta.is_empty() {
+            println!("  (no metadata)");
+        }
+        for (key, value) in meta {
+            println!("  {}: {}", key, value);
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+    }
+    Ok(())
+}
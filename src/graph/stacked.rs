@@ -0,0 +1,183 @@
+//! `graph --disk-stack`: used GB of every volume of a host stacked over
+//! time, one chart per month, so the total and the volume it grows on are
+//! read off one picture.
+//!
+//! Volumes are recorded as used % and free GB; the used GB come from the
+//! two samples of the same run. A full volume has no free space to scale
+//! from and keeps the size last seen.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, NaiveDate};
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::pngmeta::{self, ChartMeta};
+use super::style::Style;
+use super::{gaps, ticks};
+use crate::series::{self, Series};
+use crate::timespan::Zone;
+
+const HOUR_MS: i64 = 3_600_000;
+
+/// Used GB of one volume, from its used % and free GB samples
+fn used_gb(percent: &[(i64, f64)], free_gb: &[(i64, f64)]) -> Vec<(i64, f64)> {
+    let free: BTreeMap<i64, f64> = free_gb.iter().copied().collect();
+    let mut size = None;
+    let mut out = Vec::new();
+    for &(ts, pct) in percent {
+        let Some(&free) = free.get(&ts) else { continue };
+        if pct < 99.99 {
+            size = Some(free / (1.0 - pct / 100.0));
+        }
+        if let Some(size) = size {
+            out.push((ts, (size - free).max(0.0)));
+        }
+    }
+    out
+}
+
+/// Volume -> used GB samples
+type Volumes<'a> = BTreeMap<&'a str, Vec<(i64, f64)>>;
+
+/// Host -> its volumes
+fn volumes(loaded: &Series) -> BTreeMap<&str, Volumes<'_>> {
+    let mut hosts: BTreeMap<&str, Volumes<'_>> = BTreeMap::new();
+    for ((host, metric), percent) in loaded {
+        if !metric.to_ascii_uppercase().ends_with("_DRIVE") {
+            continue;
+        }
+        let Some(free) = loaded.get(&(host.clone(), format!("{}_FREE_GB", metric))) else { continue };
+        let used = used_gb(percent, free);
+        if !used.is_empty() {
+            hosts.entry(host).or_default().insert(metric, used);
+        }
+    }
+    hosts
+}
+
+/// First of the month -> wall-clock hour in ms -> volume -> average used GB
+type Months<'a> = BTreeMap<NaiveDate, BTreeMap<i64, BTreeMap<&'a str, f64>>>;
+
+fn by_hour<'a>(volumes: &Volumes<'a>, zone: Zone) -> Months<'a> {
+    let mut sums: BTreeMap<(NaiveDate, i64, &str), (f64, usize)> = BTreeMap::new();
+    for (volume, samples) in volumes {
+        for &(ts, v) in samples {
+            let Some(wall) = zone.wall_clock(ts) else { continue };
+            let Some(month) = DateTime::from_timestamp(wall, 0).and_then(|t| t.date_naive().with_day(1)) else { continue };
+            let sum = sums.entry((month, (wall * 1000).div_euclid(HOUR_MS) * HOUR_MS, volume)).or_insert((0.0, 0));
+            sum.0 += v;
+            sum.1 += 1;
+        }
+    }
+    let mut months = Months::new();
+    for ((month, hour, volume), (sum, n)) in sums {
+        months.entry(month).or_default().entry(hour).or_default().insert(volume, sum / n as f64);
+    }
+    months
+}
+
+fn draw(out: &Path, style: &Style, when: &str, host: &str, zone: Zone, hours: &BTreeMap<i64, BTreeMap<&str, f64>>) -> Result<()> {
+    // The biggest volume at the bottom, where its line is read against zero
+    let mut totals: BTreeMap<&str, f64> = BTreeMap::new();
+    for (volume, v) in hours.values().flatten() {
+        *totals.entry(volume).or_default() += v;
+    }
+    let mut order: Vec<&str> = totals.keys().copied().collect();
+    order.sort_by(|a, b| totals[b].total_cmp(&totals[a]));
+
+    // Cumulative used GB per hour, bottom volume first
+    let stacks: Vec<(i64, Vec<f64>)> = hours
+        .iter()
+        .map(|(hour, used)| {
+            let mut sum = 0.0;
+            (*hour, order.iter().map(|v| { sum += used.get(v).copied().unwrap_or(0.0); sum }).collect())
+        })
+        .collect();
+    let min_x = stacks.first().map_or(0, |s| s.0);
+    let max_x = stacks.last().map_or(0, |s| s.0).max(min_x + HOUR_MS);
+    let top = stacks.iter().filter_map(|s| s.1.last()).fold(0.0, |a: f64, b| a.max(*b));
+    let plan = ticks::plan(min_x, max_x, style.width);
+
+    let root = BitMapBackend::new(out, (style.width, style.height)).into_drawing_area();
+    root.fill(&style.background)?;
+    let x_range = (min_x..max_x)
+        .with_key_points(plan.major.clone())
+        .with_light_points(plan.minor.clone());
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            style.caption(format!("{} {} used GB by volume", when, host), host, when, "disk-stack"),
+            (style.font.as_str(), 28).into_font().color(&style.text),
+        )
+        .margin(10)
+        .x_label_area_size(60)
+        .y_label_area_size(80)
+        .build_cartesian_2d(x_range, 0.0..(top * 1.05).max(1.0))?;
+    chart
+        .configure_mesh()
+        .axis_style(style.axis)
+        .bold_line_style(style.grid_major)
+        .light_line_style(style.grid_minor)
+        .x_label_formatter(&|x| ticks::format_x(*x, plan.label_fmt))
+        .y_labels(10)
+        .y_desc("Used GB")
+        .x_desc(match zone {
+            Zone::Local => plan.axis_desc.to_string(),
+            zone => format!("{} ({})", plan.axis_desc, zone.name()),
+        })
+        .axis_desc_style((style.font.as_str(), 22).into_font().color(&style.text))
+        .label_style((style.font.as_str(), 16).into_font().color(&style.text))
+        .draw()?;
+
+    // Each band is the area under its running total, drawn top band first so
+    // the ones below cover all but their own slice; hours the host was off
+    // break the bands like gaps break a line
+    let runs = gaps::split(&stacks.iter().map(|s| (s.0, 0.0)).collect::<Vec<_>>(), Some(HOUR_MS), style.gap_factor, (0, 0)).segments;
+    for (i, volume) in order.iter().enumerate().rev() {
+        let color = Palette99::pick(i).to_rgba();
+        let mut start = 0;
+        for run in &runs {
+            let band = &stacks[start..start + run.len()];
+            start += run.len();
+            chart.draw_series(AreaSeries::new(band.iter().map(|(x, sums)| (*x, sums[i])), 0.0, color.filled()))?;
+        }
+        let last = hours.values().rev().find_map(|used| used.get(volume)).copied().unwrap_or(0.0);
+        chart
+            .draw_series(std::iter::empty::<Rectangle<(i64, f64)>>())?
+            .label(format!("{} {:.1} GB", volume, last))
+            .legend(move |(x, y)| Rectangle::new([(x, y - 6), (x + 20, y + 6)], color.filled()));
+    }
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(style.background.mix(0.85))
+        .border_style(style.axis)
+        .label_font((style.font.as_str(), 18).into_font().color(&style.text))
+        .draw()?;
+    style.decorate(&root)?;
+    root.present()?;
+    Ok(())
+}
+
+/// Entry point for `winbox-stats graph --disk-stack`
+pub fn run_stacked(style: &Style, out_dir: &Path, zone: Zone) -> Result<Vec<PathBuf>> {
+    let filter = series::Filter { metric: Some("*_Drive*"), ..Default::default() };
+    let loaded = series::load(Path::new("."), &filter)?;
+
+    fs::create_dir_all(out_dir).with_context(|| format!("create {}", out_dir.display()))?;
+    let mut written = Vec::new();
+    for (host, volumes) in volumes(&loaded) {
+        for (month, hours) in by_hour(&volumes, zone) {
+            let ym = month.format("%Y%m").to_string();
+            let out = out_dir.join(format!("{}@{}@disk-stack.png", ym, host));
+            draw(&out, style, &ym, host, zone, &hours)?;
+            pngmeta::write(&out, &ChartMeta { host, metric: "disk-stack", period: &ym, coverage: None, source: None, dated: !style.deterministic })?;
+            log::info!("wrote {}", out.display());
+            written.push(out);
+        }
+    }
+    if written.is_empty() {
+        log::warn!("no volumes with both used % and free GB recorded here");
+    }
+    Ok(written)
+}
@@ -0,0 +1,260 @@
+//! Chart size, colours and drawing options.
+//!
+//! `[charts]` in the config brands every chart for reports that go to
+//! customers; `graph --title`, `--footer`, `--logo` and `--font` override it:
+//!
+//! ```toml
+//! [charts]
+//! title = "{host} {metric}, {when}"
+//! footer = "Example Corp managed services"
+//! logo = 'C:\ProgramData\winbox-stats\logo.png'
+//! font = "Segoe UI"
+//! ```
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ThresholdStyle {
+    /// Shade the warning and critical ranges
+    #[default]
+    Band,
+    /// Draw a horizontal line at each level
+    Line,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Envelope {
+    /// Only when there are more samples than the chart has pixels
+    #[default]
+    Auto,
+    /// Always draw the min–max band with the average line
+    Always,
+    /// Always draw every raw sample
+    Never,
+}
+
+/// Fields of a `title` template; `{title}` is the caption the chart would have had
+pub const TITLE_FIELDS: [&str; 4] = ["{title}", "{host}", "{when}", "{metric}"];
+
+/// Height of the logo in the top right corner
+const LOGO_PX: u32 = 32;
+/// The system's sans-serif, unless [charts] names a font
+pub const DEFAULT_FONT: &str = "sans-serif";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChartsConfig {
+    /// Caption template from [`TITLE_FIELDS`]
+    pub title: Option<String>,
+    /// Small print along the bottom edge
+    pub footer: Option<String>,
+    /// PNG, JPEG or BMP drawn in the top right corner
+    pub logo: Option<PathBuf>,
+    /// Font family of all text, e.g. `Segoe UI`; the system sans-serif when
+    /// unset or not installed
+    pub font: Option<String>,
+}
+
+/// A logo scaled to [`LOGO_PX`] and laid over the chart background
+pub struct Logo {
+    size: (u32, u32),
+    rgb: Vec<u8>,
+}
+
+impl Logo {
+    fn load(path: &Path, background: RGBColor) -> Result<Logo> {
+        let img = image::open(path).with_context(|| format!("read logo {}", path.display()))?;
+        let img = img.resize(LOGO_PX * 8, LOGO_PX, image::imageops::FilterType::Triangle).to_rgba8();
+        // Transparent parts show the background, which is all the logo sits on
+        let bg = [background.0, background.1, background.2];
+        let rgb = img
+            .pixels()
+            .flat_map(|p| {
+                let a = p.0[3] as f64 / 255.0;
+                (0..3).map(move |i| (p.0[i] as f64 * a + bg[i] as f64 * (1.0 - a)).round() as u8)
+            })
+            .collect();
+        Ok(Logo { size: img.dimensions(), rgb })
+    }
+}
+
+/// `graph --smooth`: a rolling average drawn bold over the faded samples
+pub struct Smooth {
+    pub window_ms: i64,
+    /// As given, e.g. `15m`, for the caption
+    pub label: String,
+}
+
+pub struct Style {
+    pub width: u32,
+    pub height: u32,
+    pub background: RGBColor,
+    /// Caption, labels and axis descriptions
+    pub text: RGBColor,
+    pub axis: RGBColor,
+    pub grid_major: RGBColor,
+    pub grid_minor: RGBColor,
+    pub line: RGBColor,
+    /// The earlier month of `--compare`
+    pub baseline: RGBColor,
+    /// The right-axis metric of `--combine`
+    pub second: RGBColor,
+    pub warning: RGBColor,
+    pub critical: RGBColor,
+    pub threshold_style: ThresholdStyle,
+    /// Spacing, in sampling intervals, beyond which the line is broken
+    pub gap_factor: f64,
+    pub envelope: Envelope,
+    pub smooth: Option<Smooth>,
+    /// Logarithmic y axis, except for up/down states
+    pub log_y: bool,
+    /// Font family of all text
+    pub font: String,
+    /// Caption template; `None` keeps the usual caption
+    pub title: Option<String>,
+    pub footer: Option<String>,
+    pub logo: Option<Logo>,
+    /// The default font whatever [charts] says, and no creation time in the
+    /// PNG metadata; the glyphs still depend on the fonts installed
+    pub deterministic: bool,
+}
+
+impl Style {
+    pub fn new(theme: Theme, width: u32, height: u32, line_color: Option<&str>) -> Result<Style> {
+        let mut style = match theme {
+            Theme::Light => Style {
+                width,
+                height,
+                background: RGBColor(255, 255, 255),
+                text: RGBColor(0, 0, 0),
+                axis: RGBColor(0, 0, 0),
+                grid_major: RGBColor(200, 200, 200),
+                grid_minor: RGBColor(235, 235, 235),
+                line: RGBColor(0, 0, 255),
+                baseline: RGBColor(150, 150, 150),
+                second: RGBColor(255, 127, 14),
+                warning: RGBColor(255, 170, 0),
+                critical: RGBColor(214, 39, 40),
+                threshold_style: ThresholdStyle::default(),
+                gap_factor: 3.0,
+                envelope: Envelope::default(),
+                smooth: None,
+                log_y: false,
+                font: DEFAULT_FONT.into(),
+                title: None,
+                footer: None,
+                logo: None,
+                deterministic: false,
+            },
+            Theme::Dark => Style {
+                width,
+                height,
+                background: RGBColor(24, 26, 31),
+                text: RGBColor(216, 217, 218),
+                axis: RGBColor(140, 142, 146),
+                grid_major: RGBColor(64, 66, 72),
+                grid_minor: RGBColor(40, 42, 48),
+                line: RGBColor(87, 148, 242),
+                baseline: RGBColor(130, 132, 138),
+                second: RGBColor(255, 152, 48),
+                warning: RGBColor(250, 222, 42),
+                critical: RGBColor(242, 73, 92),
+                threshold_style: ThresholdStyle::default(),
+                gap_factor: 3.0,
+                envelope: Envelope::default(),
+                smooth: None,
+                log_y: false,
+                font: DEFAULT_FONT.into(),
+                title: None,
+                footer: None,
+                logo: None,
+                deterministic: false,
+            },
+        };
+        if let Some(c) = line_color {
+            style.line = parse_color(c)?;
+        }
+        Ok(style)
+    }
+
+    /// Take font, title, footer and logo from `[charts]`
+    pub fn brand(&mut self, charts: &ChartsConfig) -> Result<()> {
+        if let Some(font) = &charts.font {
+            self.font = font.clone();
+        }
+        if let Some(title) = &charts.title {
+            // Anything still in braces after filling every field is a typo
+            if TITLE_FIELDS.iter().fold(title.clone(), |t, f| t.replace(f, "")).contains(['{', '}']) {
+                bail!("unknown field in chart title {:?}; use {}", title, TITLE_FIELDS.join(" "));
+            }
+            self.title = Some(title.clone());
+        }
+        self.footer = charts.footer.clone();
+        if let Some(path) = &charts.logo {
+            self.logo = Some(Logo::load(path, self.background)?);
+        }
+        Ok(())
+    }
+
+    /// `title` through the title template, if there is one
+    pub fn caption(&self, title: String, host: &str, when: &str, metric: &str) -> String {
+        let Some(template) = &self.title else { return title };
+        TITLE_FIELDS.iter().zip([title.as_str(), host, when, metric]).fold(template.clone(), |t, (f, v)| t.replace(f, v))
+    }
+
+    /// Footer and logo, over a finished chart
+    pub fn decorate<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        let (width, height) = root.dim_in_pixel();
+        if let Some(footer) = &self.footer {
+            let font = (self.font.as_str(), 14).into_font().color(&self.text);
+            root.draw(&Text::new(footer.clone(), (10, height as i32 - 20), font))?;
+        }
+        if let Some(logo) = &self.logo {
+            let at = (width as i32 - logo.size.0 as i32 - 10, 5);
+            if let Some(bitmap) = BitMapElement::with_ref(at, logo.size, &logo.rgb) {
+                root.draw(&bitmap)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `#RRGGBB`, `RRGGBB` or a basic colour name
+pub fn parse_color(s: &str) -> Result<RGBColor> {
+    let named = match s.to_ascii_lowercase().as_str() {
+        "black" => Some(RGBColor(0, 0, 0)),
+        "white" => Some(RGBColor(255, 255, 255)),
+        "red" => Some(RGBColor(214, 39, 40)),
+        "green" => Some(RGBColor(44, 160, 44)),
+        "blue" => Some(RGBColor(0, 0, 255)),
+        "orange" => Some(RGBColor(255, 127, 14)),
+        "purple" => Some(RGBColor(148, 103, 189)),
+        "cyan" => Some(RGBColor(23, 190, 207)),
+        "magenta" => Some(RGBColor(227, 119, 194)),
+        "gray" | "grey" => Some(RGBColor(127, 127, 127)),
+        _ => None,
+    };
+    if let Some(c) = named {
+        return Ok(c);
+    }
+    let hex = s.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("invalid colour {:?}; use #RRGGBB or a name like blue", s);
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok(RGBColor(channel(0), channel(2), channel(4)))
+}
@@ -0,0 +1,216 @@
+//! X-axis tick strategy for arbitrary time ranges, and the log-scale y axis.
+//!
+//! X values are wall-clock milliseconds (naive local time encoded as an
+//! epoch), so second, hour and day boundaries are plain multiples of the step.
+//!
+//! With `--log-y` values are drawn as their log10 on a linear axis whose
+//! labels show the values again, so every drawing step works unchanged.
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime};
+
+pub const SECOND: i64 = 1000;
+const MINUTE: i64 = 60 * SECOND;
+const HOUR: i64 = 60 * MINUTE;
+pub const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+
+/// 1970-01-05 was the first Monday after the epoch
+const FIRST_MONDAY: i64 = 4 * DAY;
+
+/// Upper bound on labelled ticks; one label per day of a month still fits
+const MAX_MAJOR: i64 = 31;
+
+/// Horizontal room a short label needs, in pixels
+const LABEL_PX: i64 = 50;
+
+#[derive(Clone, Copy)]
+enum Step {
+    /// Fixed length in milliseconds
+    Millis(i64),
+    Week,
+    Months(u32),
+}
+
+impl Step {
+    /// Rough length, only used to pick a level before generating points
+    fn approx_ms(self) -> i64 {
+        match self {
+            Step::Millis(ms) => ms,
+            Step::Week => WEEK,
+            Step::Months(n) => n as i64 * 30 * DAY,
+        }
+    }
+
+    fn points(self, min_x: i64, max_x: i64) -> Vec<i64> {
+        match self {
+            Step::Millis(ms) => aligned(min_x, max_x, ms, 0),
+            Step::Week => aligned(min_x, max_x, WEEK, FIRST_MONDAY),
+            Step::Months(n) => month_points(min_x, max_x, n),
+        }
+    }
+}
+
+/// Major step, minor step
+const LEVELS: [(Step, Step); 22] = [
+    // Sub-second and seconds apart, for `collect --count` bursts
+    (Step::Millis(100), Step::Millis(20)),
+    (Step::Millis(250), Step::Millis(50)),
+    (Step::Millis(500), Step::Millis(100)),
+    (Step::Millis(SECOND), Step::Millis(250)),
+    (Step::Millis(5 * SECOND), Step::Millis(SECOND)),
+    (Step::Millis(15 * SECOND), Step::Millis(5 * SECOND)),
+    (Step::Millis(30 * SECOND), Step::Millis(10 * SECOND)),
+    (Step::Millis(MINUTE), Step::Millis(15 * SECOND)),
+    (Step::Millis(5 * MINUTE), Step::Millis(MINUTE)),
+    (Step::Millis(15 * MINUTE), Step::Millis(5 * MINUTE)),
+    (Step::Millis(30 * MINUTE), Step::Millis(5 * MINUTE)),
+    (Step::Millis(HOUR), Step::Millis(15 * MINUTE)),
+    (Step::Millis(3 * HOUR), Step::Millis(HOUR)),
+    (Step::Millis(6 * HOUR), Step::Millis(HOUR)),
+    (Step::Millis(12 * HOUR), Step::Millis(3 * HOUR)),
+    (Step::Millis(DAY), Step::Millis(6 * HOUR)),
+    (Step::Millis(2 * DAY), Step::Millis(DAY)),
+    (Step::Week, Step::Millis(DAY)),
+    (Step::Months(1), Step::Week),
+    (Step::Months(3), Step::Months(1)),
+    (Step::Months(6), Step::Months(1)),
+    (Step::Months(12), Step::Months(3)),
+];
+
+pub struct Ticks {
+    /// Labelled ticks with bold grid lines
+    pub major: Vec<i64>,
+    /// Unlabelled ticks with light grid lines
+    pub minor: Vec<i64>,
+    /// strftime format for major labels
+    pub label_fmt: &'static str,
+    pub axis_desc: &'static str,
+}
+
+fn aligned(min_x: i64, max_x: i64, step: i64, offset: i64) -> Vec<i64> {
+    let first = (min_x - offset).div_euclid(step) * step + offset;
+    let first = if first < min_x { first + step } else { first };
+    (0..)
+        .map(|i| first + i * step)
+        .take_while(|x| *x <= max_x)
+        .collect()
+}
+
+fn month_points(min_x: i64, max_x: i64, every: u32) -> Vec<i64> {
+    let start = to_naive(min_x);
+    let mut idx = start.year() * 12 + start.month0() as i32;
+    idx += (every as i32 - idx.rem_euclid(every as i32)) % every as i32;
+    let mut out = Vec::new();
+    loop {
+        let x = NaiveDate::from_ymd_opt(idx.div_euclid(12), idx.rem_euclid(12) as u32 + 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis();
+        if x > max_x {
+            break;
+        }
+        if x >= min_x {
+            out.push(x);
+        }
+        idx += every as i32;
+    }
+    out
+}
+
+pub fn to_naive(x: i64) -> NaiveDateTime {
+    DateTime::from_timestamp_millis(x).unwrap_or_default().naive_utc()
+}
+
+pub fn format_x(x: i64, fmt: &str) -> String {
+    to_naive(x).format(fmt).to_string()
+}
+
+fn label_format(major: Step, same_day: bool, one_week: bool, same_month: bool) -> (&'static str, &'static str) {
+    match major {
+        Step::Millis(ms) if ms < SECOND && same_day => ("%H:%M:%S%.3f", "Time"),
+        Step::Millis(ms) if ms < MINUTE && same_day => ("%H:%M:%S", "Time"),
+        Step::Millis(ms) if ms < DAY && same_day => ("%H:%M", "Time"),
+        Step::Millis(ms) if ms < DAY && one_week => ("%a %H:%M", "Weekday / Time"),
+        Step::Millis(ms) if ms < DAY => ("%d %H:%M", "Day / Time"),
+        Step::Millis(_) if one_week => ("%a %d", "Weekday"),
+        Step::Millis(_) if same_month => ("%d", "Date"),
+        Step::Millis(_) | Step::Week => ("%m-%d", "Date"),
+        Step::Months(_) => ("%Y-%m", "Month"),
+    }
+}
+
+/// `--log-y`: values to plot y, flooring zero and below at the decade under
+/// the smallest positive value
+#[derive(Clone, Copy)]
+pub struct LogY {
+    floor: f64,
+}
+
+impl LogY {
+    pub fn new(values: impl Iterator<Item = f64>) -> LogY {
+        let min = values.filter(|v| *v > 0.0).fold(f64::INFINITY, f64::min);
+        let floor = if min.is_finite() { 10f64.powf(min.log10().floor()) } else { 1.0 };
+        LogY { floor }
+    }
+
+    pub fn y(&self, v: f64) -> f64 {
+        v.max(self.floor).log10()
+    }
+
+    /// Whole decades from the floor to at least `hi`
+    pub fn range(&self, hi: f64) -> (f64, f64) {
+        let lo = self.floor.log10();
+        (lo, self.y(hi).ceil().max(lo + 1.0))
+    }
+
+    /// One label per decade
+    pub fn labels(&self, hi: f64) -> usize {
+        let (lo, hi) = self.range(hi);
+        (hi - lo) as usize + 1
+    }
+}
+
+/// Axis label of a log-scale y: the value, to three significant digits
+pub fn format_log(y: f64) -> String {
+    let v = 10f64.powf(y);
+    let decimals = (2 - v.log10().floor() as i32).max(0) as usize;
+    let s = format!("{:.*}", decimals, v);
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+/// Choose the finest major step whose labels still fit side by side in `width_px`.
+pub fn plan(min_x: i64, max_x: i64, width_px: u32) -> Ticks {
+    let max_major = (width_px as i64 / LABEL_PX).clamp(4, MAX_MAJOR);
+    let span = (max_x - min_x).max(1);
+    let first = to_naive(min_x);
+    // A range ending exactly at midnight still belongs to the day before
+    let last = to_naive((max_x - 1).max(min_x));
+    let same_day = first.date() == last.date();
+    let one_week = span <= WEEK;
+    let same_month = first.year() == last.year() && first.month() == last.month();
+
+    let (major, minor, (label_fmt, axis_desc)) = LEVELS
+        .iter()
+        .map(|&(major, minor)| (major, minor, label_format(major, same_day, one_week, same_month)))
+        .find(|(major, _, (fmt, _))| {
+            // Wide "day hour" labels need twice the room
+            let max = if fmt.len() > 5 { max_major / 2 } else { max_major };
+            span / major.approx_ms() <= max
+        })
+        .unwrap_or_else(|| {
+            let (major, minor) = LEVELS[LEVELS.len() - 1];
+            (major, minor, label_format(major, same_day, one_week, same_month))
+        });
+
+    Ticks {
+        major: major.points(min_x, max_x),
+        minor: minor.points(min_x, max_x),
+        label_fmt,
+        axis_desc,
+    }
+}
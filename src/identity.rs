@@ -0,0 +1,92 @@
+// src/identity.rs
+//! Which host a sample belongs to.
+//!
+//! Normally that is just the machine name. On a failover cluster node the
+//! clustered role can be named instead, so a workload keeps one series while it
+//! moves between physical nodes; the node that took each sample is still
+//! recorded in the `Nodes` table.
+//!
+//! A fixed host label can stand in for the machine name too, for VDI clones
+//! and machines renamed mid-month, and an instance tag keeps two collectors
+//! on one machine apart (`YYYYMM@<label>#<instance>.sqlite`).
+use anyhow::{bail, Result};
+use hostname::get as get_hostname;
+
+pub struct Identity {
+    /// Name used in the DB filename (`YYYYMM@<series>.sqlite`)
+    pub series: String,
+    /// Physical machine taking the sample
+    pub node: String,
+    /// Failover cluster this node belongs to, if any
+    pub cluster: Option<String>,
+    /// Clustered role the series follows, if requested
+    pub role: Option<String>,
+    /// Name given instead of the machine name, if any
+    pub host_label: Option<String>,
+}
+
+/// Name overrides from `collect` flags, falling back to the config
+#[derive(Default)]
+pub struct Naming<'a> {
+    pub cluster_role: Option<&'a str>,
+    pub host_label: Option<&'a str>,
+    pub instance: Option<&'a str>,
+}
+
+/// Upper-cased; only characters that are safe in a file name and cannot be
+/// confused with the `@` and `#` separators
+fn name_part(value: &str, what: &str) -> Result<Option<String>> {
+    let v = value.trim().to_uppercase();
+    if v.is_empty() {
+        return Ok(None);
+    }
+    if !v.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        bail!("{} {:?} may only contain letters, digits, '-', '_' and '.'", what, value);
+    }
+    Ok(Some(v))
+}
+
+pub fn hostname_upper() -> String {
+    get_hostname()
+        .ok()
+        .and_then(|s| s.into_string().ok())
+        .unwrap_or_else(|| "UNKNOWN".into())
+        .to_uppercase()
+}
+
+#[cfg(windows)]
+fn cluster_name() -> Option<String> {
+    use winreg::enums::HKEY_LOCAL_MACHINE;
+    use winreg::RegKey;
+
+    // Present only on nodes with the Failover Clustering feature configured
+    RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey("Cluster")
+        .ok()?
+        .get_value::<String, _>("ClusterName")
+        .ok()
+        .map(|s| s.to_uppercase())
+}
+
+#[cfg(not(windows))]
+fn cluster_name() -> Option<String> {
+    None
+}
+
+pub fn resolve(naming: &Naming) -> Result<Identity> {
+    let node = hostname_upper();
+    let cluster = cluster_name();
+    let role = naming.cluster_role.map(|r| r.trim().to_uppercase()).filter(|r| !r.is_empty());
+    if role.is_some() && cluster.is_none() {
+        log::warn!("--cluster-role given but {} is not a failover cluster node", node);
+    }
+    let host_label = naming.host_label.map(|l| name_part(l, "host label")).transpose()?.flatten();
+    if role.is_some() && host_label.is_some() {
+        log::warn!("host label ignored: the series follows the cluster role");
+    }
+    let mut series = role.clone().or_else(|| host_label.clone()).unwrap_or_else(|| node.clone());
+    if let Some(instance) = naming.instance.map(|i| name_part(i, "instance")).transpose()?.flatten() {
+        series = format!("{}#{}", series, instance);
+    }
+    Ok(Identity { series, node, cluster, role, host_label })
+}
@@ -0,0 +1,397 @@
+// src/import.rs
+//! `import`: samples exported by other tools, written into the same monthly
+//! `YYYYMM@HOST.sqlite` files the collector writes, so legacy history graphs
+//! and reports like anything collected here.
+//!
+//! Timestamps may be epoch seconds (or milliseconds), RFC 3339 with an
+//! offset, or local wall-clock text such as `2025-08-14 02:00:00`.
+//!
+//! `import perfmon` reads Performance Monitor logs: relog's CSV or TSV
+//! directly, `.blg` through `relog.exe`. Counters this tool also collects land
+//! in its own tables (`\Processor(_Total)\% Processor Time` in `CPU`, `%
+//! Free Space` of a drive as used % in `C_Drive`, ...), `[[perf_counters]]`
+//! paths in their label, and anything else in a table named after the path.
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::aliases::Aliases;
+use crate::audit;
+use crate::cli::{ImportCsvArgs, ImportJsonArgs, ImportPerfmonArgs};
+use crate::config::Config;
+use crate::perf;
+use crate::storage::{self, ensure_table, insert_sample, metric_label};
+use crate::timespan;
+
+/// Epoch values past this are milliseconds (it is the year 5138 in seconds)
+const MS_EPOCH: i64 = 100_000_000_000;
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Samples of one series, as read from a file
+type Imported = BTreeMap<(String, String), Vec<(i64, f64)>>;
+
+/// One database written, for the summary and `--output json`
+#[derive(Serialize)]
+struct Written {
+    database: PathBuf,
+    host: String,
+    metric: String,
+    rows: usize,
+}
+
+/// One record of a JSON import; `export jsonl` writes the same shape
+#[derive(Deserialize)]
+struct Record {
+    #[serde(alias = "timestamp")]
+    ts: serde_json::Value,
+    value: f64,
+    host: Option<String>,
+    metric: Option<String>,
+}
+
+/// Epoch seconds from any of the timestamp forms above
+fn parse_time(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if let Ok(n) = s.parse::<i64>() {
+        return Some(if n.abs() >= MS_EPOCH { n / 1000 } else { n });
+    }
+    if let Ok(f) = s.parse::<f64>() {
+        return Some(f as i64);
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.timestamp());
+    }
+    timespan::parse_local_time(s).and_then(|t| Local.from_local_datetime(&t).earliest()).map(|t| t.timestamp())
+}
+
+/// Fields of one CSV line; quoted fields may contain the separator and `""`
+fn split_csv(line: &str, sep: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// `timestamp,value` lines; a header and lines that do not parse (or hold
+/// NaN or an infinity, which cannot be stored) are skipped
+fn read_csv(text: &str) -> (Vec<(i64, f64)>, usize) {
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Some tools write `;` where the decimal separator is a comma
+        let sep = if line.contains(',') && !line.contains(';') { ',' } else { ';' };
+        let fields = split_csv(line, sep);
+        let parsed = match &fields[..] {
+            [ts, value, ..] => parse_time(ts).zip(value.trim().replace(',', ".").parse::<f64>().ok().filter(|v| v.is_finite())),
+            _ => None,
+        };
+        match parsed {
+            Some(sample) => samples.push(sample),
+            None if i == 0 => {}
+            None => skipped += 1,
+        }
+    }
+    (samples, skipped)
+}
+
+/// A JSON array of records, or one record per line
+fn read_json(text: &str, host: Option<&str>, metric: Option<&str>) -> Result<Imported> {
+    let records: Vec<Record> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(text).context("parse the JSON array")?
+    } else {
+        text.lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+            .map(|(i, l)| serde_json::from_str(l).with_context(|| format!("parse line {}", i + 1)))
+            .collect::<Result<_>>()?
+    };
+    let mut out = Imported::new();
+    for (i, r) in records.into_iter().enumerate() {
+        let ts = match &r.ts {
+            serde_json::Value::Number(n) => n.as_i64().map(|n| if n.abs() >= MS_EPOCH { n / 1000 } else { n }),
+            serde_json::Value::String(s) => parse_time(s),
+            _ => None,
+        };
+        let Some(ts) = ts else { bail!("record {} has an unreadable timestamp {}", i + 1, r.ts) };
+        let (Some(h), Some(m)) = (host.map(str::to_string).or(r.host), metric.map(str::to_string).or(r.metric)) else {
+            bail!("record {} has no host or metric; pass --host and --metric", i + 1)
+        };
+        out.entry((h, m)).or_default().push((ts, r.value));
+    }
+    Ok(out)
+}
+
+/// Table and conversion for a counter path without its `\\MACHINE` part
+fn perfmon_metric(path: &str, config: &Config) -> (String, fn(f64) -> f64) {
+    let same = |p: &str| path.eq_ignore_ascii_case(p);
+    if let Some(c) = config.perf_counters.iter().find(|c| same(&c.path)) {
+        return (metric_label(&c.label), |v| v);
+    }
+    let lower = path.to_ascii_lowercase();
+    if same(r"\Processor(_Total)\% Processor Time") {
+        ("CPU".into(), |v| v)
+    } else if same(r"\System\Processes") {
+        ("PROCS".into(), |v| v)
+    } else if same(r"\Process(_Total)\Handle Count") {
+        ("HANDLES".into(), |v| v)
+    } else if let Some((table, _)) = perf::SYSTEM_RATES.iter().find(|(_, p)| same(p)) {
+        (table.to_string(), |v| v)
+    } else if same(r"\Memory\Committed Bytes") {
+        ("RAM_COMMITTED_GB".into(), |v| v / GB)
+    } else if same(r"\Memory\Cache Bytes") {
+        ("RAM_CACHED_GB".into(), |v| v / GB)
+    } else if let Some(drive) = lower
+        .strip_prefix(r"\logicaldisk(")
+        .and_then(|rest| rest.strip_suffix(r":)\% free space"))
+        .filter(|d| d.len() == 1)
+    {
+        (format!("{}_Drive", drive.to_ascii_uppercase()), |v| 100.0 - v)
+    } else {
+        (metric_label(path.trim_start_matches('\\')), |v| v)
+    }
+}
+
+/// `(PDH-CSV 4.0) (Pacific Standard Time)(480)` -> 480, the minutes local
+/// time is behind UTC where the log was recorded
+fn pdh_bias(header: &str) -> Option<i64> {
+    let inner = header.trim().strip_suffix(')')?;
+    inner[inner.rfind('(')? + 1..].trim().parse().ok()
+}
+
+/// relog's CSV or TSV: a timestamp column, then one column per counter path.
+/// Skipped are lines without a readable time and NaN or infinite readings.
+fn read_perfmon(text: &str, host: Option<&str>, config: &Config) -> Result<(Imported, usize)> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let header = lines.next().context("empty file")?;
+    let sep = if header.contains('\t') { '\t' } else { ',' };
+    let columns = split_csv(header, sep);
+    let bias = columns.first().and_then(|h| pdh_bias(h));
+    let mut targets = Vec::new();
+    for column in columns.iter().skip(1) {
+        // `\\WEB01\Processor(_Total)\% Processor Time`
+        let (machine, path) = match column.strip_prefix("\\\\").and_then(|rest| rest.split_once('\\')) {
+            Some((machine, path)) => (machine, format!("\\{}", path)),
+            None => ("", column.clone()),
+        };
+        let Some(host) = host.or((!machine.is_empty()).then_some(machine)) else {
+            bail!("counter {} names no computer; pass --host", column)
+        };
+        let (metric, convert) = perfmon_metric(&path, config);
+        targets.push(((host.to_string(), metric), convert));
+    }
+
+    let mut out = Imported::new();
+    let mut skipped = 0;
+    for line in lines {
+        let fields = split_csv(line, sep);
+        let Some(t) = fields.first().and_then(|f| NaiveDateTime::parse_from_str(f.trim(), "%m/%d/%Y %H:%M:%S%.f").ok()) else {
+            skipped += 1;
+            continue;
+        };
+        let ts = match bias {
+            Some(minutes) => t.and_utc().timestamp() + minutes * 60,
+            None => match Local.from_local_datetime(&t).earliest() {
+                Some(t) => t.timestamp(),
+                None => continue,
+            },
+        };
+        // Counters without a reading at this time are blank
+        for ((key, convert), field) in targets.iter().zip(fields.iter().skip(1)) {
+            match field.trim().parse::<f64>().map(convert) {
+                Ok(v) if v.is_finite() => out.entry(key.clone()).or_default().push((ts, v)),
+                Ok(_) => skipped += 1,
+                Err(_) => {}
+            }
+        }
+    }
+    Ok((out, skipped))
+}
+
+/// relog's CSV of a binary `.blg` log, in a temporary file
+fn relog(blg: &Path) -> Result<String> {
+    if !cfg!(windows) {
+        bail!("reading .blg needs relog.exe on Windows; there, run `relog {} -f csv -o log.csv` and import log.csv", blg.display());
+    }
+    let dir = tempfile::tempdir()?;
+    let csv = dir.path().join("relog.csv");
+    let output = std::process::Command::new("relog")
+        .arg(blg)
+        .args(["-f", "csv", "-y", "-o"])
+        .arg(&csv)
+        .output()
+        .context("run relog.exe")?;
+    if !csv.is_file() {
+        bail!("relog could not convert {} ({}): {}", blg.display(), output.status, String::from_utf8_lossy(&output.stdout).trim());
+    }
+    fs::read_to_string(&csv).with_context(|| format!("read {}", csv.display()))
+}
+
+/// A host name from the input, which becomes part of a file name
+fn checked_host(host: &str) -> Result<&str> {
+    let host = host.trim();
+    if host.is_empty() || host.contains("..") || !host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        bail!("host {:?} may only contain letters, digits, '-', '_' and '.'", host);
+    }
+    Ok(host)
+}
+
+/// A metric name from the input as a table name; names that already are
+/// one keep their case
+fn table_name(metric: &str) -> String {
+    if !metric.is_empty() && metric.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        metric.to_string()
+    } else {
+        metric_label(metric)
+    }
+}
+
+/// Write every series into its monthly databases under `dir`
+fn write(dir: &Path, imported: Imported, source: &Path) -> Result<Vec<Written>> {
+    let aliases = Aliases::load(dir)?;
+    let mut written = Vec::new();
+    for ((host, metric), mut samples) in imported {
+        let host = checked_host(&host)?.to_string();
+        // Under the current name, as the collector writes it
+        let metric = aliases.resolve(&table_name(&metric));
+        samples.sort_by_key(|s| s.0);
+        let mut months: BTreeMap<String, Vec<(i64, f64)>> = BTreeMap::new();
+        for (ts, v) in samples {
+            let Some(t) = DateTime::from_timestamp(ts, 0) else { continue };
+            months.entry(t.with_timezone(&Local).format("%Y%m").to_string()).or_default().push((ts, v));
+        }
+        for (ym, samples) in months {
+            let path = dir.join(format!("{}@{}.sqlite", ym, host));
+            let mut conn = storage::open(&path)?;
+            let tx = conn.transaction()?;
+            // Local text was read in this machine's zone
+            if storage::get_meta(&tx, "timezone")?.is_none() {
+                storage::record_timezone(&tx)?;
+            }
+            ensure_table(&tx, &metric)?;
+            let text = storage::has_text_timestamps(&tx, &metric)?;
+            for &(ts, v) in &samples {
+                insert_sample(&tx, &metric, ts, v, None, text)?;
+            }
+            tx.commit()?;
+            let detail = format!("{} {} samples of {} from {}", samples.len(), host, metric, source.display());
+            audit::record(dir, "import", &path.display().to_string(), &detail)?;
+            log::info!("imported {}", detail);
+            written.push(Written { database: path, host: host.clone(), metric: metric.clone(), rows: samples.len() });
+        }
+    }
+    Ok(written)
+}
+
+fn report(written: &[Written], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(written)?);
+        return Ok(());
+    }
+    if written.is_empty() {
+        log::warn!("no samples to import");
+    }
+    for w in written {
+        println!("Imported {} samples of {} {} into {}", w.rows, w.host, w.metric, w.database.display());
+    }
+    Ok(())
+}
+
+/// Entry point for `winbox-stats import csv`
+pub fn run_import_csv(args: &ImportCsvArgs) -> Result<()> {
+    let text = fs::read_to_string(&args.file).with_context(|| format!("read {}", args.file.display()))?;
+    let (samples, skipped) = read_csv(&text);
+    if skipped > 0 {
+        log::warn!("skipped {} lines of {} without a readable timestamp and finite value", skipped, args.file.display());
+    }
+    let mut imported = Imported::new();
+    if !samples.is_empty() {
+        imported.insert((args.host.clone(), args.metric.clone()), samples);
+    }
+    report(&write(Path::new("."), imported, &args.file)?, args.json)
+}
+
+/// Entry point for `winbox-stats import json`
+pub fn run_import_json(args: &ImportJsonArgs) -> Result<()> {
+    let text = fs::read_to_string(&args.file).with_context(|| format!("read {}", args.file.display()))?;
+    let imported = read_json(&text, args.host.as_deref(), args.metric.as_deref()).with_context(|| format!("import {}", args.file.display()))?;
+    report(&write(Path::new("."), imported, &args.file)?, args.json)
+}
+
+/// Entry point for `winbox-stats import perfmon`
+pub fn run_import_perfmon(args: &ImportPerfmonArgs, config: &Config) -> Result<()> {
+    let blg = args.file.extension().is_some_and(|e| e.eq_ignore_ascii_case("blg"));
+    let text = if blg { relog(&args.file)? } else { fs::read_to_string(&args.file).with_context(|| format!("read {}", args.file.display()))? };
+    let (imported, skipped) = read_perfmon(&text, args.host.as_deref(), config).with_context(|| format!("import {}", args.file.display()))?;
+    if skipped > 0 {
+        log::warn!("skipped {} lines or readings of {} without a readable timestamp or finite value", skipped, args.file.display());
+    }
+    report(&write(Path::new("."), imported, &args.file)?, args.json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_parse_in_every_form() {
+        assert_eq!(parse_time("1723600000"), Some(1_723_600_000));
+        assert_eq!(parse_time(" 1723600000123 "), Some(1_723_600_000));
+        assert_eq!(parse_time("1723600000.75"), Some(1_723_600_000));
+        assert_eq!(parse_time("2024-08-14T02:00:00+10:00"), Some(1_723_564_800));
+        let local = Local.with_ymd_and_hms(2024, 8, 14, 12, 0, 0).earliest().unwrap().timestamp();
+        assert_eq!(parse_time("2024-08-14 12:00:00"), Some(local));
+        assert_eq!(parse_time("yesterday"), None);
+    }
+
+    #[test]
+    fn csv_fields_keep_quoted_separators() {
+        assert_eq!(split_csv("a,b,,c", ','), ["a", "b", "", "c"]);
+        assert_eq!(split_csv(r#""1,5";"say ""hi""""#, ';'), ["1,5", r#"say "hi""#]);
+        assert_eq!(split_csv("", ','), [""]);
+    }
+
+    #[test]
+    fn csv_skips_the_header_and_unusable_lines() {
+        let text = "time,value\n1723600000,12.5\n\n1723600060;13,5\nnot a time,1\n1723600120,NaN\n1723600180,inf\n1723600240\n";
+        let (samples, skipped) = read_csv(text);
+        assert_eq!(samples, [(1_723_600_000, 12.5), (1_723_600_060, 13.5)]);
+        assert_eq!(skipped, 4);
+    }
+
+    #[test]
+    fn pdh_headers_give_the_bias() {
+        assert_eq!(pdh_bias("(PDH-CSV 4.0) (Pacific Standard Time)(480)"), Some(480));
+        assert_eq!(pdh_bias("(PDH-CSV 4.0) (AUS Eastern Standard Time)(-600) "), Some(-600));
+        assert_eq!(pdh_bias("(PDH-CSV 4.0)"), None);
+        assert_eq!(pdh_bias("Time"), None);
+    }
+
+    #[test]
+    fn names_from_the_input_cannot_escape() {
+        assert_eq!(checked_host(" web01.corp ").unwrap(), "web01.corp");
+        for bad in ["", "..", "a/b", r"a\b", "../x", "c:", "a b"] {
+            assert!(checked_host(bad).is_err(), "{:?}", bad);
+        }
+        assert_eq!(table_name("C_Drive"), "C_Drive");
+        assert_eq!(table_name(r#"X"(a); DROP TABLE "CPU"; --"#), "X__A___DROP_TABLE__CPU_____");
+    }
+}
@@ -0,0 +1,66 @@
+// src/install.rs
+//! `install-systemd`: a oneshot service plus a timer that runs collection.
+use anyhow::{Context, Result};
+use std::fs;
+
+use crate::cli::InstallSystemdArgs;
+
+const UNIT_NAME: &str = "winbox-stats";
+
+fn service_unit(exe: &str, data_dir: &str) -> String {
+    format!(
+        "[Unit]
+Description=winbox-stats sample collection
+After=local-fs.target
+
+[Service]
+Type=oneshot
+WorkingDirectory={data_dir}
+ExecStart={exe} collect
+"
+    )
+}
+
+fn timer_unit(interval: &str) -> String {
+    format!(
+        "[Unit]
+Description=Run winbox-stats collection every {interval}
+
+[Timer]
+OnBootSec=1min
+OnUnitActiveSec={interval}
+AccuracySec=1s
+
+[Install]
+WantedBy=timers.target
+"
+    )
+}
+
+/// Entry point for `winbox-stats install-systemd`
+pub fn run_install_systemd(args: &InstallSystemdArgs) -> Result<()> {
+    if !cfg!(target_os = "linux") {
+        anyhow::bail!("systemd units can only be installed on Linux; use Task Scheduler on Windows");
+    }
+    let exe = std::env::current_exe().context("locate winbox-stats executable")?;
+    fs::create_dir_all(&args.data_dir)
+        .with_context(|| format!("create {}", args.data_dir.display()))?;
+
+    let units = [
+        (
+            format!("{}.service", UNIT_NAME),
+            service_unit(&exe.to_string_lossy(), &args.data_dir.to_string_lossy()),
+        ),
+        (format!("{}.timer", UNIT_NAME), timer_unit(&args.interval)),
+    ];
+    for (name, body) in units {
+        let path = args.unit_dir.join(name);
+        fs::write(&path, body).with_context(|| format!("write {}", path.display()))?;
+        println!("Wrote {}", path.display());
+    }
+    println!(
+        "Enable with: systemctl daemon-reload && systemctl enable --now {}.timer",
+        UNIT_NAME
+    );
+    Ok(())
+}
@@ -0,0 +1,86 @@
+// src/lock.rs
+//! One `collect` per series at a time. Overlapping scheduled tasks would
+//! otherwise both sample and write; the second one now waits (`--wait`) or
+//! stops with [`EXIT_BUSY`]. The lock is the operating system's file lock on
+//! `winbox-stats-<series>.lock`, so it goes away with the process even if
+//! that crashes; the file itself is left in place.
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exit code when another collect holds the lock; after 3 (UNKNOWN) so a
+/// scheduler can tell a skipped run from a failed one
+pub const EXIT_BUSY: u8 = 4;
+const POLL: Duration = Duration::from_millis(250);
+
+/// Another process holds the lock
+#[derive(Debug)]
+pub struct Busy {
+    path: PathBuf,
+    /// Process id the holder wrote into the file, if it could be read
+    pid: Option<u32>,
+}
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "another collect is already running")?;
+        if let Some(pid) = self.pid {
+            write!(f, " (process {})", pid)?;
+        }
+        write!(f, "; it holds {}. Use --wait to queue behind it", self.path.display())
+    }
+}
+
+impl std::error::Error for Busy {}
+
+/// Held until dropped
+pub struct InstanceLock {
+    _file: File,
+}
+
+pub fn lock_path(dir: &Path, series: &str) -> PathBuf {
+    dir.join(format!("winbox-stats-{}.lock", series))
+}
+
+/// Take the lock of `series` in `dir`, waiting up to `wait` seconds for the
+/// holder to finish
+pub fn acquire(dir: &Path, series: &str, wait: i64) -> Result<InstanceLock> {
+    let path = lock_path(dir, series);
+    // Not truncated on open: that would wipe the holder's pid
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("open {}", path.display()))?;
+    let deadline = Instant::now() + Duration::from_secs(wait.max(0) as u64);
+    let mut logged = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(std::fs::TryLockError::WouldBlock) if Instant::now() < deadline => {
+                if !logged {
+                    log::info!("waiting for the collect holding {}", path.display());
+                    logged = true;
+                }
+                thread::sleep(POLL);
+            }
+            Err(std::fs::TryLockError::WouldBlock) => {
+                // Windows locks are mandatory, so the holder's pid may not be readable
+                let mut text = String::new();
+                let pid = file.read_to_string(&mut text).ok().and_then(|_| text.trim().parse().ok());
+                return Err(Busy { path, pid }.into());
+            }
+            Err(std::fs::TryLockError::Error(e)) => return Err(e).with_context(|| format!("lock {}", path.display())),
+        }
+    }
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id())?;
+    Ok(InstanceLock { _file: file })
+}
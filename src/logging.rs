@@ -0,0 +1,33 @@
+// src/logging.rs
+//! `--verbose`/`--quiet`/`--log-file` wiring for the `log` macros.
+//!
+//! Diagnostics go to stderr, or appended to a file for runs under Task
+//! Scheduler or cron where nobody sees the console. `RUST_LOG` still
+//! overrides the level, e.g. `RUST_LOG=winbox_stats::push=debug`.
+use anyhow::{Context, Result};
+use env_logger::{Builder, Target};
+use log::LevelFilter;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+pub fn init(verbose: u8, quiet: bool, log_file: Option<&Path>) -> Result<()> {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    let mut builder = Builder::new();
+    builder.filter_level(level).parse_default_env();
+    if let Some(path) = log_file {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("open log file {}", path.display()))?;
+        builder.target(Target::Pipe(Box::new(file))).write_style(env_logger::WriteStyle::Never);
+    }
+    builder.try_init()?;
+    Ok(())
+}
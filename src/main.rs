@@ -1,17 +1,157 @@
 use anyhow::Result;
 use clap::Parser;
+use std::path::Path;
+use std::process::ExitCode;
 
+mod aliases;
+mod analyze;
+mod apps;
+mod archive;
+mod audit;
+mod backend;
+mod backfill;
+mod baseline;
+mod check;
 mod cli;
+mod cold;
 mod collect;
+mod config;
+mod crypto;
+mod daemon;
+mod dedupe;
+mod disks;
+mod doctor;
+mod eventlog;
+mod exec;
+mod export_json;
+mod forecast;
 mod graph;
+mod identity;
+mod import;
+mod install;
+mod lock;
+mod logging;
+mod metric_info;
+mod notify;
+mod patches;
+mod perf;
+mod pgsink;
+mod probes;
+mod progress;
+mod push;
+mod query;
+mod report;
+mod report_html;
+mod retention;
+mod serve;
+mod selfmon;
+mod series;
+mod services;
+mod shares;
+mod smart;
+mod spool;
+mod stats;
+mod statsd;
+mod storage;
+mod timespan;
+mod top;
+mod verify;
+mod viewer;
+mod virt;
+mod watch;
+mod wmi;
+mod zabbix;
 
-use cli::{Cli, Command};
+use cli::{BaselineAction, Cli, CollectArgs, Command, ExportFormat, ImportFormat, Output, ServeApi};
+use check::Status;
+use config::Config;
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// Exit code of any failure; after 0-2 for OK, warning and critical, so
+/// Nagios reads it as UNKNOWN
+const EXIT_ERROR: u8 = 3;
+
+fn main() -> ExitCode {
+    // clap's own exit code for a usage error is 2, which would read as CRITICAL
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() { ExitCode::from(EXIT_ERROR) } else { ExitCode::SUCCESS };
+        }
+    };
+    match run(cli) {
+        Ok(status) => ExitCode::from(status.exit_code()),
+        // A run skipped for another one is not a failure worth a backtrace
+        Err(e) if e.is::<lock::Busy>() => {
+            eprintln!("{}", e);
+            ExitCode::from(lock::EXIT_BUSY)
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// The command's worst threshold status, for `check` and `collect`
+fn run(mut cli: Cli) -> Result<Status> {
+    let json = cli.output == Output::Json;
+    if let (true, Some(command)) = (json, cli.command.as_mut()) {
+        command.set_json();
+    }
+    logging::init(cli.verbose, cli.quiet, cli.log_file.as_deref())?;
+    storage::set_immutable(cli.immutable);
+    // Before loading the config, so a broken one is diagnosed rather than fatal
+    if let Some(Command::Doctor(args)) = &cli.command {
+        return doctor::run_doctor(args, cli.config.as_deref()).map(|()| Status::Ok);
+    }
+    let config = Config::load(cli.config.as_deref())?;
+    let mut status = Status::Ok;
     match cli.command {
-        Some(Command::Graph) => graph::run_graph()?,
-        None => collect::run_collect(false)?,
+        Some(Command::Collect(args)) if args.every.is_some() => daemon::run_daemon(&args, config, cli.config.as_deref())?,
+        Some(Command::Collect(args)) => status = collect::run_collect(&args, &config)?,
+        Some(Command::Graph(args)) => graph::run_graph(&args, &config)?,
+        Some(Command::InstallSystemd(args)) => install::run_install_systemd(&args)?,
+        Some(Command::RenameMetric(args)) => aliases::run_rename_metric(&args)?,
+        Some(Command::TopHosts(args)) => top::run_top_hosts(&args)?,
+        Some(Command::Report(args)) if args.html || args.pdf => report_html::run_report_page(&args, &config)?,
+        Some(Command::Report(args)) => report::run_report(&args, &config)?,
+        Some(Command::Check(args)) => status = check::run_check(&args, &config)?,
+        Some(Command::Watch(args)) => watch::run_watch(&args, &config)?,
+        Some(Command::Query(args)) => query::run_query(&args)?,
+        Some(Command::Analyze(args)) => analyze::run_analyze(&args)?,
+        Some(Command::Forecast(args)) => forecast::run_forecast(&args)?,
+        Some(Command::Baseline { action: BaselineAction::Save(args) }) => baseline::run_save(&args)?,
+        Some(Command::Baseline { action: BaselineAction::Diff(args) }) => baseline::run_diff(&args)?,
+        Some(Command::Archive(args)) => archive::run_archive(&args)?,
+        Some(Command::Compact(args)) => cold::run_compact(&args)?,
+        Some(Command::ProtectKey) => crypto::run_protect_key()?,
+        Some(Command::Verify(args)) => verify::run_verify(&args)?,
+        Some(Command::Dedupe(args)) => dedupe::run_dedupe(&args)?,
+        Some(Command::Backfill(args)) => backfill::run_backfill(&args)?,
+        Some(Command::Audit(args)) => audit::run_audit(&args)?,
+        Some(Command::Doctor(_)) => unreachable!("handled before the config is loaded"),
+        Some(Command::Reload(args)) => daemon::run_reload(&args, &config)?,
+        Some(Command::Serve { api: ServeApi::Grafana(args) }) => serve::run_serve_grafana(&args)?,
+        Some(Command::Import { format: ImportFormat::Csv(args) }) => import::run_import_csv(&args)?,
+        Some(Command::Import { format: ImportFormat::Json(args) }) => import::run_import_json(&args)?,
+        Some(Command::Import { format: ImportFormat::Perfmon(args) }) => import::run_import_perfmon(&args, &config)?,
+        Some(Command::Export { dry_run, format }) => match format {
+            ExportFormat::Json { max_points } => {
+                let written = export_json::export_all_sqlite_to_json(Path::new("."), dry_run, max_points)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "written": written, "dry_run": dry_run }))?);
+                } else {
+                    let verb = if dry_run { "Would write" } else { "Wrote" };
+                    for p in written {
+                        println!("{} {}", verb, p.display());
+                    }
+                }
+            }
+            ExportFormat::Jsonl { out, max_points } => export_json::run_export_jsonl(out.as_deref(), dry_run, max_points, json)?,
+            ExportFormat::Zabbix(args) => zabbix::run_export_zabbix(&args, dry_run)?,
+        },
+        None => status = collect::run_collect(&CollectArgs { json, ..Default::default() }, &config)?,
     }
-    Ok(())
+    Ok(status)
 }
@@ -0,0 +1,147 @@
+// src/metric_info.rs
+//! What each metric measures: unit, kind, display label and y-axis range.
+//!
+//! Collection stores this in the `Metrics` table when a table is created, so
+//! graphs, reports and exports read it from the file instead of guessing
+//! from the table name. Files written before schema v4 have no description;
+//! readers fall back to [`describe`] for those.
+use serde::Serialize;
+
+use crate::apps;
+use crate::eventlog;
+use crate::exec;
+use crate::patches;
+use crate::services;
+use crate::shares;
+use crate::smart;
+use crate::virt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// 0..100 %
+    Percent,
+    /// 1 = up/passed/succeeded, 0 = not
+    State,
+    /// Any other reading, charted on a fitted axis from 0
+    Gauge,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Percent => "percent",
+            Kind::State => "state",
+            Kind::Gauge => "gauge",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Kind> {
+        match s {
+            "percent" => Some(Kind::Percent),
+            "state" => Some(Kind::State),
+            "gauge" => Some(Kind::Gauge),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricInfo {
+    /// e.g. `%`, `ms`, `GB`; empty for plain counts
+    pub unit: String,
+    pub kind: Kind,
+    /// Axis title, e.g. "CPU % Usage"
+    pub label: String,
+    /// Fixed axis range; `None` fits the data
+    pub y_min: Option<f64>,
+    pub y_max: Option<f64>,
+}
+
+impl MetricInfo {
+    fn new(kind: Kind, unit: &str, label: &str) -> Self {
+        let (y_min, y_max) = match kind {
+            Kind::Percent => (Some(0.0), Some(100.0)),
+            Kind::State => (Some(0.0), Some(1.0)),
+            Kind::Gauge => (None, None),
+        };
+        MetricInfo { unit: unit.into(), kind, label: label.into(), y_min, y_max }
+    }
+}
+
+fn is_http(m: &str, suffix: &str) -> bool {
+    m.starts_with("HTTP_") && m.ends_with(suffix)
+}
+
+/// Description of a metric this build collects, from its table name
+pub fn describe(metric: &str) -> MetricInfo {
+    use Kind::*;
+    let m = metric.to_ascii_uppercase();
+    let smart = smart::is_smart_metric(metric);
+    let (kind, unit, label) = if m == "RAM" {
+        (Percent, "%", "RAM % Usage")
+    } else if m == "CPU" {
+        (Percent, "%", "CPU % Usage")
+    } else if m.ends_with("_INODES") {
+        (Percent, "%", "Inode % Usage")
+    } else if m.ends_with("_DRIVE") {
+        (Percent, "%", "HDD % Usage")
+    } else if services::is_service_metric(metric) {
+        (State, "", "Service running (1 = up)")
+    } else if smart && m.ends_with("_HEALTH") {
+        (State, "", "SMART health (1 = passed)")
+    } else if smart && m.ends_with("_REALLOCATED") {
+        (Gauge, "sectors", "Reallocated sectors")
+    } else if smart && m.ends_with("_WEAR") {
+        (Percent, "%", "Rated life used %")
+    } else if m.starts_with("PING_") {
+        (Gauge, "ms", "Round trip ms")
+    } else if is_http(&m, "_MS") {
+        (Gauge, "ms", "Response ms")
+    } else if is_http(&m, "_STATUS") {
+        (Gauge, "", "HTTP status")
+    } else if is_http(&m, "_UP") {
+        (State, "", "Endpoint up (1 = yes)")
+    } else if exec::is_exec_ok_metric(metric) {
+        (State, "", "Command succeeded (1 = yes)")
+    } else if m.ends_with("_MFT_GB") {
+        (Gauge, "GB", "MFT GB")
+    } else if m.ends_with("_MFT_ZONE_GB") {
+        (Gauge, "GB", "MFT zone GB")
+    } else if m.ends_with("_FREE_GB") {
+        (Gauge, "GB", "Free GB")
+    } else if m.ends_with("_GB") {
+        (Gauge, "GB", "GB")
+    } else if apps::is_app_metric(metric) && m.ends_with("_CPU") {
+        (Percent, "%", "CPU % of machine")
+    } else if apps::is_app_metric(metric) && m.ends_with("_MEM") {
+        (Gauge, "GB", "Memory GB")
+    } else if shares::is_share_metric(metric) {
+        (Percent, "%", "Share % Usage")
+    } else if m == patches::REBOOT_PENDING {
+        (State, "", "Reboot pending (1 = yes)")
+    } else if m == patches::UPDATE_AGE {
+        (Gauge, "days", "Days since last update")
+    } else if m == virt::CPU_STEAL {
+        (Percent, "%", "CPU % stolen by the hypervisor")
+    } else if m == eventlog::ERRORS {
+        (Gauge, "", "Error events")
+    } else if m == eventlog::WARNINGS {
+        (Gauge, "", "Warning events")
+    } else if m == "SESSIONS" {
+        (Gauge, "", "Logged-on users")
+    } else if m == "PROCS" {
+        (Gauge, "", "Processes")
+    } else if m == "HANDLES" {
+        (Gauge, "", "Open handles")
+    } else if m == "CTX_SWITCHES" {
+        (Gauge, "/s", "Context switches/s")
+    } else if m == "INTERRUPTS" {
+        (Gauge, "/s", "Interrupts/s")
+    } else if matches!(m.as_str(), "LOAD1" | "LOAD5" | "LOAD15") {
+        (Gauge, "", "Load average")
+    } else {
+        (Gauge, "", "Value")
+    };
+    MetricInfo::new(kind, unit, label)
+}
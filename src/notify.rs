@@ -0,0 +1,257 @@
+// src/notify.rs
+//! Mail delivery for `report --email` and `check --email` through the relay
+//! in the `[smtp]` config section, so servers with no mail client can still
+//! send their output. Speaks just enough SMTP for that: EHLO, STARTTLS or
+//! implicit TLS, AUTH PLAIN and one message per connection.
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use chrono::Local;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade a plain connection (port 587)
+    #[default]
+    Starttls,
+    /// TLS from the first byte (port 465)
+    Tls,
+    /// No encryption (port 25), for relays on the local network only
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Default: 587, 465 or 25 depending on `tls`
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// PEM file of extra CA certificates, e.g. for a relay with an internal certificate
+    pub ca_file: Option<PathBuf>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+pub struct Attachment {
+    pub name: String,
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+pub struct Message {
+    pub subject: String,
+    pub body: String,
+    /// `text/plain` or `text/html`
+    pub content_type: &'static str,
+    pub attachment: Option<Attachment>,
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.read(buf),
+            Stream::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.write(buf),
+            Stream::Tls(s) => s.write(buf),
+        }
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Plain(s) => s.flush(),
+            Stream::Tls(s) => s.flush(),
+        }
+    }
+}
+
+struct Session {
+    stream: Stream,
+    /// Bytes read past the last reply; always empty when TLS starts
+    pending: Vec<u8>,
+}
+
+impl Session {
+    /// One (possibly multi-line) reply; fails unless its code is `expect`
+    fn reply(&mut self, expect: u16) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            let line = loop {
+                if let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = self.pending.drain(..=end).collect();
+                    break String::from_utf8_lossy(&line).trim_end().to_string();
+                }
+                let mut buf = [0u8; 1024];
+                let n = self.stream.read(&mut buf).context("read from SMTP server")?;
+                if n == 0 {
+                    bail!("SMTP server closed the connection");
+                }
+                self.pending.extend_from_slice(&buf[..n]);
+            };
+            text.push_str(line.get(4..).unwrap_or_default());
+            text.push('\n');
+            // "250-..." continues, "250 ..." ends the reply
+            if line.as_bytes().get(3) != Some(&b'-') {
+                let code: u16 = line.get(..3).and_then(|c| c.parse().ok()).ok_or_else(|| anyhow!("bad SMTP reply {:?}", line))?;
+                if code != expect {
+                    bail!("SMTP server answered {}: {}", code, text.trim_end());
+                }
+                return Ok(text);
+            }
+        }
+    }
+
+    fn command(&mut self, line: &str, expect: u16) -> Result<String> {
+        self.stream.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.stream.flush()?;
+        self.reply(expect)
+    }
+}
+
+/// Public roots plus the certificates in `ca_file`; also used by the Postgres sink
+pub fn tls_config(ca_file: Option<&Path>) -> Result<ClientConfig> {
+    let mut roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    if let Some(path) = ca_file {
+        for cert in CertificateDer::pem_file_iter(path).with_context(|| format!("read {}", path.display()))? {
+            roots.add(cert.with_context(|| format!("parse {}", path.display()))?)?;
+        }
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(config)
+}
+
+fn start_tls(cfg: &SmtpConfig, tcp: TcpStream) -> Result<Stream> {
+    let name = ServerName::try_from(cfg.host.clone()).with_context(|| format!("invalid SMTP host {:?}", cfg.host))?;
+    let conn = ClientConnection::new(Arc::new(tls_config(cfg.ca_file.as_deref())?), name)?;
+    Ok(Stream::Tls(Box::new(StreamOwned::new(conn, tcp))))
+}
+
+/// Base64 in 76-character lines, as MIME expects
+fn b64_lines(data: &[u8]) -> String {
+    let encoded = B64.encode(data);
+    encoded.as_bytes().chunks(76).map(|c| String::from_utf8_lossy(c)).collect::<Vec<_>>().join("\r\n")
+}
+
+/// RFC 2047 encoded word when the subject is not plain ASCII
+fn header_text(s: &str) -> String {
+    if s.is_ascii() {
+        s.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", B64.encode(s))
+    }
+}
+
+fn compose(cfg: &SmtpConfig, msg: &Message) -> String {
+    let mut out = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n",
+        cfg.from,
+        cfg.to.join(", "),
+        header_text(&msg.subject),
+        Local::now().to_rfc2822()
+    );
+    let body = format!(
+        "Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+        msg.content_type,
+        b64_lines(msg.body.as_bytes())
+    );
+    match &msg.attachment {
+        None => out.push_str(&body),
+        Some(a) => {
+            let boundary = format!("winbox-stats-{}", Local::now().timestamp_nanos_opt().unwrap_or_default());
+            out.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n", boundary));
+            out.push_str(&format!("--{}\r\n{}", boundary, body));
+            out.push_str(&format!(
+                "--{}\r\nContent-Type: {}; name=\"{}\"\r\nContent-Disposition: attachment; filename=\"{}\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+                boundary,
+                a.content_type,
+                a.name,
+                a.name,
+                b64_lines(&a.data)
+            ));
+            out.push_str(&format!("--{}--\r\n", boundary));
+        }
+    }
+    out
+}
+
+/// Deliver `msg` to every address in `cfg.to`
+pub fn send(cfg: &SmtpConfig, msg: &Message) -> Result<()> {
+    if cfg.to.is_empty() {
+        bail!("smtp.to lists no recipients");
+    }
+    let port = cfg.port.unwrap_or(match cfg.tls {
+        SmtpTls::Starttls => 587,
+        SmtpTls::Tls => 465,
+        SmtpTls::None => 25,
+    });
+    let tcp = TcpStream::connect((cfg.host.as_str(), port)).with_context(|| format!("connect to {}:{}", cfg.host, port))?;
+    tcp.set_read_timeout(Some(TIMEOUT))?;
+    tcp.set_write_timeout(Some(TIMEOUT))?;
+    let stream = match cfg.tls {
+        SmtpTls::Tls => start_tls(cfg, tcp)?,
+        _ => Stream::Plain(tcp),
+    };
+    let mut s = Session { stream, pending: Vec::new() };
+    let me = hostname::get().map(|h| h.to_string_lossy().into_owned()).unwrap_or_else(|_| "localhost".into());
+
+    s.reply(220)?;
+    let mut features = s.command(&format!("EHLO {}", me), 250)?;
+    if cfg.tls == SmtpTls::Starttls {
+        s.command("STARTTLS", 220)?;
+        let Stream::Plain(tcp) = s.stream else { unreachable!("STARTTLS on a plain connection") };
+        s.stream = start_tls(cfg, tcp)?;
+        // Capabilities may change once encrypted, so ask again
+        features = s.command(&format!("EHLO {}", me), 250)?;
+    }
+    if let Some(user) = &cfg.username {
+        if !features.lines().any(|l| l.to_ascii_uppercase().starts_with("AUTH") && l.to_ascii_uppercase().contains("PLAIN")) {
+            bail!("{} does not offer AUTH PLAIN{}", cfg.host, if cfg.tls == SmtpTls::None { " without TLS" } else { "" });
+        }
+        let token = B64.encode(format!("\0{}\0{}", user, cfg.password.as_deref().unwrap_or_default()));
+        s.command(&format!("AUTH PLAIN {}", token), 235).context("SMTP authentication failed")?;
+    }
+    s.command(&format!("MAIL FROM:<{}>", cfg.from), 250)?;
+    for to in &cfg.to {
+        s.command(&format!("RCPT TO:<{}>", to), 250).with_context(|| format!("recipient {}", to))?;
+    }
+    s.command("DATA", 354)?;
+    // Base64 bodies never start a line with '.', so no dot-stuffing is needed
+    s.stream.write_all(compose(cfg, msg).as_bytes())?;
+    s.command(".", 250)?;
+    let _ = s.command("QUIT", 221);
+    log::info!("mailed {:?} to {}", msg.subject, cfg.to.join(", "));
+    Ok(())
+}
+
+/// The `[smtp]` section, or why `--email` cannot be used
+pub fn smtp(config: &crate::config::Config) -> Result<&SmtpConfig> {
+    config.smtp.as_ref().context("--email needs an [smtp] section in the config")
+}
@@ -0,0 +1,70 @@
+// src/patches.rs
+//! Patch compliance next to resource usage, for the monthly review:
+//! `REBOOT_PENDING` (1 while installed updates wait for a restart) and
+//! `UPDATE_AGE_DAYS` (days since the newest update was installed).
+//!
+//! Windows reads the flags Windows Update and the servicing stack leave in
+//! the registry, and the install dates of `Win32_QuickFixEngineering`. On
+//! Debian and Ubuntu `/var/run/reboot-required` gives the pending reboot;
+//! other systems record nothing.
+pub const REBOOT_PENDING: &str = "REBOOT_PENDING";
+pub const UPDATE_AGE: &str = "UPDATE_AGE_DAYS";
+
+#[cfg(windows)]
+fn reboot_pending() -> bool {
+    use winreg::enums::HKEY_LOCAL_MACHINE;
+    use winreg::RegKey;
+
+    // Either key exists only until the next restart
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    [
+        r"SOFTWARE\Microsoft\Windows\CurrentVersion\Component Based Servicing\RebootPending",
+        r"SOFTWARE\Microsoft\Windows\CurrentVersion\WindowsUpdate\Auto Update\RebootRequired",
+    ]
+    .iter()
+    .any(|k| hklm.open_subkey(k).is_ok())
+}
+
+/// Date of the newest installed update
+#[cfg(windows)]
+fn last_install() -> anyhow::Result<chrono::NaiveDate> {
+    use ::wmi::{COMLibrary, Variant, WMIConnection};
+    use anyhow::Context;
+    use std::collections::HashMap;
+
+    let con = WMIConnection::new(COMLibrary::new()?)?;
+    let rows: Vec<HashMap<String, Variant>> = con.raw_query("SELECT InstalledOn FROM Win32_QuickFixEngineering")?;
+    rows.iter()
+        .filter_map(|row| match row.get("InstalledOn")? {
+            // Always month/day/year, whatever the display language
+            Variant::String(s) => chrono::NaiveDate::parse_from_str(s.trim(), "%m/%d/%Y").ok(),
+            _ => None,
+        })
+        .max()
+        .context("no installed update has a date")
+}
+
+#[cfg(windows)]
+pub fn sample() -> Vec<(String, f64)> {
+    let mut out = vec![(REBOOT_PENDING.to_string(), reboot_pending() as u8 as f64)];
+    match last_install() {
+        Ok(date) => {
+            let days = (chrono::Local::now().date_naive() - date).num_days().max(0);
+            out.push((UPDATE_AGE.to_string(), days as f64));
+        }
+        Err(e) => log::warn!("{} skipped: {:#}", UPDATE_AGE, e),
+    }
+    out
+}
+
+#[cfg(not(windows))]
+pub fn sample() -> Vec<(String, f64)> {
+    use std::path::Path;
+
+    // Only dpkg-based systems keep the flag file
+    if !Path::new("/var/lib/dpkg").is_dir() {
+        return Vec::new();
+    }
+    let pending = Path::new("/var/run/reboot-required").exists();
+    vec![(REBOOT_PENDING.to_string(), pending as u8 as f64)]
+}
@@ -0,0 +1,77 @@
+// src/perf.rs
+//! Arbitrary Windows performance counters from `[[perf_counters]]` in the
+//! config, each stored under its own label, read together with the built-in
+//! [`SYSTEM_RATES`].
+//!
+//! Paths use the English counter names (`\Processor(_Total)\% Processor Time`)
+//! so one config works on every display language.
+use serde::Deserialize;
+
+use crate::storage::metric_label;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PerfCounter {
+    /// Table name
+    pub label: String,
+    /// PDH counter path, e.g. `\SQLServer:Buffer Manager\Page life expectancy`
+    pub path: String,
+}
+
+/// Context switch and interrupt rates, which reveal driver storms that plain
+/// CPU % hides: (table, counter path)
+pub const SYSTEM_RATES: [(&str, &str); 2] = [
+    ("CTX_SWITCHES", r"\System\Context Switches/sec"),
+    ("INTERRUPTS", r"\Processor(_Total)\Interrupts/sec"),
+];
+
+#[cfg(windows)]
+pub fn sample(counters: &[PerfCounter]) -> Vec<(String, f64)> {
+    use std::time::Duration;
+    use windows_sys::Win32::System::Performance::{
+        PdhAddEnglishCounterW, PdhCloseQuery, PdhCollectQueryData, PdhGetFormattedCounterValue, PdhOpenQueryW,
+        PDH_FMT_COUNTERVALUE, PDH_FMT_DOUBLE,
+    };
+
+    let mut out = Vec::new();
+    // SAFETY: PDH handles are only used while the query is open and closed at the end
+    unsafe {
+        let mut query = 0isize;
+        let rc = PdhOpenQueryW(std::ptr::null(), 0, &mut query);
+        if rc != 0 {
+            log::warn!("open performance counter query: PDH error {:#x}", rc);
+            return out;
+        }
+        let mut added = Vec::new();
+        let configured = counters.iter().map(|c| (c.label.as_str(), c.path.as_str()));
+        for (label, path) in SYSTEM_RATES.into_iter().chain(configured) {
+            let wide: Vec<u16> = path.encode_utf16().chain(Some(0)).collect();
+            let mut counter = 0isize;
+            match PdhAddEnglishCounterW(query, wide.as_ptr(), 0, &mut counter) {
+                0 => added.push((label, path, counter)),
+                rc => log::warn!("counter {}: PDH error {:#x}", path, rc),
+            }
+        }
+        // Rate counters (per second, % time) need two readings
+        PdhCollectQueryData(query);
+        std::thread::sleep(Duration::from_secs(1));
+        PdhCollectQueryData(query);
+        for (label, path, counter) in added {
+            let mut value: PDH_FMT_COUNTERVALUE = std::mem::zeroed();
+            match PdhGetFormattedCounterValue(counter, PDH_FMT_DOUBLE, std::ptr::null_mut(), &mut value) {
+                0 => out.push((metric_label(label), value.Anonymous.doubleValue)),
+                rc => log::warn!("counter {}: PDH error {:#x}", path, rc),
+            }
+        }
+        PdhCloseQuery(query);
+    }
+    out
+}
+
+#[cfg(not(windows))]
+pub fn sample(counters: &[PerfCounter]) -> Vec<(String, f64)> {
+    for c in counters {
+        log::warn!("{} ({}) skipped: performance counters are Windows only", metric_label(&c.label), c.path);
+    }
+    Vec::new()
+}
@@ -0,0 +1,185 @@
+// src/pgsink.rs
+//! Optional second destination: every run's samples are also written to a
+//! PostgreSQL (or TimescaleDB) table, so a central historian can be fed
+//! without a receiver in between. The monthly sqlite files stay the source of
+//! truth; samples go through a local queue, `winbox-stats-postgres.db`, laid
+//! out like the push outbox, and are inserted in batches, oldest first. While
+//! the server is unreachable they stay queued with the same back-off as
+//! `--push`, and are replayed with their original timestamps once it answers.
+//!
+//! ```toml
+//! [postgres]
+//! url = "host=historian.corp.local user=stats dbname=metrics sslmode=require"
+//! table = "winbox_samples"
+//! timescale = true
+//! ```
+//!
+//! The table is created when missing, with `(host, metric, ts)` as its key;
+//! rows already there are skipped, so a batch resent after a lost commit does
+//! no harm.
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use postgres::Client;
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+use crate::notify;
+use crate::push::{self, Queued};
+
+pub const QUEUE_FILE: &str = "winbox-stats-postgres.db";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Batches inserted per run; runs after `--push` under the same lock, so
+/// a backlog is worked off a little at a time
+const MAX_BATCHES_PER_RUN: usize = 5;
+/// No new batch is started after this long
+const CATCH_UP_BUDGET: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostgresConfig {
+    /// libpq connection string or `postgresql://` URL
+    pub url: String,
+    /// Optionally schema-qualified, e.g. `metrics.winbox`
+    pub table: String,
+    /// Make a new table a TimescaleDB hypertable
+    pub timescale: bool,
+    /// Rows per INSERT
+    pub batch_size: usize,
+    /// Queued rows kept while the server is unreachable; the oldest go first
+    pub max_queue: usize,
+    /// PEM file of extra CA certificates for `sslmode=require`
+    pub ca_file: Option<PathBuf>,
+}
+
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        PostgresConfig {
+            url: String::new(),
+            table: "winbox_samples".into(),
+            timescale: false,
+            batch_size: 1000,
+            max_queue: 1_000_000,
+            ca_file: None,
+        }
+    }
+}
+
+/// `metrics.winbox` -> `"metrics"."winbox"`
+fn quote_table(table: &str) -> String {
+    let parts: Vec<String> = table.split('.').map(|p| format!("\"{}\"", p.replace('"', "\"\""))).collect();
+    parts.join(".")
+}
+
+fn connect(cfg: &PostgresConfig, table: &str) -> Result<Client> {
+    let mut pg: postgres::Config = cfg.url.parse().context("parse [postgres] url")?;
+    pg.connect_timeout(CONNECT_TIMEOUT);
+    let tls = MakeRustlsConnect::new(notify::tls_config(cfg.ca_file.as_deref())?);
+    let mut client = pg.connect(tls)?;
+    // Checked first: IF NOT EXISTS would log a server notice every run
+    let exists: bool = client.query_one("SELECT to_regclass($1) IS NOT NULL", &[&table])?.get(0);
+    if exists {
+        return Ok(client);
+    }
+    // One transaction, so a failed hypertable leaves no plain table behind
+    let mut tx = client.transaction()?;
+    tx.batch_execute(&format!(
+        "CREATE TABLE {table}(
+            host   text NOT NULL,
+            metric text NOT NULL,
+            ts     timestamptz NOT NULL,
+            value  double precision NOT NULL,
+            PRIMARY KEY(host, metric, ts)
+        )"
+    ))?;
+    if cfg.timescale {
+        let literal = table.replace('\'', "''");
+        tx.batch_execute(&format!("SELECT create_hypertable('{literal}', 'ts', migrate_data => TRUE)"))
+            .context("create hypertable; is the timescaledb extension installed?")?;
+    }
+    tx.commit()?;
+    Ok(client)
+}
+
+fn insert(client: &mut Client, table: &str, batch: &[Queued]) -> Result<u64> {
+    let hosts: Vec<&str> = batch.iter().map(|q| q.host.as_str()).collect();
+    let metrics: Vec<&str> = batch.iter().map(|q| q.metric.as_str()).collect();
+    let times: Vec<f64> = batch.iter().map(|q| q.ts as f64).collect();
+    let values: Vec<f64> = batch.iter().map(|q| q.value).collect();
+    let mut tx = client.transaction()?;
+    let rows = tx.execute(
+        &format!(
+            "INSERT INTO {table}(host, metric, ts, value)
+             SELECT h, m, to_timestamp(t), v FROM unnest($1::text[], $2::text[], $3::float8[], $4::float8[]) AS u(h, m, t, v)
+             ON CONFLICT DO NOTHING"
+        ),
+        &[&hosts, &metrics, &times, &values],
+    )?;
+    tx.commit()?;
+    Ok(rows)
+}
+
+/// Insert queued rows until the queue is empty, an insert fails, or the
+/// per-run batch limit or time budget is reached. Returns rows taken off the
+/// queue.
+fn flush(conn: &Connection, cfg: &PostgresConfig, now: i64) -> Result<usize> {
+    if now < push::get_state(conn, "next_attempt")? {
+        return Ok(0);
+    }
+    let table = quote_table(&cfg.table);
+    let mut client = match connect(cfg, &table) {
+        Ok(c) => c,
+        Err(e) => {
+            let delay = push::back_off(conn, now)?;
+            bail!("postgres: {:#}; retrying in {}s", e, delay);
+        }
+    };
+    let started = Instant::now();
+    let mut sent = 0;
+    for _ in 0..MAX_BATCHES_PER_RUN {
+        if started.elapsed() >= CATCH_UP_BUDGET {
+            break;
+        }
+        let batch = push::next_batch(conn, cfg.batch_size.max(1))?;
+        if batch.is_empty() {
+            break;
+        }
+        match insert(&mut client, &table, &batch) {
+            Ok(rows) => debug!("postgres: inserted {} of {} rows", rows, batch.len()),
+            Err(e) => {
+                let delay = push::back_off(conn, now)?;
+                bail!("postgres: insert into {} failed ({:#}); retrying in {}s", cfg.table, e, delay);
+            }
+        }
+        push::delivered(conn, &batch)?;
+        sent += batch.len();
+    }
+    Ok(sent)
+}
+
+/// Queue this run's samples and write the backlog to Postgres.
+pub fn write(dir: &Path, cfg: &PostgresConfig, host: &str, ts: i64, samples: &[(String, f64)]) -> Result<()> {
+    if cfg.url.is_empty() {
+        bail!("[postgres] needs a url");
+    }
+    let mut conn = push::open_queue(&dir.join(QUEUE_FILE))?;
+    let dropped = push::enqueue(&mut conn, host, ts, samples, cfg.max_queue)?;
+    if dropped > 0 {
+        warn!("postgres queue full, dropped {} oldest samples", dropped);
+    }
+    let sent = flush(&conn, cfg, ts)?;
+    let left: i64 = conn.query_row(r#"SELECT COUNT(*) FROM "Outbox""#, [], |r| r.get(0))?;
+    if left > 0 {
+        warn!("postgres: wrote {}, {} still queued", sent, left);
+    } else {
+        info!("postgres: wrote {}", sent);
+    }
+    Ok(())
+}
+
+/// Samples waiting for Postgres, or `None` when the sink was never used
+pub fn queued(dir: &Path) -> Result<Option<i64>> {
+    push::queued_in(&dir.join(QUEUE_FILE))
+}
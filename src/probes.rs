@@ -0,0 +1,247 @@
+// src/probes.rs
+//! Network probes from the config, recorded next to the host metrics.
+//!
+//! `[[ping]]` entries become `PING_<name>_MS` round-trip times. A probe
+//! without `port` is an ICMP echo; with `port` it times a TCP connect instead,
+//! for targets behind firewalls that drop ping. A probe that gets no answer
+//! records nothing, so outages show up as gaps in the charts.
+//!
+//! `[[http]]` entries GET a URL and record `HTTP_<name>_MS` (time to the full
+//! response), `HTTP_<name>_STATUS` (0 without a response) and `HTTP_<name>_UP`.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::storage::metric_label;
+
+/// `host` value that stands for this machine's default gateway
+const GATEWAY: &str = "gateway";
+
+fn default_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_http_timeout_ms() -> u64 {
+    5000
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PingProbe {
+    /// Name or address, or `gateway`
+    pub host: String,
+    /// Table label (default: the host)
+    pub name: Option<String>,
+    /// Time a TCP connect to this port instead of an ICMP echo
+    pub port: Option<u16>,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpProbe {
+    /// Table label
+    pub name: String,
+    pub url: String,
+    /// Status that counts as up (default: any 2xx or 3xx)
+    pub expect_status: Option<u16>,
+    /// Accept any TLS certificate, e.g. for internal CAs this machine does not trust
+    #[serde(default)]
+    pub insecure: bool,
+    #[serde(default = "default_http_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl PingProbe {
+    fn table(&self) -> String {
+        format!("PING_{}_MS", metric_label(self.name.as_deref().unwrap_or(&self.host)))
+    }
+}
+
+/// IPv4 next hop of the default route
+#[cfg(windows)]
+fn default_gateway() -> Result<IpAddr> {
+    use windows_sys::Win32::NetworkManagement::IpHelper::{GetBestRoute, MIB_IPFORWARDROW};
+
+    // SAFETY: plain output struct
+    let mut row: MIB_IPFORWARDROW = unsafe { std::mem::zeroed() };
+    let rc = unsafe { GetBestRoute(0, 0, &mut row) };
+    if rc != 0 {
+        anyhow::bail!("find default gateway: {}", std::io::Error::from_raw_os_error(rc as i32));
+    }
+    Ok(IpAddr::from(row.dwForwardNextHop.to_ne_bytes()))
+}
+
+/// IPv4 next hop of the default route, from /proc/net/route
+#[cfg(not(windows))]
+fn default_gateway() -> Result<IpAddr> {
+    let routes = std::fs::read_to_string("/proc/net/route").context("read /proc/net/route")?;
+    for line in routes.lines().skip(1) {
+        let f: Vec<&str> = line.split_whitespace().collect();
+        if f.len() > 2 && f[1] == "00000000" {
+            if let Ok(gw) = u32::from_str_radix(f[2], 16) {
+                // Little-endian hex as the kernel prints it
+                return Ok(IpAddr::from(gw.to_le_bytes()));
+            }
+        }
+    }
+    anyhow::bail!("no default route")
+}
+
+fn resolve(host: &str) -> Result<IpAddr> {
+    if host.eq_ignore_ascii_case(GATEWAY) {
+        return default_gateway();
+    }
+    let ips: Vec<IpAddr> = (host, 0)
+        .to_socket_addrs()
+        .with_context(|| format!("resolve {}", host))?
+        .map(|a| a.ip())
+        .collect();
+    // Prefer IPv4: ICMP probes on Windows are IPv4 only
+    ips.iter()
+        .find(|ip| ip.is_ipv4())
+        .or(ips.first())
+        .copied()
+        .with_context(|| format!("resolve {}", host))
+}
+
+fn tcp_ms(ip: IpAddr, port: u16, timeout: Duration) -> Result<f64> {
+    let start = Instant::now();
+    TcpStream::connect_timeout(&SocketAddr::new(ip, port), timeout)?;
+    Ok(start.elapsed().as_secs_f64() * 1000.0)
+}
+
+#[cfg(windows)]
+fn icmp_ms(ip: IpAddr, timeout: Duration) -> Result<f64> {
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        IcmpCloseHandle, IcmpCreateFile, IcmpSendEcho, ICMP_ECHO_REPLY,
+    };
+
+    let IpAddr::V4(v4) = ip else {
+        anyhow::bail!("ICMP probes support IPv4 only; set `port` for a TCP probe");
+    };
+    let payload = [0u8; 32];
+    let mut reply = vec![0u8; std::mem::size_of::<ICMP_ECHO_REPLY>() + payload.len() + 8];
+    // SAFETY: the handle is checked and closed; the reply buffer is sized as the API requires
+    unsafe {
+        let handle = IcmpCreateFile();
+        if handle == INVALID_HANDLE_VALUE {
+            anyhow::bail!("open ICMP handle: {}", std::io::Error::last_os_error());
+        }
+        let n = IcmpSendEcho(
+            handle,
+            u32::from_ne_bytes(v4.octets()),
+            payload.as_ptr().cast(),
+            payload.len() as u16,
+            std::ptr::null(),
+            reply.as_mut_ptr().cast(),
+            reply.len() as u32,
+            timeout.as_millis() as u32,
+        );
+        let err = std::io::Error::last_os_error();
+        IcmpCloseHandle(handle);
+        if n == 0 {
+            anyhow::bail!("{}", err);
+        }
+        let echo = &*(reply.as_ptr() as *const ICMP_ECHO_REPLY);
+        if echo.Status != 0 {
+            anyhow::bail!("ICMP status {}", echo.Status);
+        }
+        Ok(echo.RoundTripTime as f64)
+    }
+}
+
+/// Raw ICMP needs privileges on Unix, so use the system `ping`
+#[cfg(not(windows))]
+fn icmp_ms(ip: IpAddr, timeout: Duration) -> Result<f64> {
+    let secs = timeout.as_secs().max(1).to_string();
+    let out = std::process::Command::new("ping")
+        .args(["-n", "-c", "1", "-W", &secs, &ip.to_string()])
+        .output()
+        .context("run ping")?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    text.split("time=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|ms| ms.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("no reply"))
+}
+
+fn probe(p: &PingProbe) -> Result<f64> {
+    let ip = resolve(&p.host)?;
+    let timeout = Duration::from_millis(p.timeout_ms.max(1));
+    match p.port {
+        Some(port) => tcp_ms(ip, port, timeout),
+        None => icmp_ms(ip, timeout),
+    }
+}
+
+/// Status and milliseconds to the end of the body; redirects are followed
+fn http_get(p: &HttpProbe) -> Result<(u16, f64)> {
+    let tls = ureq::tls::TlsConfig::builder().disable_verification(p.insecure).build();
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_millis(p.timeout_ms.max(1))))
+        .http_status_as_error(false)
+        .tls_config(tls)
+        .build()
+        .new_agent();
+    let start = Instant::now();
+    let mut response = agent.get(&p.url).call()?;
+    response.body_mut().read_to_vec()?;
+    Ok((response.status().as_u16(), start.elapsed().as_secs_f64() * 1000.0))
+}
+
+fn http_samples(p: &HttpProbe, result: Result<(u16, f64)>) -> Vec<(String, f64)> {
+    let table = |suffix: &str| format!("HTTP_{}_{}", metric_label(&p.name), suffix);
+    match result {
+        Ok((status, ms)) => {
+            let up = match p.expect_status {
+                Some(expected) => status == expected,
+                None => (200..400).contains(&status),
+            };
+            if !up {
+                log::warn!("{} answered {}", p.url, status);
+            }
+            vec![
+                (table("MS"), ms),
+                (table("STATUS"), status as f64),
+                (table("UP"), if up { 1.0 } else { 0.0 }),
+            ]
+        }
+        Err(e) => {
+            log::warn!("GET {}: {:#}", p.url, e);
+            vec![(table("STATUS"), 0.0), (table("UP"), 0.0)]
+        }
+    }
+}
+
+/// GET every endpoint concurrently
+pub fn sample_http(probes: &[HttpProbe]) -> Vec<(String, f64)> {
+    std::thread::scope(|s| {
+        let running: Vec<_> = probes.iter().map(|p| (p, s.spawn(|| http_get(p)))).collect();
+        running
+            .into_iter()
+            .flat_map(|(p, h)| http_samples(p, h.join().expect("probe thread panicked")))
+            .collect()
+    })
+}
+
+/// Run every probe concurrently; failures are logged and leave no sample
+pub fn sample(probes: &[PingProbe]) -> Vec<(String, f64)> {
+    std::thread::scope(|s| {
+        let running: Vec<_> = probes.iter().map(|p| (p, s.spawn(|| probe(p)))).collect();
+        running
+            .into_iter()
+            .filter_map(|(p, h)| match h.join().expect("probe thread panicked") {
+                Ok(ms) => Some((p.table(), ms)),
+                Err(e) => {
+                    log::warn!("ping {}: {:#}", p.host, e);
+                    None
+                }
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,33 @@
+// src/progress.rs
+//! One-line progress counter on stderr for long batch jobs. Silent when
+//! stderr is not a terminal, so logs and pipes stay clean.
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct Progress {
+    label: &'static str,
+    total: usize,
+    done: AtomicUsize,
+    enabled: bool,
+}
+
+impl Progress {
+    pub fn new(label: &'static str, total: usize) -> Progress {
+        let enabled = total > 1 && io::stderr().is_terminal();
+        Progress { label, total, done: AtomicUsize::new(0), enabled }
+    }
+
+    /// Safe to call from several threads
+    pub fn tick(&self) {
+        let n = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.enabled {
+            eprint!("\r{} {}/{}", self.label, n, self.total);
+        }
+    }
+
+    pub fn finish(&self) {
+        if self.enabled {
+            eprintln!();
+        }
+    }
+}
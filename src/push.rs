@@ -0,0 +1,326 @@
+// src/push.rs
+//! Outbound push of samples to a central collector.
+//!
+//! Samples are first appended to an on-disk outbox next to the databases and
+//! then sent oldest-first in batches. A failed send backs off exponentially
+//! and leaves the rows queued for the next run, so a flaky link neither loses
+//! samples nor holds up collection. The outbox is capped; when full the oldest
+//! rows are dropped with a warning. A batch the endpoint rejects outright (a
+//! 4xx other than 408 or 429) is moved to the outbox's `Dead` table instead
+//! of being retried forever.
+//!
+//! Once the endpoint answers again the backlog is replayed with the original
+//! timestamps, strictly oldest-first. Replay runs inside the collection, under
+//! its lock, so each run sends at most a few batches for a few seconds and
+//! leaves the rest to the next one; a minute's schedule is never held up.
+//! Rows carry `seq`, their position in the outbox, which only grows: a JSON
+//! field, or an integer field of each line with `--push-format influx`.
+//! Ordering on the receiving side is up to the receiver: it can use `seq` to
+//! apply late rows in order and to drop duplicates when a batch is resent
+//! after a lost response, but nothing here enforces that.
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Not `.sqlite`, so graph/export never mistake it for a metrics database
+pub const OUTBOX_FILE: &str = "winbox-stats-outbox.db";
+
+/// Batches sent per run; the rest waits for the next run
+const MAX_BATCHES_PER_RUN: usize = 20;
+/// No new batch is started after this long, so with a slow endpoint a run
+/// still finishes well inside a one-minute collection interval
+const CATCH_UP_BUDGET: Duration = Duration::from_secs(15);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const BACKOFF_BASE_SECS: i64 = 30;
+const BACKOFF_MAX_SECS: i64 = 3600;
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum PushFormat {
+    /// JSON array of {host, metric, ts, value}
+    #[default]
+    Json,
+    /// InfluxDB line protocol, second precision
+    Influx,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Queued {
+    #[serde(rename = "seq")]
+    pub id: i64,
+    pub host: String,
+    pub metric: String,
+    pub ts: i64,
+    pub value: f64,
+}
+
+pub struct PushTarget<'a> {
+    pub url: &'a str,
+    pub format: PushFormat,
+    pub batch_size: usize,
+    pub max_queue: usize,
+}
+
+fn open_outbox(dir: &Path) -> Result<Connection> {
+    open_queue(&dir.join(OUTBOX_FILE))
+}
+
+/// An outbox at `path`; the Postgres sink keeps its own in the same layout
+pub(crate) fn open_queue(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path).with_context(|| format!("open {}", path.display()))?;
+    conn.busy_timeout(HTTP_TIMEOUT)?;
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS "Outbox"(
+            "Id"        INTEGER PRIMARY KEY AUTOINCREMENT,
+            "Host"      TEXT NOT NULL,
+            "Metric"    TEXT NOT NULL,
+            "Timestamp" INTEGER NOT NULL,
+            "Value"     REAL NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS "State"("Key" TEXT PRIMARY KEY NOT NULL, "Value" INTEGER NOT NULL);
+        CREATE TABLE IF NOT EXISTS "Dead"(
+            "Id"        INTEGER PRIMARY KEY,
+            "Host"      TEXT NOT NULL,
+            "Metric"    TEXT NOT NULL,
+            "Timestamp" INTEGER NOT NULL,
+            "Value"     REAL NOT NULL,
+            "Error"     TEXT NOT NULL
+        );
+        "#,
+    )?;
+    Ok(conn)
+}
+
+/// Samples waiting in the outbox, or `None` when there is no outbox yet
+pub fn queued(dir: &Path) -> Result<Option<i64>> {
+    queued_in(&dir.join(OUTBOX_FILE))
+}
+
+pub(crate) fn queued_in(path: &Path) -> Result<Option<i64>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let conn = open_queue(path)?;
+    Ok(Some(conn.query_row(r#"SELECT COUNT(*) FROM "Outbox""#, [], |r| r.get(0))?))
+}
+
+pub(crate) fn get_state(conn: &Connection, key: &str) -> Result<i64> {
+    Ok(conn
+        .query_row(r#"SELECT "Value" FROM "State" WHERE "Key" = ?1"#, params![key], |r| r.get(0))
+        .optional()?
+        .unwrap_or(0))
+}
+
+fn set_state(conn: &Connection, key: &str, value: i64) -> Result<()> {
+    conn.execute(
+        r#"INSERT OR REPLACE INTO "State"("Key","Value") VALUES (?1, ?2)"#,
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+/// Append samples and enforce the queue cap; returns the number of rows dropped.
+pub(crate) fn enqueue(conn: &mut Connection, host: &str, ts: i64, samples: &[(String, f64)], max_queue: usize) -> Result<usize> {
+    let tx = conn.transaction()?;
+    for (metric, value) in samples {
+        tx.execute(
+            r#"INSERT INTO "Outbox"("Host","Metric","Timestamp","Value") VALUES (?1, ?2, ?3, ?4)"#,
+            params![host, metric, ts, value],
+        )?;
+    }
+    let queued: i64 = tx.query_row(r#"SELECT COUNT(*) FROM "Outbox""#, [], |r| r.get(0))?;
+    let excess = (queued - max_queue as i64).max(0);
+    if excess > 0 {
+        tx.execute(
+            r#"DELETE FROM "Outbox" WHERE "Id" IN (SELECT "Id" FROM "Outbox" ORDER BY "Id" LIMIT ?1)"#,
+            params![excess],
+        )?;
+    }
+    tx.commit()?;
+    Ok(excess as usize)
+}
+
+pub(crate) fn next_batch(conn: &Connection, size: usize) -> Result<Vec<Queued>> {
+    let mut stmt = conn.prepare(
+        r#"SELECT "Id","Host","Metric","Timestamp","Value" FROM "Outbox" ORDER BY "Id" LIMIT ?1"#,
+    )?;
+    let rows = stmt.query_map(params![size as i64], |r| {
+        Ok(Queued {
+            id: r.get(0)?,
+            host: r.get(1)?,
+            metric: r.get(2)?,
+            ts: r.get(3)?,
+            value: r.get(4)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+/// Escape tag values for Influx line protocol
+fn influx_tag(s: &str) -> String {
+    s.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+fn encode(batch: &[Queued], format: PushFormat) -> Result<(&'static str, String)> {
+    Ok(match format {
+        PushFormat::Json => ("application/json", serde_json::to_string(batch)?),
+        PushFormat::Influx => {
+            let lines: Vec<String> = batch
+                .iter()
+                .map(|q| {
+                    format!(
+                        "winbox,host={},metric={} value={},seq={}i {}",
+                        influx_tag(&q.host),
+                        influx_tag(&q.metric),
+                        q.value,
+                        q.id,
+                        q.ts
+                    )
+                })
+                .collect();
+            ("text/plain; charset=utf-8", lines.join("\n"))
+        }
+    })
+}
+
+/// Send queued rows oldest-first until the queue is empty, a send fails, or
+/// the per-run batch limit or time budget is reached. Returns rows delivered.
+fn flush(conn: &Connection, target: &PushTarget, now: i64) -> Result<usize> {
+    if now < get_state(conn, "next_attempt")? {
+        return Ok(0);
+    }
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(HTTP_TIMEOUT))
+        .build()
+        .new_agent();
+
+    let started = Instant::now();
+    let mut sent = 0;
+    for _ in 0..MAX_BATCHES_PER_RUN {
+        if started.elapsed() >= CATCH_UP_BUDGET {
+            break;
+        }
+        let batch = next_batch(conn, target.batch_size)?;
+        if batch.is_empty() {
+            break;
+        }
+        let (content_type, body) = encode(&batch, target.format)?;
+        debug!("push: POST {} rows to {}", batch.len(), target.url);
+        match agent.post(target.url).header("Content-Type", content_type).send(body) {
+            Ok(_) => {
+                delivered(conn, &batch)?;
+                sent += batch.len();
+            }
+            Err(e) if rejected(&e) => {
+                warn!("{} rejected {} rows ({}); moved to the Dead table of {}", target.url, batch.len(), e, OUTBOX_FILE);
+                set_aside(conn, &batch, &e.to_string())?;
+            }
+            Err(e) => {
+                let delay = back_off(conn, now)?;
+                anyhow::bail!("push to {} failed ({}); retrying in {}s", target.url, e, delay);
+            }
+        }
+    }
+    Ok(sent)
+}
+
+/// A client error the same batch would get again; 408 and 429 ask for a retry
+fn rejected(e: &ureq::Error) -> bool {
+    matches!(e, ureq::Error::StatusCode(code) if (400..500).contains(code) && !matches!(code, 408 | 429))
+}
+
+/// Move a batch the endpoint will never accept out of the outbox, so it does
+/// not hold up the rows behind it
+fn set_aside(conn: &Connection, batch: &[Queued], why: &str) -> Result<()> {
+    let last_id = batch.last().map(|q| q.id).unwrap_or_default();
+    conn.execute(
+        r#"INSERT OR REPLACE INTO "Dead"("Id","Host","Metric","Timestamp","Value","Error")
+           SELECT "Id","Host","Metric","Timestamp","Value", ?2 FROM "Outbox" WHERE "Id" <= ?1"#,
+        params![last_id, why],
+    )?;
+    delivered(conn, batch)
+}
+
+/// Record a failed send; returns the seconds until the next attempt
+pub(crate) fn back_off(conn: &Connection, now: i64) -> Result<i64> {
+    let failures = get_state(conn, "failures")? + 1;
+    let delay = (BACKOFF_BASE_SECS << (failures - 1).min(16)).min(BACKOFF_MAX_SECS);
+    set_state(conn, "failures", failures)?;
+    set_state(conn, "next_attempt", now + delay)?;
+    Ok(delay)
+}
+
+/// Remove a batch that was sent and clear the back-off
+pub(crate) fn delivered(conn: &Connection, batch: &[Queued]) -> Result<()> {
+    let last_id = batch.last().map(|q| q.id).unwrap_or_default();
+    conn.execute(r#"DELETE FROM "Outbox" WHERE "Id" <= ?1"#, params![last_id])?;
+    set_state(conn, "failures", 0)?;
+    set_state(conn, "next_attempt", 0)?;
+    Ok(())
+}
+
+/// Queue this run's samples and try to deliver the backlog.
+pub fn push_samples(dir: &Path, target: &PushTarget, host: &str, ts: i64, samples: &[(String, f64)]) -> Result<()> {
+    let mut conn = open_outbox(dir)?;
+    let dropped = enqueue(&mut conn, host, ts, samples, target.max_queue)?;
+    if dropped > 0 {
+        warn!("push outbox full, dropped {} oldest samples", dropped);
+    }
+    let sent = flush(&conn, target, ts)?;
+    let left: i64 = conn.query_row(r#"SELECT COUNT(*) FROM "Outbox""#, [], |r| r.get(0))?;
+    if left > 0 {
+        warn!("push: sent {}, {} still queued", sent, left);
+    } else {
+        info!("push: sent {}", sent);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(id: i64) -> Queued {
+        Queued { id, host: "WEB 01".into(), metric: "CPU".into(), ts: 1_723_600_000, value: 12.5 }
+    }
+
+    #[test]
+    fn both_formats_carry_seq() {
+        let batch = [queued(41), queued(42)];
+        let (_, json) = encode(&batch, PushFormat::Json).unwrap();
+        assert!(json.contains(r#""seq":42"#));
+        let (_, lines) = encode(&batch, PushFormat::Influx).unwrap();
+        assert_eq!(lines.lines().last(), Some(r"winbox,host=WEB\ 01,metric=CPU value=12.5,seq=42i 1723600000"));
+    }
+
+    #[test]
+    fn only_final_client_errors_are_rejections() {
+        for code in [400, 404, 413, 422] {
+            assert!(rejected(&ureq::Error::StatusCode(code)), "{}", code);
+        }
+        for code in [408, 429, 500, 503] {
+            assert!(!rejected(&ureq::Error::StatusCode(code)), "{}", code);
+        }
+        assert!(!rejected(&ureq::Error::ConnectionFailed));
+    }
+
+    #[test]
+    fn rejected_batches_are_moved_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = open_outbox(dir.path()).unwrap();
+        let samples = [("CPU".to_string(), 1.0), ("RAM".to_string(), 2.0), ("DISK".to_string(), 3.0)];
+        enqueue(&mut conn, "WEB01", 1_723_600_000, &samples, 100).unwrap();
+        back_off(&conn, 0).unwrap();
+        let batch = next_batch(&conn, 2).unwrap();
+        set_aside(&conn, &batch, "http status: 413").unwrap();
+        let left: Vec<String> = next_batch(&conn, 10).unwrap().into_iter().map(|q| q.metric).collect();
+        assert_eq!(left, ["DISK"]);
+        let dead: i64 = conn.query_row(r#"SELECT COUNT(*) FROM "Dead""#, [], |r| r.get(0)).unwrap();
+        assert_eq!(dead, 2);
+        assert_eq!(get_state(&conn, "failures").unwrap(), 0);
+    }
+}
@@ -0,0 +1,116 @@
+// src/query.rs
+//! `query`: one metric's samples across every database here, optionally
+//! limited to a host and time range, summarised per day or month, or drawn
+//! as a sparkline for a quick look over SSH.
+use anyhow::Result;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::cli::QueryArgs;
+use crate::metric_info::{self, Kind};
+use crate::report;
+use crate::series::{self, Infos, Series};
+use crate::storage;
+use crate::timespan;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Serialize)]
+struct Sample<'a> {
+    host: &'a str,
+    metric: &'a str,
+    ts: String,
+    value: f64,
+}
+
+/// One bar per time slice of equal length, each the average of its samples;
+/// slices without samples stay blank so gaps show
+fn sparkline(samples: &[(i64, f64)], width: usize, (lo, hi): (f64, f64)) -> String {
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else { return String::new() };
+    let span = (last.0 - first.0 + 1) as f64;
+    let mut slices = vec![(0.0, 0usize); width];
+    for (ts, v) in samples {
+        let i = (((ts - first.0) as f64 / span) * width as f64) as usize;
+        let slice = &mut slices[i.min(width - 1)];
+        slice.0 += v;
+        slice.1 += 1;
+    }
+    let range = (hi - lo).max(f64::EPSILON);
+    slices
+        .iter()
+        .map(|&(sum, n)| match n {
+            0 => ' ',
+            _ => {
+                let level = ((sum / n as f64 - lo) / range * BARS.len() as f64).floor();
+                BARS[(level.max(0.0) as usize).min(BARS.len() - 1)]
+            }
+        })
+        .collect()
+}
+
+fn print_sparklines((series, infos): &(Series, Infos), width: Option<usize>) {
+    let width = width
+        .or_else(|| std::io::stdout().is_terminal().then(|| ratatui::crossterm::terminal::size().ok()).flatten().map(|(w, _)| w as usize))
+        .unwrap_or(80)
+        .max(1);
+    for ((host, metric), samples) in series {
+        let info = infos.get(&(host.clone(), metric.clone())).cloned().unwrap_or_else(|| metric_info::describe(metric));
+        let (min, max) = samples.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), s| (lo.min(s.1), hi.max(s.1)));
+        // Percentages on their full scale, so 40% never looks like a full bar
+        let scale = match info.kind {
+            Kind::Percent | Kind::State => (info.y_min.unwrap_or(0.0), info.y_max.unwrap_or(100.0)),
+            Kind::Gauge => (min.min(0.0), max),
+        };
+        let (Some(first), Some(last)) = (samples.first(), samples.last()) else { continue };
+        println!(
+            "{}  {}  min {:.2}  max {:.2} {}  {} .. {}",
+            host,
+            metric,
+            min,
+            max,
+            info.unit,
+            storage::format_ts(first.0),
+            storage::format_ts(last.0)
+        );
+        println!("{}", sparkline(samples, width, scale));
+    }
+}
+
+/// Entry point for `winbox-stats query`
+pub fn run_query(args: &QueryArgs) -> Result<()> {
+    let filter = series::Filter {
+        metric: Some(&args.metric),
+        host: args.host.as_deref(),
+        from: args.from.as_deref().map(|s| timespan::local_epoch(s, "--from")).transpose()?,
+        to: args.to.as_deref().map(|s| timespan::local_epoch(s, "--to")).transpose()?,
+        ..Default::default()
+    };
+    let (series, infos) = series::load_described(Path::new("."), &filter)?;
+    if series.is_empty() && !args.json {
+        log::warn!("no samples for {}", args.metric);
+        return Ok(());
+    }
+    if args.sparkline {
+        print_sparklines(&(series, infos), args.width);
+        return Ok(());
+    }
+    if let Some(per) = args.by {
+        return report::print_rows(&report::rows((series, infos), per), args.json);
+    }
+
+    let samples: Vec<Sample> = series
+        .iter()
+        .flat_map(|((host, metric), samples)| {
+            samples.iter().map(move |(ts, value)| Sample { host, metric, ts: storage::format_ts(*ts), value: *value })
+        })
+        .collect();
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&samples)?);
+        return Ok(());
+    }
+    for s in &samples {
+        println!("{}  {}  {}  {:.2}", s.host, s.metric, s.ts, s.value);
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+// src/report.rs
+//! `report`: min, average, max and p50/p95/p99 of every metric per host, per
+//! month or day, for capacity planning.
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::cli::ReportArgs;
+use crate::config::Config;
+use crate::notify;
+use crate::series::{self, Infos, Series};
+use crate::stats::{self, Per, Summary};
+
+#[derive(Serialize)]
+pub struct Row {
+    pub host: String,
+    pub metric: String,
+    /// e.g. `%` or `ms`; empty for plain counts
+    pub unit: String,
+    pub period: String,
+    #[serde(flatten)]
+    pub summary: Summary,
+}
+
+pub fn rows((series, infos): (Series, Infos), per: Per) -> Vec<Row> {
+    let mut out = Vec::new();
+    for (key, samples) in series {
+        let unit = infos.get(&key).map(|i| i.unit.clone()).unwrap_or_default();
+        let (host, metric) = key;
+        for (period, summary) in stats::summarize_per(&samples, per) {
+            out.push(Row { host: host.clone(), metric: metric.clone(), unit: unit.clone(), period, summary });
+        }
+    }
+    out
+}
+
+pub fn format_rows(rows: &[Row], json: bool) -> Result<String> {
+    if json {
+        return Ok(serde_json::to_string_pretty(rows)? + "\n");
+    }
+    let hw = rows.iter().map(|r| r.host.len()).max().unwrap_or(0).max(4);
+    let mw = rows.iter().map(|r| r.metric.len()).max().unwrap_or(0).max(6);
+    let uw = rows.iter().map(|r| r.unit.len()).max().unwrap_or(0).max(4);
+    let pw = rows.iter().map(|r| r.period.len()).max().unwrap_or(0).max(6);
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{:<hw$}  {:<mw$}  {:<uw$}  {:<pw$}  {:>8}  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}",
+        "HOST", "METRIC", "UNIT", "PERIOD", "SAMPLES", "MIN", "AVG", "MAX", "P50", "P95", "P99"
+    )?;
+    for r in rows {
+        let s = &r.summary;
+        writeln!(
+            out,
+            "{:<hw$}  {:<mw$}  {:<uw$}  {:<pw$}  {:>8}  {:>9.2}  {:>9.2}  {:>9.2}  {:>9.2}  {:>9.2}  {:>9.2}",
+            r.host, r.metric, r.unit, r.period, s.samples, s.min, s.avg, s.max, s.p50, s.p95, s.p99
+        )?;
+    }
+    Ok(out)
+}
+
+pub fn print_rows(rows: &[Row], json: bool) -> Result<()> {
+    print!("{}", format_rows(rows, json)?);
+    Ok(())
+}
+
+/// Entry point for `winbox-stats report`
+pub fn run_report(args: &ReportArgs, config: &Config) -> Result<()> {
+    let smtp = if args.email { Some(notify::smtp(config)?) } else { None };
+    let filter = series::Filter {
+        metric: args.metric.as_deref(),
+        host: args.host.as_deref(),
+        month: args.month.as_deref(),
+        ..Default::default()
+    };
+    let rows = rows(series::load_described(Path::new("."), &filter)?, args.by);
+    if rows.is_empty() && !args.json {
+        log::warn!("no samples to report");
+        return Ok(());
+    }
+    let output = format_rows(&rows, args.json)?;
+    print!("{}", output);
+    if let Some(smtp) = smtp {
+        let message = notify::Message {
+            subject: format!("winbox-stats report {}", args.month.as_deref().unwrap_or("all months")),
+            body: output,
+            content_type: if args.json { "application/json" } else { "text/plain" },
+            attachment: None,
+        };
+        notify::send(smtp, &message)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,292 @@
+// src/report_html.rs
+//! `report --html`: one self-contained page per month with the summary table
+//! of every host, the change of each average against the previous month, and
+//! an inline SVG chart per metric, so it can be mailed out as it is.
+//! `report --pdf` prints the same page through a headless Edge or Chrome.
+use anyhow::{bail, Context, Result};
+use chrono::{Local, Months, NaiveDate};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cli::ReportArgs;
+use crate::config::Config;
+use crate::graph::plot::{render_svg, split_stem_sqlite};
+use crate::graph::style::{Style, Theme};
+use crate::metric_info;
+use crate::notify;
+use crate::patches;
+use crate::selfmon;
+use crate::series::{self, Infos, Series};
+use crate::stats::{self, Summary};
+use crate::storage;
+use crate::viewer;
+
+const CHART_WIDTH: u32 = 900;
+const CHART_HEIGHT: u32 = 320;
+
+const CSS: &str = "body{font-family:Segoe UI,Helvetica,Arial,sans-serif;margin:2em;color:#222}\
+h1{margin-bottom:0}.meta{color:#666;margin-top:.3em}h2{margin-top:2em;border-bottom:1px solid #ccc}\
+table{border-collapse:collapse;margin:1em 0}th,td{padding:.3em .8em;text-align:right}\
+th:first-child,td:first-child{text-align:left}tr:nth-child(even){background:#f4f4f4}\
+.up{color:#c0392b}.down{color:#27ae60}.flat,.none{color:#888}\
+figure{margin:1em 0;break-inside:avoid}figure svg{max-width:100%;height:auto}@page{margin:1.5cm}";
+
+/// `202511` or `2025-11` -> first day of that month
+pub fn parse_month(s: &str) -> Option<NaiveDate> {
+    let ym = s.replace('-', "");
+    NaiveDate::from_ymd_opt(ym.get(..4)?.parse().ok()?, ym.get(4..)?.parse().ok()?, 1)
+}
+
+/// Newest month that has a database here
+pub fn latest_month(dir: &Path) -> Option<NaiveDate> {
+    storage::find_databases(dir, usize::MAX)
+        .iter()
+        .filter_map(|p| parse_month(&split_stem_sqlite(&storage::db_stem(p)).0))
+        .max()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Arrow and class for the change of the average since last month
+fn trend(now: f64, before: Option<f64>) -> (&'static str, String) {
+    let Some(before) = before else { return ("none", "–".into()) };
+    let delta = now - before;
+    // Changes within 2% of the old level are noise, not a trend
+    if delta.abs() <= 0.02 * before.abs().max(1.0) {
+        ("flat", format!("→ {:+.2}", delta))
+    } else if delta > 0.0 {
+        ("up", format!("▲ {:+.2}", delta))
+    } else {
+        ("down", format!("▼ {:+.2}", delta))
+    }
+}
+
+/// (host, metric, samples) of one chart
+type Entry<'a> = (&'a str, &'a str, &'a [(i64, f64)]);
+
+/// "reboot pending, last update installed 12 days ago" from a host's newest
+/// patch samples, if it has any
+fn patch_status(metrics: &[Entry]) -> Option<String> {
+    let latest = |name: &str| metrics.iter().find(|m| m.1 == name).and_then(|m| m.2.last()).map(|s| s.1);
+    let mut parts = Vec::new();
+    if let Some(pending) = latest(patches::REBOOT_PENDING) {
+        parts.push(if pending >= 0.5 { "reboot pending".to_string() } else { "no reboot pending".to_string() });
+    }
+    if let Some(days) = latest(patches::UPDATE_AGE) {
+        parts.push(format!("last update installed {:.0} days ago", days));
+    }
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// Lines about each host's collector from its `month` databases: what it
+/// runs on and its own health (`SELFMON`), by host
+fn collector_notes(dir: &Path, month: &str) -> BTreeMap<String, Vec<String>> {
+    let mut out: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for p in storage::find_databases(dir, usize::MAX) {
+        let (ym, host, _) = split_stem_sqlite(&storage::db_stem(&p));
+        if host.is_empty() || !series::same_month(&ym, month) {
+            continue;
+        }
+        let Ok(db) = storage::open_read_only(&p) else { continue };
+        let notes = out.entry(host.to_ascii_uppercase()).or_default();
+        if let Ok(Some(platform)) = storage::get_meta(&db.conn, "virtualization") {
+            notes.push(format!("Runs on {}", platform));
+        }
+        if let Ok(Some(health)) = selfmon::read(&db.conn) {
+            notes.push(format!("Collector: {}", health));
+        }
+    }
+    out
+}
+
+fn render(
+    month: &str,
+    previous: &str,
+    (current, infos): &(Series, Infos),
+    before: &BTreeMap<(String, String), f64>,
+    notes: &BTreeMap<String, Vec<String>>,
+    config: &Config,
+) -> Result<String> {
+    let mut style = Style::new(Theme::Light, CHART_WIDTH, CHART_HEIGHT, None)?;
+    style.brand(&config.charts)?;
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Capacity report {}</title>", month)?;
+    writeln!(html, "<style>{}</style></head><body>", CSS)?;
+    writeln!(html, "<h1>Capacity report {}</h1>", month)?;
+    writeln!(
+        html,
+        "<p class=\"meta\">Generated {} by winbox-stats. Change compares each average with {}.</p>",
+        Local::now().format("%Y-%m-%d %H:%M"),
+        previous
+    )?;
+
+    // Series are ordered by host, so each host's metrics are adjacent
+    let entries: Vec<Entry> = current.iter().map(|((host, metric), samples)| (host.as_str(), metric.as_str(), &samples[..])).collect();
+    for metrics in entries.chunk_by(|a, b| a.0 == b.0) {
+        let host = metrics[0].0;
+        writeln!(html, "<h2>{}</h2>", escape(host))?;
+        for note in notes.get(host).into_iter().flatten() {
+            writeln!(html, "<p class=\"meta\">{}</p>", escape(note))?;
+        }
+        if let Some(status) = patch_status(metrics) {
+            writeln!(html, "<p class=\"meta\">Patches: {}</p>", status)?;
+        }
+        writeln!(
+            html,
+            "<table><tr><th>Metric</th><th>Samples</th><th>Min</th><th>Avg</th><th>Max</th><th>P95</th><th>P99</th><th>vs {}</th></tr>",
+            previous
+        )?;
+        for (_, metric, samples) in metrics {
+            let values: Vec<f64> = samples.iter().map(|s| s.1).collect();
+            let Some(Summary { samples, min, avg, max, p95, p99, .. }) = stats::summarize(&values) else { continue };
+            let key = (host.to_string(), metric.to_string());
+            let (class, change) = trend(avg, before.get(&key).copied());
+            let name = match infos.get(&key).map(|i| i.unit.as_str()) {
+                Some(unit) if !unit.is_empty() => format!("{} ({})", metric, unit),
+                _ => metric.to_string(),
+            };
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{:.2}</td><td>{:.2}</td><td>{:.2}</td><td>{:.2}</td><td>{:.2}</td><td class=\"{}\">{}</td></tr>",
+                escape(&name),
+                samples,
+                min,
+                avg,
+                max,
+                p95,
+                p99,
+                class,
+                change
+            )?;
+        }
+        writeln!(html, "</table>")?;
+        for (_, metric, samples) in metrics {
+            let key = (host.to_string(), metric.to_string());
+            let info = infos.get(&key).cloned().unwrap_or_else(|| metric_info::describe(metric));
+            let svg = render_svg(&style, month, host, metric, &info, samples, config.threshold_for(metric))
+                .with_context(|| format!("chart {} {}", host, metric))?;
+            writeln!(html, "<figure>{}</figure>", svg)?;
+        }
+    }
+    writeln!(html, "</body></html>")?;
+    Ok(html)
+}
+
+/// Edge or Chrome, whichever is installed, for printing the page to PDF
+fn find_browser() -> Option<PathBuf> {
+    if cfg!(windows) {
+        let roots = ["ProgramFiles(x86)", "ProgramFiles", "LOCALAPPDATA"].into_iter().filter_map(std::env::var_os);
+        roots
+            .flat_map(|root| {
+                [r"Microsoft\Edge\Application\msedge.exe", r"Google\Chrome\Application\chrome.exe"].map(|exe| Path::new(&root).join(exe))
+            })
+            .find(|p| p.is_file())
+    } else {
+        let path = std::env::var_os("PATH")?;
+        std::env::split_paths(&path)
+            .flat_map(|dir| ["microsoft-edge", "google-chrome", "chromium", "chromium-browser"].map(|exe| dir.join(exe)))
+            .find(|p| p.is_file())
+    }
+}
+
+/// Print `html` to `out` with a headless browser
+fn print_pdf(html: &str, out: &Path, browser: Option<&Path>) -> Result<()> {
+    let browser = match browser {
+        Some(b) => b.to_path_buf(),
+        None => find_browser().context("no Edge or Chrome found to print the PDF; pass --browser")?,
+    };
+    // A fresh name no other user can have placed a file or link at
+    let mut page = tempfile::Builder::new().prefix("winbox-stats-report-").suffix(".html").tempfile().context("create the page to print")?;
+    page.write_all(html.as_bytes()).with_context(|| format!("write {}", page.path().display()))?;
+    // Closed, so the browser can open it on Windows too; removed when dropped
+    let page = page.into_temp_path();
+    // The browser resolves relative paths against its own working directory
+    let target = std::path::absolute(out)?;
+    // So a stale file is not mistaken for this run's output
+    if target.exists() {
+        fs::remove_file(&target).with_context(|| format!("replace {}", target.display()))?;
+    }
+    let result = Command::new(&browser)
+        .args(["--headless", "--disable-gpu", "--no-pdf-header-footer"])
+        .arg(format!("--print-to-pdf={}", target.display()))
+        .arg(&*page)
+        .output()
+        .with_context(|| format!("run {}", browser.display()));
+    let _ = page.close();
+    let output = result?;
+    if !target.is_file() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.lines().find(|l| !l.trim().is_empty()) {
+            Some(line) => bail!("{} wrote no PDF ({}): {}", browser.display(), output.status, line),
+            None => bail!("{} wrote no PDF ({})", browser.display(), output.status),
+        }
+    }
+    Ok(())
+}
+
+/// Entry point for `winbox-stats report --html` and `--pdf`
+pub fn run_report_page(args: &ReportArgs, config: &Config) -> Result<()> {
+    let smtp = if args.email { Some(notify::smtp(config)?) } else { None };
+    let dir = Path::new(".");
+    let start = match &args.month {
+        Some(m) => parse_month(m).with_context(|| format!("invalid --month {:?}; use e.g. 2025-11", m))?,
+        None => latest_month(dir).context("no databases here to report on")?,
+    };
+    let month = start.format("%Y-%m").to_string();
+    let previous = (start - Months::new(1)).format("%Y-%m").to_string();
+    let filter = |ym| series::Filter { metric: args.metric.as_deref(), host: args.host.as_deref(), month: Some(ym), ..Default::default() };
+    let current = series::load_described(dir, &filter(&month))?;
+    if current.0.is_empty() {
+        log::warn!("no samples for {}", month);
+        return Ok(());
+    }
+    let before: BTreeMap<(String, String), f64> = series::load(dir, &filter(&previous))?
+        .into_iter()
+        .filter_map(|(key, samples)| {
+            let values: Vec<f64> = samples.iter().map(|s| s.1).collect();
+            Some((key, stats::summarize(&values)?.avg))
+        })
+        .collect();
+
+    let html = render(&month, &previous, &current, &before, &collector_notes(dir, &month), config)?;
+    let ext = if args.pdf { "pdf" } else { "html" };
+    let out = args.out.clone().unwrap_or_else(|| PathBuf::from(format!("report-{}.{}", month, ext)));
+    if args.pdf {
+        print_pdf(&html, &out, args.browser.as_deref())?;
+    } else {
+        fs::write(&out, &html).with_context(|| format!("write {}", out.display()))?;
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "written": [&out] }))?);
+    } else {
+        println!("Wrote {}", out.display());
+    }
+    if args.open {
+        viewer::open(&out)?;
+    }
+
+    let Some(smtp) = smtp else { return Ok(()) };
+    let subject = format!("Capacity report {}", month);
+    let message = if args.pdf {
+        let name = out.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| format!("report-{}.pdf", month));
+        notify::Message {
+            body: format!("{} is attached.\n", subject),
+            subject,
+            content_type: "text/plain",
+            attachment: Some(notify::Attachment {
+                name,
+                content_type: "application/pdf",
+                data: fs::read(&out).with_context(|| format!("read {}", out.display()))?,
+            }),
+        }
+    } else {
+        notify::Message { subject, body: html, content_type: "text/html", attachment: None }
+    };
+    notify::send(smtp, &message)
+}
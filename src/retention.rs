@@ -0,0 +1,138 @@
+// src/retention.rs
+//! Round-robin retention (`[retention]` in the config), applied by `collect`
+//! to the monthly databases of the series it writes.
+//!
+//! Samples older than `raw` are replaced by 5-minute averages, those older
+//! than `five_minute` by hourly averages, and monthly files entirely older
+//! than `hourly` are deleted. Each file remembers in `Meta` how far it has
+//! been rolled up, so a run only touches the hour or so that aged since the
+//! last roll-up.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::audit;
+use crate::graph::plot::split_stem_sqlite;
+use crate::storage::{self, insert_sample, list_writable_tables, read_rows_between};
+use crate::timespan;
+use crate::verify::month_bounds;
+
+/// Buckets that must have aged before a tier is rolled up further
+const ROLLUP_STEP: i64 = 12;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub enabled: bool,
+    /// Keep every sample this long
+    pub raw: String,
+    /// Then 5-minute averages this long
+    pub five_minute: String,
+    /// Then hourly averages this long; older months are deleted
+    pub hourly: String,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig { enabled: false, raw: "7d".into(), five_minute: "90d".into(), hourly: "730d".into() }
+    }
+}
+
+/// Average `[from, to)` of every table into `secs` buckets; returns (samples, averages)
+fn roll_up(conn: &mut rusqlite::Connection, secs: i64, from: i64, to: i64) -> Result<(usize, usize)> {
+    let tx = conn.transaction()?;
+    let (mut before, mut after) = (0, 0);
+    for t in list_writable_tables(&tx)? {
+        let rows = read_rows_between(&tx, &t, from, to)?;
+        let mut buckets: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+        for (rowid, ts, v) in &rows {
+            tx.execute(&format!(r#"DELETE FROM "{}" WHERE rowid = ?1"#, t), [rowid])?;
+            // Months can start mid-bucket where the UTC offset is not whole hours
+            let b = buckets.entry((ts.div_euclid(secs) * secs).max(from)).or_insert((0.0, 0));
+            b.0 += v;
+            b.1 += 1;
+        }
+        let text = storage::has_text_timestamps(&tx, &t)?;
+        for (ts, (sum, n)) in &buckets {
+            insert_sample(&tx, &t, *ts, sum / *n as f64, None, text)?;
+        }
+        before += rows.len();
+        after += buckets.len();
+    }
+    storage::set_meta(&tx, &format!("rollup_{}s", secs), &to.to_string())?;
+    tx.commit()?;
+    Ok((before, after))
+}
+
+fn remove_with_sidecars(path: &Path) -> Result<()> {
+    fs::remove_file(path).with_context(|| format!("remove {}", path.display()))?;
+    for side in ["-wal", "-shm"] {
+        let mut s = path.to_path_buf().into_os_string();
+        s.push(side);
+        let _ = fs::remove_file(s);
+    }
+    Ok(())
+}
+
+/// Apply the retention tiers to every monthly database of `series` in `dir`;
+/// returns what was deleted or rolled up, as recorded in the audit log
+pub fn maintain(dir: &Path, series: &str, cfg: &RetentionConfig, now: i64) -> Result<Vec<String>> {
+    let mut done_here = Vec::new();
+    if !cfg.enabled {
+        return Ok(done_here);
+    }
+    let raw = timespan::parse(&cfg.raw).context("retention.raw")?;
+    let five_minute = timespan::parse(&cfg.five_minute).context("retention.five_minute")?;
+    let hourly = timespan::parse(&cfg.hourly).context("retention.hourly")?;
+    // Each tier ends where the coarser one takes over; align to its buckets so none is split
+    let tiers = [(300, (now - raw).div_euclid(300) * 300), (3600, (now - raw - five_minute).div_euclid(3600) * 3600)];
+    let expired = now - raw - five_minute - hourly;
+
+    for p in storage::find_databases(dir, 1) {
+        let (ym, host, metric) = split_stem_sqlite(&storage::db_stem(&p));
+        if storage::is_archive(&p) || metric.is_some() || !host.eq_ignore_ascii_case(series) {
+            continue;
+        }
+        let Some((start, end)) = month_bounds(&ym) else { continue };
+        let target = p.display().to_string();
+        if end <= expired {
+            remove_with_sidecars(&p)?;
+            audit::record(dir, "retention", &target, &format!("deleted, older than {}", cfg.hourly))?;
+            log::info!("retention: deleted {}", target);
+            done_here.push(format!("{}: deleted", target));
+            continue;
+        }
+        let mut conn = None;
+        for (secs, cutoff) in tiers {
+            let to = cutoff.min(end);
+            if start >= to {
+                continue;
+            }
+            let conn = match &mut conn {
+                Some(c) => c,
+                None => conn.insert(storage::open(&p)?),
+            };
+            let done: i64 = storage::get_meta(conn, &format!("rollup_{}s", secs))?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(start);
+            // Work in steps of 12 buckets so the audit log gets an entry per hour, not per run
+            if done >= to || (to - done < secs * ROLLUP_STEP && to < end) {
+                continue;
+            }
+            let (before, after) = roll_up(conn, secs, done, to).with_context(|| format!("roll up {}", target))?;
+            if before > after {
+                // Past months are no longer written to, so give the space back now
+                if end <= now {
+                    conn.execute_batch("VACUUM")?;
+                }
+                let detail = format!("{} samples -> {} {}-second averages before {}", before, after, secs, storage::format_ts(to));
+                audit::record(dir, "retention", &target, &detail)?;
+                log::info!("retention: {}: {}", target, detail);
+                done_here.push(format!("{}: {}", target, detail));
+            }
+        }
+    }
+    Ok(done_here)
+}
@@ -0,0 +1,108 @@
+// src/selfmon.rs
+//! The collector's own health, so a gap in a chart can be put down to a slow
+//! or failing collector rather than guessed at. Every run writes one row to
+//! the `SELFMON` table: how long sampling and the database write took, how
+//! many samples were written, and which metrics the previous run recorded
+//! but this one did not (a probe, counter or command that failed and was
+//! skipped with a warning). `doctor` and the HTML/PDF report summarise it.
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::storage::{list_writable_tables, SELFMON_TABLE, TS_COL};
+
+/// Tables with a sample from the run at `previous` (epoch seconds) that
+/// `samples` has nothing for
+pub fn missing(conn: &Connection, previous: i64, samples: &[(String, f64)]) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    for table in list_writable_tables(conn)? {
+        if samples.iter().any(|(t, _)| t.eq_ignore_ascii_case(&table)) {
+            continue;
+        }
+        // Sub-second runs store fractional seconds
+        let sql = format!(r#"SELECT 1 FROM "{}" WHERE "{TS_COL}" >= ?1 AND "{TS_COL}" < ?1 + 1 LIMIT 1"#, table);
+        if conn.query_row(&sql, params![previous], |_| Ok(())).optional()?.is_some() {
+            out.push(table);
+        }
+    }
+    Ok(out)
+}
+
+pub fn record(conn: &Connection, ts: i64, sample_ms: f64, write_ms: f64, samples: usize, missing: &[String]) -> Result<()> {
+    conn.execute(
+        &format!(r#"INSERT INTO "{}"("{TS_COL}","SampleMs","WriteMs","Samples","Missing") VALUES (?1, ?2, ?3, ?4, ?5)"#, SELFMON_TABLE),
+        params![ts, sample_ms, write_ms, samples as i64, missing.join(",")],
+    )?;
+    Ok(())
+}
+
+/// Summary of the `SELFMON` rows of one database
+pub struct Health {
+    pub runs: usize,
+    pub avg_sample_ms: f64,
+    pub max_sample_ms: f64,
+    pub avg_write_ms: f64,
+    pub max_write_ms: f64,
+    /// Metric -> runs it was missing from
+    pub missing: BTreeMap<String, usize>,
+}
+
+/// `None` for databases without the table (read-only opens are not migrated) or rows
+pub fn read(conn: &Connection) -> Result<Option<Health>> {
+    let exists: Option<i64> = conn
+        .query_row("SELECT 1 FROM sqlite_master WHERE type='table' AND name=?1", params![SELFMON_TABLE], |r| r.get(0))
+        .optional()?;
+    if exists.is_none() {
+        return Ok(None);
+    }
+    let mut stmt = conn.prepare(&format!(r#"SELECT "SampleMs","WriteMs","Missing" FROM "{}""#, SELFMON_TABLE))?;
+    let rows = stmt.query_map([], |r| Ok((r.get::<_, f64>(0)?, r.get::<_, f64>(1)?, r.get::<_, String>(2)?)))?;
+    let mut health = Health { runs: 0, avg_sample_ms: 0.0, max_sample_ms: 0.0, avg_write_ms: 0.0, max_write_ms: 0.0, missing: BTreeMap::new() };
+    for row in rows {
+        let (sample_ms, write_ms, missing) = row?;
+        health.runs += 1;
+        health.avg_sample_ms += sample_ms;
+        health.avg_write_ms += write_ms;
+        health.max_sample_ms = health.max_sample_ms.max(sample_ms);
+        health.max_write_ms = health.max_write_ms.max(write_ms);
+        for metric in missing.split(',').filter(|m| !m.is_empty()) {
+            *health.missing.entry(metric.to_string()).or_default() += 1;
+        }
+    }
+    if health.runs == 0 {
+        return Ok(None);
+    }
+    health.avg_sample_ms /= health.runs as f64;
+    health.avg_write_ms /= health.runs as f64;
+    Ok(Some(health))
+}
+
+/// `1.2 s`, `45 ms`
+fn millis(ms: f64) -> String {
+    if ms >= 1000.0 {
+        format!("{:.1} s", ms / 1000.0)
+    } else {
+        format!("{:.0} ms", ms)
+    }
+}
+
+/// "2880 runs, sampling 1.1 s (max 4.2 s), writing 12 ms (max 310 ms); missing PING_GW in 14"
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} runs, sampling {} (max {}), writing {} (max {})",
+            self.runs,
+            millis(self.avg_sample_ms),
+            millis(self.max_sample_ms),
+            millis(self.avg_write_ms),
+            millis(self.max_write_ms)
+        )?;
+        if !self.missing.is_empty() {
+            let missing: Vec<String> = self.missing.iter().map(|(m, n)| format!("{} in {}", m, n)).collect();
+            write!(f, "; missing {}", missing.join(", "))?;
+        }
+        Ok(())
+    }
+}
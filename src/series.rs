@@ -0,0 +1,88 @@
+// src/series.rs
+//! Samples gathered from every database under a directory, keyed by host and
+//! metric, for the commands that summarise across files (`top-hosts`,
+//! `report`, `query`, `analyze`, `forecast`).
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::aliases::Aliases;
+use crate::config::glob_match;
+use crate::graph::plot::{is_adhoc, split_stem_sqlite};
+use crate::metric_info::{self, MetricInfo};
+use crate::storage::{self, list_metric_tables, read_samples};
+
+/// (host, metric) -> samples in time order
+pub type Series = BTreeMap<(String, String), Vec<(i64, f64)>>;
+
+/// (host, metric) -> what the metric measures, from the last file read
+pub type Infos = BTreeMap<(String, String), MetricInfo>;
+
+#[derive(Default)]
+pub struct Filter<'a> {
+    /// Metric name or `*` pattern
+    pub metric: Option<&'a str>,
+    pub host: Option<&'a str>,
+    /// `YYYY-MM` or `YYYYMM`
+    pub month: Option<&'a str>,
+    /// Epoch seconds, inclusive
+    pub from: Option<i64>,
+    /// Epoch seconds, exclusive
+    pub to: Option<i64>,
+}
+
+/// `2025-08` and `202508` name the same month
+pub fn same_month(ym: &str, wanted: &str) -> bool {
+    ym.replace('-', "") == wanted.replace('-', "")
+}
+
+/// Every matching series under `dir`, subfolders included
+pub fn load(dir: &Path, filter: &Filter) -> Result<Series> {
+    Ok(load_described(dir, filter)?.0)
+}
+
+/// [`load`], plus the description of each series
+pub fn load_described(dir: &Path, filter: &Filter) -> Result<(Series, Infos)> {
+    let aliases = Aliases::load(dir)?;
+    let wanted = filter.metric.map(|m| aliases.resolve(m));
+    let mut out = Series::new();
+    let mut infos = Infos::new();
+    for p in storage::find_databases(dir, usize::MAX) {
+        let stem = storage::db_stem(&p);
+        let (ym, host, metric_opt) = split_stem_sqlite(&stem);
+        if host.is_empty()
+            || is_adhoc(&stem)
+            || filter.month.is_some_and(|m| !same_month(&ym, m))
+            || filter.host.is_some_and(|h| !h.eq_ignore_ascii_case(&host))
+        {
+            continue;
+        }
+        let db = storage::open_read_only(&p)?;
+        let mut tables = list_metric_tables(&db.conn)?;
+        if metric_opt.is_some() {
+            tables.truncate(1);
+        }
+        for t in tables {
+            // Per-metric DBs carry the metric in the filename, monthly DBs in the table name
+            let metric = aliases.resolve(metric_opt.as_deref().unwrap_or(&t));
+            if wanted.as_deref().is_some_and(|w| !glob_match(w, &metric)) {
+                continue;
+            }
+            let samples = read_samples(&db.conn, &t).with_context(|| format!("read {} table {}", p.display(), t))?;
+            // A per-metric DB's table is not named after its metric
+            let info = if metric_opt.is_some() { metric_info::describe(&metric) } else { storage::metric_info(&db.conn, &t) };
+            infos.insert((host.to_ascii_uppercase(), metric.clone()), info);
+            out.entry((host.to_ascii_uppercase(), metric))
+                .or_default()
+                .extend(samples.into_iter().filter(|s| {
+                    filter.from.is_none_or(|f| s.0 >= f) && filter.to.is_none_or(|t| s.0 < t)
+                }));
+        }
+    }
+    out.retain(|_, samples| !samples.is_empty());
+    infos.retain(|key, _| out.contains_key(key));
+    for samples in out.values_mut() {
+        samples.sort_by_key(|s| s.0);
+    }
+    Ok((out, infos))
+}
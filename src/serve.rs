@@ -0,0 +1,288 @@
+// src/serve.rs
+//! `serve grafana`: a read-only HTTP API over the databases in the current
+//! directory for Grafana.
+//!
+//! The SimpleJSON / JSON datasource endpoints (`POST /search`, `/query`,
+//! `/annotations`) are served at the root. The Infinity datasource can use
+//! `GET /metrics` and `GET /series?target=..&from=..&to=..` (epoch ms).
+//! Series are named `HOST/METRIC`. Samples are read on every request, so new
+//! ones show up without a restart; the list of series is rebuilt when a
+//! database is added or removed, and at least once a minute.
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::aliases::Aliases;
+use crate::cli::ServeGrafanaArgs;
+use crate::cold;
+use crate::graph::plot::split_stem_sqlite;
+use crate::storage::{self, list_metric_tables, read_rows_between};
+use crate::verify::month_bounds;
+
+/// Where each series lives: (database, table, month as epoch range)
+type Index = BTreeMap<String, Vec<(PathBuf, String, Option<(i64, i64)>)>>;
+
+/// Largest request body read; Grafana's queries are a few KB
+const MAX_BODY: u64 = 1 << 20;
+/// Metrics added to an existing file show up after this long
+const INDEX_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// The [`Index`] kept between requests, so each one does not open every database
+#[derive(Default)]
+struct Catalog {
+    files: Vec<PathBuf>,
+    built: Option<Instant>,
+    index: Index,
+}
+
+impl Catalog {
+    fn get(&mut self, dir: &Path) -> Result<&Index> {
+        let files = storage::find_databases(dir, 1);
+        if files != self.files || self.built.is_none_or(|t| t.elapsed() >= INDEX_MAX_AGE) {
+            self.index = index(dir)?;
+            self.files = files;
+            self.built = Some(Instant::now());
+        }
+        Ok(&self.index)
+    }
+}
+
+fn index(dir: &Path) -> Result<Index> {
+    let aliases = Aliases::load(dir)?;
+    let mut out = Index::new();
+    for p in storage::find_databases(dir, 1) {
+        let (ym, host, metric_opt) = split_stem_sqlite(&storage::db_stem(&p));
+        if host.is_empty() {
+            continue;
+        }
+        let db = match storage::open_read_only(&p) {
+            Ok(db) => db,
+            Err(e) => {
+                log::warn!("{}: {:#}", p.display(), e);
+                continue;
+            }
+        };
+        let mut tables = list_metric_tables(&db.conn)?;
+        if metric_opt.is_some() {
+            tables.truncate(1);
+        }
+        let bounds = month_bounds(&ym.replace('-', ""));
+        for t in tables {
+            // Per-metric DBs carry the metric in the filename, monthly DBs in the table name
+            let metric = aliases.resolve(metric_opt.as_deref().unwrap_or(&t));
+            let name = format!("{}/{}", host.to_ascii_uppercase(), metric);
+            out.entry(name).or_default().push((p.clone(), t, bounds));
+        }
+    }
+    Ok(out)
+}
+
+/// Samples of one series in `[from, to]` (epoch seconds), oldest first
+fn series(index: &Index, target: &str, from: i64, to: i64) -> Result<Vec<(i64, f64)>> {
+    let Some(parts) = index.iter().find(|(k, _)| k.eq_ignore_ascii_case(target)).map(|(_, v)| v) else {
+        return Ok(Vec::new());
+    };
+    let mut out = Vec::new();
+    for (path, table, bounds) in parts {
+        // Skip months outside the range without opening (or inflating) them
+        if bounds.is_some_and(|(start, end)| end <= from || start > to) {
+            continue;
+        }
+        let db = storage::open_read_only(path)?;
+        let context = || format!("read {} table {}", path.display(), table);
+        let rows = read_rows_between(&db.conn, table, from, to.saturating_add(1)).with_context(context)?;
+        out.extend(rows.into_iter().map(|(_, ts, value)| (ts, value)));
+        // Compacted samples are not rows
+        let cold = cold::samples(&db.conn, table).with_context(context)?;
+        out.extend(cold.into_iter().map(|(ts_ms, value)| (ts_ms.div_euclid(1000), value)).filter(|s| s.0 >= from && s.0 <= to));
+    }
+    out.sort_by_key(|s| s.0);
+    Ok(out)
+}
+
+/// Average consecutive samples so at most `max` points are returned
+fn thin(samples: Vec<(i64, f64)>, max: usize) -> Vec<(i64, f64)> {
+    if max == 0 || samples.len() <= max {
+        return samples;
+    }
+    let per = samples.len().div_ceil(max);
+    samples
+        .chunks(per)
+        .map(|c| (c[0].0, c.iter().map(|s| s.1).sum::<f64>() / c.len() as f64))
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct SearchBody {
+    #[serde(default)]
+    target: String,
+}
+
+#[derive(Deserialize)]
+struct Range {
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    target: String,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryBody {
+    range: Range,
+    targets: Vec<Target>,
+    #[serde(default)]
+    max_data_points: usize,
+}
+
+fn epoch(rfc3339: &str) -> Result<i64> {
+    Ok(DateTime::parse_from_rfc3339(rfc3339).with_context(|| format!("bad time {}", rfc3339))?.timestamp())
+}
+
+fn search(dir: &Path, catalog: &mut Catalog, body: &str) -> Result<Value> {
+    let wanted = serde_json::from_str::<SearchBody>(body).map(|b| b.target).unwrap_or_default().to_ascii_uppercase();
+    let names: Vec<String> = catalog.get(dir)?.keys().filter(|k| k.to_ascii_uppercase().contains(&wanted)).cloned().collect();
+    Ok(json!(names))
+}
+
+fn query(dir: &Path, catalog: &mut Catalog, body: &str) -> Result<Value> {
+    let q: QueryBody = serde_json::from_str(body).context("parse query")?;
+    let (from, to) = (epoch(&q.range.from)?, epoch(&q.range.to)?);
+    let index = catalog.get(dir)?;
+    let mut out = Vec::new();
+    for t in &q.targets {
+        let points = thin(series(index, &t.target, from, to)?, q.max_data_points);
+        if t.kind.as_deref() == Some("table") {
+            out.push(json!({
+                "type": "table",
+                "columns": [{"text": "Time", "type": "time"}, {"text": t.target, "type": "number"}],
+                "rows": points.iter().map(|(ts, v)| json!([ts * 1000, v])).collect::<Vec<_>>(),
+            }));
+        } else {
+            out.push(json!({
+                "target": t.target,
+                "datapoints": points.iter().map(|(ts, v)| json!([v, ts * 1000])).collect::<Vec<_>>(),
+            }));
+        }
+    }
+    Ok(json!(out))
+}
+
+/// `GET /series` for the Infinity datasource: rows of `{time, value}`
+fn infinity_series(dir: &Path, catalog: &mut Catalog, url: &str) -> Result<Value> {
+    let params: BTreeMap<&str, String> = url
+        .split_once('?')
+        .map(|(_, q)| q)
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (k, percent_decode(v)))
+        .collect();
+    let target = params.get("target").context("missing target")?;
+    let ms = |key: &str, default: i64| -> Result<i64> {
+        params.get(key).map_or(Ok(default), |v| v.parse().with_context(|| format!("bad {}", key)))
+    };
+    let (from, to) = (ms("from", 0)? / 1000, ms("to", i64::MAX)? / 1000);
+    let max = params.get("max").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let points = thin(series(catalog.get(dir)?, target, from, to)?, max);
+    Ok(json!(points.iter().map(|(ts, v)| json!({"time": ts * 1000, "value": v})).collect::<Vec<_>>()))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => match std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(b) => {
+                    out.push(b);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn respond(request: Request, status: u16, body: String) {
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+        .with_header(header("Access-Control-Allow-Origin", "*"))
+        .with_header(header("Access-Control-Allow-Headers", "accept, content-type"))
+        .with_header(header("Access-Control-Allow-Methods", "GET, POST, OPTIONS"));
+    if let Err(e) = request.respond(response) {
+        log::warn!("send response: {}", e);
+    }
+}
+
+fn handle(dir: &Path, catalog: &mut Catalog, mut request: Request) {
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().take(MAX_BODY + 1).read_to_string(&mut body) {
+        respond(request, 400, json!({ "error": e.to_string() }).to_string());
+        return;
+    }
+    if body.len() as u64 > MAX_BODY {
+        respond(request, 413, json!({ "error": format!("request body over {} bytes", MAX_BODY) }).to_string());
+        return;
+    }
+    let url = request.url().to_string();
+    let path = url.split('?').next().unwrap_or_default().trim_end_matches('/');
+    let result = match (request.method(), path) {
+        (Method::Options, _) => Ok(Value::Null),
+        (Method::Get, "") => Ok(json!("winbox-stats")),
+        (Method::Post, "/search") => search(dir, catalog, &body),
+        (Method::Get, "/metrics") => search(dir, catalog, ""),
+        (Method::Post, "/query") => query(dir, catalog, &body),
+        (Method::Get, "/series") => infinity_series(dir, catalog, &url),
+        (Method::Post, "/annotations") => Ok(json!([])),
+        _ => {
+            respond(request, 404, json!({ "error": "not found" }).to_string());
+            return;
+        }
+    };
+    match result {
+        Ok(Value::Null) => respond(request, 200, String::new()),
+        Ok(v) => respond(request, 200, v.to_string()),
+        Err(e) => {
+            log::warn!("{} {}: {:#}", request.method(), url, e);
+            respond(request, 400, json!({ "error": format!("{:#}", e) }).to_string());
+        }
+    }
+}
+
+/// Entry point for `winbox-stats serve grafana`; runs until killed
+pub fn run_serve_grafana(args: &ServeGrafanaArgs) -> Result<()> {
+    let server = Server::http(&args.listen).map_err(|e| anyhow::anyhow!("listen on {}: {}", args.listen, e))?;
+    println!("Serving Grafana API for the databases here on http://{}", args.listen);
+    let dir = Path::new(".");
+    let mut catalog = Catalog::default();
+    for request in server.incoming_requests() {
+        log::info!("{} {}", request.method(), request.url());
+        handle(dir, &mut catalog, request);
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+// src/services.rs
+//! Up/down state of the services listed under `services` in the config,
+//! recorded as `SVC_<name>` tables holding 1 (running) or 0 (anything else).
+use anyhow::Result;
+
+use crate::storage::metric_label;
+
+/// Table prefix for service state metrics
+pub const SERVICE_PREFIX: &str = "SVC_";
+
+/// `SVC_` plus the service name, upper-cased, with anything unusual as `_`
+pub fn table_name(service: &str) -> String {
+    format!("{}{}", SERVICE_PREFIX, metric_label(service))
+}
+
+pub fn is_service_metric(metric: &str) -> bool {
+    metric.len() > SERVICE_PREFIX.len() && metric[..SERVICE_PREFIX.len()].eq_ignore_ascii_case(SERVICE_PREFIX)
+}
+
+/// Whether the service is running; an error if it does not exist or cannot be queried
+#[cfg(windows)]
+pub fn is_running(service: &str) -> Result<bool> {
+    use std::io;
+    use windows_sys::Win32::System::Services::{
+        CloseServiceHandle, OpenSCManagerW, OpenServiceW, QueryServiceStatus, SC_MANAGER_CONNECT,
+        SERVICE_QUERY_STATUS, SERVICE_RUNNING, SERVICE_STATUS,
+    };
+
+    let wide: Vec<u16> = service.encode_utf16().chain(Some(0)).collect();
+    // SAFETY: handles are checked before use and closed on every path
+    unsafe {
+        let scm = OpenSCManagerW(std::ptr::null(), std::ptr::null(), SC_MANAGER_CONNECT);
+        if scm.is_null() {
+            anyhow::bail!("open service control manager: {}", io::Error::last_os_error());
+        }
+        let svc = OpenServiceW(scm, wide.as_ptr(), SERVICE_QUERY_STATUS);
+        if svc.is_null() {
+            let err = io::Error::last_os_error();
+            CloseServiceHandle(scm);
+            anyhow::bail!("open service {}: {}", service, err);
+        }
+        let mut status: SERVICE_STATUS = std::mem::zeroed();
+        let ok = QueryServiceStatus(svc, &mut status);
+        let err = io::Error::last_os_error();
+        CloseServiceHandle(svc);
+        CloseServiceHandle(scm);
+        if ok == 0 {
+            anyhow::bail!("query service {}: {}", service, err);
+        }
+        Ok(status.dwCurrentState == SERVICE_RUNNING)
+    }
+}
+
+/// Whether the systemd unit is active; an error if it does not exist
+#[cfg(not(windows))]
+pub fn is_running(service: &str) -> Result<bool> {
+    use anyhow::Context;
+    use std::process::Command;
+
+    let out = Command::new("systemctl")
+        .args(["show", "--property=LoadState,ActiveState", "--value", service])
+        .output()
+        .context("run systemctl")?;
+    if !out.status.success() {
+        let err = String::from_utf8_lossy(&out.stderr);
+        anyhow::bail!("query service {}: {}", service, err.lines().next().unwrap_or("systemctl failed"));
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    let mut lines = text.lines();
+    let (load, active) = (lines.next().unwrap_or(""), lines.next().unwrap_or(""));
+    if load == "not-found" || load.is_empty() {
+        anyhow::bail!("service {} not found", service);
+    }
+    Ok(active == "active")
+}
+
+/// One 0/1 reading per configured service; unknown services are skipped with a warning
+pub fn sample(services: &[String]) -> Vec<(String, f64)> {
+    let mut out = Vec::new();
+    for s in services {
+        match is_running(s) {
+            Ok(up) => out.push((table_name(s), if up { 1.0 } else { 0.0 })),
+            Err(e) => log::warn!("{:#}", e),
+        }
+    }
+    out
+}
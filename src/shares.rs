@@ -0,0 +1,139 @@
+// src/shares.rs
+//! Mapped network drives (Windows) and NFS/SMB mounts (Linux), which disk
+//! discovery leaves out. With `[shares] enabled = true` each one is recorded
+//! as `SHARE_<name>` (used %) and `SHARE_<name>_FREE_GB`: the capacity is
+//! shared with every other host using the share, so it gets its own prefix
+//! and its own thresholds (`"SHARE_*"`) rather than counting as a local
+//! `*_Drive`.
+use serde::Deserialize;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::disks::{label_for_mount_point, Volume};
+
+/// Table prefix for network share metrics
+pub const SHARE_PREFIX: &str = "SHARE_";
+
+/// A server that does not answer in time is skipped for this run
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SharesConfig {
+    pub enabled: bool,
+}
+
+pub fn is_share_metric(metric: &str) -> bool {
+    metric.to_ascii_uppercase().starts_with(SHARE_PREFIX)
+}
+
+/// `Z:\` -> `SHARE_Z`, `/mnt/backup` -> `SHARE_MNT_BACKUP`
+fn share_label(mount_point: &str) -> String {
+    format!("{}{}", SHARE_PREFIX, label_for_mount_point(mount_point).trim_end_matches("_Drive"))
+}
+
+/// Drive letters mapped to a network path in this logon session
+#[cfg(windows)]
+fn mount_points() -> Vec<String> {
+    use windows_sys::Win32::Storage::FileSystem::{GetDriveTypeW, GetLogicalDrives};
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_REMOTE;
+
+    // SAFETY: no arguments; returns a bitmask of drive letters
+    let mask = unsafe { GetLogicalDrives() };
+    (0..26u8)
+        .filter(|i| mask & (1 << i) != 0)
+        .map(|i| format!("{}:\\", (b'A' + i) as char))
+        .filter(|root| {
+            let w: Vec<u16> = root.encode_utf16().chain(Some(0)).collect();
+            // SAFETY: `w` is NUL-terminated
+            unsafe { GetDriveTypeW(w.as_ptr()) == DRIVE_REMOTE }
+        })
+        .collect()
+}
+
+/// Total and available bytes
+#[cfg(windows)]
+fn usage(root: &str) -> Option<(u64, u64)> {
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let w: Vec<u16> = root.encode_utf16().chain(Some(0)).collect();
+    let (mut available, mut total, mut free) = (0u64, 0u64, 0u64);
+    // SAFETY: `w` is NUL-terminated and the out-pointers are valid
+    if unsafe { GetDiskFreeSpaceExW(w.as_ptr(), &mut available, &mut total, &mut free) } == 0 {
+        return None;
+    }
+    Some((total, available))
+}
+
+/// Network filesystems in `/proc/mounts`
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<String> {
+    const NETWORK_FS: [&str; 7] = ["nfs", "nfs4", "cifs", "smb3", "smbfs", "fuse.sshfs", "ceph"];
+
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (mount_point, fs) = (fields.nth(1)?, fields.next()?);
+            // Spaces and tabs in the path are written as octal escapes
+            NETWORK_FS.contains(&fs).then(|| mount_point.replace("\\040", " ").replace("\\011", "\t"))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn usage(mount_point: &str) -> Option<(u64, u64)> {
+    let c_path = std::ffi::CString::new(mount_point).ok()?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut st) } != 0 {
+        return None;
+    }
+    let block = st.f_frsize as u64;
+    Some((st.f_blocks as u64 * block, st.f_bavail as u64 * block))
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn mount_points() -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn usage(_mount_point: &str) -> Option<(u64, u64)> {
+    None
+}
+
+/// [`usage`] on a thread of its own: a hard NFS mount or a vanished SMB
+/// server can block the call for minutes
+fn usage_within(mount_point: &str) -> Option<(u64, u64)> {
+    let (tx, rx) = mpsc::channel();
+    let mp = mount_point.to_string();
+    thread::spawn(move || {
+        let _ = tx.send(usage(&mp));
+    });
+    match rx.recv_timeout(TIMEOUT) {
+        Ok(found) => found,
+        Err(_) => {
+            log::warn!("{} did not answer within {}s; skipped", mount_point, TIMEOUT.as_secs());
+            None
+        }
+    }
+}
+
+pub fn sample(cfg: &SharesConfig) -> Vec<(String, f64)> {
+    if !cfg.enabled {
+        return Vec::new();
+    }
+    let mut out = Vec::new();
+    for mp in mount_points() {
+        let Some((total_bytes, available_bytes)) = usage_within(&mp) else { continue };
+        if total_bytes == 0 {
+            continue;
+        }
+        let share = Volume { mount_point: mp.clone().into(), label: share_label(&mp), id: None, total_bytes, available_bytes };
+        out.push((share.label.clone(), share.used_percent()));
+        out.push((format!("{}_FREE_GB", share.label), share.free_gb()));
+    }
+    out
+}
@@ -0,0 +1,124 @@
+// src/smart.rs
+//! SMART health per physical disk, read through `smartctl` (smartmontools 7+
+//! for JSON output), stored as `SMART_<disk>_HEALTH` (1 = passed),
+//! `SMART_<disk>_REALLOCATED` (sectors) and `SMART_<disk>_WEAR` (% of rated
+//! life used).
+//!
+//! An optional `on_alert` program is run when a disk starts failing its
+//! health check or its reallocated sector count grows.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::storage::metric_label;
+
+/// Table prefix for SMART metrics
+pub const SMART_PREFIX: &str = "SMART_";
+
+/// ATA attributes whose normalised value counts down from 100 as flash wears
+const ATA_WEAR_ATTRS: [u64; 3] = [
+    177, // Wear_Leveling_Count (Samsung)
+    231, // SSD_Life_Left
+    233, // Media_Wearout_Indicator (Intel)
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmartConfig {
+    pub enabled: bool,
+    /// smartctl executable (default: `smartctl` on the PATH)
+    pub smartctl: Option<PathBuf>,
+    /// Program run with the alert message as its only argument
+    pub on_alert: Option<PathBuf>,
+}
+
+fn smartctl(cfg: &SmartConfig, args: &[&str]) -> Result<Value> {
+    let exe = cfg.smartctl.clone().unwrap_or_else(|| PathBuf::from("smartctl"));
+    let out = Command::new(&exe).args(args).output().with_context(|| format!("run {}", exe.display()))?;
+    // The exit status is a bitmask that also flags disk problems; only the JSON matters
+    serde_json::from_slice(&out.stdout).with_context(|| format!("parse output of smartctl {}", args.join(" ")))
+}
+
+/// `/dev/sda` -> `SDA`, `/dev/nvme0` -> `NVME0`
+fn disk_label(device: &str) -> String {
+    metric_label(device.rsplit(['/', '\\']).next().unwrap_or(device))
+}
+
+/// Readings of one disk from `smartctl -a -j`
+fn readings(label: &str, info: &Value) -> Vec<(String, f64)> {
+    let mut out = Vec::new();
+    let table = |name: &str| format!("{}{}_{}", SMART_PREFIX, label, name);
+
+    if let Some(passed) = info["smart_status"]["passed"].as_bool() {
+        out.push((table("HEALTH"), if passed { 1.0 } else { 0.0 }));
+    }
+    let attrs = info["ata_smart_attributes"]["table"].as_array().cloned().unwrap_or_default();
+    let attr = |id: u64| attrs.iter().find(|a| a["id"].as_u64() == Some(id));
+    if let Some(raw) = attr(5).and_then(|a| a["raw"]["value"].as_f64()) {
+        out.push((table("REALLOCATED"), raw));
+    }
+    let nvme_used = info["nvme_smart_health_information_log"]["percentage_used"].as_f64();
+    let ata_left = ATA_WEAR_ATTRS.iter().find_map(|&id| attr(id)).and_then(|a| a["value"].as_f64());
+    if let Some(used) = nvme_used.or(ata_left.map(|left| 100.0 - left)) {
+        out.push((table("WEAR"), used));
+    }
+    out
+}
+
+/// One set of readings per disk smartctl can see; empty unless enabled
+pub fn sample(cfg: &SmartConfig) -> Vec<(String, f64)> {
+    if !cfg.enabled {
+        return Vec::new();
+    }
+    let scan = match smartctl(cfg, &["--scan", "-j"]) {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("{:#}", e);
+            return Vec::new();
+        }
+    };
+    let mut out = Vec::new();
+    for dev in scan["devices"].as_array().cloned().unwrap_or_default() {
+        let Some(name) = dev["name"].as_str() else { continue };
+        let mut args = vec!["-a", "-j", "-n", "standby"];
+        if let Some(kind) = dev["type"].as_str() {
+            args.extend(["-d", kind]);
+        }
+        args.push(name);
+        match smartctl(cfg, &args) {
+            Ok(info) => out.extend(readings(&disk_label(name), &info)),
+            Err(e) => log::warn!("{:#}", e),
+        }
+    }
+    out
+}
+
+pub fn is_smart_metric(metric: &str) -> bool {
+    metric.len() > SMART_PREFIX.len() && metric[..SMART_PREFIX.len()].eq_ignore_ascii_case(SMART_PREFIX)
+}
+
+/// Alert text for a reading worse than the previous one in this month's file
+pub fn alert(table: &str, value: f64, previous: Option<f64>) -> Option<String> {
+    let disk = table.strip_prefix(SMART_PREFIX)?;
+    if let Some(disk) = disk.strip_suffix("_HEALTH") {
+        return (value == 0.0 && previous != Some(0.0)).then(|| format!("disk {} is failing its SMART health check", disk));
+    }
+    if let Some(disk) = disk.strip_suffix("_REALLOCATED") {
+        let before = previous?;
+        return (value > before).then(|| format!("disk {} reallocated sectors rose from {} to {}", disk, before, value));
+    }
+    None
+}
+
+/// Log the alert and hand it to `on_alert`, if configured
+pub fn raise(cfg: &SmartConfig, message: &str) {
+    log::warn!("{}", message);
+    let Some(program) = &cfg.on_alert else { return };
+    match Command::new(program).arg(message).status() {
+        Ok(s) if s.success() => {}
+        Ok(s) => log::warn!("{} exited with {}", program.display(), s),
+        Err(e) => log::warn!("run {}: {}", program.display(), e),
+    }
+}
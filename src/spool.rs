@@ -0,0 +1,160 @@
+// src/spool.rs
+//! Local spool for databases on a network share (`collect --spool-dir`).
+//!
+//! Each run is committed to a small queue database on local disk first and
+//! then moved, oldest first, into its monthly database on the share. Writes
+//! to the share retry with backoff; whatever cannot be delivered stays queued
+//! for the next run, so a share outage delays samples instead of losing them,
+//! and a half-finished write never reaches the monthly file.
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::backend::{self, Backend};
+use crate::collect::Run;
+use crate::smart::{self, SmartConfig};
+
+/// Not `.sqlite`, so graph/export never mistake it for a metrics database
+pub const SPOOL_FILE: &str = "winbox-stats-spool.db";
+
+/// Attempts per queued run before leaving it for the next collection
+const ATTEMPTS: u32 = 3;
+const RETRY_BASE: Duration = Duration::from_secs(2);
+
+fn open_spool(dir: &Path) -> Result<Connection> {
+    std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    let path = dir.join(SPOOL_FILE);
+    let conn = Connection::open(&path).with_context(|| format!("open {}", path.display()))?;
+    conn.busy_timeout(Duration::from_secs(10))?;
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS "Runs"(
+            "Id"  INTEGER PRIMARY KEY AUTOINCREMENT,
+            "Run" TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS "Dead"(
+            "Id"    INTEGER PRIMARY KEY,
+            "Run"   TEXT NOT NULL,
+            "Error" TEXT NOT NULL
+        );
+        "#,
+    )?;
+    Ok(conn)
+}
+
+/// Queued runs, oldest first. A run that does not read back (written by a
+/// newer build, or damaged) is moved to `Dead` rather than blocking the
+/// queue behind it forever.
+fn pending(conn: &Connection) -> Result<Vec<(i64, Run)>> {
+    let mut stmt = conn.prepare(r#"SELECT "Id","Run" FROM "Runs" ORDER BY "Id""#)?;
+    let rows = stmt
+        .query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut out = Vec::new();
+    for (id, json) in rows {
+        match serde_json::from_str(&json) {
+            Ok(run) => out.push((id, run)),
+            Err(e) => {
+                log::warn!("queued run {} is unreadable ({}); moved to the Dead table of {}", id, e, SPOOL_FILE);
+                conn.execute(r#"INSERT OR REPLACE INTO "Dead"("Id","Run","Error") VALUES (?1, ?2, ?3)"#, params![id, json, e.to_string()])?;
+                conn.execute(r#"DELETE FROM "Runs" WHERE "Id" = ?1"#, params![id])?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// A commit that succeeded just before the share dropped may not have been
+/// unqueued; its first sample is then already there
+fn already_written(db: &dyn Backend, run: &Run) -> bool {
+    let Some((table, _)) = run.samples.first() else { return true };
+    db.contains(table, run.ts * 1000 + run.millis as i64)
+}
+
+fn deliver(run: &Run) -> Result<Vec<String>> {
+    let path = Path::new(&run.db_name);
+    let mut db = backend::open_on_share(path)?;
+    if already_written(db.as_ref(), run) {
+        return Ok(Vec::new());
+    }
+    db.write_run(run).with_context(|| format!("write {}", path.display()))
+}
+
+fn deliver_with_retry(run: &Run) -> Result<Vec<String>> {
+    let mut attempt = 1;
+    loop {
+        match deliver(run) {
+            Ok(alerts) => return Ok(alerts),
+            Err(e) if attempt < ATTEMPTS => {
+                let delay = RETRY_BASE * 2u32.pow(attempt - 1);
+                log::info!("{:#}; retrying in {}s", e, delay.as_secs());
+                sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// What [`write`] moved out of the queue
+pub struct Delivered {
+    /// Records written to the monthly databases, this run's included
+    pub records: usize,
+    /// Samples in those records
+    pub rows: usize,
+    /// Records left queued for the next run
+    pub queued: usize,
+    /// SMART alerts raised by the records written
+    pub alerts: Vec<String>,
+}
+
+/// Queue this run, then move everything queued into the monthly databases
+pub fn write(dir: &Path, run: &Run, smart_cfg: &SmartConfig) -> Result<Delivered> {
+    let conn = open_spool(dir)?;
+    // serde_json writes NaN as null, which does not read back as a number
+    let finite = Run { samples: run.samples.iter().filter(|(_, v)| v.is_finite()).cloned().collect(), ..run.clone() };
+    conn.execute(r#"INSERT INTO "Runs"("Run") VALUES (?1)"#, params![serde_json::to_string(&finite)?])?;
+
+    let queued = pending(&conn)?;
+    let total = queued.len();
+    let mut written = 0;
+    let mut rows = 0;
+    let mut raised = Vec::new();
+    for (id, queued_run) in queued {
+        // Stop at the first failure so samples reach each file in time order
+        match deliver_with_retry(&queued_run) {
+            Ok(alerts) => {
+                conn.execute(r#"DELETE FROM "Runs" WHERE "Id" = ?1"#, params![id])?;
+                for message in &alerts {
+                    smart::raise(smart_cfg, message);
+                }
+                raised.extend(alerts);
+                written += 1;
+                rows += queued_run.samples.len();
+            }
+            Err(e) => {
+                log::warn!("{:#}; {} records stay queued in {}", e, total - written, dir.join(SPOOL_FILE).display());
+                break;
+            }
+        }
+    }
+    Ok(Delivered { records: written, rows, queued: total - written, alerts: raised })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_runs_are_moved_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = open_spool(dir.path()).unwrap();
+        conn.execute(r#"INSERT INTO "Runs"("Run") VALUES ('{"samples":[["CPU",null]]}')"#, []).unwrap();
+        assert!(pending(&conn).unwrap().is_empty());
+        let dead: i64 = conn.query_row(r#"SELECT COUNT(*) FROM "Dead""#, [], |r| r.get(0)).unwrap();
+        let queued: i64 = conn.query_row(r#"SELECT COUNT(*) FROM "Runs""#, [], |r| r.get(0)).unwrap();
+        assert_eq!((dead, queued), (1, 0));
+    }
+}
@@ -0,0 +1,105 @@
+// src/stats.rs
+//! Summary statistics over a series of sample values.
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stat {
+    Min,
+    Avg,
+    Max,
+    P50,
+    P90,
+    P95,
+    P99,
+    /// Most recent sample
+    Last,
+}
+
+/// Nearest-rank percentile of already sorted values, `q` in 0..=100
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    let rank = ((q / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// `values` in time order; `None` when there are none
+pub fn compute(stat: Stat, values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let sorted = || {
+        let mut v = values.to_vec();
+        v.sort_by(f64::total_cmp);
+        v
+    };
+    Some(match stat {
+        Stat::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+        Stat::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        Stat::Avg => values.iter().sum::<f64>() / values.len() as f64,
+        Stat::P50 => percentile(&sorted(), 50.0),
+        Stat::P90 => percentile(&sorted(), 90.0),
+        Stat::P95 => percentile(&sorted(), 95.0),
+        Stat::P99 => percentile(&sorted(), 99.0),
+        Stat::Last => values[values.len() - 1],
+    })
+}
+
+/// Calendar buckets for `report` and `query --by`, in local time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Per {
+    Day,
+    #[default]
+    Month,
+}
+
+impl Per {
+    /// `2025-08-14` or `2025-08`
+    pub fn key(self, ts: i64) -> String {
+        let t = DateTime::from_timestamp(ts, 0).unwrap_or_default().with_timezone(&Local);
+        match self {
+            Per::Day => t.format("%Y-%m-%d").to_string(),
+            Per::Month => t.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// The statistics capacity planning asks for, over one period
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub samples: usize,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+pub fn summarize(values: &[f64]) -> Option<Summary> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    Some(Summary {
+        samples: values.len(),
+        min: sorted[0],
+        avg: values.iter().sum::<f64>() / values.len() as f64,
+        max: sorted[sorted.len() - 1],
+        p50: percentile(&sorted, 50.0),
+        p95: percentile(&sorted, 95.0),
+        p99: percentile(&sorted, 99.0),
+    })
+}
+
+/// Summaries per period, oldest first; `samples` in time order
+pub fn summarize_per(samples: &[(i64, f64)], per: Per) -> Vec<(String, Summary)> {
+    let mut buckets: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for (ts, v) in samples {
+        buckets.entry(per.key(*ts)).or_default().push(*v);
+    }
+    buckets.into_iter().filter_map(|(k, v)| Some((k, summarize(&v)?))).collect()
+}
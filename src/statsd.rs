@@ -0,0 +1,85 @@
+// src/statsd.rs
+//! `collect --statsd`: mirror every sample to StatsD as a gauge, or to
+//! Graphite as a plaintext line, for dashboards that already read from
+//! there. Best effort: nothing is queued, as the databases keep the samples.
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Keeps each UDP datagram inside one Ethernet frame
+const MAX_DATAGRAM: usize = 1400;
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum StatsdFormat {
+    /// StatsD gauges over UDP: `winbox.HOST.CPU:12.5|g`
+    #[default]
+    Statsd,
+    /// StatsD gauges over TCP, one per line
+    StatsdTcp,
+    /// Graphite plaintext over TCP: `winbox.HOST.CPU 12.5 1700000000`
+    Graphite,
+}
+
+pub struct StatsdTarget<'a> {
+    /// HOST:PORT
+    pub addr: &'a str,
+    pub format: StatsdFormat,
+    pub prefix: &'a str,
+}
+
+/// Dots separate path levels, so a dot in a host name (or anything else
+/// outside letters, digits, `_` and `-`) becomes `_`
+fn path_part(s: &str) -> String {
+    s.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' }).collect()
+}
+
+fn lines(target: &StatsdTarget, series: &str, ts: i64, samples: &[(String, f64)]) -> Vec<String> {
+    samples
+        .iter()
+        .map(|(metric, value)| {
+            let path = format!("{}.{}.{}", target.prefix, path_part(series), path_part(metric));
+            match target.format {
+                StatsdFormat::Statsd | StatsdFormat::StatsdTcp => format!("{}:{}|g", path, value),
+                StatsdFormat::Graphite => format!("{} {} {}", path, value, ts),
+            }
+        })
+        .collect()
+}
+
+/// Send one run's samples to `target`
+pub fn send(target: &StatsdTarget, series: &str, ts: i64, samples: &[(String, f64)]) -> Result<()> {
+    let lines = lines(target, series, ts, samples);
+    match target.format {
+        StatsdFormat::Statsd => {
+            let socket = UdpSocket::bind("0.0.0.0:0").context("open a UDP socket")?;
+            socket.connect(target.addr).with_context(|| format!("resolve {}", target.addr))?;
+            // As many lines per datagram as fit, newline separated as StatsD accepts them
+            let mut packet = String::new();
+            for line in lines {
+                if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_DATAGRAM {
+                    socket.send(packet.as_bytes()).with_context(|| format!("send to {}", target.addr))?;
+                    packet.clear();
+                }
+                if !packet.is_empty() {
+                    packet.push('\n');
+                }
+                packet.push_str(&line);
+            }
+            if !packet.is_empty() {
+                socket.send(packet.as_bytes()).with_context(|| format!("send to {}", target.addr))?;
+            }
+        }
+        StatsdFormat::StatsdTcp | StatsdFormat::Graphite => {
+            let addr = target.addr.to_socket_addrs().ok().and_then(|mut a| a.next()).with_context(|| format!("resolve {}", target.addr))?;
+            let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).with_context(|| format!("connect to {}", target.addr))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            let body: String = lines.iter().map(|l| format!("{}\n", l)).collect();
+            stream.write_all(body.as_bytes()).with_context(|| format!("send to {}", target.addr))?;
+        }
+    }
+    log::debug!("mirrored {} samples to {}", samples.len(), target.addr);
+    Ok(())
+}
@@ -0,0 +1,978 @@
+// src/storage.rs
+//! Shared SQLite layout for collection, graphing and export.
+//!
+//! Every metric lives in its own table with a `Timestamp` and a `Value`
+//! column. The schema version is tracked in `PRAGMA user_version` and the
+//! migrations below are applied in order whenever a database is opened.
+//!
+//! Timestamps are UNIX epoch seconds (UTC). Sub-second samples (bursts
+//! under a second apart) store a fractional number of seconds, kept to the
+//! millisecond; whole seconds stay integers. Tables created before schema v2
+//! keep their local-time text timestamps and are read transparently.
+//!
+//! Clocks can go back (an NTP correction, or the repeated hour of local
+//! text when DST ends). Samples are kept in the order they were taken: the
+//! optional `Seq` column numbers collection runs, and readers break equal
+//! timestamps by insertion order.
+//!
+//! Old months can be compacted (see `cold`): their tables are replaced by
+//! compressed rows of the `Cold` table, which [`list_metric_tables`] and
+//! [`read_samples_ms`] read as if the tables were still there.
+//!
+//! Graph, export, query and the other readers open databases read-only and
+//! never migrate them, so they take no write lock and leave no journal next
+//! to a collector's live file. With `--immutable` they also skip locking
+//! altogether, for files on a share that is mounted read-only or whose locks
+//! are unreliable; a sample written meanwhile may then be missed.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, LocalResult, NaiveDateTime, SecondsFormat, TimeZone};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row, Transaction};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tempfile::TempPath;
+use walkdir::WalkDir;
+
+use crate::audit;
+use crate::cold;
+use crate::crypto;
+use crate::metric_info::{self, Kind, MetricInfo};
+
+/// Canonical column names for metric tables
+pub const TS_COL: &str = "Timestamp";
+pub const VALUE_COL: &str = "Value";
+/// Collection run number, written when `sequence = true` in the config
+pub const SEQ_COL: &str = "Seq";
+
+/// Metadata table listing every metric table in the file
+pub const METRICS_TABLE: &str = "Metrics";
+
+/// Key/value table describing the database itself (e.g. collection timezone)
+pub const META_TABLE: &str = "Meta";
+
+/// Physical node that took each sample when a series follows a cluster role
+pub const NODES_TABLE: &str = "Nodes";
+
+/// The collector's own timings and the metrics each run missed
+pub const SELFMON_TABLE: &str = "SELFMON";
+
+/// Compacted metric tables, one compressed row each
+pub const COLD_TABLE: &str = "Cold";
+
+/// Tables that never hold samples
+const INTERNAL_TABLES: [&str; 5] = [METRICS_TABLE, META_TABLE, NODES_TABLE, SELFMON_TABLE, COLD_TABLE];
+
+type Migration = fn(&Transaction) -> Result<()>;
+
+/// Ordered migrations; after applying `MIGRATIONS[i]` the version is `i + 1`.
+const MIGRATIONS: &[Migration] = &[
+    migrate_v1_canonical_columns,
+    migrate_v2_meta_table,
+    migrate_v3_nodes_table,
+    migrate_v4_metric_descriptions,
+    migrate_v5_sequence_column,
+    migrate_v6_unique_timestamps,
+    migrate_v7_selfmon_table,
+];
+
+pub const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+/// How long to wait on a locked database before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Locks on a network share are slower to clear
+const SHARE_BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Set by `--immutable`
+static IMMUTABLE: AtomicBool = AtomicBool::new(false);
+
+/// Open databases for reading as `immutable=1`: no locks, no journal checks
+pub fn set_immutable(on: bool) {
+    IMMUTABLE.store(on, Ordering::Relaxed);
+}
+
+/// v1: rename legacy `ts`/`value` columns and register existing tables in `Metrics`
+fn migrate_v1_canonical_columns(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(&format!(
+        r#"CREATE TABLE IF NOT EXISTS "{m}"("Name" TEXT PRIMARY KEY NOT NULL);"#,
+        m = METRICS_TABLE
+    ))?;
+    for table in list_writable_tables(tx)? {
+        let (tc, vc) = pick_cols(tx, &table)?;
+        if tc != TS_COL {
+            tx.execute_batch(&format!(
+                r#"ALTER TABLE "{table}" RENAME COLUMN "{tc}" TO "{TS_COL}";"#
+            ))?;
+        }
+        if vc != VALUE_COL {
+            tx.execute_batch(&format!(
+                r#"ALTER TABLE "{table}" RENAME COLUMN "{vc}" TO "{VALUE_COL}";"#
+            ))?;
+        }
+        tx.execute(&format!(r#"INSERT OR IGNORE INTO "{}"("Name") VALUES (?1)"#, METRICS_TABLE), params![table])?;
+    }
+    Ok(())
+}
+
+/// v2: database-level metadata; new metric tables store epoch timestamps from here on
+fn migrate_v2_meta_table(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(&format!(
+        r#"CREATE TABLE IF NOT EXISTS "{m}"("Key" TEXT PRIMARY KEY NOT NULL, "Value" TEXT NOT NULL);"#,
+        m = META_TABLE
+    ))?;
+    Ok(())
+}
+
+/// v3: per-sample node names for cluster-role series
+fn migrate_v3_nodes_table(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(&format!(
+        r#"CREATE TABLE IF NOT EXISTS "{n}"("{TS_COL}" INTEGER NOT NULL, "Node" TEXT NOT NULL);"#,
+        n = NODES_TABLE
+    ))?;
+    Ok(())
+}
+
+/// v4: unit, kind, label and axis range per metric; existing rows stay empty and are described by name when read
+fn migrate_v4_metric_descriptions(tx: &Transaction) -> Result<()> {
+    for col in [r#""Unit" TEXT"#, r#""Kind" TEXT"#, r#""Label" TEXT"#, r#""YMin" REAL"#, r#""YMax" REAL"#] {
+        tx.execute_batch(&format!(r#"ALTER TABLE "{}" ADD COLUMN {};"#, METRICS_TABLE, col))?;
+    }
+    Ok(())
+}
+
+/// v5: optional run number per sample, so collection order survives the clock going back
+fn migrate_v5_sequence_column(tx: &Transaction) -> Result<()> {
+    for table in list_writable_tables(tx)? {
+        if !has_column(tx, &table, SEQ_COL)? {
+            tx.execute_batch(&format!(r#"ALTER TABLE "{table}" ADD COLUMN "{SEQ_COL}" INTEGER;"#))?;
+        }
+    }
+    Ok(())
+}
+
+/// v6: one row per timestamp, so a retried write replaces the sample instead of
+/// adding another; of existing duplicates the last written is kept, as an upsert would
+fn migrate_v6_unique_timestamps(tx: &Transaction) -> Result<()> {
+    for table in list_writable_tables(tx)? {
+        if !has_text_timestamps(tx, &table)? {
+            tx.execute_batch(&format!(
+                r#"DELETE FROM "{table}" WHERE rowid NOT IN (SELECT MAX(rowid) FROM "{table}" GROUP BY "{TS_COL}");"#
+            ))?;
+        }
+        index_timestamps(tx, &table)?;
+    }
+    Ok(())
+}
+
+/// v7: one row per collection run with its sampling and write times in ms and
+/// the metrics the previous run had but this one did not (comma-separated)
+fn migrate_v7_selfmon_table(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(&format!(
+        r#"CREATE TABLE IF NOT EXISTS "{s}"("{TS_COL}" INTEGER NOT NULL, "SampleMs" REAL NOT NULL, "WriteMs" REAL NOT NULL, "Samples" INTEGER NOT NULL, "Missing" TEXT NOT NULL);"#,
+        s = SELFMON_TABLE
+    ))?;
+    Ok(())
+}
+
+/// Unique index on the timestamp. Legacy local-text tables keep a plain one, as
+/// the hour repeated when DST ends has the same text twice.
+pub fn index_timestamps(conn: &Connection, table: &str) -> Result<()> {
+    if has_text_timestamps(conn, table)? {
+        conn.execute_batch(&format!(r#"CREATE INDEX IF NOT EXISTS "ix_{table}_{TS_COL}" ON "{table}"("{TS_COL}");"#))?;
+    } else {
+        conn.execute_batch(&format!(
+            r#"CREATE UNIQUE INDEX IF NOT EXISTS "ux_{table}_{TS_COL}" ON "{table}"("{TS_COL}");
+               DROP INDEX IF EXISTS "ix_{table}_{TS_COL}";"#
+        ))?;
+    }
+    Ok(())
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+    let names = stmt.query_map([], |r| r.get::<_, String>(1))?;
+    for name in names {
+        if name?.eq_ignore_ascii_case(column) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+pub fn schema_version(conn: &Connection) -> Result<i32> {
+    Ok(conn.query_row("PRAGMA user_version", [], |r| r.get(0))?)
+}
+
+/// Bring `conn` up to `SCHEMA_VERSION`, one transaction per step.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let current = schema_version(conn)?;
+    if current > SCHEMA_VERSION {
+        anyhow::bail!(
+            "schema version {} is newer than this build supports ({})",
+            current,
+            SCHEMA_VERSION
+        );
+    }
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = i as i32 + 1;
+        let tx = conn.transaction()?;
+        step(&tx).with_context(|| format!("migrate to schema version {}", version))?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Directory holding a database file (and its audit log)
+pub fn data_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+fn readable(conn: &Connection) -> bool {
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |r| r.get::<_, i64>(0)).is_ok()
+}
+
+/// Open a connection, keyed if encryption is configured for `key_dir`
+pub fn connect(path: &Path, flags: OpenFlags, key_dir: &Path) -> Result<Connection> {
+    let open = || -> Result<Connection> {
+        let conn = Connection::open_with_flags(path, flags).with_context(|| format!("open {}", path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(conn)
+    };
+    let conn = open()?;
+    let Some(key) = crypto::database_key(key_dir)? else {
+        if !readable(&conn) {
+            anyhow::bail!(
+                "{} is not a readable database; if it is encrypted, set {}",
+                path.display(),
+                crypto::KEY_ENV
+            );
+        }
+        return Ok(conn);
+    };
+    crypto::apply_key(&conn, &key)?;
+    if readable(&conn) {
+        return Ok(conn);
+    }
+    // Files from before encryption was enabled stay plain
+    let plain = open()?;
+    if readable(&plain) {
+        log::debug!("{} is not encrypted", path.display());
+        return Ok(plain);
+    }
+    anyhow::bail!("{} cannot be read with the configured database key", path.display())
+}
+
+/// Open (or create) a database and apply pending migrations.
+pub fn open(path: &Path) -> Result<Connection> {
+    let mut conn = connect(path, OpenFlags::default(), data_dir(path))?;
+    let before = schema_version(&conn)?;
+    // Only files that already hold data count as rewritten; new files just get the schema
+    let had_data = !list_metric_tables(&conn)?.is_empty();
+    migrate(&mut conn).with_context(|| format!("migrate {}", path.display()))?;
+    if had_data && before < SCHEMA_VERSION {
+        log::info!("migrated {} from schema v{} to v{}", path.display(), before, SCHEMA_VERSION);
+        // A damaged log must not stop the database from opening
+        if let Err(e) = audit::record(
+            data_dir(path),
+            "migrate",
+            &path.display().to_string(),
+            &format!("schema v{} -> v{}", before, SCHEMA_VERSION),
+        ) {
+            log::warn!("migration of {} not audited: {:#}", path.display(), e);
+        }
+    }
+    Ok(conn)
+}
+
+/// Compressed monthly archives readable by graph/export
+pub const ARCHIVE_EXTS: [&str; 3] = ["gz", "zst", "zip"];
+
+/// `YYYYMM@HOST.sqlite[.gz|.zst|.zip]`
+pub fn is_database(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    name.ends_with(".sqlite") || ARCHIVE_EXTS.iter().any(|e| name.ends_with(&format!(".sqlite.{}", e)))
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .map(|e| ARCHIVE_EXTS.iter().any(|a| e.eq_ignore_ascii_case(a)))
+        .unwrap_or(false)
+}
+
+/// File name without `.sqlite` and any compression suffix
+pub fn db_stem(path: &Path) -> String {
+    let mut p = path.to_path_buf();
+    if is_archive(&p) {
+        p.set_extension("");
+    }
+    p.file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Databases (plain or archived) in `dir`, sorted by name
+pub fn find_databases(dir: &Path, max_depth: usize) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .max_depth(max_depth)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file() && is_database(e.path()))
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// An open database. Archives are inflated to a temp file that is removed on drop.
+pub struct Db {
+    pub conn: Connection,
+    // Declared after `conn` so the connection closes before the file goes away
+    _inflated: Option<TempPath>,
+}
+
+impl From<Connection> for Db {
+    fn from(conn: Connection) -> Db {
+        Db { conn, _inflated: None }
+    }
+}
+
+fn inflate(path: &Path) -> Result<TempPath> {
+    let src = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut tmp = tempfile::Builder::new().suffix(".sqlite").tempfile()?;
+    let ext = path.extension().unwrap_or_default().to_ascii_lowercase();
+    if ext == "gz" {
+        io::copy(&mut flate2::read::GzDecoder::new(src), tmp.as_file_mut())
+    } else if ext == "zip" {
+        // `archive` writes the database as the only entry
+        let mut zip = zip::ZipArchive::new(src).with_context(|| format!("read {}", path.display()))?;
+        let mut entry = zip.by_index(0).with_context(|| format!("read {}", path.display()))?;
+        io::copy(&mut entry, tmp.as_file_mut())
+    } else {
+        io::copy(&mut zstd::stream::read::Decoder::new(src)?, tmp.as_file_mut())
+    }
+    .with_context(|| format!("decompress {}", path.display()))?;
+    Ok(tmp.into_temp_path())
+}
+
+/// `file:` URI of `path` with `immutable=1`; `C:\x` becomes `file:///C:/x`
+/// and `\\server\share` `file:////server/share`
+fn immutable_uri(path: &Path) -> Result<String> {
+    let abs = std::path::absolute(path).with_context(|| format!("resolve {}", path.display()))?;
+    let mut uri = String::from("file://");
+    let s = abs.to_string_lossy().replace('\\', "/");
+    if !s.starts_with('/') {
+        uri.push('/');
+    }
+    for c in s.chars() {
+        match c {
+            '%' | '?' | '#' => uri.push_str(&format!("%{:02X}", c as u32)),
+            _ => uri.push(c),
+        }
+    }
+    uri.push_str("?immutable=1");
+    Ok(uri)
+}
+
+/// Open any database read-only and as-is: no migrations, nothing written.
+pub fn open_read_only(path: &Path) -> Result<Db> {
+    if !is_archive(path) {
+        let conn = if IMMUTABLE.load(Ordering::Relaxed) {
+            let uri = immutable_uri(path)?;
+            let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI;
+            connect(Path::new(&uri), flags, data_dir(path)).with_context(|| format!("open {} immutable", path.display()))?
+        } else {
+            connect(path, OpenFlags::SQLITE_OPEN_READ_ONLY, data_dir(path))?
+        };
+        return Ok(Db { conn, _inflated: None });
+    }
+    let tmp = inflate(path)?;
+    // Never migrate an archive copy: changes would be lost and mis-audited
+    let conn = connect(&tmp, OpenFlags::SQLITE_OPEN_READ_ONLY, data_dir(path))
+        .with_context(|| format!("open inflated {}", path.display()))?;
+    Ok(Db { conn, _inflated: Some(tmp) })
+}
+
+/// Open a database the collector writes to. WAL lets graph/export read while a
+/// sample is being written instead of failing with `database is locked`.
+pub fn open_for_collection(path: &Path) -> Result<Connection> {
+    let conn = open(path)?;
+    let mode: String = conn.pragma_update_and_check(None, "journal_mode", "WAL", |r| r.get(0))?;
+    if !mode.eq_ignore_ascii_case("wal") {
+        log::warn!("{} stays in {} journal mode", path.display(), mode);
+    }
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    Ok(conn)
+}
+
+/// Open a database on a network share. WAL needs shared memory that SMB and
+/// NFS do not provide, so use a rollback journal and wait longer for locks.
+pub fn open_on_share(path: &Path) -> Result<Connection> {
+    let conn = open(path)?;
+    let mode: String = conn.pragma_update_and_check(None, "journal_mode", "DELETE", |r| r.get(0))?;
+    if !mode.eq_ignore_ascii_case("delete") {
+        anyhow::bail!("{} is in {} journal mode and is still in use elsewhere", path.display(), mode);
+    }
+    conn.pragma_update(None, "synchronous", "FULL")?;
+    conn.busy_timeout(SHARE_BUSY_TIMEOUT)?;
+    Ok(conn)
+}
+
+pub fn set_meta(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        &format!(r#"INSERT OR REPLACE INTO "{}"("Key","Value") VALUES (?1, ?2)"#, META_TABLE),
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+pub fn get_meta(conn: &Connection, key: &str) -> Result<Option<String>> {
+    Ok(conn
+        .query_row(&format!(r#"SELECT "Value" FROM "{}" WHERE "Key" = ?1"#, META_TABLE), params![key], |r| r.get(0))
+        .optional()?)
+}
+
+/// Record the collecting host's timezone so readers elsewhere can label local time
+pub fn record_timezone(conn: &Connection) -> Result<()> {
+    let tz = iana_time_zone::get_timezone().unwrap_or_else(|_| Local::now().format("%:z").to_string());
+    set_meta(conn, "timezone", &tz)
+}
+
+/// Remember `ts` as the newest clock reading; returns how many seconds the
+/// clock went back since the previous run, if it did
+pub fn record_clock(conn: &Connection, ts: i64) -> Result<Option<i64>> {
+    let previous = get_meta(conn, "last_ts")?.and_then(|v| v.parse::<i64>().ok());
+    set_meta(conn, "last_ts", &ts.to_string())?;
+    let Some(back) = previous.map(|p| p - ts).filter(|&d| d > 0) else { return Ok(None) };
+    let steps = get_meta(conn, "clock_steps")?.and_then(|v| v.parse::<i64>().ok()).unwrap_or(0);
+    set_meta(conn, "clock_steps", &(steps + 1).to_string())?;
+    Ok(Some(back))
+}
+
+/// Next collection run number of this file, starting at 1
+pub fn next_seq(conn: &Connection) -> Result<i64> {
+    let seq = get_meta(conn, "seq")?.and_then(|v| v.parse::<i64>().ok()).unwrap_or(0) + 1;
+    set_meta(conn, "seq", &seq.to_string())?;
+    Ok(seq)
+}
+
+pub fn record_node(conn: &Connection, ts: i64, node: &str) -> Result<()> {
+    conn.execute(
+        &format!(r#"INSERT INTO "{}"("{TS_COL}","Node") VALUES (?1, ?2)"#, NODES_TABLE),
+        params![ts, node],
+    )?;
+    Ok(())
+}
+
+/// List `table` in `Metrics` with its description; rows from before schema v4 get one too
+fn register_metric(conn: &Connection, table: &str) -> Result<()> {
+    let info = metric_info::describe(table);
+    conn.execute(
+        &format!(
+            r#"INSERT INTO "{}"("Name","Unit","Kind","Label","YMin","YMax") VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+               ON CONFLICT("Name") DO UPDATE SET "Unit" = ?2, "Kind" = ?3, "Label" = ?4, "YMin" = ?5, "YMax" = ?6
+               WHERE "Kind" IS NULL"#,
+            METRICS_TABLE
+        ),
+        params![table, info.unit, info.kind.as_str(), info.label, info.y_min, info.y_max],
+    )?;
+    Ok(())
+}
+
+/// Stored description of `table`, or one derived from its name for files
+/// written before schema v4
+pub fn metric_info(conn: &Connection, table: &str) -> MetricInfo {
+    let stored = conn
+        .query_row(
+            &format!(r#"SELECT "Unit","Kind","Label","YMin","YMax" FROM "{}" WHERE "Name" = ?1"#, METRICS_TABLE),
+            params![table],
+            |r| {
+                Ok((r.get::<_, Option<String>>(0)?, r.get::<_, Option<String>>(1)?, r.get::<_, Option<String>>(2)?, r.get(3)?, r.get(4)?))
+            },
+        )
+        .optional();
+    match stored {
+        Ok(Some((unit, Some(kind), Some(label), y_min, y_max))) => match Kind::parse(&kind) {
+            Some(kind) => MetricInfo { unit: unit.unwrap_or_default(), kind, label, y_min, y_max },
+            None => metric_info::describe(table),
+        },
+        // Read-only files that predate v4 have no such columns
+        _ => metric_info::describe(table),
+    }
+}
+
+/// Upper-case with anything but letters and digits as `_`, for user-named tables
+pub fn metric_label(s: &str) -> String {
+    s.trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+pub fn ensure_table(conn: &Connection, table: &str) -> Result<()> {
+    let sql = format!(
+        r#"
+        CREATE TABLE IF NOT EXISTS "{t}"(
+            "{TS_COL}" INTEGER NOT NULL,
+            "{VALUE_COL}" REAL NOT NULL,
+            "{SEQ_COL}" INTEGER
+        );
+        "#,
+        t = table
+    );
+    conn.execute_batch(&sql)?;
+    index_timestamps(conn, table)?;
+    register_metric(conn, table)
+}
+
+/// Legacy tables declare a TEXT timestamp holding local wall-clock time.
+/// Writers ask once per table and pass the answer to [`insert_sample`].
+pub fn has_text_timestamps(conn: &Connection, table: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+    // 1 = name, 2 = declared type
+    let cols = stmt.query_map([], |r| Ok((r.get::<_, String>(1)?, r.get::<_, String>(2)?)))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    // The timestamp column as pick_cols finds it
+    let time = cols
+        .iter()
+        .find(|(name, _)| matches!(name.to_lowercase().as_str(), "timestamp" | "ts" | "time"))
+        .or_else(|| cols.iter().find(|(name, _)| name == TS_COL));
+    Ok(time.is_some_and(|(_, decl)| decl.to_ascii_uppercase().contains("TEXT")))
+}
+
+/// Insert one sample, replacing any already stored at `ts`; `ts` is UNIX
+/// epoch seconds, `seq` the collection run if numbered, `text` what
+/// [`has_text_timestamps`] says of the table
+pub fn insert_sample(conn: &Connection, table: &str, ts: i64, value: f64, seq: Option<i64>, text: bool) -> Result<()> {
+    insert_sample_ms(conn, table, ts * 1000, value, seq, text)
+}
+
+/// As [`insert_sample`], at `ts_ms` UNIX epoch milliseconds
+pub fn insert_sample_ms(conn: &Connection, table: &str, ts_ms: i64, value: f64, seq: Option<i64>, text: bool) -> Result<()> {
+    let sql = format!(
+        r#"INSERT INTO "{t}"("{TS_COL}","{VALUE_COL}","{SEQ_COL}") VALUES (?1, ?2, ?3)"#,
+        t = table
+    );
+    if text {
+        conn.execute(&sql, params![stored_ts(ts_ms, true), value, seq])?;
+    } else {
+        let upsert = format!(r#"{sql} ON CONFLICT("{TS_COL}") DO UPDATE SET "{VALUE_COL}" = excluded."{VALUE_COL}", "{SEQ_COL}" = excluded."{SEQ_COL}""#);
+        conn.execute(&upsert, params![stored_ts(ts_ms, false), value, seq])?;
+    }
+    Ok(())
+}
+
+/// `ts_ms` as a table stores it: epoch seconds, fractional only for a
+/// sub-second sample, or local text in a table with `text` timestamps
+pub fn stored_ts(ts_ms: i64, text: bool) -> Value {
+    if text {
+        // Keep a legacy table internally consistent until the next monthly file
+        Value::Text(legacy_text(ts_ms.div_euclid(1000)))
+    } else if ts_ms % 1000 == 0 {
+        Value::Integer(ts_ms / 1000)
+    } else {
+        Value::Real(ts_ms as f64 / 1000.0)
+    }
+}
+
+/// Whether `table` has a sample at `ts_ms`, however its timestamps are stored
+pub fn has_sample_ms(conn: &Connection, table: &str, ts_ms: i64) -> Result<bool> {
+    let text = has_text_timestamps(conn, table)?;
+    let sql = format!(r#"SELECT 1 FROM "{}" WHERE "{}" = ?1 LIMIT 1"#, table, TS_COL);
+    Ok(conn.query_row(&sql, [stored_ts(ts_ms, text)], |_| Ok(())).optional()?.is_some())
+}
+
+/// Tables holding samples as rows, skipping SQLite internals, our own
+/// metadata tables and compacted tables
+pub fn list_writable_tables(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let mut out = Vec::new();
+    let rows = stmt.query_map([], |r: &Row| r.get::<_, String>(0))?;
+    for t in rows {
+        let t = t?;
+        if !INTERNAL_TABLES.iter().any(|i| i.eq_ignore_ascii_case(&t)) {
+            out.push(t);
+        }
+    }
+    Ok(out)
+}
+
+/// All sample tables, compacted ones included
+pub fn list_metric_tables(conn: &Connection) -> Result<Vec<String>> {
+    let mut out = list_writable_tables(conn)?;
+    for name in cold::names(conn)? {
+        if !out.iter().any(|t| t.eq_ignore_ascii_case(&name)) {
+            out.push(name);
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// Whether `table` exists as a table of rows, not only compacted
+pub fn is_writable_table(conn: &Connection, table: &str) -> Result<bool> {
+    let found: Option<i64> = conn
+        .query_row("SELECT 1 FROM sqlite_master WHERE type='table' AND name=?1 COLLATE NOCASE", params![table], |r| r.get(0))
+        .optional()?;
+    Ok(found.is_some())
+}
+
+/// Samples in a metric table, compacted ones included
+pub fn count_samples(conn: &Connection, table: &str) -> Result<usize> {
+    let mut n = cold::count(conn, table)?;
+    if is_writable_table(conn, table)? {
+        let rows: i64 = conn.query_row(&format!(r#"SELECT COUNT(*) FROM "{}""#, table), [], |r| r.get(0))?;
+        n += rows as usize;
+    }
+    Ok(n)
+}
+
+/// Resolve the time/value columns of a table, accepting legacy `ts`/`value` names
+pub fn pick_cols(conn: &Connection, table: &str) -> Result<(String, String)> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+    let mut time_col: Option<String> = None;
+    let mut val_col: Option<String> = None;
+
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(1)?; // 1 = name
+        let lname = name.to_lowercase();
+        if time_col.is_none() && (lname == "timestamp" || lname == "ts" || lname == "time") {
+            time_col = Some(name.clone());
+        }
+        if val_col.is_none() && (lname == "value" || lname == "val") {
+            val_col = Some(name.clone());
+        }
+    }
+
+    let tc = time_col.unwrap_or_else(|| TS_COL.to_string());
+    let vc = val_col.unwrap_or_else(|| VALUE_COL.to_string());
+    Ok((tc, vc))
+}
+
+pub fn parse_ts(s: &str) -> Option<NaiveDateTime> {
+    // Text formats written by older collectors and third-party tools
+    const F: [&str; 5] = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y/%m/%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+    ];
+    for f in F {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, f) {
+            return Some(dt);
+        }
+    }
+    None
+}
+
+fn legacy_text(ts: i64) -> String {
+    DateTime::from_timestamp(ts, 0)
+        .unwrap_or_default()
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Epoch seconds from either storage format; text is local wall-clock time
+pub fn ts_to_epoch(v: ValueRef) -> Option<i64> {
+    ts_to_epoch_after(v, None)
+}
+
+/// As [`ts_to_epoch_after`] in milliseconds, keeping the fraction of a
+/// sub-second sample
+fn ts_to_epoch_ms_after(v: ValueRef, prev_ms: Option<i64>) -> Option<i64> {
+    match v {
+        ValueRef::Real(f) => Some((f * 1000.0).round() as i64),
+        v => ts_to_epoch_after(v, prev_ms.map(|p| p.div_euclid(1000))).map(|ts| ts * 1000),
+    }
+}
+
+/// As [`ts_to_epoch`], reading local text in the repeated hour when DST ends
+/// as its second pass if the first would fall before the previous sample `prev`
+fn ts_to_epoch_after(v: ValueRef, prev: Option<i64>) -> Option<i64> {
+    match v {
+        ValueRef::Integer(i) => Some(i),
+        ValueRef::Real(f) => Some(f as i64),
+        ValueRef::Text(t) => {
+            let s = std::str::from_utf8(t).ok()?;
+            if let Ok(i) = s.parse::<i64>() {
+                return Some(i);
+            }
+            let naive = parse_ts(s)?;
+            match Local.from_local_datetime(&naive) {
+                LocalResult::Single(dt) => Some(dt.timestamp()),
+                LocalResult::Ambiguous(a, b) => {
+                    let (first, second) = (a.timestamp().min(b.timestamp()), a.timestamp().max(b.timestamp()));
+                    Some(if prev.is_some_and(|p| first < p) { second } else { first })
+                }
+                LocalResult::None => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// RFC 3339 in the local offset, e.g. `2025-08-01T10:00:00+10:00`
+pub fn format_ts(ts: i64) -> String {
+    DateTime::from_timestamp(ts, 0)
+        .unwrap_or_default()
+        .with_timezone(&Local)
+        .to_rfc3339()
+}
+
+/// As [`format_ts`] from epoch milliseconds, with the fraction only when there is one
+pub fn format_ts_ms(ts_ms: i64) -> String {
+    DateTime::from_timestamp_millis(ts_ms)
+        .unwrap_or_default()
+        .with_timezone(&Local)
+        .to_rfc3339_opts(SecondsFormat::AutoSi, false)
+}
+
+/// Most recent value in a metric table
+pub fn last_value(conn: &Connection, table: &str) -> Result<Option<f64>> {
+    let (tc, vc) = pick_cols(conn, table)?;
+    let sql = format!(r#"SELECT "{vc}" FROM "{table}" ORDER BY "{tc}" DESC LIMIT 1"#);
+    Ok(conn.query_row(&sql, [], |r| r.get(0)).optional()?)
+}
+
+/// Equal timestamps (the clock went back) keep the order they were written in.
+/// Local text sorts the two passes of a repeated DST hour together, so those
+/// tables are read in insertion order and sorted once converted.
+fn sample_order(tc: &str, text: bool) -> String {
+    if text {
+        "rowid".to_string()
+    } else {
+        format!(r#""{tc}" ASC, rowid ASC"#)
+    }
+}
+
+/// (rowid, epoch seconds, value) rows with `from <= ts < to`, oldest first
+pub fn read_rows_between(conn: &Connection, table: &str, from: i64, to: i64) -> Result<Vec<(i64, i64, f64)>> {
+    let rows = read_rows_between_ms(conn, table, from.saturating_mul(1000), to.saturating_mul(1000))?;
+    Ok(rows.into_iter().map(|(rowid, ts_ms, value)| (rowid, ts_ms.div_euclid(1000), value)).collect())
+}
+
+/// As [`read_rows_between`] in epoch milliseconds. Compacted samples have no
+/// rowid and are left out.
+pub fn read_rows_between_ms(conn: &Connection, table: &str, from: i64, to: i64) -> Result<Vec<(i64, i64, f64)>> {
+    if !is_writable_table(conn, table)? {
+        return Ok(Vec::new());
+    }
+    let (tc, vc) = pick_cols(conn, table)?;
+    // Text timestamps do not compare with epoch numbers, so filter those here
+    let text = has_text_timestamps(conn, table)?;
+    let filter = if text { String::new() } else { format!(r#"WHERE "{tc}" >= ?1 AND "{tc}" < ?2"#) };
+    let sql = format!(r#"SELECT rowid, "{tc}", "{vc}" FROM "{table}" {filter} ORDER BY {}"#, sample_order(&tc, text));
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = if text { stmt.query([])? } else { stmt.query(params![from as f64 / 1000.0, to as f64 / 1000.0])? };
+    let mut out = Vec::new();
+    let mut prev = None;
+    while let Some(row) = rows.next()? {
+        let Some(ts) = ts_to_epoch_ms_after(row.get_ref(1)?, prev) else { continue };
+        prev = Some(ts);
+        if ts >= from && ts < to {
+            out.push((row.get(0)?, ts, row.get(2)?));
+        }
+    }
+    if text {
+        out.sort_by_key(|r| r.1);
+    }
+    Ok(out)
+}
+
+/// (epoch seconds, value) rows of a metric table, oldest first
+pub fn read_samples(conn: &Connection, table: &str) -> Result<Vec<(i64, f64)>> {
+    Ok(read_samples_ms(conn, table)?.into_iter().map(|(ts_ms, value)| (ts_ms.div_euclid(1000), value)).collect())
+}
+
+/// As [`read_samples`] in epoch milliseconds
+pub fn read_samples_ms(conn: &Connection, table: &str) -> Result<Vec<(i64, f64)>> {
+    let cold = cold::samples(conn, table)?;
+    if !is_writable_table(conn, table)? {
+        return Ok(cold);
+    }
+    let mut out = read_table_samples_ms(conn, table)?;
+    if !cold.is_empty() {
+        // Written after compaction, e.g. by a backfill; stable, so the cold samples come first at equal times
+        out.splice(0..0, cold);
+        out.sort_by_key(|s| s.0);
+    }
+    Ok(out)
+}
+
+/// Samples stored as rows of `table`, oldest first, leaving out any compacted ones
+pub fn read_table_samples_ms(conn: &Connection, table: &str) -> Result<Vec<(i64, f64)>> {
+    let text = has_text_timestamps(conn, table)?;
+    let mut out = Vec::new();
+    scan_table_samples_ms(conn, table, text, &mut |ts, value| out.push((ts, value)))?;
+    if text {
+        out.sort_by_key(|s| s.0);
+    }
+    Ok(out)
+}
+
+/// Rows of a metric table in storage order; text timestamps are not sorted
+fn scan_table_samples_ms(conn: &Connection, table: &str, text: bool, f: &mut impl FnMut(i64, f64)) -> Result<()> {
+    let (tc, vc) = pick_cols(conn, table)?;
+    let sql = format!(r#"SELECT "{tc}", "{vc}" FROM "{table}" ORDER BY {}"#, sample_order(&tc, text));
+    let mut stmt = conn.prepare(&sql)?;
+    let mut prev = None;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        if let Some(ts) = ts_to_epoch_ms_after(row.get_ref(0)?, prev) {
+            prev = Some(ts);
+            f(ts, row.get(1)?);
+        }
+    }
+    Ok(())
+}
+
+/// As [`read_samples_ms`], handing samples to `f` one at a time, so a large
+/// table is never held in memory. Compacted and text-timestamp tables are
+/// still read whole, as they have to be decoded or sorted first.
+pub fn for_each_sample_ms(conn: &Connection, table: &str, mut f: impl FnMut(i64, f64)) -> Result<()> {
+    if cold::count(conn, table)? > 0 || !is_writable_table(conn, table)? || has_text_timestamps(conn, table)? {
+        read_samples_ms(conn, table)?.into_iter().for_each(|(ts, value)| f(ts, value));
+        return Ok(());
+    }
+    scan_table_samples_ms(conn, table, false, &mut f)
+}
+
+/// Times of the first and last sample of `table`, in epoch milliseconds
+pub fn sample_span_ms(conn: &Connection, table: &str) -> Result<Option<(i64, i64)>> {
+    if cold::count(conn, table)? > 0 || !is_writable_table(conn, table)? || has_text_timestamps(conn, table)? {
+        let samples = read_samples_ms(conn, table)?;
+        return Ok(samples.first().zip(samples.last()).map(|(a, b)| (a.0, b.0)));
+    }
+    let (tc, _) = pick_cols(conn, table)?;
+    let sql = format!(r#"SELECT MIN("{tc}"), MAX("{tc}") FROM "{table}""#);
+    let span = conn.query_row(&sql, [], |r| {
+        Ok(ts_to_epoch_ms_after(r.get_ref(0)?, None).zip(ts_to_epoch_ms_after(r.get_ref(1)?, None)))
+    })?;
+    Ok(span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row("SELECT 1 FROM sqlite_master WHERE type='table' AND name=?1", params![name], |_| Ok(())).is_ok()
+    }
+
+    fn local_ms(text: &str) -> i64 {
+        let naive = parse_ts(text).unwrap();
+        Local.from_local_datetime(&naive).earliest().unwrap().timestamp() * 1000
+    }
+
+    /// A file as the first collectors left it: `ts`/`value` columns, local
+    /// text timestamps in one table, a repeated sample in another
+    fn legacy_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE "CPU"("ts" TEXT, "value" REAL NOT NULL);
+            INSERT INTO "CPU" VALUES ('2026-01-01 00:05:00', 2.0), ('2026-01-01 00:00:00', 1.0), ('1767226200', 3.0);
+            CREATE TABLE "MEM"("ts" INTEGER, "value" REAL NOT NULL);
+            INSERT INTO "MEM" VALUES (60, 1.0), (60, 2.0), (120, 3.0);
+            "#,
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn each_migration_step_applies_in_order() {
+        let mut conn = legacy_db();
+        for (i, step) in MIGRATIONS.iter().enumerate() {
+            let tx = conn.transaction().unwrap();
+            step(&tx).unwrap();
+            tx.pragma_update(None, "user_version", i as i32 + 1).unwrap();
+            tx.commit().unwrap();
+            match i + 1 {
+                1 => {
+                    assert_eq!(pick_cols(&conn, "CPU").unwrap(), (TS_COL.to_string(), VALUE_COL.to_string()));
+                    assert!(has_column(&conn, "MEM", TS_COL).unwrap() && !has_column(&conn, "MEM", "ts").unwrap());
+                    let names: Vec<String> = conn
+                        .prepare(&format!(r#"SELECT "Name" FROM "{}" ORDER BY "Name""#, METRICS_TABLE))
+                        .unwrap()
+                        .query_map([], |r| r.get(0))
+                        .unwrap()
+                        .collect::<rusqlite::Result<_>>()
+                        .unwrap();
+                    assert_eq!(names, ["CPU", "MEM"]);
+                }
+                2 => assert!(table_exists(&conn, META_TABLE)),
+                3 => assert!(table_exists(&conn, NODES_TABLE)),
+                4 => assert!(has_column(&conn, METRICS_TABLE, "Unit").unwrap() && has_column(&conn, METRICS_TABLE, "YMax").unwrap()),
+                5 => assert!(has_column(&conn, "CPU", SEQ_COL).unwrap() && has_column(&conn, "MEM", SEQ_COL).unwrap()),
+                6 => {
+                    // The last write of a repeated sample wins, as an upsert would have it
+                    assert_eq!(read_samples_ms(&conn, "MEM").unwrap(), [(60_000, 2.0), (120_000, 3.0)]);
+                    // Local text may repeat an hour, so it is not deduplicated
+                    assert_eq!(count_samples(&conn, "CPU").unwrap(), 3);
+                }
+                7 => assert!(table_exists(&conn, SELFMON_TABLE)),
+                v => panic!("no check for schema version {}", v),
+            }
+        }
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn migrate_is_idempotent_and_refuses_newer_files() {
+        let mut conn = legacy_db();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert_eq!(count_samples(&conn, "MEM").unwrap(), 2);
+
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+
+    #[test]
+    fn a_repeated_sample_replaces_the_stored_one() {
+        let mut conn = legacy_db();
+        migrate(&mut conn).unwrap();
+        insert_sample(&conn, "MEM", 60, 5.0, Some(1), false).unwrap();
+        assert_eq!(read_samples_ms(&conn, "MEM").unwrap(), [(60_000, 5.0), (120_000, 3.0)]);
+        // Legacy tables take repeated text, as the hour DST ends in does
+        insert_sample(&conn, "CPU", local_ms("2026-01-01 00:00:00") / 1000, 4.0, None, true).unwrap();
+        assert_eq!(count_samples(&conn, "CPU").unwrap(), 4);
+    }
+
+    #[test]
+    fn legacy_text_timestamps_read_as_epoch() {
+        let mut conn = legacy_db();
+        migrate(&mut conn).unwrap();
+        // Oldest first whatever order the rows were written in
+        let mut expected = vec![(local_ms("2026-01-01 00:05:00"), 2.0), (local_ms("2026-01-01 00:00:00"), 1.0), (1_767_226_200_000, 3.0)];
+        expected.sort_by_key(|s| s.0);
+        assert_eq!(read_samples_ms(&conn, "CPU").unwrap(), expected);
+        assert_eq!(ts_to_epoch(ValueRef::Text(b"1767226200")), Some(1_767_226_200));
+        assert_eq!(ts_to_epoch(ValueRef::Integer(60)), Some(60));
+        assert_eq!(ts_to_epoch(ValueRef::Text(b"not a time")), None);
+        // Written back in the same local text, so the table stays consistent
+        assert_eq!(stored_ts(local_ms("2026-01-01 00:05:00"), true), Value::Text("2026-01-01 00:05:00".into()));
+    }
+
+    #[test]
+    fn text_timestamps_are_told_by_the_declared_type() {
+        let mut conn = legacy_db();
+        conn.execute_batch(r#"CREATE TABLE "DISK"("time" TEXT, "val" REAL); CREATE TABLE "NET"("Value" REAL, "Timestamp" TEXT);"#).unwrap();
+        assert!(has_text_timestamps(&conn, "CPU").unwrap());
+        assert!(!has_text_timestamps(&conn, "MEM").unwrap());
+        assert!(has_text_timestamps(&conn, "DISK").unwrap());
+        assert!(has_text_timestamps(&conn, "NET").unwrap());
+        migrate(&mut conn).unwrap();
+        ensure_table(&conn, "NEW").unwrap();
+        assert!(has_text_timestamps(&conn, "CPU").unwrap());
+        assert!(!has_text_timestamps(&conn, "NEW").unwrap());
+        assert!(!has_text_timestamps(&conn, "MISSING").unwrap());
+    }
+}
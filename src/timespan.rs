@@ -0,0 +1,138 @@
+// src/timespan.rs
+//! Human time spans on the command line: `90s`, `30m`, `6h`, `2d`, `1w`,
+//! local points in time such as `2025-08-14 02:00`, and time zones.
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use std::str::FromStr;
+
+use crate::storage;
+
+/// Seconds in a span; a bare number is taken as seconds
+pub fn parse(s: &str) -> Result<i64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: i64 = num
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid time span {:?}; use e.g. 30m, 6h or 2d", s))?;
+    let unit_secs = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "s" | "sec" | "secs" => 1,
+        "m" | "min" | "mins" => 60,
+        "h" | "hour" | "hours" => 3600,
+        "d" | "day" | "days" => 86_400,
+        "w" | "week" | "weeks" => 7 * 86_400,
+        _ => anyhow::bail!("invalid time span {:?}; use e.g. 30m, 6h or 2d", s),
+    };
+    if n <= 0 {
+        anyhow::bail!("time span {:?} must be positive", s);
+    }
+    n.checked_mul(unit_secs).ok_or_else(|| anyhow::anyhow!("invalid time span {:?}; use e.g. 30m, 6h or 2d", s))
+}
+
+/// Milliseconds in a span, which may also be given as `250ms` or `0.5s`,
+/// for sub-second burst intervals
+pub fn parse_ms(s: &str) -> Result<i64> {
+    let t = s.trim().to_ascii_lowercase();
+    let fraction = |num: &str, scale: f64| -> Option<i64> {
+        let n: f64 = num.trim().parse().ok()?;
+        (n.is_finite() && n > 0.0).then(|| (n * scale).round().max(1.0) as i64)
+    };
+    let ms = if let Some(num) = t.strip_suffix("ms") {
+        fraction(num, 1.0)
+    } else if let Some(num) = t.strip_suffix('s').filter(|n| n.contains('.')) {
+        fraction(num, 1000.0)
+    } else {
+        parse(s)?.checked_mul(1000)
+    };
+    ms.ok_or_else(|| anyhow::anyhow!("invalid time span {:?}; use e.g. 250ms, 0.5s or 5s", s))
+}
+
+/// Local wall-clock time in the stored timestamp formats, with or without seconds
+pub fn parse_local_time(s: &str) -> Option<NaiveDateTime> {
+    storage::parse_ts(s)
+        .or_else(|| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").ok())
+        .or_else(|| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M").ok())
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(|d| d.and_time(NaiveTime::MIN)))
+}
+
+/// Epoch seconds of a local time given as `flag`, e.g. `--from`
+pub fn local_epoch(s: &str, flag: &str) -> Result<i64> {
+    parse_local_time(s)
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+        .map(|t| t.timestamp())
+        .ok_or_else(|| anyhow::anyhow!("invalid {} {:?}; use e.g. \"2025-08-14 02:00\" or 2025-08-14", flag, s))
+}
+
+/// Time zone charts are labelled in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    /// Wherever the charts are drawn
+    Local,
+    Utc,
+    Named(chrono_tz::Tz),
+    /// Collectors record an offset such as `+10:00` when the zone has no name
+    Fixed(FixedOffset),
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    /// `local`, `utc`, an IANA name such as `Europe/Berlin`, or `+10:00`
+    fn from_str(s: &str) -> Result<Zone, String> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("local") {
+            Ok(Zone::Local)
+        } else if s.eq_ignore_ascii_case("utc") {
+            Ok(Zone::Utc)
+        } else if let Ok(tz) = s.parse::<chrono_tz::Tz>() {
+            Ok(Zone::Named(tz))
+        } else if let Ok(offset) = s.parse::<FixedOffset>() {
+            Ok(Zone::Fixed(offset))
+        } else {
+            Err(format!("unknown time zone {:?}; use local, utc or an IANA name such as Europe/Berlin", s))
+        }
+    }
+}
+
+impl Zone {
+    /// Wall-clock time of `ts` in this zone, as epoch seconds of the same reading in UTC
+    pub fn wall_clock(self, ts: i64) -> Option<i64> {
+        let t = DateTime::from_timestamp(ts, 0)?;
+        let naive = match self {
+            Zone::Local => t.with_timezone(&Local).naive_local(),
+            Zone::Utc => t.with_timezone(&Utc).naive_local(),
+            Zone::Named(tz) => t.with_timezone(&tz).naive_local(),
+            Zone::Fixed(offset) => t.with_timezone(&offset).naive_local(),
+        };
+        Some(naive.and_utc().timestamp())
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Zone::Local => "local time".into(),
+            Zone::Utc => "UTC".into(),
+            Zone::Named(tz) => tz.name().into(),
+            Zone::Fixed(offset) => format!("UTC{}", offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_parse_and_overflow_is_an_error() {
+        assert_eq!(parse("90").unwrap(), 90);
+        assert_eq!(parse("30m").unwrap(), 1800);
+        assert_eq!(parse("2 days").unwrap(), 172_800);
+        assert_eq!(parse_ms("250ms").unwrap(), 250);
+        assert_eq!(parse_ms("0.5s").unwrap(), 500);
+        assert_eq!(parse_ms("1w").unwrap(), 604_800_000);
+        assert!(parse("0h").is_err());
+        assert!(parse("5y").is_err());
+        assert!(parse("99999999999999999w").is_err());
+        assert!(parse_ms("99999999999999999w").is_err());
+        assert!(parse_ms("9223372036854776s").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+// src/top.rs
+//! `top-hosts`: rank hosts by one statistic of one metric across every
+//! database under the current directory, for monthly reviews without charts.
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::aliases::Aliases;
+use crate::cli::TopHostsArgs;
+use crate::series;
+use crate::stats::{self, Stat};
+
+#[derive(Serialize)]
+struct Ranked {
+    rank: usize,
+    host: String,
+    metric: String,
+    stat: Stat,
+    value: f64,
+    samples: usize,
+}
+
+/// Samples of `metric` per host, in time order
+fn collect_by_host(dir: &Path, metric: &str, month: Option<&str>) -> Result<BTreeMap<String, Vec<(i64, f64)>>> {
+    let filter = series::Filter { metric: Some(metric), month, ..Default::default() };
+    let mut by_host: BTreeMap<String, Vec<(i64, f64)>> = BTreeMap::new();
+    for ((host, _), samples) in series::load(dir, &filter)? {
+        by_host.entry(host).or_default().extend(samples);
+    }
+    for samples in by_host.values_mut() {
+        samples.sort_by_key(|s| s.0);
+    }
+    Ok(by_host)
+}
+
+/// Entry point for `winbox-stats top-hosts`
+pub fn run_top_hosts(args: &TopHostsArgs) -> Result<()> {
+    let dir = Path::new(".");
+    let by_host = collect_by_host(dir, &args.metric, args.month.as_deref())?;
+    let metric = Aliases::load(dir)?.resolve(&args.metric);
+
+    let mut ranked: Vec<Ranked> = by_host
+        .into_iter()
+        .filter_map(|(host, samples)| {
+            let values: Vec<f64> = samples.iter().map(|s| s.1).collect();
+            let value = stats::compute(args.stat, &values)?;
+            Some(Ranked { rank: 0, host, metric: metric.clone(), stat: args.stat, value, samples: values.len() })
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.value.total_cmp(&a.value).then_with(|| a.host.cmp(&b.host)));
+    ranked.truncate(args.n);
+    for (i, r) in ranked.iter_mut().enumerate() {
+        r.rank = i + 1;
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&ranked)?);
+        return Ok(());
+    }
+    if ranked.is_empty() {
+        log::warn!("no samples for {}", metric);
+        return Ok(());
+    }
+    let width = ranked.iter().map(|r| r.host.len()).max().unwrap_or(0).max(4);
+    let stat = format!("{:?}", args.stat).to_ascii_uppercase();
+    println!("{:>4}  {:<width$}  {:>8}  {:>8}", "#", "HOST", stat, "SAMPLES");
+    for r in &ranked {
+        println!("{:>4}  {:<width$}  {:>8.2}  {:>8}", r.rank, r.host, r.value, r.samples);
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+// src/verify.rs
+//! `verify`: integrity check of every database here, plus the checks SQLite
+//! cannot do itself: timestamps that parse, never go backwards and fall inside
+//! the month in the file name. `--salvage` copies whatever rows are still
+//! readable out of a damaged file.
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audit;
+use crate::cli::VerifyArgs;
+use crate::cold;
+use crate::graph::plot::split_stem_sqlite;
+use crate::storage::{self, ensure_table, insert_sample, list_metric_tables};
+
+/// Salvaged copies go here, under their original names
+const SALVAGE_DIR: &str = "salvaged";
+
+/// One database for `verify --output json`
+#[derive(Serialize)]
+struct Verified {
+    database: PathBuf,
+    ok: bool,
+    problems: Vec<String>,
+    /// Where `--salvage` copied the readable rows, and how many
+    #[serde(skip_serializing_if = "Option::is_none")]
+    salvaged: Option<(PathBuf, usize)>,
+}
+
+/// Epoch range `[start, end)` of a local `YYYYMM` month
+pub fn month_bounds(ym: &str) -> Option<(i64, i64)> {
+    let y: i32 = ym.get(..4)?.parse().ok()?;
+    let m: u32 = ym.get(4..)?.parse().ok()?;
+    let start = NaiveDate::from_ymd_opt(y, m, 1)?;
+    let end = if m == 12 { NaiveDate::from_ymd_opt(y + 1, 1, 1)? } else { NaiveDate::from_ymd_opt(y, m + 1, 1)? };
+    let epoch = |d: NaiveDate| Local.from_local_datetime(&d.and_time(NaiveTime::MIN)).earliest().map(|t| t.timestamp());
+    Some((epoch(start)?, epoch(end)?))
+}
+
+/// Readable samples of a table in storage order, and what was wrong with the rest
+struct Scan {
+    samples: Vec<(i64, f64)>,
+    bad_ts: usize,
+    bad_value: usize,
+    /// Reading stopped early, e.g. on a damaged page
+    error: Option<String>,
+}
+
+fn scan(conn: &Connection, table: &str) -> Scan {
+    let mut out = Scan { samples: Vec::new(), bad_ts: 0, bad_value: 0, error: None };
+    let result = (|| -> Result<()> {
+        // Compacted samples were checked when written; a damaged blob fails to decode
+        out.samples.extend(cold::samples(conn, table)?.into_iter().map(|(ts, v)| (ts.div_euclid(1000), v)));
+        if !storage::is_writable_table(conn, table)? {
+            return Ok(());
+        }
+        let (tc, vc) = storage::pick_cols(conn, table)?;
+        let mut stmt = conn.prepare(&format!(r#"SELECT "{tc}", "{vc}" FROM "{table}" ORDER BY rowid"#))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            match (storage::ts_to_epoch(row.get_ref(0)?), row.get::<_, Option<f64>>(1)) {
+                (Some(ts), Ok(Some(v))) if v.is_finite() => out.samples.push((ts, v)),
+                (None, _) => out.bad_ts += 1,
+                _ => out.bad_value += 1,
+            }
+        }
+        Ok(())
+    })();
+    out.error = result.err().map(|e| e.to_string());
+    out
+}
+
+/// Problems found in one database, as report lines
+fn check(path: &Path, conn: &Connection) -> Vec<String> {
+    let mut problems = Vec::new();
+    match conn.prepare("PRAGMA integrity_check").and_then(|mut s| {
+        s.query_map([], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()
+    }) {
+        Ok(lines) if lines.len() == 1 && lines[0] == "ok" => {}
+        Ok(lines) => problems.extend(lines.into_iter().map(|l| format!("integrity: {}", l))),
+        Err(e) => problems.push(format!("integrity: {}", e)),
+    }
+
+    let (ym, _, _) = split_stem_sqlite(&storage::db_stem(path));
+    let bounds = month_bounds(&ym);
+    let tables = match list_metric_tables(conn) {
+        Ok(t) => t,
+        Err(e) => {
+            problems.push(format!("cannot list tables: {:#}", e));
+            return problems;
+        }
+    };
+    for t in tables {
+        let s = scan(conn, &t);
+        if let Some(e) = &s.error {
+            problems.push(format!("{}: reading stopped after {} rows: {}", t, s.samples.len(), e));
+        }
+        if s.bad_ts > 0 {
+            problems.push(format!("{}: {} timestamps cannot be parsed", t, s.bad_ts));
+        }
+        if s.bad_value > 0 {
+            problems.push(format!("{}: {} values are missing or not numbers", t, s.bad_value));
+        }
+        let backwards = s.samples.windows(2).filter(|w| w[1].0 < w[0].0).count();
+        if backwards > 0 {
+            problems.push(format!("{}: timestamps go backwards {} times", t, backwards));
+        }
+        if let Some((from, to)) = bounds {
+            let outside = s.samples.iter().filter(|p| p.0 < from || p.0 >= to).count();
+            if outside > 0 {
+                problems.push(format!("{}: {} samples fall outside {}", t, outside, ym));
+            }
+        }
+    }
+    problems
+}
+
+/// Copy every readable, parseable sample into `salvaged/<name>`, in time order
+fn salvage(path: &Path, conn: &Connection) -> Result<(PathBuf, usize)> {
+    let dir = storage::data_dir(path);
+    let name = format!("{}.sqlite", storage::db_stem(path));
+    let dest = dir.join(SALVAGE_DIR).join(&name);
+    if dest.exists() {
+        anyhow::bail!("{} already exists", dest.display());
+    }
+    fs::create_dir_all(dest.parent().unwrap_or(dir))?;
+    let mut out = storage::open(&dest)?;
+    let tx = out.transaction()?;
+    let mut rows = 0;
+    for t in list_metric_tables(conn).unwrap_or_default() {
+        let mut samples = scan(conn, &t).samples;
+        samples.sort_by_key(|s| s.0);
+        ensure_table(&tx, &t)?;
+        let text = storage::has_text_timestamps(&tx, &t)?;
+        for (ts, v) in &samples {
+            insert_sample(&tx, &t, *ts, *v, None, text)?;
+        }
+        rows += samples.len();
+    }
+    tx.commit()?;
+    Ok((dest, rows))
+}
+
+/// Entry point for `winbox-stats verify`; fails if any database has problems
+pub fn run_verify(args: &VerifyArgs) -> Result<()> {
+    let dir = Path::new(".");
+    let mut verified = Vec::new();
+    for p in storage::find_databases(dir, 1) {
+        let db = match storage::open_read_only(&p) {
+            Ok(db) => db,
+            Err(e) => {
+                let problems = vec![format!("cannot open: {:#}", e)];
+                verified.push(Verified { database: p, ok: false, problems, salvaged: None });
+                continue;
+            }
+        };
+        let problems = check(&p, &db.conn);
+        let salvaged = if args.salvage && !problems.is_empty() {
+            let (dest, rows) = salvage(&p, &db.conn).with_context(|| format!("salvage {}", p.display()))?;
+            audit::record(dir, "salvage", &p.display().to_string(), &format!("{} rows -> {}", rows, dest.display()))?;
+            Some((dest, rows))
+        } else {
+            None
+        };
+        verified.push(Verified { database: p, ok: problems.is_empty(), problems, salvaged });
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&verified)?);
+    } else {
+        for v in &verified {
+            println!("{}  {}", if v.ok { "OK " } else { "BAD" }, v.database.display());
+            for line in &v.problems {
+                println!("     {}", line);
+            }
+            if let Some((dest, rows)) = &v.salvaged {
+                println!("     salvaged {} rows into {}", rows, dest.display());
+            }
+        }
+    }
+    let bad = verified.iter().filter(|v| !v.ok).count();
+    if bad > 0 {
+        anyhow::bail!("{} databases have problems", bad);
+    }
+    Ok(())
+}
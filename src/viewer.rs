@@ -0,0 +1,25 @@
+// src/viewer.rs
+//! Hand finished charts and reports to the desktop's default viewer, for
+//! `graph --open` and `report --open`.
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Open `path` (a file or folder) with whatever the desktop associates with it
+pub fn open(path: &Path) -> Result<()> {
+    let mut cmd = if cfg!(windows) {
+        // `start` is a cmd built-in; the empty argument is the window title
+        let mut c = Command::new("cmd");
+        c.args(["/C", "start", ""]);
+        c
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    let status = cmd.arg(path).status().with_context(|| format!("open {}", path.display()))?;
+    if !status.success() {
+        anyhow::bail!("could not open {} ({})", path.display(), status);
+    }
+    Ok(())
+}
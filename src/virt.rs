@@ -0,0 +1,119 @@
+// src/virt.rs
+//! Hypervisor and container detection. A guest's `CPU` only counts the time
+//! it was given, so on an oversubscribed host it reads low while the
+//! workload waits; each run records what it runs on (`virtualization` in
+//! `Meta`, shown in reports) and, in Linux guests, `CPU_STEAL`: the share of
+//! the CPU window the hypervisor gave to someone else.
+//!
+//! Windows guests have no steal or ready counter of their own; VMware's
+//! `\VM Processor(*)\CPU stolen time` can be added under `[[perf_counters]]`
+//! where VMware Tools is installed.
+pub const CPU_STEAL: &str = "CPU_STEAL";
+
+/// Substrings of the firmware's manufacturer and model, lower case
+const HYPERVISORS: [(&str, &str); 9] = [
+    ("vmware", "VMware"),
+    ("virtualbox", "VirtualBox"),
+    ("innotek", "VirtualBox"),
+    ("microsoft corporation virtual machine", "Hyper-V"),
+    ("qemu", "KVM"),
+    ("kvm", "KVM"),
+    ("xen", "Xen"),
+    ("amazon ec2", "AWS"),
+    ("google compute engine", "Google Cloud"),
+];
+
+fn hypervisor_from(vendor: &str, model: &str) -> Option<&'static str> {
+    let both = format!("{} {}", vendor.trim(), model.trim()).to_ascii_lowercase();
+    HYPERVISORS.iter().find(|(needle, _)| both.contains(needle)).map(|(_, name)| *name)
+}
+
+#[cfg(windows)]
+fn hypervisor() -> Option<String> {
+    use ::wmi::{COMLibrary, Variant, WMIConnection};
+    use std::collections::HashMap;
+
+    let con = WMIConnection::new(COMLibrary::new().ok()?).ok()?;
+    let rows: Vec<HashMap<String, Variant>> = con.raw_query("SELECT Manufacturer, Model FROM Win32_ComputerSystem").ok()?;
+    let text = |row: &HashMap<String, Variant>, key: &str| match row.get(key) {
+        Some(Variant::String(s)) => s.clone(),
+        _ => String::new(),
+    };
+    // `HypervisorPresent` is also true on hosts running Hyper-V or VBS, so only the firmware strings count
+    let row = rows.first()?;
+    hypervisor_from(&text(row, "Manufacturer"), &text(row, "Model")).map(String::from)
+}
+
+#[cfg(target_os = "linux")]
+fn hypervisor() -> Option<String> {
+    let dmi = |f: &str| std::fs::read_to_string(format!("/sys/class/dmi/id/{}", f)).unwrap_or_default();
+    if let Some(name) = hypervisor_from(&dmi("sys_vendor"), &dmi("product_name")) {
+        return Some(name.to_string());
+    }
+    // No firmware strings (e.g. ARM guests), but the kernel knows it is a guest
+    is_guest().then(|| "unknown hypervisor".to_string())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn hypervisor() -> Option<String> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn container() -> Option<&'static str> {
+    use std::path::Path;
+
+    if std::env::var_os("KUBERNETES_SERVICE_HOST").is_some() {
+        return Some("Kubernetes");
+    }
+    if Path::new("/.dockerenv").exists() {
+        return Some("Docker");
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some("Podman");
+    }
+    let cgroup = std::fs::read_to_string("/proc/1/cgroup").unwrap_or_default();
+    [("kubepods", "Kubernetes"), ("docker", "Docker"), ("lxc", "LXC")]
+        .iter()
+        .find(|(needle, _)| cgroup.contains(needle))
+        .map(|(_, name)| *name)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn container() -> Option<&'static str> {
+    None
+}
+
+/// `VMware`, `Docker`, `Docker on KVM`; `None` on bare metal
+pub fn detect() -> Option<String> {
+    match (container(), hypervisor()) {
+        (Some(c), Some(h)) => Some(format!("{} on {}", c, h)),
+        (Some(c), None) => Some(c.to_string()),
+        (None, h) => h,
+    }
+}
+
+/// The CPU flag the kernel sets when running under a hypervisor
+#[cfg(target_os = "linux")]
+pub fn is_guest() -> bool {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    cpuinfo.lines().any(|l| l.starts_with("flags") && l.split_whitespace().any(|f| f == "hypervisor"))
+}
+
+/// (all, steal) jiffies from the `cpu` line of `/proc/stat`
+#[cfg(target_os = "linux")]
+pub fn cpu_times() -> Option<(u64, u64)> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let line = stat.lines().find_map(|l| l.strip_prefix("cpu "))?;
+    // user nice system idle iowait irq softirq steal; guest time is already in user
+    let fields: Vec<u64> = line.split_whitespace().take(8).filter_map(|f| f.parse().ok()).collect();
+    (fields.len() == 8).then(|| (fields.iter().sum(), fields[7]))
+}
+
+/// Stolen share of the time since `start`, in %
+#[cfg(target_os = "linux")]
+pub fn steal_percent(start: Option<(u64, u64)>) -> Option<f64> {
+    let ((all0, steal0), (all, steal)) = (start?, cpu_times()?);
+    let elapsed = all.saturating_sub(all0);
+    (elapsed > 0).then(|| steal.saturating_sub(steal0) as f64 / elapsed as f64 * 100.0)
+}
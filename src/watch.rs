@@ -0,0 +1,210 @@
+// src/watch.rs
+//! `watch`: a live terminal dashboard of CPU, RAM, disk and network use, for
+//! a quick look when logged on to a box mid-incident. Sampling runs on its own
+//! thread so keys stay responsive; `--write` also stores every sample in this
+//! month's database as `collect` would.
+use anyhow::{bail, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use sysinfo::Networks;
+
+use crate::aliases::Aliases;
+use crate::backend;
+use crate::cli::WatchArgs;
+use crate::collect::{self, Run};
+use crate::config::Config;
+use crate::disks;
+use crate::identity::{self, Identity};
+use crate::metric_info;
+use crate::timespan;
+use crate::virt;
+
+/// How often keys are checked while waiting for the next sample
+const POLL: Duration = Duration::from_millis(100);
+/// Samples kept per metric; more than any terminal is wide
+const HISTORY: usize = 1000;
+
+/// One round of the sampler thread
+struct Reading {
+    values: Vec<(String, f64)>,
+    /// Why the sample could not be written, with `--write`
+    error: Option<String>,
+}
+
+struct Track {
+    name: String,
+    unit: String,
+    history: VecDeque<f64>,
+}
+
+/// The dashboard shows the headline metrics; `--write` still stores everything
+fn shown(metric: &str) -> bool {
+    metric == "CPU" || metric == "RAM" || metric.ends_with("_Drive")
+}
+
+/// KB/s received and sent on all interfaces since the last refresh
+fn network_rates(networks: &mut Networks, elapsed: Duration) -> [(String, f64); 2] {
+    networks.refresh();
+    let secs = elapsed.as_secs_f64().max(0.001);
+    let (rx, tx) = networks.iter().fold((0u64, 0u64), |(rx, tx), (_, n)| (rx + n.received(), tx + n.transmitted()));
+    [("NET_IN".into(), rx as f64 / 1024.0 / secs), ("NET_OUT".into(), tx as f64 / 1024.0 / secs)]
+}
+
+fn write(config: &Config, id: &Identity, virtualization: Option<&str>, sample_ms: f64, samples: Vec<(String, f64)>) -> Result<()> {
+    let run = Run {
+        db_name: collect::monthly_db_name(&id.series, config.storage.backend),
+        ts: collect::now_timestamp(),
+        millis: 0,
+        node: id.node.clone(),
+        cluster: id.cluster.clone(),
+        role: id.role.clone(),
+        host_label: id.host_label.clone(),
+        virtualization: virtualization.map(String::from),
+        sample_ms: Some(sample_ms),
+        sequence: config.sequence,
+        partial: false,
+        samples,
+    };
+    backend::open_for_collection(Path::new(&run.db_name))?.write_run(&run)?;
+    Ok(())
+}
+
+fn sample_loop(config: &Config, id: Option<&Identity>, interval: Duration, tx: mpsc::Sender<Reading>, stop: &AtomicBool) {
+    let aliases = Aliases::load(Path::new(".")).unwrap_or_default();
+    if id.is_some() {
+        if let Err(e) = disks::remember(Path::new("."), &config.volume_names) {
+            log::warn!("{:#}", e);
+        }
+    }
+    let virtualization = id.and_then(|_| virt::detect());
+    let mut networks = Networks::new_with_refreshed_list();
+    let mut last = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        let started = Instant::now();
+        let samples: Vec<(String, f64)> =
+            collect::sample_all(config).into_iter().map(|(table, value)| (aliases.resolve(&table), value)).collect();
+        let sample_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let mut values: Vec<(String, f64)> = samples.iter().filter(|(m, _)| shown(m)).cloned().collect();
+        values.extend(network_rates(&mut networks, last.elapsed()));
+        last = Instant::now();
+        let error = id.and_then(|id| write(config, id, virtualization.as_deref(), sample_ms, samples).err()).map(|e| format!("write failed: {:#}", e));
+        if tx.send(Reading { values, error }).is_err() {
+            return;
+        }
+        while started.elapsed() < interval && !stop.load(Ordering::Relaxed) {
+            thread::sleep(POLL);
+        }
+    }
+}
+
+/// Green, or yellow/red past the config thresholds
+fn color(config: &Config, metric: &str, value: f64) -> Color {
+    let th = config.threshold_for(metric);
+    if th.and_then(|t| t.critical).is_some_and(|c| value >= c) {
+        Color::Red
+    } else if th.and_then(|t| t.warning).is_some_and(|w| value >= w) {
+        Color::Yellow
+    } else {
+        Color::Green
+    }
+}
+
+fn draw(frame: &mut Frame, header: &str, tracks: &[Track], config: &Config) {
+    let mut rows = vec![Constraint::Length(1)];
+    rows.extend(tracks.iter().map(|_| Constraint::Length(4)));
+    rows.push(Constraint::Min(0));
+    let areas = Layout::vertical(rows).split(frame.area());
+    frame.render_widget(Line::from(header), areas[0]);
+    for (track, area) in tracks.iter().zip(&areas[1..]) {
+        let now = track.history.back().copied().unwrap_or_default();
+        // Newest on the right; one bar per column inside the border
+        let width = area.width.saturating_sub(2) as usize;
+        let skip = track.history.len().saturating_sub(width);
+        // Tenths, as the bars take whole numbers
+        let data: Vec<u64> = track.history.iter().skip(skip).map(|v| (v.max(0.0) * 10.0).round() as u64).collect();
+        let title = format!(" {} {:.1} {} ", track.name, now, track.unit);
+        let mut spark = Sparkline::default()
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .data(&data)
+            .style(Style::default().fg(color(config, &track.name, now)));
+        if track.unit == "%" {
+            spark = spark.max(1000);
+        }
+        frame.render_widget(spark, *area);
+    }
+}
+
+fn ui_loop(terminal: &mut DefaultTerminal, rx: &mpsc::Receiver<Reading>, title: &str, config: &Config) -> Result<()> {
+    let mut tracks: Vec<Track> = Vec::new();
+    let mut status = "sampling…".to_string();
+    loop {
+        while let Ok(reading) = rx.try_recv() {
+            for (name, value) in reading.values {
+                let i = match tracks.iter().position(|t| t.name == name) {
+                    Some(i) => i,
+                    None => {
+                        let unit = if name.starts_with("NET_") { "KB/s".into() } else { metric_info::describe(&name).unit };
+                        tracks.push(Track { name, unit, history: VecDeque::new() });
+                        tracks.len() - 1
+                    }
+                };
+                let history = &mut tracks[i].history;
+                if history.len() == HISTORY {
+                    history.pop_front();
+                }
+                history.push_back(value);
+            }
+            status = reading.error.unwrap_or_default();
+        }
+        let header = format!("{}  (q to quit)  {}", title, status);
+        terminal.draw(|f| draw(f, &header, &tracks, config))?;
+        if event::poll(POLL)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Entry point for `winbox-stats watch`
+pub fn run_watch(args: &WatchArgs, config: &Config) -> Result<()> {
+    if !std::io::stdout().is_terminal() {
+        bail!("watch needs a terminal; use collect to record samples from scripts");
+    }
+    let interval = Duration::from_secs(timespan::parse(&args.interval)? as u64);
+    let id = identity::resolve(&identity::Naming {
+        cluster_role: None,
+        host_label: config.host_label.as_deref(),
+        instance: config.instance.as_deref(),
+    })?;
+    let mut title = format!("winbox-stats watch  {}  every {}", id.series, args.interval);
+    if args.write {
+        title += &format!("  writing to {}", collect::monthly_db_name(&id.series, config.storage.backend));
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let stop = AtomicBool::new(false);
+    let writer = args.write.then_some(&id);
+    thread::scope(|s| {
+        s.spawn(|| sample_loop(config, writer, interval, tx, &stop));
+        let mut terminal = ratatui::init();
+        let result = ui_loop(&mut terminal, &rx, &title, config);
+        ratatui::restore();
+        stop.store(true, Ordering::Relaxed);
+        result
+    })
+}
@@ -0,0 +1,95 @@
+// src/wmi.rs
+//! WMI queries from `[[wmi]]` in the config, for numbers sysinfo does not
+//! expose. Each query should select one numeric property; the value from the
+//! first row returned is stored under the entry's label.
+use serde::Deserialize;
+
+use crate::storage::metric_label;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct WmiQuery {
+    /// Table name
+    pub label: String,
+    /// WQL, e.g. `SELECT LoadPercentage FROM Win32_Processor`
+    pub query: String,
+    /// Namespace (default: `ROOT\CIMV2`)
+    pub namespace: Option<String>,
+    /// Property to read when the query returns more than one number
+    pub property: Option<String>,
+}
+
+/// NaN and infinities count as not a number: they cannot be stored
+#[cfg(windows)]
+fn number(v: &::wmi::Variant) -> Option<f64> {
+    use ::wmi::Variant;
+    let n = match *v {
+        Variant::I1(n) => n as f64,
+        Variant::I2(n) => n as f64,
+        Variant::I4(n) => n as f64,
+        Variant::I8(n) => n as f64,
+        Variant::UI1(n) => n as f64,
+        Variant::UI2(n) => n as f64,
+        Variant::UI4(n) => n as f64,
+        Variant::UI8(n) => n as f64,
+        Variant::R4(n) => n as f64,
+        Variant::R8(n) => n,
+        Variant::Bool(b) => b as u8 as f64,
+        // WMI reports 64-bit integers as strings
+        Variant::String(ref s) => s.trim().parse().ok()?,
+        _ => return None,
+    };
+    n.is_finite().then_some(n)
+}
+
+#[cfg(windows)]
+fn run(q: &WmiQuery) -> anyhow::Result<f64> {
+    use ::wmi::{COMLibrary, Variant, WMIConnection};
+    use anyhow::Context;
+    use std::collections::HashMap;
+
+    let com = COMLibrary::new()?;
+    let con = match &q.namespace {
+        Some(ns) => WMIConnection::with_namespace_path(ns, com)?,
+        None => WMIConnection::new(com)?,
+    };
+    let rows: Vec<HashMap<String, Variant>> = con.raw_query(&q.query)?;
+    let row = rows.into_iter().next().context("no rows")?;
+    if let Some(name) = &q.property {
+        let (_, v) = row
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .with_context(|| format!("no property {}", name))?;
+        return number(v).with_context(|| format!("{} is not a number", name));
+    }
+    // Key properties come back too, so skip anything that is not a number
+    let numbers: Vec<f64> = row.values().filter_map(number).collect();
+    match numbers[..] {
+        [n] => Ok(n),
+        [] => anyhow::bail!("no numeric property in the result"),
+        _ => anyhow::bail!("several numeric properties in the result; set `property`"),
+    }
+}
+
+#[cfg(windows)]
+pub fn sample(queries: &[WmiQuery]) -> Vec<(String, f64)> {
+    queries
+        .iter()
+        .filter_map(|q| match run(q) {
+            Ok(v) => Some((metric_label(&q.label), v)),
+            Err(e) => {
+                log::warn!("WMI {}: {:#}", q.query, e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+pub fn sample(queries: &[WmiQuery]) -> Vec<(String, f64)> {
+    for q in queries {
+        log::warn!("{} ({}) skipped: WMI queries are Windows only", metric_label(&q.label), q.query);
+    }
+    Vec::new()
+}
@@ -0,0 +1,156 @@
+// src/zabbix.rs
+//! `export zabbix`: send samples to a Zabbix server or proxy over the sender
+//! protocol, as `zabbix_sender` does, into trapper items. Each metric becomes
+//! the key `winbox.<metric>` (lowercase, e.g. `winbox.c_drive`) of the Zabbix
+//! host named like the series.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cli::ExportZabbixArgs;
+use crate::progress::Progress;
+use crate::series;
+use crate::timespan;
+
+const DEFAULT_PORT: u16 = 10051;
+/// Values per request, as zabbix_sender sends them
+const BATCH: usize = 250;
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Signature and protocol version 1 in front of every message
+const HEADER: &[u8; 5] = b"ZBXD\x01";
+/// Replies are a line of JSON; anything this big is not Zabbix
+const MAX_REPLY: usize = 1 << 20;
+
+#[derive(Serialize)]
+struct Item<'a> {
+    host: &'a str,
+    key: String,
+    value: String,
+    clock: i64,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    request: &'static str,
+    data: &'a [Item<'a>],
+}
+
+#[derive(Deserialize)]
+struct Response {
+    response: String,
+    #[serde(default)]
+    info: String,
+}
+
+/// `C_Drive` -> `winbox.c_drive`; characters item keys do not allow become `_`
+fn item_key(prefix: &str, metric: &str) -> String {
+    let name: String = metric
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    format!("{}.{}", prefix, name)
+}
+
+fn address(server: &str) -> String {
+    if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{}:{}", server, DEFAULT_PORT)
+    }
+}
+
+/// One request and its reply; a connection per batch, as the server expects
+fn send(addr: &str, items: &[Item]) -> Result<Response> {
+    let payload = serde_json::to_vec(&Request { request: "sender data", data: items })?;
+    let mut stream = TcpStream::connect(addr).with_context(|| format!("connect to {}", addr))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut message = HEADER.to_vec();
+    message.extend((payload.len() as u64).to_le_bytes());
+    message.extend(payload);
+    stream.write_all(&message).with_context(|| format!("send to {}", addr))?;
+
+    let mut head = [0u8; 13];
+    stream.read_exact(&mut head).with_context(|| format!("read the reply of {}", addr))?;
+    let len = u64::from_le_bytes(head[5..].try_into()?) as usize;
+    if &head[..4] != b"ZBXD" || len > MAX_REPLY {
+        bail!("{} did not answer with the Zabbix protocol", addr);
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).with_context(|| format!("read the reply of {}", addr))?;
+    let reply: Response = serde_json::from_slice(&body).with_context(|| format!("parse the reply of {}", addr))?;
+    if reply.response != "success" {
+        bail!("{} refused the values: {}", addr, reply.info);
+    }
+    Ok(reply)
+}
+
+/// `processed: 3; failed: 1; total: 4; seconds spent: 0.000055` -> 1
+fn failed(info: &str) -> usize {
+    info.split(';').find_map(|part| part.trim().strip_prefix("failed:")?.trim().parse().ok()).unwrap_or(0)
+}
+
+/// Entry point for `winbox-stats export zabbix`
+pub fn run_export_zabbix(args: &ExportZabbixArgs, dry_run: bool) -> Result<()> {
+    let ranged = args.from.is_some() || args.to.is_some();
+    let filter = series::Filter {
+        metric: args.metric.as_deref(),
+        host: args.host.as_deref(),
+        from: args.from.as_deref().map(|s| timespan::local_epoch(s, "--from")).transpose()?,
+        to: args.to.as_deref().map(|s| timespan::local_epoch(s, "--to")).transpose()?,
+        ..Default::default()
+    };
+    let series = series::load(Path::new("."), &filter)?;
+    let mut items = Vec::new();
+    for ((host, metric), samples) in &series {
+        // Without a range only the newest sample, for a scheduled run after each collection
+        let picked = if ranged { &samples[..] } else { &samples[samples.len().saturating_sub(1)..] };
+        for &(ts, value) in picked {
+            items.push(Item { host, key: item_key(&args.key_prefix, metric), value: value.to_string(), clock: ts });
+        }
+    }
+    if items.is_empty() {
+        log::warn!("no samples to send");
+        return Ok(());
+    }
+
+    let addr = address(&args.server);
+    if dry_run && args.json {
+        let planned = serde_json::json!({ "server": addr, "dry_run": true, "items": items });
+        println!("{}", serde_json::to_string_pretty(&planned)?);
+        return Ok(());
+    }
+    if dry_run {
+        // zabbix_sender's input file format: host, key, timestamp, value
+        for item in &items {
+            println!("{} {} {} {}", item.host, item.key, item.clock, item.value);
+        }
+        log::info!("would send {} values to {}", items.len(), addr);
+        return Ok(());
+    }
+    let batches: Vec<&[Item]> = items.chunks(BATCH).collect();
+    let progress = Progress::new("Sending", batches.len());
+    let mut rejected = 0;
+    for batch in batches {
+        rejected += failed(&send(&addr, batch)?.info);
+        progress.tick();
+    }
+    progress.finish();
+    if rejected > 0 {
+        log::warn!(
+            "Zabbix did not accept {} values; check that the hosts exist and have {}.* trapper items",
+            rejected,
+            args.key_prefix
+        );
+    }
+    if args.json {
+        let sent = serde_json::json!({ "server": addr, "sent": items.len() - rejected, "failed": rejected });
+        println!("{}", serde_json::to_string_pretty(&sent)?);
+    } else {
+        println!("Sent {} values to {}", items.len() - rejected, addr);
+    }
+    Ok(())
+}